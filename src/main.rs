@@ -1,9 +1,16 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::env;
 use std::fmt::Write as _;
 use std::fs;
-use std::io;
-use std::process::Command;
+use std::io::{self, BufRead, BufReader, IsTerminal, Write as _};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
 
 // Pane metadata returned by tmux.
 #[derive(Debug, Clone)]
@@ -13,21 +20,67 @@ struct PaneInfo {
     pane_pid: i32,
     history_size: i64,
     history_limit: i64,
+    // Unix seconds the pane's session was created, and whether any client shows it.
+    session_created: Option<u64>,
+    session_attached: Option<bool>,
+    // Set when the pane came from a server found by socket discovery, not the
+    // default one; queries about the pane go to that socket.
+    socket: Option<String>,
 }
 
 // One output row in the final report.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 struct ProcRecord {
     pid: i32,
     command: String,
+    command_name: String,
+    user: String,
     swap_bytes: u64,
     physical_bytes: u64,
     rss_bytes: u64,
     tmux_target: String,
+    // The pane's server when it came from socket discovery (PaneInfo.socket).
+    tmux_socket: Option<String>,
     tmux_window_name: String,
     pane_history_size: i64,
     pane_history_limit: i64,
     pane_history_bytes: u64,
+    // pane_history_bytes times --history-overhead: what tmux keeps for that text.
+    pane_history_estimated_bytes: u64,
+    // Whether capture-pane succeeded; None outside tmux or with --no-history-bytes.
+    history_measured: Option<bool>,
+    // The owning tmux session's creation time and attach state; None outside tmux.
+    session_created: Option<u64>,
+    session_attached: Option<bool>,
+    app_parent_pid: Option<i32>,
+    role: Option<String>,
+    // Position in the pane's tree: shell, app, or helper; None when unattached.
+    tag: Option<&'static str>,
+    severity: Option<Severity>,
+    // Below --vmmap-min-rss: swap and physical were not measured.
+    vmmap_skipped: bool,
+    anon_bytes: Option<u64>,
+    file_backed_bytes: Option<u64>,
+    gpu_bytes: Option<u64>,
+    // --energy: macOS "energy impact" (top's POWER column) over a one-second sample.
+    energy_impact: Option<f64>,
+    // --disk-io: (read, written) storage bytes since the process started.
+    disk_io: Option<(u64, u64)>,
+    // Unix seconds the process started, from ps's elapsed time.
+    started_at: Option<u64>,
+    // Cumulative pages read back from disk (macOS pageins, Linux major faults).
+    pageins: Option<u64>,
+    // (minor, major) faults since the previous --watch tick.
+    fault_delta: Option<(u64, u64)>,
+    // --alert-growth: grew past the threshold since the previous tick.
+    growth_alert: Option<bool>,
+    // --since-last: (swap, physical, rss) change since the last export; None if new.
+    since_last: Option<(i64, i64, i64)>,
+    // --percent: share of the report's total swap / physical, in percent.
+    swap_pct: Option<f64>,
+    physical_pct: Option<f64>,
+    // Another top-level app runs in the same pane, usually a double launch.
+    duplicate_instance: bool,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -37,34 +90,279 @@ enum OutputFormat {
     Csv,
     Yaml,
     Markdown,
+    // Self-contained page with inline SVG charts; only `report --db` renders it.
+    Html,
+    // --porcelain: the stable scripting contract for the process and pane views.
+    Porcelain,
+    // A whole Markdown document (summary, both tables, recommendations); live
+    // process and pane views only.
+    MdReport,
+    // Horizontal bar chart of memory per pane, for wikis and issues.
+    Svg,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum MatchMode {
     Exact,
     Full,
+    // Shell-style `*` and `?` against the process name.
+    Glob,
+    // Like Full, but the pattern must span whole argv words.
+    FullWord,
+}
+
+// Display settings for the human-oriented (table/Markdown) renderers.
+#[derive(Debug, Clone, Default)]
+struct RenderOptions {
+    max_command_width: Option<usize>,
+    color: bool,
+    gpu: bool,
+    energy: bool,
+    disk_io: bool,
+    pageins: bool,
+    // --bars: proportional bar after this metric's column in tables.
+    bars: Option<Metric>,
+    percent: bool,
+    cumulative: bool,
+    watch: bool,
+    table_style: TableStyle,
+    yaml_style: YamlStyle,
+    // Set when stdout is a terminal table; rows are fitted to this many columns.
+    term_width: Option<usize>,
+    // --schema-version; None renders the current field set.
+    schema_version: Option<u32>,
+    pids: PidsDisplay,
+    // --since-last found an export to compare with: show delta columns and
+    // fields, including for rows that are new.
+    since_last: bool,
+    header_labels: Vec<(String, String)>,
+}
+
+// How the pane view's PIDs column is shown in tables and Markdown; structured
+// formats always carry the full list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum PidsDisplay {
+    #[default]
+    Summary,
+    Full,
+    None,
+}
+
+// Plain stays tab-separated for `cut`/`awk`; the others pad columns for reading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum TableStyle {
+    #[default]
+    Plain,
+    Ascii,
+    Unicode,
+    Compact,
+}
+
+// Document wraps the records in one mapping with generated_at and a summary,
+// for embedding into larger YAML files; Sequence is the bare record list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum YamlStyle {
+    #[default]
+    Sequence,
+    Document,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ColorMode {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AppProfile {
+    Opencode,
+}
+
+// One line of `ps -A` used to walk process trees without per-PID calls.
+#[derive(Debug, Clone)]
+struct PsEntry {
+    pid: i32,
+    ppid: i32,
+    command: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Subcommand {
+    Report,
+    Advise,
+    Tune,
+    Leaks,
+    Serve,
+    Doctor,
+    Version,
+    InstallService,
+    Snapshot,
+    Merge,
+    InstallHooks,
+    Plugin,
+    InstallKeybinding,
+}
+
+// `snapshot save|list|diff`; snapshots live as JSON files under the state dir.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SnapshotCommand {
+    Save(String),
+    List,
+    Diff(String, String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ServiceManager {
+    Launchd,
+    Systemd,
+}
+
+// What the installed service runs: a --every recorder or the serve dashboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ServiceMode {
+    Record,
+    Serve,
 }
 
 #[derive(Debug, Clone)]
 struct Cli {
-    process_pattern: String,
-    match_mode: MatchMode,
+    command: Subcommand,
+    // Every --process, with its own "glob:"-style mode prefix or --match-mode.
+    process_patterns: Vec<(MatchMode, String)>,
+    // --port: also report whatever listens on these TCP ports.
+    ports: Vec<u16>,
+    // --cwd: also report processes working somewhere under these directories.
+    cwd_paths: Vec<std::path::PathBuf>,
+    // --open-file: also report processes holding these files open.
+    open_files: Vec<std::path::PathBuf>,
+    // --ignore-case: pgrep -i, whatever the match mode.
+    ignore_case: bool,
     view_mode: ViewMode,
     stdout_format: OutputFormat,
     export_path: Option<String>,
     export_format: Option<OutputFormat>,
+    export_append: bool,
+    lock_wait: Duration,
     no_history_bytes: bool,
+    config_path: Option<String>,
+    policy_path: Option<String>,
+    history_target_bytes: u64,
+    apply: bool,
+    dry_run: bool,
+    yes: bool,
+    app: Option<AppProfile>,
+    wide: bool,
+    max_command_width: Option<usize>,
+    user: Option<String>,
+    // --env KEY=VALUE: matched processes must carry every one of these variables.
+    env_filters: Vec<(String, String)>,
+    color: ColorMode,
+    table_style: TableStyle,
+    yaml_style: YamlStyle,
+    quiet: bool,
+    max_concurrent: usize,
+    // --retries: extra attempts for a tmux/ps/vmmap call that fails transiently.
+    retries: u32,
+    watch: Option<Duration>,
+    // --watch-adaptive: the --watch interval is a starting point that stretches
+    // while memory is stable and shrinks when it grows fast.
+    watch_adaptive: bool,
+    // --on-pressure: sleep until the OS reports memory pressure, then collect.
+    on_pressure: bool,
+    every: Option<Duration>,
+    jitter: Duration,
+    cache_ttl: Duration,
+    vmmap_min_rss: Option<u64>,
+    pid: Option<i32>,
+    gpu: bool,
+    // --energy: add macOS energy impact per process (top -stats power).
+    energy: bool,
+    // --disk-io: add per-process storage read/write columns.
+    disk_io: bool,
+    // --pageins: add the cumulative pageins column.
+    pageins: bool,
+    // --bars / --bars-metric: which column gets a proportional bar.
+    bars: Option<Metric>,
+    percent: bool,
+    // --cumulative: running total of the swap share down the sorted table.
+    cumulative: bool,
+    // --pane-detail: each pane row is followed by its processes in the pane table.
+    pane_detail: bool,
+    // report --db: one row per process, ranked by footprint per hour of uptime.
+    per_process: bool,
+    record_path: Option<String>,
+    db_path: Option<String>,
+    leak_threshold_bytes: u64,
+    // --verify: cross-check each process's numbers instead of reporting them.
+    verify: bool,
+    verify_tolerance_pct: f64,
+    listen: String,
+    schema_version: Option<u32>,
+    status_json: bool,
+    log_format: LogFormat,
+    // --capture-bundle / --replay-bundle: directory of recorded command outputs.
+    capture_bundle: Option<String>,
+    replay_bundle: Option<String>,
+    envelope: bool,
+    // --self-metrics: the run's own cost (subprocesses, wall time, peak RSS) in
+    // envelope metadata.
+    self_metrics: bool,
+    // --json-schema-url: published schema named by "$schema" in JSON envelopes.
+    json_schema_url: Option<String>,
+    // --mail: recipient of the rendered report; --sendmail (or the config's
+    // `sendmail`) is the command that delivers it.
+    mail: Option<String>,
+    sendmail: Option<String>,
+    // The config's `headers`: column renames for tables, CSV, and Markdown.
+    header_labels: Vec<(String, String)>,
+    service_manager: ServiceManager,
+    service_mode: ServiceMode,
+    service_args: Vec<String>,
+    pids_display: PidsDisplay,
+    include_shell: bool,
+    history_overhead: f64,
+    // --history-max-lines: capture only the newest n history lines and scale up.
+    history_max_lines: Option<u64>,
+    alert_growth: Option<GrowthThreshold>,
+    alert_notify: bool,
+    since_last: bool,
+    snapshot: Option<SnapshotCommand>,
+    // `merge`: the export files to combine.
+    merge_paths: Vec<String>,
+    // --group-by: a tmux format evaluated per pane; rows sharing a value add up.
+    group_by: Option<String>,
+    mark_panes: bool,
+    // --badge-windows: windows at or above this footprint get a " [2.1G]" name suffix.
+    badge_windows: Option<u64>,
+    badge_clear: bool,
+    // install-keybinding: the tmux key (in the prefix table) that opens the popup.
+    binding_key: String,
+    // --current-session/-window/-pane or --all-sessions; None defers to the config file.
+    scope: Option<TmuxScope>,
+    // The session, window, or pane target collection is limited to; filled in by run().
+    tmux_scope: Option<String>,
+    // Keep our own PID and its ps/tmux/vmmap children when a pattern matches them.
+    include_self: bool,
+    // --orphans-only: keep only processes whose parent chain reaches no pane.
+    orphans_only: bool,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum ViewMode {
     Process,
     Pane,
+    Budget,
+    Mappings,
+    Tmux,
+    Project,
+    Session,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 struct PaneRecord {
     tmux_target: String,
+    tmux_socket: Option<String>,
     tmux_window_name: String,
     process_count: usize,
     pids: Vec<i32>,
@@ -74,975 +372,15850 @@ struct PaneRecord {
     pane_history_size: i64,
     pane_history_limit: i64,
     pane_history_bytes: u64,
+    pane_history_estimated_bytes: u64,
+    history_measured: Option<bool>,
+    session_created: Option<u64>,
+    session_attached: Option<bool>,
+    // --include-shell: the pane's root shell RSS, already part of rss_bytes.
+    shell_rss_bytes: Option<u64>,
+    severity: Option<Severity>,
+    growth_alert: Option<bool>,
+    since_last: Option<(i64, i64, i64)>,
+    swap_pct: Option<f64>,
+    physical_pct: Option<f64>,
+    // Top-level app processes (tag "app", no app parent); more than one is flagged.
+    app_instances: usize,
 }
 
-fn main() {
-    if let Err(err) = run() {
-        eprintln!("error: {err}");
-        std::process::exit(1);
-    }
+// tmux's own processes: scrollback lives in the server, not in the panes.
+#[derive(Debug, Clone, Default)]
+struct TmuxRecord {
+    kind: &'static str,
+    pid: i32,
+    // Clients only: the session they show and the terminal they run in.
+    session: Option<String>,
+    tty: Option<String>,
+    swap_bytes: u64,
+    physical_bytes: u64,
+    rss_bytes: u64,
+    vmmap_skipped: bool,
+    // Server only: capture-pane bytes summed over every pane; None with --no-history-bytes.
+    pane_history_bytes: Option<u64>,
+    pane_count: Option<usize>,
 }
 
-fn run() -> Result<(), String> {
-    let cli = parse_cli()?;
+// One mapping (or same-named group of regions) in a process address space.
+#[derive(Debug, Clone, Default)]
+struct MappingRecord {
+    name: String,
+    kind: &'static str,
+    regions: usize,
+    size_bytes: u64,
+    resident_bytes: u64,
+    swap_bytes: u64,
+}
 
-    // We keep this resilient: if tmux is not available, we still report process memory.
-    let panes = match list_tmux_panes() {
-        Ok(v) => v,
-        Err(e) => {
-            eprintln!("warning: tmux panes unavailable: {e}");
-            Vec::new()
-        }
-    };
-    let pane_by_pid = panes
-        .iter()
-        .map(|p| (p.pane_pid, p.clone()))
-        .collect::<HashMap<_, _>>();
+// A typed value for the structured (JSON/CSV/YAML) renderers.
+#[derive(Debug, Clone, PartialEq)]
+enum Field {
+    Int(i64),
+    Uint(u64),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+    List(Vec<i32>),
+    StrList(Vec<String>),
+    // A list of small objects, e.g. a group's `{command, count}` entries.
+    Records(Vec<Vec<(&'static str, Field)>>),
+    Null,
+}
 
-    let pids = pgrep_pattern(&cli.process_pattern, cli.match_mode)
-        .map_err(|e| format!("failed to discover processes: {e}"))?;
+// Minimal YAML subset used for config and policy files (no external parser).
+#[derive(Debug, Clone, PartialEq)]
+enum YamlValue {
+    Scalar(String),
+    List(Vec<YamlValue>),
+    Map(Vec<(String, YamlValue)>),
+}
 
-    let mut ppid_cache = HashMap::<i32, i32>::new();
-    let mut history_cache = HashMap::<String, u64>::new();
+#[derive(Debug, Clone, Default)]
+struct Config {
+    budgets: Vec<Budget>,
+    severity: Vec<SeverityThreshold>,
+    // `current_session: true` makes --current-session the default inside tmux.
+    current_session: bool,
+    // `json_schema_url`: the --json-schema-url used whenever --envelope is on.
+    json_schema_url: Option<String>,
+    // `sendmail`: the delivery command for --mail, e.g. `msmtp -a nightly`.
+    sendmail: Option<String>,
+    // `headers`: column key (see header_key) -> label shown instead.
+    headers: Vec<(String, String)>,
+}
 
-    let mut rows = Vec::<ProcRecord>::new();
-    for pid in pids {
-        let command = ps_command(pid).unwrap_or_else(|_| "<unavailable>".to_string());
-        let rss_bytes = ps_rss_bytes(pid).unwrap_or(0);
-        let (swap_bytes, physical_bytes) = vmmap_memory(pid).unwrap_or((0, 0));
+// Per-metric cutoffs for the severity column; a value above `crit` wins over `warn`.
+#[derive(Debug, Clone)]
+struct SeverityThreshold {
+    metric: Metric,
+    warn: Option<u64>,
+    crit: Option<u64>,
+}
 
-        let owner = find_owning_pane(pid, &pane_by_pid, &mut ppid_cache);
-        let (
-            tmux_target,
-            tmux_window_name,
-            pane_history_size,
-            pane_history_limit,
-            pane_history_bytes,
-        ) = if let Some(pane) = owner {
-            let history_bytes = if cli.no_history_bytes {
-                0
-            } else if let Some(v) = history_cache.get(&pane.target) {
-                *v
-            } else {
-                let measured = capture_pane_bytes(&pane.target).unwrap_or(0);
-                history_cache.insert(pane.target.clone(), measured);
-                measured
-            };
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Severity {
+    Ok,
+    Warn,
+    Crit,
+}
 
-            (
-                pane.target,
-                pane.window_name,
-                pane.history_size,
-                pane.history_limit,
-                history_bytes,
-            )
-        } else {
-            ("?".to_string(), "?".to_string(), -1, -1, 0)
-        };
+// Limits applied to every pane whose window name matches `window_glob`.
+#[derive(Debug, Clone)]
+struct Budget {
+    window_glob: String,
+    limits: Vec<(Metric, u64)>,
+}
 
-        rows.push(ProcRecord {
-            pid,
-            command,
-            swap_bytes,
-            physical_bytes,
-            rss_bytes,
-            tmux_target,
-            tmux_window_name,
-            pane_history_size,
-            pane_history_limit,
-            pane_history_bytes,
-        });
-    }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Metric {
+    Swap,
+    Physical,
+    Rss,
+    HistoryBytes,
+    HistoryLines,
+}
 
-    // Sort most memory-hungry first.
-    rows.sort_by(|a, b| {
-        b.swap_bytes
-            .cmp(&a.swap_bytes)
-            .then_with(|| b.physical_bytes.cmp(&a.physical_bytes))
-            .then_with(|| a.pid.cmp(&b.pid))
-    });
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum BudgetStatus {
+    Under,
+    Over,
+    #[default]
+    Unbudgeted,
+}
 
-    let panes = aggregate_by_pane(&rows);
+// One declarative rule from a --policy file.
+#[derive(Debug, Clone)]
+struct PolicyRule {
+    name: String,
+    session: Option<String>,
+    window: Option<String>,
+    process: Option<String>,
+    metric: Metric,
+    limit: u64,
+    action: PolicyAction,
+}
 
-    let output = match cli.view_mode {
-        ViewMode::Process => render_process(&rows, cli.stdout_format),
-        ViewMode::Pane => render_pane(&panes, cli.stdout_format),
-    };
-    print!("{output}");
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PolicyAction {
+    Warn,
+    Exit,
+    Kill,
+    ClearHistory,
+}
 
-    if let Some(path) = cli.export_path {
-        let fmt = cli
-            .export_format
-            .or_else(|| infer_format_from_path(&path))
-            .unwrap_or(OutputFormat::Json);
-        let body = match cli.view_mode {
-            ViewMode::Process => render_process(&rows, fmt),
-            ViewMode::Pane => render_pane(&panes, fmt),
-        };
-        fs::write(&path, body).map_err(|e| format!("failed writing export file '{path}': {e}"))?;
-        let count = match cli.view_mode {
-            ViewMode::Process => rows.len(),
-            ViewMode::Pane => panes.len(),
-        };
-        eprintln!("exported {} records to {}", count, path);
-    }
+// One external command that changes state (kill, clear-history, tune).
+#[derive(Debug, Clone)]
+struct PlannedAction {
+    action: &'static str,
+    target: String,
+    program: &'static str,
+    args: Vec<String>,
+    metric: &'static str,
+    before: u64,
+}
 
-    Ok(())
+// A rule that matched a pane (or a single process for process-scoped rules).
+#[derive(Debug, Clone)]
+struct PolicyBreach {
+    rule: String,
+    action: PolicyAction,
+    metric: Metric,
+    used: u64,
+    limit: u64,
+    tmux_target: String,
+    pids: Vec<i32>,
 }
 
-fn parse_cli() -> Result<Cli, String> {
-    // Intentionally no external CLI crate: tiny binary, tiny dependency surface.
-    let mut process_pattern = "opencode".to_string();
-    let mut match_mode = MatchMode::Exact;
-    let mut view_mode = ViewMode::Process;
-    let mut stdout_format = OutputFormat::Table;
-    let mut export_path: Option<String> = None;
-    let mut export_format: Option<OutputFormat> = None;
-    let mut no_history_bytes = false;
+// Suggested history-limit for one tmux session.
+#[derive(Debug, Clone, Default)]
+struct HistoryAdvice {
+    session: String,
+    pane_count: usize,
+    current_limit: i64,
+    max_history_lines: i64,
+    bytes_per_line: u64,
+    suggested_limit: i64,
+    saved_now_bytes: u64,
+    saved_at_capacity_bytes: u64,
+}
 
-    let args = env::args().skip(1).collect::<Vec<_>>();
-    let mut i = 0usize;
-    while i < args.len() {
-        let arg = &args[i];
-        match arg.as_str() {
-            "--process" => {
-                i += 1;
-                process_pattern = args.get(i).ok_or("--process requires a value")?.to_string();
-            }
-            "--match-mode" => {
-                i += 1;
-                let v = args.get(i).ok_or("--match-mode requires a value")?;
-                match_mode = match v.to_ascii_lowercase().as_str() {
-                    "exact" => MatchMode::Exact,
-                    "full" => MatchMode::Full,
-                    _ => return Err(format!("unsupported --match-mode value: {v}")),
-                };
-            }
-            "--format" => {
-                i += 1;
-                let v = args.get(i).ok_or("--format requires a value")?;
-                stdout_format = parse_format(v)?;
-            }
-            "--view" => {
-                i += 1;
-                let v = args.get(i).ok_or("--view requires a value")?;
-                view_mode = parse_view_mode(v)?;
-            }
-            "--export" => {
-                i += 1;
-                export_path = Some(
-                    args.get(i)
-                        .ok_or("--export requires a file path")?
-                        .to_string(),
-                );
-            }
-            "--export-format" => {
-                i += 1;
-                let v = args.get(i).ok_or("--export-format requires a value")?;
-                export_format = Some(parse_format(v)?);
-            }
-            "--no-history-bytes" => no_history_bytes = true,
-            "-h" | "--help" => {
-                print_help();
-                std::process::exit(0);
-            }
-            _ => return Err(format!("unknown argument: {arg}\n\nUse --help for usage.")),
-        }
-        i += 1;
-    }
+// --verify: one process measured by every available source.
+#[derive(Debug, Clone, Default)]
+struct VerifyRecord {
+    pid: i32,
+    command: String,
+    rss_bytes: u64,
+    // vmmap's "Physical footprint" and footprint(1); None where unavailable.
+    vmmap_bytes: Option<u64>,
+    footprint_tool_bytes: Option<u64>,
+    // How far the two footprint sources disagree, relative to the larger one.
+    spread_pct: Option<f64>,
+    status: &'static str,
+}
 
-    Ok(Cli {
-        process_pattern,
-        match_mode,
-        view_mode,
-        stdout_format,
-        export_path,
-        export_format,
-        no_history_bytes,
-    })
+// A process or pane whose recorded footprint keeps climbing.
+#[derive(Debug, Clone, Default)]
+struct LeakRecord {
+    scope: &'static str,
+    subject: String,
+    tmux_target: String,
+    command: String,
+    samples: usize,
+    span_secs: u64,
+    first_bytes: u64,
+    last_bytes: u64,
+    growth_per_hour_bytes: u64,
+    confidence: &'static str,
 }
 
-fn print_help() {
-    println!("opencode-tmux-mem 🔍");
-    println!();
-    println!("Usage:");
-    println!("  opencode-tmux-mem [options]");
-    println!();
-    println!("Options:");
-    println!("  --process <pattern>         Process pattern (default: opencode)");
-    println!("  --match-mode <exact|full>   PID scan mode (default: exact)");
-    println!("  --view <process|pane>       Output view mode (default: process)");
-    println!("  --format <fmt>              table|json|csv|yaml|markdown (default: table)");
-    println!("  --export <path>             Export to file");
-    println!("  --export-format <fmt>       Export format override");
-    println!("  --no-history-bytes          Skip tmux capture-pane byte estimation");
-    println!("  -h, --help                  Show help");
+// Panes added up under a shared key, for --group-by.
+#[derive(Debug, Clone, Default)]
+struct GroupRecord {
+    key: String,
+    pane_count: usize,
+    process_count: usize,
+    swap_bytes: u64,
+    physical_bytes: u64,
+    rss_bytes: u64,
+    pane_history_bytes: u64,
+    // Matched processes per command name, most common first.
+    commands: Vec<(String, usize)>,
+    // --view session only.
+    session_created: Option<u64>,
+    session_attached: Option<bool>,
 }
 
-fn parse_format(v: &str) -> Result<OutputFormat, String> {
-    match v.to_ascii_lowercase().as_str() {
-        "table" => Ok(OutputFormat::Table),
-        "json" => Ok(OutputFormat::Json),
-        "csv" => Ok(OutputFormat::Csv),
-        "yaml" | "yml" => Ok(OutputFormat::Yaml),
-        "markdown" | "md" => Ok(OutputFormat::Markdown),
-        _ => Err(format!("unsupported format: {v}")),
-    }
+// A saved snapshot, for `snapshot list`.
+#[derive(Debug, Clone, Default)]
+struct SnapshotRecord {
+    name: String,
+    saved_at: String,
+    version: String,
+    process_count: usize,
+    swap_bytes: u64,
+    physical_bytes: u64,
+    rss_bytes: u64,
 }
 
-fn parse_view_mode(v: &str) -> Result<ViewMode, String> {
-    match v.to_ascii_lowercase().as_str() {
-        "process" => Ok(ViewMode::Process),
-        "pane" => Ok(ViewMode::Pane),
-        _ => Err(format!("unsupported view mode: {v}")),
-    }
+// One pane across two snapshots, for `snapshot diff`; zeros where it was absent.
+#[derive(Debug, Clone, Default)]
+struct SnapshotDiffRecord {
+    tmux_target: String,
+    tmux_window_name: String,
+    // (processes, swap, physical, rss) in the first and second snapshot.
+    before: (usize, u64, u64, u64),
+    after: (usize, u64, u64, u64),
 }
 
-fn infer_format_from_path(path: &str) -> Option<OutputFormat> {
-    let lower = path.to_ascii_lowercase();
-    if lower.ends_with(".json") {
-        Some(OutputFormat::Json)
-    } else if lower.ends_with(".csv") {
-        Some(OutputFormat::Csv)
-    } else if lower.ends_with(".yaml") || lower.ends_with(".yml") {
-        Some(OutputFormat::Yaml)
-    } else if lower.ends_with(".md") || lower.ends_with(".markdown") {
-        Some(OutputFormat::Markdown)
-    } else {
-        None
-    }
+// One export (or one sample time of a recording), for `merge`.
+#[derive(Debug, Clone, Default)]
+struct MergeRecord {
+    host: String,
+    timestamp: String,
+    source: String,
+    process_count: u64,
+    pane_count: usize,
+    swap_bytes: u64,
+    physical_bytes: u64,
+    rss_bytes: u64,
+    top_pane: Option<String>,
+    top_pane_rss_bytes: u64,
 }
 
-fn run_cmd(program: &str, args: &[&str]) -> io::Result<String> {
-    let out = Command::new(program).args(args).output()?;
-    if !out.status.success() {
-        return Err(io::Error::other(format!(
-            "command failed: {} {} => {}",
-            program,
-            args.join(" "),
-            String::from_utf8_lossy(&out.stderr)
-        )));
-    }
-    Ok(String::from_utf8_lossy(&out.stdout).to_string())
+// One pane's footprint over a recording, for `report --db`.
+#[derive(Debug, Clone, Default)]
+struct PaneTrend {
+    tmux_target: String,
+    tmux_window_name: String,
+    // (unix seconds, summed footprint bytes), oldest first.
+    points: Vec<(u64, u64)>,
+    min_bytes: u64,
+    max_bytes: u64,
+    growth_per_hour_bytes: Option<i64>,
 }
 
-fn list_tmux_panes() -> io::Result<Vec<PaneInfo>> {
-    let raw = run_cmd(
-        "tmux",
-        &[
-            "list-panes",
-            "-a",
-            "-F",
-            "#{session_name}:#{window_index}.#{pane_index}\t#{window_name}\t#{pane_pid}\t#{history_size}\t#{history_limit}",
-        ],
-    )?;
+// One process over a recording, for `report --db --per-process`.
+#[derive(Debug, Clone, Default)]
+struct ProcessTrend {
+    pid: i32,
+    command: String,
+    tmux_target: String,
+    samples: usize,
+    last_ts: u64,
+    last_bytes: u64,
+    growth_per_hour_bytes: Option<i64>,
+    // From process start (or, in older recordings, the first sample) to the last sample.
+    uptime_secs: u64,
+    uptime_from_start: bool,
+    // The last footprint spread over the uptime: how fast the process piled it up.
+    lifetime_per_hour_bytes: u64,
+}
 
-    let mut panes = Vec::new();
-    for line in raw.lines() {
-        let mut parts = line.split('\t');
-        let target = parts.next().unwrap_or("").to_string();
-        let window_name = parts.next().unwrap_or("").to_string();
-        let pane_pid = parts
-            .next()
-            .unwrap_or("0")
-            .trim()
-            .parse::<i32>()
-            .unwrap_or(0);
-        let history_size = parts
-            .next()
-            .unwrap_or("0")
-            .trim()
-            .parse::<i64>()
-            .unwrap_or(0);
-        let history_limit = parts
-            .next()
-            .unwrap_or("0")
-            .trim()
-            .parse::<i64>()
-            .unwrap_or(0);
-        if !target.is_empty() && pane_pid > 0 {
-            panes.push(PaneInfo {
-                target,
-                window_name,
-                pane_pid,
-                history_size,
-                history_limit,
-            });
-        }
-    }
-    Ok(panes)
+// One process row read back from a --record file.
+#[derive(Debug, Clone, Default)]
+struct RecordedSample {
+    ts: u64,
+    pid: i32,
+    command: String,
+    tmux_target: String,
+    tmux_window_name: String,
+    footprint_bytes: u64,
+    // Recordings made before `started_at` existed leave it unset.
+    started_at: Option<u64>,
 }
 
-fn pgrep_pattern(pattern: &str, match_mode: MatchMode) -> io::Result<Vec<i32>> {
-    let args = match match_mode {
-        MatchMode::Exact => vec!["-x", pattern],
-        MatchMode::Full => vec!["-f", pattern],
-    };
-    let out = Command::new("pgrep").args(args).output()?;
-    if !out.status.success() && out.status.code() == Some(1) {
-        return Ok(Vec::new());
-    }
-    if !out.status.success() {
-        return Err(io::Error::other(
-            String::from_utf8_lossy(&out.stderr).to_string(),
-        ));
-    }
-    let mut pids = Vec::new();
-    for line in String::from_utf8_lossy(&out.stdout).lines() {
-        if let Ok(pid) = line.trim().parse::<i32>() {
-            pids.push(pid);
-        }
-    }
-    pids.sort_unstable();
-    pids.dedup();
-    Ok(pids)
+// One pane/metric comparison in the budget view.
+#[derive(Debug, Clone, Default)]
+struct BudgetRecord {
+    tmux_target: String,
+    tmux_window_name: String,
+    budget: String,
+    metric: Option<Metric>,
+    used: u64,
+    limit: u64,
+    status: BudgetStatus,
 }
 
-fn ps_command(pid: i32) -> io::Result<String> {
-    Ok(run_cmd("ps", &["-p", &pid.to_string(), "-o", "command="])?
-        .trim()
-        .to_string())
+// One environment check printed by `doctor`.
+#[derive(Debug, Clone, Default)]
+struct DoctorCheck {
+    name: &'static str,
+    status: CheckStatus,
+    detail: String,
+    // What to do about a warning or failure; empty when passing.
+    hint: String,
 }
 
-fn ps_rss_bytes(pid: i32) -> io::Result<u64> {
-    let raw = run_cmd("ps", &["-p", &pid.to_string(), "-o", "rss="])?;
-    let kb = raw.trim().parse::<u64>().unwrap_or(0);
-    Ok(kb.saturating_mul(1024))
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum CheckStatus {
+    #[default]
+    Pass,
+    Warn,
+    Fail,
 }
 
-fn ps_ppid(pid: i32, cache: &mut HashMap<i32, i32>) -> i32 {
-    if let Some(v) = cache.get(&pid) {
-        return *v;
-    }
-    let ppid = run_cmd("ps", &["-p", &pid.to_string(), "-o", "ppid="])
-        .ok()
-        .and_then(|s| s.trim().parse::<i32>().ok())
-        .unwrap_or(0);
-    cache.insert(pid, ppid);
-    ppid
+// What --status-json reports about the run, filled in as it goes.
+#[derive(Debug)]
+struct RunStatus {
+    records: Option<usize>,
+    // (swap, physical, RSS) summed over the last collection.
+    totals: Option<(u64, u64, u64)>,
+    warnings: Vec<String>,
+    // A crit severity, an over-budget pane, or a policy `exit` rule was hit.
+    breached: bool,
+    // Something found could not be fully measured (ps, vmmap, tmux, ...).
+    partial: bool,
 }
 
-fn find_owning_pane(
-    pid: i32,
-    pane_by_pid: &HashMap<i32, PaneInfo>,
-    ppid_cache: &mut HashMap<i32, i32>,
-) -> Option<PaneInfo> {
-    // Follow parent PID chain until we hit a pane root PID.
-    let mut cur = pid;
-    let mut guard = 0usize;
-    while cur > 0 && guard < 512 {
-        if let Some(pane) = pane_by_pid.get(&cur) {
-            return Some(pane.clone());
-        }
-        cur = ps_ppid(cur, ppid_cache);
-        guard += 1;
-    }
-    None
+static RUN_STATUS: Mutex<RunStatus> = Mutex::new(RunStatus {
+    records: None,
+    totals: None,
+    warnings: Vec::new(),
+    breached: false,
+    partial: false,
+});
+
+// Exit statuses scripts can branch on; 0 is a clean run.
+const EXIT_ERROR: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_THRESHOLD: i32 = 3;
+const EXIT_PARTIAL: i32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LogFormat {
+    Text,
+    Json,
 }
 
-fn vmmap_memory(pid: i32) -> io::Result<(u64, u64)> {
-    let raw = run_cmd("vmmap", &["-summary", &pid.to_string()])?;
-    let mut swap_bytes = 0u64;
-    let mut physical_bytes = 0u64;
+// --log-format json, set once in main before anything is logged.
+static LOG_JSON: AtomicBool = AtomicBool::new(false);
+// The stage of the run diagnostics come from: startup, collect, output, export,
+// mail, or policy.
+static LOG_PHASE: Mutex<&str> = Mutex::new("startup");
+// The tool's own cost: when main started and how many subprocesses it launched.
+static RUN_STARTED: OnceLock<Instant> = OnceLock::new();
+static SUBPROCESSES: AtomicUsize = AtomicUsize::new(0);
+static BUNDLE: OnceLock<Bundle> = OnceLock::new();
 
-    for line in raw.lines() {
-        let t = line.trim_start();
-        if t.starts_with("Physical footprint:") {
-            if let Some(v) = t.split(':').nth(1) {
-                let tok = v.split_whitespace().next().unwrap_or("0B");
-                physical_bytes = parse_compact_bytes(tok);
-            }
+// --capture-bundle writes each command's output next to an index; --replay-bundle
+// serves the same outputs back, so a run can be reproduced on another machine.
+#[derive(Debug)]
+enum Bundle {
+    Capture {
+        dir: std::path::PathBuf,
+        next: Mutex<usize>,
+    },
+    // Outputs per command line, in the order they were captured.
+    Replay(Mutex<HashMap<String, VecDeque<BundleEntry>>>),
+}
+
+#[derive(Debug, Clone, Default)]
+struct BundleEntry {
+    // None when the program could not be started; `missing` means it was not found.
+    exit_code: Option<i32>,
+    missing: bool,
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+}
+
+fn main() {
+    let started = *RUN_STARTED.get_or_init(Instant::now);
+    let (result, status_json, usage_error) = match parse_cli() {
+        Ok(cli) => {
+            LOG_JSON.store(cli.log_format == LogFormat::Json, Ordering::SeqCst);
+            let (result, usage_error) = match load_config(cli.config_path.as_deref()) {
+                Ok(config) => match check_cli(&cli, &config) {
+                    Ok(()) => (open_bundle(&cli).and_then(|_| run(&cli, &config)), false),
+                    Err(e) => (Err(e), true),
+                },
+                Err(e) => (Err(e), false),
+            };
+            (result, cli.status_json, usage_error)
         }
-        if t.starts_with("TOTAL") && !t.contains("minus reserved") {
-            let cols = t.split_whitespace().collect::<Vec<_>>();
-            if cols.len() >= 5 {
-                swap_bytes = parse_compact_bytes(cols[4]);
-            }
-            break;
+        // A wrapper still wants its status line when the command line is wrong.
+        Err(e) => {
+            let args = env::args().collect::<Vec<_>>();
+            let json = args
+                .windows(2)
+                .any(|w| w[0] == "--log-format" && w[1] == "json");
+            LOG_JSON.store(json, Ordering::SeqCst);
+            (Err(e), args.iter().any(|a| a == "--status-json"), true)
         }
+    };
+    if let Err(err) = &result {
+        log_event("error", err, None);
+    }
+    let status = RUN_STATUS.lock().unwrap_or_else(|e| e.into_inner());
+    let code = exit_code(&status, result.is_err(), usage_error);
+    if status_json {
+        eprintln!(
+            "{}",
+            status_line(&status, result.as_ref().err(), code, started.elapsed())
+        );
+    }
+    if code != 0 {
+        std::process::exit(code);
     }
+}
 
-    Ok((swap_bytes, physical_bytes))
+// A failure outranks a breach, which outranks missing data.
+fn exit_code(status: &RunStatus, failed: bool, usage_error: bool) -> i32 {
+    match (failed, usage_error) {
+        (true, true) => EXIT_USAGE,
+        (true, false) => EXIT_ERROR,
+        _ if status.breached => EXIT_THRESHOLD,
+        _ if status.partial => EXIT_PARTIAL,
+        _ => 0,
+    }
 }
 
-fn capture_pane_bytes(target: &str) -> io::Result<u64> {
-    let out = run_cmd(
-        "tmux",
-        &["capture-pane", "-p", "-S", "-", "-E", "-", "-t", target],
-    )?;
-    Ok(out.len() as u64)
+// A warning about data this run could not collect; it ends with EXIT_PARTIAL.
+fn warn_partial(message: String) {
+    warn(message);
+    mark_partial();
 }
 
-fn parse_compact_bytes(token: &str) -> u64 {
-    let t = token.trim();
-    if t.is_empty() {
-        return 0;
-    }
-    if let Ok(v) = t.parse::<u64>() {
-        return v;
-    }
+fn mark_breached() {
+    RUN_STATUS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .breached = true;
+}
 
-    let (num, unit) = t.split_at(t.len().saturating_sub(1));
-    let n = num.parse::<f64>().unwrap_or(0.0);
-    let m = match unit.to_ascii_uppercase().as_str() {
-        "B" => 1.0,
-        "K" => 1024.0,
-        "M" => 1024.0 * 1024.0,
-        "G" => 1024.0 * 1024.0 * 1024.0,
-        "T" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
-        _ => 1.0,
-    };
-    (n * m) as u64
+fn mark_partial() {
+    RUN_STATUS.lock().unwrap_or_else(|e| e.into_inner()).partial = true;
 }
 
-fn human_bytes(bytes: u64) -> String {
-    let units = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
-    let mut v = bytes as f64;
-    let mut i = 0usize;
-    while v >= 1024.0 && i < units.len() - 1 {
-        v /= 1024.0;
-        i += 1;
+fn warn(message: String) {
+    log_event("warn", &message, None);
+    record_warning(message);
+}
+
+fn set_log_phase(phase: &'static str) {
+    *LOG_PHASE.lock().unwrap_or_else(|e| e.into_inner()) = phase;
+}
+
+// Text keeps the familiar "warning: ..." lines and drops the duration.
+fn log_event(level: &str, message: &str, duration: Option<Duration>) {
+    if LOG_JSON.load(Ordering::SeqCst) {
+        let phase = *LOG_PHASE.lock().unwrap_or_else(|e| e.into_inner());
+        eprintln!(
+            "{}",
+            log_line(
+                unix_now(),
+                level,
+                phase,
+                std::process::id(),
+                message,
+                duration
+            )
+        );
+        return;
     }
-    if i == 0 {
-        format!("{} {}", bytes, units[i])
-    } else {
-        format!("{v:.2} {}", units[i])
+    match level {
+        "error" => eprintln!("error: {message}"),
+        "warn" => eprintln!("warning: {message}"),
+        _ => eprintln!("{message}"),
     }
 }
 
-fn render_process(rows: &[ProcRecord], fmt: OutputFormat) -> String {
-    match fmt {
-        OutputFormat::Table => render_table(rows),
-        OutputFormat::Json => render_json(rows),
-        OutputFormat::Csv => render_csv(rows),
-        OutputFormat::Yaml => render_yaml(rows),
-        OutputFormat::Markdown => render_markdown(rows),
-    }
+fn log_line(
+    ts: u64,
+    level: &str,
+    phase: &str,
+    pid: u32,
+    message: &str,
+    duration: Option<Duration>,
+) -> String {
+    format!(
+        "{{\"ts\":\"{}\",\"level\":\"{level}\",\"phase\":\"{phase}\",\"pid\":{pid},\"message\":\"{}\",\"duration_ms\":{}}}",
+        format_utc_timestamp(ts),
+        escape_json(message),
+        duration.map_or("null".to_string(), |d| d.as_millis().to_string())
+    )
 }
 
-fn render_pane(rows: &[PaneRecord], fmt: OutputFormat) -> String {
-    match fmt {
-        OutputFormat::Table => render_pane_table(rows),
-        OutputFormat::Json => render_pane_json(rows),
-        OutputFormat::Csv => render_pane_csv(rows),
-        OutputFormat::Yaml => render_pane_yaml(rows),
-        OutputFormat::Markdown => render_pane_markdown(rows),
+// Watch repeats the same warnings every tick; the status line lists each once.
+fn record_warning(message: String) {
+    let mut status = RUN_STATUS.lock().unwrap_or_else(|e| e.into_inner());
+    if !status.warnings.contains(&message) {
+        status.warnings.push(message);
     }
 }
 
-fn aggregate_by_pane(rows: &[ProcRecord]) -> Vec<PaneRecord> {
-    let mut by_pane = HashMap::<String, PaneRecord>::new();
-    for row in rows {
-        let entry = by_pane
-            .entry(row.tmux_target.clone())
-            .or_insert_with(|| PaneRecord {
-                tmux_target: row.tmux_target.clone(),
-                tmux_window_name: row.tmux_window_name.clone(),
-                process_count: 0,
-                pids: Vec::new(),
-                swap_bytes: 0,
-                physical_bytes: 0,
-                rss_bytes: 0,
-                pane_history_size: row.pane_history_size,
-                pane_history_limit: row.pane_history_limit,
-                pane_history_bytes: row.pane_history_bytes,
-            });
-
-        entry.process_count += 1;
-        entry.pids.push(row.pid);
-        entry.swap_bytes = entry.swap_bytes.saturating_add(row.swap_bytes);
-        entry.physical_bytes = entry.physical_bytes.saturating_add(row.physical_bytes);
-        entry.rss_bytes = entry.rss_bytes.saturating_add(row.rss_bytes);
+fn status_line(
+    status: &RunStatus,
+    error: Option<&String>,
+    exit_code: i32,
+    elapsed: Duration,
+) -> String {
+    let exit_reason = match exit_code {
+        EXIT_ERROR => "error",
+        EXIT_USAGE => "usage",
+        EXIT_THRESHOLD => "threshold",
+        EXIT_PARTIAL => "partial",
+        _ if INTERRUPTED.load(Ordering::SeqCst) => "interrupted",
+        _ => "ok",
+    };
+    let totals = status
+        .totals
+        .map_or("null".to_string(), |(swap, physical, rss)| {
+            format!("{{\"swap_bytes\":{swap},\"physical_bytes\":{physical},\"rss_bytes\":{rss}}}")
+        });
+    let warnings = status
+        .warnings
+        .iter()
+        .map(|w| format!("\"{}\"", escape_json(w)))
+        .collect::<Vec<_>>()
+        .join(",");
+    format!(
+        "{{\"exit_reason\":\"{exit_reason}\",\"exit_code\":{exit_code},\"error\":{},\"records\":{},\"totals\":{totals},\"warnings\":[{warnings}],\"duration_ms\":{}}}",
+        error.map_or("null".to_string(), |e| format!("\"{}\"", escape_json(e))),
+        status.records.map_or("null".to_string(), |n| n.to_string()),
+        elapsed.as_millis(),
+    )
+}
 
-        // Keep the richest pane history metadata and avoid double-counting bytes.
-        if row.pane_history_size > entry.pane_history_size {
-            entry.pane_history_size = row.pane_history_size;
+// Flag combinations that can't work; failing here exits with the usage status.
+fn check_cli(cli: &Cli, config: &Config) -> Result<(), String> {
+    let html = cli.stdout_format == OutputFormat::Html
+        || resolved_export_format(cli) == Some(OutputFormat::Html);
+    if html && (cli.command != Subcommand::Report || cli.db_path.is_none()) {
+        return Err("html output requires report --db <file>".to_string());
+    }
+    let live_report = cli.command == Subcommand::Report && cli.db_path.is_none();
+    if renders_whole_report(cli)
+        && !(live_report
+            && cli.group_by.is_none()
+            && matches!(cli.view_mode, ViewMode::Process | ViewMode::Pane))
+    {
+        return Err("md-report and svg support the process and pane views of report".to_string());
+    }
+    if cli.stdout_format == OutputFormat::Porcelain
+        && !(live_report && matches!(cli.view_mode, ViewMode::Process | ViewMode::Pane))
+    {
+        return Err("--porcelain supports the process and pane views of report".to_string());
+    }
+    if cli.capture_bundle.is_some() && cli.replay_bundle.is_some() {
+        return Err("--capture-bundle and --replay-bundle cannot be combined".to_string());
+    }
+    if cli.alert_growth.is_some() && cli.watch.is_none() {
+        return Err("--alert-growth and --alert-notify only apply to --watch".to_string());
+    }
+    if cli.alert_notify && cli.alert_growth.is_none() && !cli.on_pressure {
+        return Err("--alert-notify requires --alert-growth or --on-pressure".to_string());
+    }
+    if cli.verify
+        && (!live_report
+            || cli.watch.is_some()
+            || cli.every.is_some()
+            || cli.on_pressure
+            || [Some(cli.stdout_format), resolved_export_format(cli)]
+                .into_iter()
+                .flatten()
+                .any(|fmt| {
+                    matches!(
+                        fmt,
+                        OutputFormat::Porcelain | OutputFormat::MdReport | OutputFormat::Svg
+                    )
+                }))
+    {
+        return Err(
+            "--verify runs once on a live report, as a table, JSON, CSV, YAML, or Markdown"
+                .to_string(),
+        );
+    }
+    if cli.orphans_only {
+        if !live_report || !matches!(cli.view_mode, ViewMode::Process | ViewMode::Pane) {
+            return Err("--orphans-only supports the process and pane views of report".to_string());
         }
-        if row.pane_history_limit > entry.pane_history_limit {
-            entry.pane_history_limit = row.pane_history_limit;
+        if cli.scope.is_some_and(|s| s != TmuxScope::All) {
+            return Err(
+                "--orphans-only cannot be combined with --current-session, --current-window, or --current-pane"
+                    .to_string(),
+            );
         }
-        if row.pane_history_bytes > entry.pane_history_bytes {
-            entry.pane_history_bytes = row.pane_history_bytes;
+    }
+    if cli.watch.is_some() && cli.every.is_some() {
+        return Err("--watch and --every cannot be combined".to_string());
+    }
+    if cli.command == Subcommand::Report
+        && cli.db_path.is_some()
+        && (cli.watch.is_some() || cli.every.is_some())
+    {
+        return Err(
+            "--db reads a recording and cannot be combined with --watch or --every".to_string(),
+        );
+    }
+    if cli.command == Subcommand::Leaks && cli.db_path.is_none() {
+        return Err("leaks requires --db <file> (written by --record)".to_string());
+    }
+    if live_report && cli.view_mode == ViewMode::Mappings && cli.pid.is_none() {
+        return Err("--view mappings requires --pid <pid>".to_string());
+    }
+    if cli.on_pressure && (!live_report || cli.watch.is_some() || cli.every.is_some()) {
+        return Err(
+            "--on-pressure is its own loop for live reports; drop --watch and --every".to_string(),
+        );
+    }
+    if cli.energy && !cfg!(target_os = "macos") {
+        return Err("--energy reads top's POWER column, which only macOS has".to_string());
+    }
+    if cli.per_process {
+        if cli.command != Subcommand::Report || cli.db_path.is_none() {
+            return Err("--per-process applies to report --db".to_string());
+        }
+        if html {
+            return Err("--per-process has no html format; html charts panes".to_string());
         }
     }
-
-    let mut pane_rows = by_pane.into_values().collect::<Vec<_>>();
-    pane_rows.sort_by(|a, b| {
-        b.swap_bytes
-            .cmp(&a.swap_bytes)
-            .then_with(|| b.physical_bytes.cmp(&a.physical_bytes))
-            .then_with(|| a.tmux_target.cmp(&b.tmux_target))
-    });
-    pane_rows
+    if cli.bars.is_some()
+        && !(live_report
+            && cli.stdout_format == OutputFormat::Table
+            && cli.group_by.is_none()
+            && matches!(cli.view_mode, ViewMode::Process | ViewMode::Pane))
+    {
+        return Err("--bars draws in the process and pane tables of report".to_string());
+    }
+    if cli.pane_detail
+        && !(live_report
+            && cli.stdout_format == OutputFormat::Table
+            && cli.group_by.is_none()
+            && cli.view_mode == ViewMode::Pane)
+    {
+        return Err("--pane-detail expands the pane table of report (--view pane)".to_string());
+    }
+    if cli.percent
+        && !(live_report
+            && cli.group_by.is_none()
+            && matches!(cli.view_mode, ViewMode::Process | ViewMode::Pane))
+    {
+        return Err("--percent supports the process and pane views of report".to_string());
+    }
+    if cli.cumulative
+        && !(live_report
+            && cli.stdout_format == OutputFormat::Table
+            && cli.group_by.is_none()
+            && matches!(cli.view_mode, ViewMode::Process | ViewMode::Pane))
+    {
+        return Err(
+            "--cumulative adds a column to the process and pane tables of report".to_string(),
+        );
+    }
+    if cli.history_max_lines.is_some() && cli.no_history_bytes {
+        return Err(
+            "--history-max-lines samples pane capture; drop --no-history-bytes".to_string(),
+        );
+    }
+    if cli.self_metrics && !cli.envelope {
+        return Err("--self-metrics adds fields to the envelope; add --envelope".to_string());
+    }
+    if cli.json_schema_url.is_some() && !cli.envelope {
+        return Err(
+            "--json-schema-url adds \"$schema\" to the envelope; add --envelope".to_string(),
+        );
+    }
+    if cli.mail.is_some() {
+        if !matches!(
+            cli.stdout_format,
+            OutputFormat::Html | OutputFormat::Markdown | OutputFormat::MdReport
+        ) {
+            return Err(
+                "--mail sends HTML or Markdown; add --format md-report, markdown, or html"
+                    .to_string(),
+            );
+        }
+        if cli.watch.is_some() {
+            return Err("--mail cannot be combined with --watch; use --every".to_string());
+        }
+    } else if cli.sendmail.is_some() {
+        return Err("--sendmail only applies to --mail".to_string());
+    }
+    if cli.watch_adaptive && cli.watch.is_none() {
+        return Err("--watch-adaptive adjusts --watch; add --watch <interval>".to_string());
+    }
+    if !cli.jitter.is_zero() && cli.every.is_none() {
+        return Err("--jitter only applies to --every".to_string());
+    }
+    if cli.mark_panes {
+        if cli.command != Subcommand::Report || cli.db_path.is_some() {
+            return Err("--mark-panes applies to live reports".to_string());
+        }
+        if config.severity.is_empty() {
+            return Err(
+                "--mark-panes needs severity thresholds in the config file (see --config)"
+                    .to_string(),
+            );
+        }
+    }
+    if (cli.badge_windows.is_some() || cli.badge_clear)
+        && (cli.command != Subcommand::Report || cli.db_path.is_some())
+    {
+        return Err("--badge-windows and --badge-clear apply to live reports".to_string());
+    }
+    if cli.badge_clear
+        && (cli.badge_windows.is_some() || cli.watch.is_some() || cli.every.is_some())
+    {
+        return Err(
+            "--badge-clear runs once on its own; drop --badge-windows, --watch, and --every"
+                .to_string(),
+        );
+    }
+    if cli.group_by.is_some()
+        && (cli.command != Subcommand::Report
+            || cli.db_path.is_some()
+            || !matches!(cli.view_mode, ViewMode::Process | ViewMode::Pane))
+    {
+        return Err("--group-by applies to the process and pane views of report".to_string());
+    }
+    if cli.group_by.is_some() && (cli.since_last || cli.stdout_format == OutputFormat::Porcelain) {
+        return Err("--since-last and --porcelain cannot be combined with --group-by".to_string());
+    }
+    if cli.since_last {
+        if cli.export_path.is_none() || cli.export_append {
+            return Err("--since-last requires --export".to_string());
+        }
+        if !matches!(
+            resolved_export_format(cli),
+            Some(OutputFormat::Json | OutputFormat::Csv)
+        ) {
+            return Err("--since-last compares against JSON or CSV exports".to_string());
+        }
+        if cli.command != Subcommand::Report
+            || cli.db_path.is_some()
+            || !matches!(cli.view_mode, ViewMode::Process | ViewMode::Pane)
+        {
+            return Err("--since-last supports the process and pane views of report".to_string());
+        }
+    }
+    if cli.export_append
+        && matches!(
+            resolved_export_format(cli),
+            Some(OutputFormat::Json | OutputFormat::Html | OutputFormat::Svg)
+        )
+    {
+        return Err(
+            "--export-append supports csv, yaml, markdown, and table; use --record for JSON lines"
+                .to_string(),
+        );
+    }
+    Ok(())
 }
 
-fn render_table(rows: &[ProcRecord]) -> String {
-    let mut out = String::new();
-    out.push_str(
-        "PID\tTmux window.pane\tWindow\tSwap\tPhysical\tRSS\tPaneHistory\tHistory lines\tCommand\n",
-    );
-    for row in rows {
-        let history_lines = if row.pane_history_size >= 0 {
-            format!("{}/{}", row.pane_history_size, row.pane_history_limit)
+fn run(cli: &Cli, config: &Config) -> Result<(), String> {
+    // The config default only kicks in inside tmux; the flag insists on it.
+    let scope = cli.scope.unwrap_or(
+        if config.current_session && !cli.orphans_only && env::var_os("TMUX").is_some() {
+            TmuxScope::Session
         } else {
-            "-".to_string()
+            TmuxScope::All
+        },
+    );
+    // Config defaults fill in whatever the command line left unset.
+    let resolved;
+    let cli = if scope == TmuxScope::All
+        && (cli.json_schema_url.is_some() || config.json_schema_url.is_none())
+        && (cli.sendmail.is_some() || config.sendmail.is_none())
+        && config.headers.is_empty()
+    {
+        cli
+    } else {
+        resolved = Cli {
+            tmux_scope: match scope {
+                TmuxScope::All => None,
+                _ => Some(current_tmux_target(scope)?),
+            },
+            json_schema_url: cli
+                .json_schema_url
+                .clone()
+                .or_else(|| config.json_schema_url.clone()),
+            sendmail: cli.sendmail.clone().or_else(|| config.sendmail.clone()),
+            header_labels: config.headers.clone(),
+            ..cli.clone()
+        };
+        &resolved
+    };
+    match cli.command {
+        Subcommand::Report if cli.db_path.is_some() => run_history(cli),
+        Subcommand::Report if cli.on_pressure => run_on_pressure(cli, config),
+        Subcommand::Report if cli.verify => run_verify(cli),
+        Subcommand::Report => match (cli.watch, cli.every) {
+            (Some(_), Some(_)) => unreachable!("checked in check_cli"),
+            (Some(interval), None) => run_watch(cli, config, interval),
+            (None, Some(interval)) => run_every(cli, config, interval),
+            (None, None) => run_report(cli, config, &mut CollectCache::default()).map(|_| ()),
+        },
+        Subcommand::Advise => run_advise(cli),
+        Subcommand::Tune => run_tune(cli),
+        Subcommand::Leaks => run_leaks(cli),
+        Subcommand::Serve => run_serve(cli, config),
+        Subcommand::Doctor => run_doctor(cli),
+        Subcommand::InstallService => run_install_service(cli),
+        Subcommand::InstallHooks => run_install_hooks(cli),
+        Subcommand::Plugin => run_plugin(cli),
+        Subcommand::InstallKeybinding => run_install_keybinding(cli),
+        Subcommand::Snapshot => run_snapshot(cli, config),
+        Subcommand::Merge => run_merge(cli),
+        Subcommand::Version => {
+            print!("{}", render_version(cli.stdout_format));
+            Ok(())
+        }
+    }
+}
+
+const PROGRESS_MIN_PIDS: usize = 5;
+const DEFAULT_MAX_CONCURRENT: usize = 4;
+const DEFAULT_RETRIES: u32 = 2;
+const MAX_RETRIES: u32 = 8;
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(50);
+
+// Retries `f` while `transient` says the failure may pass, sleeping 50ms, 100ms,
+// 200ms, ... in between; the last error is returned once retries run out.
+fn with_retries<T>(
+    retries: u32,
+    mut f: impl FnMut() -> io::Result<T>,
+    transient: impl Fn(&io::Error) -> bool,
+) -> io::Result<T> {
+    let mut attempt = 0;
+    loop {
+        match f() {
+            Err(e) if attempt < retries && transient(&e) => {
+                thread::sleep(RETRY_BASE_DELAY * 2u32.pow(attempt));
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+// A missing binary, a missing server, or a pane that closed mid-scan won't come
+// back within a few hundred milliseconds; anything else (a busy server, a
+// dropped connection) might.
+fn transient_tmux_error(e: &io::Error) -> bool {
+    let message = e.to_string();
+    e.kind() != io::ErrorKind::NotFound
+        && ![
+            "no server running",
+            "error connecting to",
+            "can't find pane",
+            "can't find window",
+            "can't find session",
+        ]
+        .iter()
+        .any(|m| message.contains(m))
+}
+
+// vmmap refuses processes it may not inspect (another user's, or ones SIP
+// protects), and asking again gets the same answer.
+fn vmmap_denied(e: &io::Error) -> bool {
+    let message = e.to_string().to_lowercase();
+    e.kind() == io::ErrorKind::PermissionDenied
+        || [
+            "appropriate privileges",
+            "not permitted",
+            "permission denied",
+        ]
+        .iter()
+        .any(|m| message.contains(m))
+}
+
+extern "C" {
+    fn kill(pid: i32, signal: i32) -> i32;
+}
+
+const EPERM: i32 = 1;
+
+// Signal 0 only checks: EPERM still means the process exists (another user's).
+fn process_alive(pid: i32) -> bool {
+    // A replay has no live processes to ask; the bundled output decides what ran.
+    if matches!(BUNDLE.get(), Some(Bundle::Replay(_))) {
+        return true;
+    }
+    // SAFETY: signal 0 delivers nothing.
+    let found = unsafe { kill(pid, 0) == 0 };
+    found || io::Error::last_os_error().raw_os_error() == Some(EPERM)
+}
+
+// Expensive measurements reused across --watch ticks until --cache-ttl runs out.
+// vmmap entries remember the command so a recycled PID is measured again.
+#[derive(Debug, Default)]
+struct CollectCache {
+    vmmap: HashMap<i32, (Instant, String, Option<VmmapSummary>)>,
+    history: HashMap<String, (Instant, u64)>,
+    // (minor, major) fault counters from the previous tick, for per-interval deltas.
+    faults: HashMap<i32, (u64, u64)>,
+    // Footprints from the previous tick for --alert-growth, per PID (with its
+    // command, so a recycled PID starts over) and per pane.
+    process_footprints: HashMap<i32, (String, u64)>,
+    pane_footprints: HashMap<String, u64>,
+    // Double launches warned about on the previous tick, as sorted PID sets, so
+    // --watch warns about each once instead of on every tick.
+    duplicate_pids: Vec<Vec<i32>>,
+    // Long-running modes keep a control-mode client open for pane queries.
+    persistent_tmux: bool,
+    tmux_control: Option<TmuxControl>,
+}
+
+// A `tmux -C` client: commands go in on stdin, replies come back as
+// %begin/%end blocks on stdout. no-output keeps pane output off the pipe and
+// ignore-size stops the client from resizing windows.
+#[derive(Debug)]
+struct TmuxControl {
+    child: std::process::Child,
+    stdin: std::process::ChildStdin,
+    stdout: BufReader<TimedStdout>,
+}
+
+// Which part of the invoking tmux client a run is limited to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TmuxScope {
+    All,
+    Session,
+    Window,
+    Pane,
+}
+
+// --alert-growth: how much a row may grow between two watch ticks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum GrowthThreshold {
+    Percent(f64),
+    Bytes(u64),
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct VmmapSummary {
+    swap_bytes: u64,
+    physical_bytes: u64,
+    gpu_bytes: u64,
+    // Resident bytes of the region table, split by kind as mapping_kind does.
+    anon_bytes: u64,
+    file_backed_bytes: u64,
+}
+
+// The per-PID probes (ps, vmmap) that can run off the main thread.
+struct ProcSample {
+    command: String,
+    user: String,
+    rss_bytes: u64,
+    swap_bytes: u64,
+    physical_bytes: u64,
+    gpu_bytes: Option<u64>,
+    vmmap: Option<VmmapSummary>,
+    vmmap_measured: bool,
+    vmmap_skipped: bool,
+    anon_file: Option<(u64, u64)>,
+    disk_io: Option<(u64, u64)>,
+}
+
+// None when the process exited mid-scan, so it is left out rather than
+// reported (and recorded) with zeroed memory.
+fn sample_process(cli: &Cli, pid: i32, cache: &CollectCache) -> Option<ProcSample> {
+    // A missing tool (vmmap off macOS) fails the same way every time.
+    let alive = |e: &io::Error| e.kind() != io::ErrorKind::NotFound && process_alive(pid);
+    let command = match with_retries(cli.retries, || ps_command(pid, cli.wide), alive) {
+        Ok(command) => command,
+        Err(_) if !process_alive(pid) => return None,
+        Err(e) => {
+            warn_partial(format!("pid {pid}: command unavailable: {e}"));
+            "<unavailable>".to_string()
+        }
+    };
+    let rss_bytes = match with_retries(cli.retries, || ps_rss_bytes(pid), alive) {
+        Ok(rss) => rss,
+        Err(_) if !process_alive(pid) => return None,
+        Err(e) => {
+            warn_partial(format!("pid {pid}: RSS unavailable: {e}"));
+            0
+        }
+    };
+    let vmmap_skipped = cli.vmmap_min_rss.is_some_and(|min| rss_bytes < min);
+    let cached = cache
+        .vmmap
+        .get(&pid)
+        .filter(|(at, cached_command, _)| {
+            *cached_command == command && at.elapsed() < cli.cache_ttl
+        })
+        .map(|(_, _, memory)| *memory);
+    let vmmap = if vmmap_skipped {
+        None
+    } else {
+        cached.unwrap_or_else(|| {
+            let measured = with_retries(
+                cli.retries,
+                || vmmap_memory(pid),
+                |e| alive(e) && !vmmap_denied(e),
+            );
+            // No vmmap at all (Linux) is the normal case there, not a gap.
+            if measured.as_ref().is_err_and(alive) {
+                warn_partial(format!("pid {pid}: vmmap failed"));
+            }
+            measured.ok()
+        })
+    };
+    let summary = vmmap.unwrap_or_default();
+    Some(ProcSample {
+        command,
+        user: ps_user(pid).unwrap_or_else(|_| "?".to_string()),
+        rss_bytes,
+        swap_bytes: summary.swap_bytes,
+        physical_bytes: summary.physical_bytes,
+        gpu_bytes: vmmap.map(|v| v.gpu_bytes),
+        vmmap,
+        vmmap_measured: cached.is_none() && !vmmap_skipped,
+        vmmap_skipped,
+        anon_file: anon_file_bytes(pid, vmmap.as_ref()),
+        disk_io: if cli.disk_io {
+            process_disk_io(pid)
+        } else {
+            None
+        },
+    })
+}
+
+// Runs `f` over `items` on at most `limit` threads, keeping input order.
+fn parallel_map<T: Sync, R: Send>(items: &[T], limit: usize, f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    if limit <= 1 || items.len() <= 1 {
+        return items.iter().map(f).collect();
+    }
+    let next = AtomicUsize::new(0);
+    let (tx, rx) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..limit.min(items.len()) {
+            let tx = tx.clone();
+            let (next, f) = (&next, &f);
+            scope.spawn(move || loop {
+                let idx = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(idx) else {
+                    break;
+                };
+                let _ = tx.send((idx, f(item)));
+            });
+        }
+    });
+    drop(tx);
+    let mut out = rx.into_iter().collect::<Vec<_>>();
+    out.sort_by_key(|(idx, _)| *idx);
+    out.into_iter().map(|(_, r)| r).collect()
+}
+
+// A tmux scope keeps only processes in its panes; --orphans-only the reverse.
+fn is_reported_owner(in_pane: bool, scoped: bool, orphans_only: bool) -> bool {
+    match (in_pane, orphans_only) {
+        (true, true) => false,
+        (false, _) => !scoped,
+        (true, false) => true,
+    }
+}
+
+// For --watch, --every, serve, and --on-pressure: one tmux client for the whole
+// run instead of a fork per query per tick. Bundles need every query as its own
+// command, so they keep forking.
+fn persistent_cache() -> CollectCache {
+    CollectCache {
+        persistent_tmux: BUNDLE.get().is_none(),
+        ..CollectCache::default()
+    }
+}
+
+fn collect_rows(cli: &Cli, cache: &mut CollectCache) -> Result<Vec<ProcRecord>, String> {
+    set_log_phase("collect");
+    let started = Instant::now();
+    let listed =
+        match tmux_control_query(cache, &[vec!["list-panes", "-a", "-F", LIST_PANES_FORMAT]]) {
+            Some(mut replies) => replies
+                .remove(0)
+                .map(|raw| parse_tmux_panes(&raw, None))
+                .map_err(io::Error::other),
+            None => with_retries(cli.retries, list_tmux_panes, transient_tmux_error),
+        };
+    // We keep this resilient: if tmux is not available, we still report process memory.
+    let panes = match listed {
+        Ok(v) => v,
+        // No tmux, or no server, just means nothing runs in tmux.
+        Err(e) if !transient_tmux_error(&e) => {
+            warn(format!("tmux panes unavailable: {e}"));
+            Vec::new()
+        }
+        Err(e) => {
+            warn_partial(format!("tmux panes unavailable: {e}"));
+            Vec::new()
+        }
+    };
+    let pane_by_pid = panes
+        .iter()
+        .filter(|p| in_tmux_scope(&p.target, cli.tmux_scope.as_deref()))
+        .map(|p| (p.pane_pid, p.clone()))
+        .collect::<HashMap<_, _>>();
+
+    let mut pids = discover_pids(cli).map_err(|e| format!("failed to discover processes: {e}"))?;
+
+    let mut ppid_cache = HashMap::<i32, i32>::new();
+    if !cli.include_self {
+        pids.retain(|&pid| !is_self_or_child(pid, &mut ppid_cache));
+    }
+
+    let mut targets = pids.iter().map(|pid| (*pid, None)).collect::<Vec<_>>();
+    // Helpers are matched against the full PID list, so a scoped run still
+    // finds an in-session app's children.
+    if let Some(app) = cli.app {
+        match ps_process_table() {
+            Ok(table) => {
+                ppid_cache.extend(table.iter().map(|p| (p.pid, p.ppid)));
+                targets.extend(
+                    app_helper_pids(app, &pids, &table)
+                        .into_iter()
+                        .map(|(pid, parent)| (pid, Some(parent))),
+                );
+            }
+            Err(e) => warn_partial(format!(
+                "process table unavailable, helpers not grouped: {e}"
+            )),
+        }
+    }
+
+    // Owners are resolved before sampling so a scoped run never pays for vmmap
+    // on processes outside its session.
+    let (targets, owners): (Vec<_>, Vec<_>) = targets
+        .into_iter()
+        .map(|target| {
+            let owner = find_owning_pane(target.0, &pane_by_pid, &mut ppid_cache);
+            (target, owner)
+        })
+        .filter(|(_, owner)| {
+            is_reported_owner(owner.is_some(), cli.tmux_scope.is_some(), cli.orphans_only)
+        })
+        .unzip();
+
+    // vmmap takes about a second per PID, so long scans report where they are.
+    let total = targets.len();
+    let show_progress = !cli.quiet
+        && total > PROGRESS_MIN_PIDS
+        && io::stderr().is_terminal()
+        && !LOG_JSON.load(Ordering::SeqCst);
+    let done = AtomicUsize::new(0);
+    let shared_cache = &*cache;
+    let samples = parallel_map(&targets, cli.max_concurrent, |&(pid, _)| {
+        let sample = sample_process(cli, pid, shared_cache);
+        if show_progress {
+            let n = done.fetch_add(1, Ordering::Relaxed) + 1;
+            eprint!("\rscanning {n}/{total} (pid {pid})\x1b[K");
+        }
+        sample
+    });
+    if show_progress {
+        eprint!("\r\x1b[K");
+    }
+    let (targets, (owners, samples)): (Vec<_>, (Vec<_>, Vec<_>)) = targets
+        .into_iter()
+        .zip(owners.into_iter().zip(samples))
+        .filter_map(|(target, (owner, sample))| Some((target, (owner, sample?))))
+        .unzip();
+    let now = Instant::now();
+    cache
+        .vmmap
+        .retain(|pid, _| targets.iter().any(|(p, _)| p == pid));
+    for ((pid, _), sample) in targets.iter().zip(&samples) {
+        if sample.vmmap_measured {
+            cache
+                .vmmap
+                .insert(*pid, (now, sample.command.clone(), sample.vmmap));
+        }
+    }
+
+    // nvidia-smi reports every GPU process at once; vmmap covers Metal on macOS.
+    let nvidia = if cli.gpu {
+        nvidia_gpu_memory().ok()
+    } else {
+        None
+    };
+    let energy = if cli.energy {
+        match top_energy_impact() {
+            Ok(by_pid) => Some(by_pid),
+            Err(e) => {
+                warn_partial(format!("energy impact unavailable: {e}"));
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    // One ps call covers every process's start time, and its fault counters when
+    // pageins or per-tick fault deltas (watch mode only) need them.
+    let sample_faults = cli.watch.is_some();
+    let counters = match process_counters(cli.pageins || sample_faults) {
+        Ok(by_pid) => by_pid,
+        Err(e) => {
+            warn_partial(format!("process start times unavailable: {e}"));
+            HashMap::new()
+        }
+    };
+    let current_faults = if sample_faults {
+        targets
+            .iter()
+            .filter_map(|(pid, _)| Some((*pid, counters.get(pid)?.faults?)))
+            .collect()
+    } else {
+        HashMap::new()
+    };
+    let previous_faults = std::mem::replace(&mut cache.faults, current_faults);
+
+    let mut history_targets = owners
+        .iter()
+        .flatten()
+        .map(|(pane, _)| (pane.target.clone(), pane.socket.clone(), pane.history_size))
+        .collect::<Vec<_>>();
+    history_targets.sort();
+    history_targets.dedup();
+    let history_by_pane = if cli.no_history_bytes {
+        HashMap::new()
+    } else {
+        cache.history.retain(|target, (at, _)| {
+            history_targets.iter().any(|(t, _, _)| t == target) && at.elapsed() < cli.cache_ttl
+        });
+        history_targets.retain(|(target, _, _)| !cache.history.contains_key(target));
+        let start = capture_start(cli.history_max_lines);
+        let captures = history_targets
+            .iter()
+            .map(|(target, _, _)| vec!["capture-pane", "-p", "-S", &start, "-E", "-", "-t", target])
+            .collect::<Vec<_>>();
+        let controlled = match history_targets
+            .iter()
+            .all(|(_, socket, _)| socket.is_none())
+        {
+            true => tmux_control_query(cache, &captures),
+            false => None,
+        };
+        let measured = match controlled {
+            Some(replies) => replies
+                .into_iter()
+                .zip(&history_targets)
+                .map(|(reply, (_, _, size))| match reply {
+                    Ok(out) => Some(sampled_history_bytes(&out, *size, cli.history_max_lines)),
+                    Err(e) => {
+                        warn_partial(format!("capture-pane failed: {e}"));
+                        None
+                    }
+                })
+                .collect(),
+            None => parallel_map(
+                &history_targets,
+                cli.max_concurrent,
+                |(target, socket, size)| match with_retries(
+                    cli.retries,
+                    || capture_pane(target, socket.as_deref(), &start),
+                    transient_tmux_error,
+                ) {
+                    Ok(out) => Some(sampled_history_bytes(&out, *size, cli.history_max_lines)),
+                    Err(e) => {
+                        warn_partial(format!("capture-pane -t {target} failed: {e}"));
+                        None
+                    }
+                },
+            ),
+        };
+        let now = Instant::now();
+        // Failed captures stay out of the cache so the next tick tries again.
+        for ((target, _, _), bytes) in history_targets.into_iter().zip(measured) {
+            if let Some(bytes) = bytes {
+                cache.history.insert(target, (now, bytes));
+            }
+        }
+        cache
+            .history
+            .iter()
+            .map(|(target, (_, bytes))| (target.clone(), *bytes))
+            .collect::<HashMap<_, _>>()
+    };
+
+    let mut rows = Vec::<ProcRecord>::new();
+    for (((pid, app_parent_pid), sample), owner) in targets.into_iter().zip(samples).zip(owners) {
+        let tag = owner
+            .as_ref()
+            .map(|(_, depth)| process_tag(*depth, &sample.command, app_parent_pid.is_some()));
+        let (
+            tmux_target,
+            tmux_socket,
+            tmux_window_name,
+            pane_history_size,
+            pane_history_limit,
+            pane_history_bytes,
+            history_measured,
+            session_created,
+            session_attached,
+        ) = if let Some((pane, _)) = owner {
+            let history_bytes = history_by_pane.get(&pane.target).copied();
+            (
+                pane.target,
+                pane.socket,
+                pane.window_name,
+                pane.history_size,
+                pane.history_limit,
+                history_bytes.unwrap_or(0),
+                (!cli.no_history_bytes).then_some(history_bytes.is_some()),
+                pane.session_created,
+                pane.session_attached,
+            )
+        } else {
+            (
+                "?".to_string(),
+                None,
+                "?".to_string(),
+                -1,
+                -1,
+                0,
+                None,
+                None,
+                None,
+            )
+        };
+
+        rows.push(ProcRecord {
+            pid,
+            command_name: command_name(&sample.command),
+            role: process_role(&sample.command),
+            tag,
+            command: sample.command,
+            user: sample.user,
+            swap_bytes: sample.swap_bytes,
+            physical_bytes: sample.physical_bytes,
+            rss_bytes: sample.rss_bytes,
+            tmux_target,
+            tmux_socket,
+            tmux_window_name,
+            pane_history_size,
+            pane_history_limit,
+            pane_history_bytes,
+            pane_history_estimated_bytes: history_estimate(
+                pane_history_bytes,
+                cli.history_overhead,
+            ),
+            history_measured,
+            session_created,
+            session_attached,
+            app_parent_pid,
+            severity: None,
+            vmmap_skipped: sample.vmmap_skipped,
+            gpu_bytes: match &nvidia {
+                _ if !cli.gpu => None,
+                Some(by_pid) => Some(by_pid.get(&pid).copied().unwrap_or(0)),
+                None => sample.gpu_bytes,
+            },
+            energy_impact: energy
+                .as_ref()
+                .map(|by_pid| by_pid.get(&pid).copied().unwrap_or(0.0)),
+            disk_io: sample.disk_io,
+            started_at: counters.get(&pid).map(|ps| ps.started_at),
+            pageins: counters
+                .get(&pid)
+                .and_then(|ps| ps.faults)
+                .filter(|_| cli.pageins)
+                .map(|(_, major)| major),
+            fault_delta: fault_delta(
+                previous_faults.get(&pid).copied(),
+                cache.faults.get(&pid).copied(),
+            ),
+            growth_alert: None,
+            since_last: None,
+            swap_pct: None,
+            physical_pct: None,
+            duplicate_instance: false,
+            anon_bytes: sample.anon_file.map(|(anon, _)| anon),
+            file_backed_bytes: sample.anon_file.map(|(_, file)| file),
+        });
+    }
+
+    // Sort most memory-hungry first.
+    rows.sort_by(|a, b| {
+        b.swap_bytes
+            .cmp(&a.swap_bytes)
+            .then_with(|| b.physical_bytes.cmp(&a.physical_bytes))
+            .then_with(|| a.pid.cmp(&b.pid))
+    });
+    // Only JSON logs get a line per collection; text stays quiet.
+    if LOG_JSON.load(Ordering::SeqCst) {
+        log_event(
+            "info",
+            &format!("collected {} processes", rows.len()),
+            Some(started.elapsed()),
+        );
+    }
+
+    Ok(rows)
+}
+
+// Print to stdout and, when requested, write the same data to the export file.
+fn emit(
+    cli: &Cli,
+    opts: &RenderOptions,
+    count: usize,
+    render: impl Fn(OutputFormat, &RenderOptions) -> String,
+) -> Result<(), String> {
+    set_log_phase("output");
+    let render = |fmt, o: &RenderOptions| relabel_headers(fmt, render(fmt, o), &o.header_labels);
+    let body = wrap_envelope(cli, cli.stdout_format, render(cli.stdout_format, opts));
+    RUN_STATUS.lock().unwrap_or_else(|e| e.into_inner()).records = Some(count);
+    if let Some(interval) = cli.watch {
+        // Redraw in place on a terminal; piped watch output just appends.
+        if io::stdout().is_terminal() {
+            print!("\x1b[H\x1b[2J");
+        }
+        println!("Every {interval:?}: {}\n", format_utc_timestamp(unix_now()));
+    }
+    print!("{body}");
+    if let Some(to) = &cli.mail {
+        send_mail(cli, to, cli.stdout_format, &body)?;
+    }
+
+    if let (Some(template), Some(fmt)) = (&cli.export_path, resolved_export_format(cli)) {
+        set_log_phase("export");
+        let started = Instant::now();
+        let path = &expand_path_template(template, unix_now(), short_hostname);
+        // Export files never carry terminal escapes.
+        let plain = RenderOptions {
+            color: false,
+            ..opts.clone()
+        };
+        let body = wrap_envelope(cli, fmt, render(fmt, &plain));
+        if cli.export_append {
+            append_locked(path, "export file", cli.lock_wait, |existing| {
+                appended_export(fmt, body, existing)
+            })?;
+            log_event(
+                "info",
+                &format!("appended {count} records to {path}"),
+                Some(started.elapsed()),
+            );
+        } else {
+            write_atomic(path, &body)
+                .map_err(|e| format!("failed writing export file '{path}': {e}"))?;
+            log_event(
+                "info",
+                &format!("exported {count} records to {path}"),
+                Some(started.elapsed()),
+            );
+        }
+    }
+    Ok(())
+}
+
+const DEFAULT_SENDMAIL: &str = "sendmail";
+
+// SMTP is left to the sendmail-compatible command (sendmail, msmtp, ...) and its
+// own configuration; -i keeps a lone "." line from ending the message early.
+fn send_mail(cli: &Cli, to: &str, fmt: OutputFormat, body: &str) -> Result<(), String> {
+    set_log_phase("mail");
+    let command = cli.sendmail.as_deref().unwrap_or(DEFAULT_SENDMAIL);
+    let mut words = command.split_whitespace();
+    let program = words.next().unwrap_or(DEFAULT_SENDMAIL);
+    let mut args = words.collect::<Vec<_>>();
+    args.extend(["-i", to]);
+    if cli.dry_run {
+        log_event(
+            "info",
+            &format!("dry-run: {}", shell_command_line(program, &args)),
+            None,
+        );
+        return Ok(());
+    }
+    let subject = format!(
+        "opencode-tmux-mem report for {} at {}",
+        short_hostname(),
+        format_utc_timestamp(unix_now())
+    );
+    let mut child = subprocess(program)
+        .args(&args)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to run '{program}' for --mail: {e}"))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(mail_message(to, &subject, fmt, body).as_bytes())
+            .map_err(|e| format!("failed to hand the report to '{program}': {e}"))?;
+    }
+    let out = child
+        .wait_with_output()
+        .map_err(|e| format!("failed to run '{program}' for --mail: {e}"))?;
+    if !out.status.success() {
+        let stderr = String::from_utf8_lossy(&out.stderr).trim().to_string();
+        return Err(format!(
+            "'{command}' could not send the report to {to}: {}",
+            if stderr.is_empty() {
+                out.status.to_string()
+            } else {
+                stderr
+            }
+        ));
+    }
+    log_event("info", &format!("mailed report to {to}"), None);
+    Ok(())
+}
+
+// Markdown goes out as text/plain, which every mail client shows as is.
+fn mail_message(to: &str, subject: &str, fmt: OutputFormat, body: &str) -> String {
+    let content_type = match fmt {
+        OutputFormat::Html => "text/html",
+        _ => "text/plain",
+    };
+    format!(
+        "To: {to}\nSubject: {subject}\nMIME-Version: 1.0\nContent-Type: {content_type}; charset=utf-8\nContent-Transfer-Encoding: 8bit\n\n{body}"
+    )
+}
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+// Set by build.rs; empty when built outside a git checkout.
+const GIT_COMMIT: &str = env!("OTM_GIT_COMMIT");
+const BUILD_EPOCH: &str = env!("OTM_BUILD_EPOCH");
+const FEATURES: &str = env!("OTM_FEATURES");
+
+fn build_info_fields() -> Vec<(&'static str, Field)> {
+    vec![
+        ("tool", Field::Str(env!("CARGO_PKG_NAME").to_string())),
+        ("version", Field::Str(VERSION.to_string())),
+        (
+            "git_commit",
+            if GIT_COMMIT.is_empty() {
+                Field::Null
+            } else {
+                Field::Str(GIT_COMMIT.to_string())
+            },
+        ),
+        (
+            "build_date",
+            Field::Str(format_utc_timestamp(BUILD_EPOCH.parse().unwrap_or(0))),
+        ),
+        (
+            "features",
+            Field::StrList(
+                FEATURES
+                    .split(',')
+                    .filter(|f| !f.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+            ),
+        ),
+    ]
+}
+
+fn render_version(fmt: OutputFormat) -> String {
+    let fields = build_info_fields();
+    match fmt {
+        OutputFormat::Json => format!("{}\n", json_object(&fields)),
+        OutputFormat::Yaml => {
+            let mut out = "---\n".to_string();
+            for (name, value) in &fields {
+                let _ = writeln!(out, "{name}: {}", yaml_field(value));
+            }
+            out
+        }
+        _ => format!(
+            "{} {VERSION} ({}, built {})\n",
+            env!("CARGO_PKG_NAME"),
+            if GIT_COMMIT.is_empty() {
+                "unknown commit"
+            } else {
+                GIT_COMMIT
+            },
+            &format_utc_timestamp(BUILD_EPOCH.parse().unwrap_or(0))[..10],
+        ),
+    }
+}
+
+fn json_object(fields: &[(&'static str, Field)]) -> String {
+    let body = fields
+        .iter()
+        .map(|(name, value)| format!("\"{name}\":{}", json_field(value)))
+        .collect::<Vec<_>>()
+        .join(",");
+    format!("{{{body}}}")
+}
+
+// Metadata every envelope carries: the build plus when and with which schema it was made.
+fn envelope_meta(cli: &Cli) -> Vec<(&'static str, Field)> {
+    let mut meta = build_info_fields();
+    meta.push(("generated_at", Field::Str(format_utc_timestamp(unix_now()))));
+    meta.push(("host", Field::Str(short_hostname())));
+    meta.push((
+        "schema_version",
+        Field::Uint(cli.schema_version.unwrap_or(SCHEMA_VERSION).into()),
+    ));
+    if cli.self_metrics {
+        meta.extend(self_metric_fields(&self_metrics()));
+    }
+    meta
+}
+
+// What the monitor itself has cost so far; daemons accumulate across ticks.
+struct SelfMetrics {
+    subprocesses: usize,
+    wall: Duration,
+    peak_rss_bytes: Option<u64>,
+}
+
+fn self_metrics() -> SelfMetrics {
+    SelfMetrics {
+        subprocesses: SUBPROCESSES.load(Ordering::SeqCst),
+        wall: RUN_STARTED.get_or_init(Instant::now).elapsed(),
+        peak_rss_bytes: peak_rss_bytes(),
+    }
+}
+
+fn self_metric_fields(metrics: &SelfMetrics) -> Vec<(&'static str, Field)> {
+    vec![
+        (
+            "self_subprocesses",
+            Field::Uint(metrics.subprocesses as u64),
+        ),
+        ("self_wall_ms", Field::Uint(metrics.wall.as_millis() as u64)),
+        (
+            "self_peak_rss_bytes",
+            metrics.peak_rss_bytes.map_or(Field::Null, Field::Uint),
+        ),
+    ]
+}
+
+// Every subprocess goes through here so --self-metrics and /metrics can count them.
+fn subprocess(program: &str) -> Command {
+    SUBPROCESSES.fetch_add(1, Ordering::SeqCst);
+    Command::new(program)
+}
+
+// struct rusage: two timevals, then ru_maxrss and thirteen more longs.
+#[repr(C)]
+struct Rusage {
+    ru_utime: [i64; 2],
+    ru_stime: [i64; 2],
+    ru_maxrss: i64,
+    ru_rest: [i64; 13],
+}
+
+extern "C" {
+    fn getrusage(who: i32, usage: *mut Rusage) -> i32;
+}
+
+const RUSAGE_SELF: i32 = 0;
+
+// ru_maxrss is in kilobytes on Linux and in bytes on macOS.
+fn peak_rss_bytes() -> Option<u64> {
+    let mut usage = Rusage {
+        ru_utime: [0; 2],
+        ru_stime: [0; 2],
+        ru_maxrss: 0,
+        ru_rest: [0; 13],
+    };
+    // SAFETY: `usage` is a properly sized struct rusage that outlives the call.
+    if unsafe { getrusage(RUSAGE_SELF, &mut usage) } != 0 {
+        return None;
+    }
+    let maxrss = u64::try_from(usage.ru_maxrss).ok()?;
+    Some(if cfg!(target_os = "macos") {
+        maxrss
+    } else {
+        maxrss * 1024
+    })
+}
+
+// --envelope turns a JSON/YAML record list into {meta, records}; other formats pass through.
+fn wrap_envelope(cli: &Cli, fmt: OutputFormat, body: String) -> String {
+    if cli.envelope {
+        envelope(
+            &envelope_meta(cli),
+            cli.json_schema_url.as_deref(),
+            fmt,
+            body,
+        )
+    } else {
+        body
+    }
+}
+
+// A schema URL becomes the JSON envelope's leading "$schema" key, which editors
+// and validators look for; YAML has no such convention and leaves it out.
+fn envelope(
+    meta: &[(&'static str, Field)],
+    schema_url: Option<&str>,
+    fmt: OutputFormat,
+    body: String,
+) -> String {
+    match fmt {
+        OutputFormat::Json => format!(
+            "{{\n{}  \"meta\": {},\n  \"records\": {}\n}}\n",
+            schema_url.map_or(String::new(), |url| format!(
+                "  \"$schema\": \"{}\",\n",
+                escape_json(url)
+            )),
+            json_object(meta),
+            body.trim_end()
+        ),
+        OutputFormat::Yaml => {
+            let mut out = "---\nmeta:\n".to_string();
+            for (name, value) in meta {
+                let _ = writeln!(out, "  {name}: {}", yaml_field(value));
+            }
+            let records = body.strip_prefix("---\n").unwrap_or(&body);
+            // --yaml-style document already has its own top-level keys.
+            if records.starts_with("generated_at: ") {
+                out.push_str(records);
+            } else if records.is_empty() {
+                out.push_str("records: []\n");
+            } else {
+                out.push_str("records:\n");
+                for line in records.lines() {
+                    let _ = writeln!(out, "  {line}");
+                }
+            }
+            out
+        }
+        _ => body,
+    }
+}
+
+// Written next to the target and renamed into place, so readers see either the
+// old export or the new one, never a truncated file.
+fn write_atomic(path: &str, contents: &str) -> io::Result<()> {
+    let target = std::path::Path::new(path);
+    let name = target
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?;
+    let tmp = target.with_file_name(format!(
+        ".{}.{}.tmp",
+        name.to_string_lossy(),
+        std::process::id()
+    ));
+    let written = fs::File::create(&tmp).and_then(|mut file| {
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()
+    });
+    let result = written.and_then(|()| fs::rename(&tmp, target));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+// Later runs add rows to an existing export: CSV keeps a single header and YAML
+// becomes a multi-document stream.
+fn appended_export(fmt: OutputFormat, body: String, existing: bool) -> String {
+    match fmt {
+        _ if !existing => body,
+        OutputFormat::Csv => body
+            .split_once('\n')
+            .map_or(String::new(), |(_, rows)| rows.to_string()),
+        OutputFormat::Yaml => format!("---\n{body}"),
+        _ => body,
+    }
+}
+
+const LOCK_EX: i32 = 2;
+const LOCK_NB: i32 = 4;
+const LOCK_POLL: Duration = Duration::from_millis(100);
+
+extern "C" {
+    fn flock(fd: i32, operation: i32) -> i32;
+}
+
+// Advisory lock so two cron-started runs can't interleave rows in one file.
+// It is released when the file is closed.
+fn lock_file(file: &fs::File, path: &str, wait: Duration) -> Result<(), String> {
+    let deadline = Instant::now() + wait;
+    loop {
+        // SAFETY: the descriptor is owned by `file`, which outlives the call.
+        if unsafe { flock(file.as_raw_fd(), LOCK_EX | LOCK_NB) } == 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        match err.kind() {
+            io::ErrorKind::Interrupted => continue,
+            io::ErrorKind::WouldBlock => {}
+            _ => return Err(format!("failed locking '{path}': {err}")),
+        }
+        let now = Instant::now();
+        if now >= deadline || !sleep_interruptible(LOCK_POLL.min(deadline - now)) {
+            return Err(format!(
+                "'{path}' is locked by another opencode-tmux-mem run; retry later or pass --lock-wait"
+            ));
+        }
+    }
+}
+
+// `contents` learns whether the file already had data once the lock is held.
+fn append_locked(
+    path: &str,
+    what: &str,
+    wait: Duration,
+    contents: impl FnOnce(bool) -> String,
+) -> Result<(), String> {
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("failed opening {what} '{path}': {e}"))?;
+    lock_file(&file, path, wait)?;
+    let existing = file.metadata().is_ok_and(|m| m.len() > 0);
+    file.write_all(contents(existing).as_bytes())
+        .map_err(|e| format!("failed writing {what} '{path}': {e}"))
+}
+
+fn resolved_export_format(cli: &Cli) -> Option<OutputFormat> {
+    let path = cli.export_path.as_deref()?;
+    Some(
+        cli.export_format
+            .or_else(|| infer_format_from_path(path))
+            .unwrap_or(OutputFormat::Json),
+    )
+}
+
+// Set by SIGINT/SIGTERM. Long-running modes finish the current tick or request,
+// so recordings and exports are never cut off mid-write, then exit cleanly.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+// Set by SIGUSR1 (sent by the install-hooks tmux hooks): collect now.
+static REFRESH_REQUESTED: AtomicBool = AtomicBool::new(false);
+const SIGINT: i32 = 2;
+const SIGTERM: i32 = 15;
+const SIGUSR1: i32 = if cfg!(target_os = "linux") { 10 } else { 30 };
+
+// What a second signal must still undo before _exit: the watch screen, while it
+// is up, and the --every daemon's PID file (NUL-terminated path, expected first line).
+static WATCH_SCREEN: AtomicBool = AtomicBool::new(false);
+static DAEMON_PID_FILE: OnceLock<(Vec<u8>, Vec<u8>)> = OnceLock::new();
+
+extern "C" {
+    fn signal(signum: i32, handler: extern "C" fn(i32)) -> usize;
+    fn _exit(status: i32) -> !;
+    fn write(fd: i32, buf: *const u8, count: usize) -> isize;
+    fn open(path: *const u8, flags: i32, ...) -> i32;
+    fn read(fd: i32, buf: *mut u8, count: usize) -> isize;
+    fn close(fd: i32) -> i32;
+    fn unlink(path: *const u8) -> i32;
+}
+
+const O_RDONLY: i32 = 0;
+
+// A second signal means "now": _exit is async-signal-safe, unlike process::exit.
+extern "C" fn on_interrupt(signum: i32) {
+    if INTERRUPTED.swap(true, Ordering::SeqCst) {
+        release_for_exit(
+            1,
+            WATCH_SCREEN.load(Ordering::SeqCst),
+            DAEMON_PID_FILE.get(),
+        );
+        // SAFETY: _exit only terminates the process.
+        unsafe { _exit(128 + signum) }
+    }
+}
+
+// Runs inside the signal handler, so only async-signal-safe calls: the terminal
+// restore goes straight to `out` with write(2), and the PID file is removed only
+// while it still names this process, as run_every does on a clean exit.
+fn release_for_exit(out: i32, watch_screen: bool, pid_file: Option<&(Vec<u8>, Vec<u8>)>) {
+    if watch_screen {
+        // SAFETY: the buffer is a static string of the given length.
+        unsafe { write(out, LEAVE_WATCH_SCREEN.as_ptr(), LEAVE_WATCH_SCREEN.len()) };
+    }
+    let Some((path, first_line)) = pid_file else {
+        return;
+    };
+    let mut buf = [0u8; 64];
+    // SAFETY: `path` is NUL-terminated and `buf` outlives the read.
+    let read_len = unsafe {
+        let fd = open(path.as_ptr(), O_RDONLY);
+        if fd < 0 {
+            return;
+        }
+        let n = read(fd, buf.as_mut_ptr(), buf.len());
+        close(fd);
+        n
+    };
+    if usize::try_from(read_len).is_ok_and(|n| buf[..n].starts_with(first_line)) {
+        // SAFETY: `path` is NUL-terminated.
+        unsafe { unlink(path.as_ptr()) };
+    }
+}
+
+fn install_signal_handlers() {
+    // SAFETY: the handler only touches atomics, makes async-signal-safe calls
+    // (write, open, read, close, unlink), and calls _exit.
+    unsafe {
+        signal(SIGINT, on_interrupt);
+        signal(SIGTERM, on_interrupt);
+    }
+}
+
+extern "C" fn on_refresh(_signum: i32) {
+    REFRESH_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+fn install_refresh_handler() {
+    // SAFETY: the handler only touches an atomic.
+    unsafe {
+        signal(SIGUSR1, on_refresh);
+    }
+}
+
+fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+const SIGNAL_POLL: Duration = Duration::from_millis(100);
+
+// Sleeps in short steps so a signal is noticed promptly; false once interrupted.
+fn sleep_interruptible(duration: Duration) -> bool {
+    let wake = Instant::now() + duration;
+    while !interrupted() && Instant::now() < wake {
+        thread::sleep(SIGNAL_POLL.min(wake - Instant::now()));
+    }
+    !interrupted()
+}
+
+// Like sleep_interruptible, but a refresh request (SIGUSR1) also ends the wait.
+fn sleep_until_refresh(duration: Duration) {
+    let wake = Instant::now() + duration;
+    while !interrupted()
+        && !REFRESH_REQUESTED.swap(false, Ordering::SeqCst)
+        && Instant::now() < wake
+    {
+        thread::sleep(SIGNAL_POLL.min(wake - Instant::now()));
+    }
+}
+
+// Alternate screen with a hidden cursor while watching; the shell comes back untouched.
+const ENTER_WATCH_SCREEN: &str = "\x1b[?1049h\x1b[?25l";
+const LEAVE_WATCH_SCREEN: &str = "\x1b[?25h\x1b[?1049l";
+
+// Re-runs the report every `interval`; only --cache-ttl decides what gets re-measured.
+// Ctrl-C ends the loop after the current tick and prints a summary of the session.
+fn run_watch(cli: &Cli, config: &Config, interval: Duration) -> Result<(), String> {
+    install_signal_handlers();
+    let tty = io::stdout().is_terminal();
+    if tty {
+        print!("{ENTER_WATCH_SCREEN}");
+        let _ = io::stdout().flush();
+        WATCH_SCREEN.store(true, Ordering::SeqCst);
+    }
+    let started = Instant::now();
+    let mut cache = persistent_cache();
+    let (mut rss_totals, mut swap_totals) = (Vec::new(), Vec::new());
+    let mut result = Ok(());
+    let (mut current, mut footprint) = (interval, None);
+    while !interrupted() {
+        // The header names the wait that led up to this tick.
+        let tick = Cli {
+            watch: Some(current),
+            ..cli.clone()
+        };
+        match run_report(&tick, config, &mut cache) {
+            Ok(rows) => {
+                rss_totals.push(rows.iter().map(|r| r.rss_bytes).sum::<u64>());
+                swap_totals.push(rows.iter().map(|r| r.swap_bytes).sum::<u64>());
+                let now = rows
+                    .iter()
+                    .map(|r| footprint_bytes(r.swap_bytes, r.physical_bytes, r.rss_bytes))
+                    .sum::<u64>();
+                if cli.watch_adaptive {
+                    if let Some(before) = footprint {
+                        current = adapt_interval(current, interval, before, now);
+                    }
+                }
+                footprint = Some(now);
+            }
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        }
+        let _ = io::stdout().flush();
+        sleep_interruptible(current);
+    }
+    if tty {
+        WATCH_SCREEN.store(false, Ordering::SeqCst);
+        print!("{LEAVE_WATCH_SCREEN}");
+        let _ = io::stdout().flush();
+    }
+    let summary = watch_summary(started.elapsed(), &mut rss_totals, &mut swap_totals);
+    // A JSON log gets the summary as one event, its table rows joined by "; ".
+    if LOG_JSON.load(Ordering::SeqCst) {
+        let rows = summary.trim().lines().collect::<Vec<_>>().join("; ");
+        log_event("info", &rows.replace('\t', " "), None);
+    } else {
+        eprint!("{summary}");
+    }
+    result
+}
+
+// --watch-adaptive bounds: never faster than a quarter of --watch (or 1s), never
+// slower than eight times it.
+const ADAPTIVE_MIN: Duration = Duration::from_secs(1);
+const ADAPTIVE_MAX_FACTOR: u32 = 8;
+// Growth between two ticks that halves the interval, and the change (either
+// way) under which memory counts as stable and the interval grows by half.
+const ADAPTIVE_FAST: GrowthThreshold = GrowthThreshold::Percent(5.0);
+const ADAPTIVE_STABLE_PCT: f64 = 1.0;
+
+fn adapt_interval(current: Duration, base: Duration, before: u64, now: u64) -> Duration {
+    let min = (base / 4).max(ADAPTIVE_MIN).min(base);
+    let max = base * ADAPTIVE_MAX_FACTOR;
+    let change = now.abs_diff(before) as f64 * 100.0 / before.max(1) as f64;
+    if grew_past(before, now, ADAPTIVE_FAST) {
+        (current / 2).max(min)
+    } else if change < ADAPTIVE_STABLE_PCT {
+        (current * 3 / 2).min(max)
+    } else {
+        current
+    }
+}
+
+// A background logger: collect (and --record/--export) on a cadence without
+// redrawing anything. A failed run is a warning; the next one is tried on time.
+// The --every loop is the daemon: it leaves its PID where the install-hooks tmux
+// hooks find it, and collects early when they send SIGUSR1. The PID file's second
+// line is the daemon's start time, so a hook never signals a recycled PID.
+fn run_every(cli: &Cli, config: &Config, interval: Duration) -> Result<(), String> {
+    install_signal_handlers();
+    install_refresh_handler();
+    let pid_path = daemon_pid_path();
+    if let Some(path) = &pid_path {
+        let written = std::path::Path::new(path)
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| write_atomic(path, &daemon_pid_file(std::process::id())));
+        match written {
+            Ok(()) => {
+                let path = format!("{path}\0").into_bytes();
+                let first_line = format!("{}\n", std::process::id()).into_bytes();
+                let _ = DAEMON_PID_FILE.set((path, first_line));
+            }
+            Err(e) => warn(format!("failed writing daemon PID file '{path}': {e}")),
+        }
+    }
+    let mut cache = persistent_cache();
+    while !interrupted() {
+        match run_report(cli, config, &mut cache) {
+            Ok(rows) => {
+                if let Some(path) = daemon_status_path() {
+                    write_status_summary(&path, cli, &rows);
+                }
+            }
+            Err(e) => warn(e),
+        }
+        let _ = io::stdout().flush();
+        sleep_until_refresh(interval + jitter(cli.jitter));
+    }
+    // Leave a newer daemon's PID file alone.
+    if let Some(path) = &pid_path {
+        if fs::read_to_string(path).is_ok_and(|raw| {
+            raw.lines().next().map(str::trim) == Some(&std::process::id().to_string())
+        }) {
+            let _ = fs::remove_file(path);
+        }
+    }
+    Ok(())
+}
+
+// After a collection, pressure has to persist this long to trigger the next one;
+// PSI keeps firing every window while the stall lasts.
+const PRESSURE_COOLDOWN: Duration = Duration::from_secs(60);
+
+// Costs a blocked poll (Linux) or one sysctl every few seconds (macOS) until the
+// kernel reports memory pressure; only then does it run a full collection.
+fn run_on_pressure(cli: &Cli, config: &Config) -> Result<(), String> {
+    install_signal_handlers();
+    let mut source = open_pressure_source()?;
+    log_event(
+        "info",
+        &format!("waiting for memory pressure ({PRESSURE_SIGNAL})"),
+        None,
+    );
+    let mut cache = persistent_cache();
+    while wait_for_pressure(&mut source)? {
+        log_event(
+            "info",
+            &format!(
+                "memory pressure at {}; collecting",
+                format_utc_timestamp(unix_now())
+            ),
+            None,
+        );
+        match run_report(cli, config, &mut cache) {
+            Ok(rows) => {
+                if cli.alert_notify {
+                    notify(&pressure_message(&rows), "pressure");
+                }
+            }
+            Err(e) => warn(e),
+        }
+        let _ = io::stdout().flush();
+        if !sleep_interruptible(PRESSURE_COOLDOWN) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn pressure_message(rows: &[ProcRecord]) -> String {
+    let footprint = |r: &ProcRecord| footprint_bytes(r.swap_bytes, r.physical_bytes, r.rss_bytes);
+    let mut message = format!(
+        "Memory pressure: {} processes hold {}",
+        rows.len(),
+        human_bytes(rows.iter().map(footprint).sum())
+    );
+    if let Some(pane) = aggregate_by_pane(rows)
+        .into_iter()
+        .filter(|p| p.tmux_target != "?")
+        .max_by_key(|p| footprint_bytes(p.swap_bytes, p.physical_bytes, p.rss_bytes))
+    {
+        let _ = write!(
+            message,
+            "; largest pane {} ({})",
+            pane.tmux_target,
+            human_bytes(footprint_bytes(
+                pane.swap_bytes,
+                pane.physical_bytes,
+                pane.rss_bytes
+            ))
+        );
+    }
+    message
+}
+
+// Linux PSI trigger: some task stalled on memory for 150ms within a 2s window.
+// Unprivileged triggers need a window that is a multiple of 2s.
+#[cfg(target_os = "linux")]
+const PSI_MEMORY: &str = "/proc/pressure/memory";
+#[cfg(target_os = "linux")]
+const PSI_TRIGGER: &str = "some 150000 2000000";
+
+#[cfg(target_os = "linux")]
+struct PressureSource(fs::File);
+
+#[cfg(target_os = "linux")]
+const PRESSURE_SIGNAL: &str = "a PSI trigger on /proc/pressure/memory";
+
+// poll(2) also backs the tmux control client's read timeout (TimedStdout).
+#[repr(C)]
+struct PollFd {
+    fd: i32,
+    events: i16,
+    revents: i16,
+}
+
+#[cfg(target_os = "linux")]
+type NfdsT = std::ffi::c_ulong;
+#[cfg(not(target_os = "linux"))]
+type NfdsT = std::ffi::c_uint;
+
+extern "C" {
+    fn poll(fds: *mut PollFd, nfds: NfdsT, timeout: i32) -> i32;
+}
+
+#[cfg(target_os = "linux")]
+fn open_pressure_source() -> Result<PressureSource, String> {
+    let mut file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(PSI_MEMORY)
+        .map_err(|e| format!("--on-pressure needs PSI ({PSI_MEMORY}, Linux 4.20+): {e}"))?;
+    file.write_all(format!("{PSI_TRIGGER}\0").as_bytes())
+        .map_err(|e| format!("failed to set a PSI trigger on {PSI_MEMORY}: {e}"))?;
+    Ok(PressureSource(file))
+}
+
+// Returns false once interrupted; polls in short slices so Ctrl-C is noticed.
+#[cfg(target_os = "linux")]
+fn wait_for_pressure(source: &mut PressureSource) -> Result<bool, String> {
+    const POLLPRI: i16 = 0x2;
+    const POLLERR: i16 = 0x8;
+    while !interrupted() {
+        let mut fd = PollFd {
+            fd: source.0.as_raw_fd(),
+            events: POLLPRI,
+            revents: 0,
+        };
+        // SAFETY: one pollfd that lives across the call; the file stays open.
+        let ready = unsafe { poll(&mut fd, 1, SIGNAL_POLL.as_millis() as i32) };
+        if ready < 0 {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(format!("waiting on {PSI_MEMORY} failed: {e}"));
+        }
+        if fd.revents & POLLERR != 0 {
+            return Err(format!("{PSI_MEMORY} trigger was closed by the kernel"));
+        }
+        if fd.revents & POLLPRI != 0 {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+// macOS: kern.memorystatus_vm_pressure_level is 1 (normal), 2 (warn), or
+// 4 (critical), the same levels memory pressure notifications carry.
+#[cfg(target_os = "macos")]
+struct PressureSource;
+
+#[cfg(target_os = "macos")]
+const PRESSURE_SIGNAL: &str = "kern.memorystatus_vm_pressure_level at warn or critical";
+
+#[cfg(target_os = "macos")]
+const PRESSURE_POLL: Duration = Duration::from_secs(2);
+
+#[cfg(target_os = "macos")]
+extern "C" {
+    fn sysctlbyname(
+        name: *const u8,
+        oldp: *mut i32,
+        oldlenp: *mut usize,
+        newp: *const u8,
+        newlen: usize,
+    ) -> i32;
+}
+
+#[cfg(target_os = "macos")]
+fn vm_pressure_level() -> Option<i32> {
+    let mut level = 0i32;
+    let mut len = std::mem::size_of::<i32>();
+    // SAFETY: NUL-terminated name; `level` and `len` outlive the call.
+    let rc = unsafe {
+        sysctlbyname(
+            b"kern.memorystatus_vm_pressure_level\0".as_ptr(),
+            &mut level,
+            &mut len,
+            std::ptr::null(),
+            0,
+        )
+    };
+    (rc == 0).then_some(level)
+}
+
+#[cfg(target_os = "macos")]
+fn open_pressure_source() -> Result<PressureSource, String> {
+    vm_pressure_level().map(|_| PressureSource).ok_or_else(|| {
+        "--on-pressure could not read kern.memorystatus_vm_pressure_level".to_string()
+    })
+}
+
+#[cfg(target_os = "macos")]
+fn wait_for_pressure(_source: &mut PressureSource) -> Result<bool, String> {
+    loop {
+        if vm_pressure_level().is_some_and(|level| level >= 2) {
+            return Ok(true);
+        }
+        if !sleep_interruptible(PRESSURE_POLL) {
+            return Ok(false);
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+struct PressureSource;
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+const PRESSURE_SIGNAL: &str = "";
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn open_pressure_source() -> Result<PressureSource, String> {
+    Err("--on-pressure needs Linux PSI or macOS memory pressure levels".to_string())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn wait_for_pressure(_source: &mut PressureSource) -> Result<bool, String> {
+    Ok(false)
+}
+
+fn daemon_pid_path() -> Option<String> {
+    state_dir().map(|dir| format!("{dir}/daemon.pid"))
+}
+
+// PID, then `ps -o lstart=` for it, exactly as the refresh hooks will compare it.
+fn daemon_pid_file(pid: u32) -> String {
+    let started = run_cmd("ps", &["-p", &pid.to_string(), "-o", "lstart="]).unwrap_or_else(|e| {
+        warn(format!(
+            "daemon start time unavailable, tmux hooks won't signal it: {e}"
+        ));
+        String::new()
+    });
+    format!("{pid}\n{}\n", started.trim_end_matches('\n'))
+}
+
+// Refused in paths spliced into the hooks: quotes end the shell words, `$`, `\`, and
+// backticks expand in them, and tmux expands `#{...}` before the shell runs.
+fn hook_safe_path(path: &str) -> bool {
+    !path.contains(['\'', '"', '$', '`', '\\', '#'])
+}
+
+// tmux events that change which panes exist.
+const REFRESH_HOOKS: [&str; 4] = [
+    "after-split-window",
+    "after-new-window",
+    "after-kill-pane",
+    "pane-exited",
+];
+// A fixed slot in each hook array: re-installing replaces ours, other hooks stay.
+const HOOK_INDEX: u32 = 77;
+
+// `tmux set-hook` arguments that make each event nudge the --every daemon. The PID
+// is only signalled while its start time matches the file: a stale file left by a
+// crash must not hit whatever process reuses the PID (SIGUSR1 kills by default).
+fn refresh_hook_args(pid_path: &str) -> Vec<Vec<String>> {
+    let command = format!(
+        "run-shell -b 'f=\"{pid_path}\"; pid=$(head -n 1 \"$f\" 2>/dev/null) && [ -n \"$pid\" ] \
+         && [ \"$(ps -p \"$pid\" -o lstart= 2>/dev/null)\" = \"$(sed -n 2p \"$f\")\" ] \
+         && kill -USR1 \"$pid\" 2>/dev/null; true'"
+    );
+    REFRESH_HOOKS
+        .iter()
+        .map(|hook| {
+            vec![
+                "set-hook".to_string(),
+                "-g".to_string(),
+                format!("{hook}[{HOOK_INDEX}]"),
+                command.clone(),
+            ]
+        })
+        .collect()
+}
+
+const DEFAULT_BINDING_KEY: &str = "M";
+
+// Opens a live --watch view in a popup over the current pane (tmux 3.2+).
+fn keybinding_args(key: &str, exe: &str, forwarded: &[String]) -> Vec<String> {
+    let mut command = vec!["report".to_string()];
+    command.extend(forwarded.iter().cloned());
+    if !forwarded.iter().any(|a| a == "--watch") {
+        command.extend(["--watch".to_string(), "5s".to_string()]);
+    }
+    let command = command.iter().map(String::as_str).collect::<Vec<_>>();
+    [
+        "bind-key",
+        key,
+        "display-popup",
+        "-E",
+        "-w",
+        "90%",
+        "-h",
+        "80%",
+    ]
+    .into_iter()
+    .map(str::to_string)
+    .chain([shell_command_line(exe, &command)])
+    .collect()
+}
+
+fn run_install_keybinding(cli: &Cli) -> Result<(), String> {
+    let exe = env::current_exe()
+        .map_err(|e| format!("failed locating the opencode-tmux-mem binary: {e}"))?;
+    let args = keybinding_args(&cli.binding_key, &exe.to_string_lossy(), &cli.service_args);
+    if !cli.apply {
+        let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+        println!("{}", shell_command_line("tmux", &args));
+        log_event(
+            "info",
+            "install-keybinding: re-run with --apply to bind it in the running tmux server",
+            None,
+        );
+        return Ok(());
+    }
+    let actions = vec![PlannedAction {
+        action: "install-keybinding",
+        target: format!("prefix {}", cli.binding_key),
+        program: "tmux",
+        args,
+        metric: "keybinding",
+        before: 0,
+    }];
+    if !confirm_actions_stdin(&actions, cli.dry_run, cli.yes)? {
+        return Ok(());
+    }
+    if execute_actions(&actions, cli.dry_run) > 0 {
+        return Err("install-keybinding: tmux bind-key failed".to_string());
+    }
+    Ok(())
+}
+
+fn daemon_status_path() -> Option<String> {
+    state_dir().map(|dir| format!("{dir}/status.json"))
+}
+
+// The daemon's summary counts while it is younger than this.
+const PLUGIN_DAEMON_MAX_AGE: u64 = 15 * 60;
+// Without a daemon, one quick collection serves status refreshes for this long
+// (or --cache-ttl).
+const PLUGIN_SNAPSHOT_TTL: u64 = 30;
+
+// For tmux's `#()`: print one short line from the daemon's summary or a recent
+// snapshot. A fallback collection is ps-only, so vmmap and capture-pane never run
+// inside a status-line refresh.
+fn run_plugin(cli: &Cli) -> Result<(), String> {
+    let now = unix_now();
+    let user = env::var("USER")
+        .or_else(|_| env::var("LOGNAME"))
+        .unwrap_or_else(|_| "default".to_string());
+    let snapshot = env::temp_dir()
+        .join(format!("opencode-tmux-mem-status-{user}.json"))
+        .to_string_lossy()
+        .to_string();
+    let ttl = match cli.cache_ttl.as_secs() {
+        0 => PLUGIN_SNAPSHOT_TTL,
+        secs => secs,
+    };
+    // A summary collected with other flags (another --process, --app, or scope)
+    // counts different processes, so only a matching one is reused.
+    let flags = Field::Str(collection_flags(cli));
+    let fresh = |path: Option<String>, max_age: u64| {
+        let summary = parse_json_object(&fs::read_to_string(path?).ok()?).ok()?;
+        if summary.get("flags") != Some(&flags) {
+            return None;
+        }
+        match summary.get("ts") {
+            Some(Field::Uint(ts)) if now.saturating_sub(*ts) <= max_age => Some(summary),
+            _ => None,
+        }
+    };
+    let summary = match fresh(daemon_status_path(), PLUGIN_DAEMON_MAX_AGE)
+        .or_else(|| fresh(Some(snapshot.clone()), ttl))
+    {
+        Some(summary) => summary,
+        None => {
+            let quick = Cli {
+                vmmap_min_rss: Some(u64::MAX),
+                no_history_bytes: true,
+                quiet: true,
+                ..cli.clone()
+            };
+            let rows = collect_rows(&quick, &mut CollectCache::default())?;
+            write_status_summary(&snapshot, cli, &rows);
+            parse_json_object(&status_summary(now, &collection_flags(cli), &rows))?
+        }
+    };
+    println!("{}", plugin_line(&summary));
+    Ok(())
+}
+
+// One flat JSON object: the totals a status line needs, and the flags that
+// chose which processes they cover.
+fn status_summary(ts: u64, flags: &str, rows: &[ProcRecord]) -> String {
+    let footprint = rows
+        .iter()
+        .map(|r| footprint_bytes(r.swap_bytes, r.physical_bytes, r.rss_bytes))
+        .sum::<u64>();
+    json_object(&[
+        ("ts", Field::Uint(ts)),
+        ("flags", Field::Str(flags.to_string())),
+        ("process_count", Field::Uint(rows.len() as u64)),
+        ("footprint_bytes", Field::Uint(footprint)),
+        (
+            "swap_bytes",
+            Field::Uint(rows.iter().map(|r| r.swap_bytes).sum()),
+        ),
+        (
+            "rss_bytes",
+            Field::Uint(rows.iter().map(|r| r.rss_bytes).sum()),
+        ),
+    ])
+}
+
+// The status line must keep working, so a failed write only warns.
+fn write_status_summary(path: &str, cli: &Cli, rows: &[ProcRecord]) {
+    let written = std::path::Path::new(path)
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| {
+            write_atomic(
+                path,
+                &status_summary(unix_now(), &collection_flags(cli), rows),
+            )
+        });
+    if let Err(e) = written {
+        warn(format!("failed writing status summary '{path}': {e}"));
+    }
+}
+
+// The flags that decide which processes a collection covers, in one string.
+fn collection_flags(cli: &Cli) -> String {
+    format!(
+        "{:?}",
+        (
+            &cli.process_patterns,
+            &cli.ports,
+            &cli.cwd_paths,
+            &cli.open_files,
+            cli.ignore_case,
+            &cli.user,
+            &cli.env_filters,
+            cli.app,
+            &cli.tmux_scope,
+            cli.include_self,
+            cli.orphans_only,
+        )
+    )
+}
+
+fn plugin_line(summary: &HashMap<String, Field>) -> String {
+    format!(
+        "{} ({})",
+        compact_bytes(record_uint(summary, "footprint_bytes")),
+        record_uint(summary, "process_count")
+    )
+}
+
+fn run_install_hooks(cli: &Cli) -> Result<(), String> {
+    let pid_path =
+        daemon_pid_path().ok_or("cannot locate a state directory: set HOME or XDG_STATE_HOME")?;
+    if !hook_safe_path(&pid_path) {
+        return Err(format!(
+            "cannot quote the daemon PID path for tmux: {pid_path}"
+        ));
+    }
+    let hooks = refresh_hook_args(&pid_path);
+    if !cli.apply {
+        for args in &hooks {
+            let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+            println!("{}", shell_command_line("tmux", &args));
+        }
+        log_event(
+            "info",
+            "install-hooks: re-run with --apply to register them with the running tmux server",
+            None,
+        );
+        return Ok(());
+    }
+    let actions = hooks
+        .into_iter()
+        .map(|args| PlannedAction {
+            action: "install-hook",
+            target: args[2].clone(),
+            program: "tmux",
+            args,
+            metric: "hook",
+            before: 0,
+        })
+        .collect::<Vec<_>>();
+    if !confirm_actions_stdin(&actions, cli.dry_run, cli.yes)? {
+        return Ok(());
+    }
+    let failures = execute_actions(&actions, cli.dry_run);
+    if failures > 0 {
+        return Err(format!("install-hooks: {failures} command(s) failed"));
+    }
+    Ok(())
+}
+
+// Up to `max` of extra delay, so loggers started together drift apart.
+fn jitter(max: Duration) -> Duration {
+    use std::hash::{BuildHasher, Hasher};
+    let max_ms = max.as_millis() as u64;
+    if max_ms == 0 {
+        return Duration::ZERO;
+    }
+    let random = std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish();
+    Duration::from_millis(random % (max_ms + 1))
+}
+
+fn watch_summary(elapsed: Duration, rss: &mut [u64], swap: &mut [u64]) -> String {
+    let mut out = format!(
+        "\nWatch summary: {} samples over {}\n",
+        rss.len(),
+        human_duration(elapsed.as_secs())
+    );
+    out.push_str("Metric\tp50\tp95\tmax\n");
+    for (name, values) in [("Total RSS", rss), ("Total swap", swap)] {
+        values.sort_unstable();
+        let _ = writeln!(
+            out,
+            "{name}\t{}\t{}\t{}",
+            human_bytes(percentile(values, 50)),
+            human_bytes(percentile(values, 95)),
+            human_bytes(values.last().copied().unwrap_or(0)),
+        );
+    }
+    out
+}
+
+// Nearest-rank percentile of an ascending slice; 0 when empty.
+fn percentile(sorted: &[u64], pct: usize) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (pct * sorted.len()).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+// Process and pane rows with severity levels from the config applied.
+fn collect_scored(
+    cli: &Cli,
+    config: &Config,
+    cache: &mut CollectCache,
+) -> Result<(Vec<ProcRecord>, Vec<PaneRecord>), String> {
+    let mut rows = collect_rows(cli, cache)?;
+    for row in &mut rows {
+        row.severity = severity_of(&config.severity, |m| process_metric(row, m));
+    }
+    let mut panes = aggregate_by_pane(&rows);
+    flag_duplicate_instances(&mut rows, &panes, &mut cache.duplicate_pids);
+    if cli.include_shell {
+        add_shell_memory(&mut panes, &pane_shell_rss(cli, &rows));
+    }
+    for pane in &mut panes {
+        pane.severity = severity_of(&config.severity, |m| pane_metric(pane, m));
+    }
+    Ok((rows, panes))
+}
+
+// md-report and svg draw on both the process rows and the panes, whichever view
+// is selected.
+fn renders_whole_report(cli: &Cli) -> bool {
+    [Some(cli.stdout_format), resolved_export_format(cli)]
+        .into_iter()
+        .any(|fmt| matches!(fmt, Some(OutputFormat::MdReport | OutputFormat::Svg)))
+}
+
+// Returns the collected process rows so --watch can keep session statistics.
+fn run_report(
+    cli: &Cli,
+    config: &Config,
+    cache: &mut CollectCache,
+) -> Result<Vec<ProcRecord>, String> {
+    let mut opts = render_options(cli);
+    if cli.view_mode == ViewMode::Mappings {
+        return run_mappings(cli, &opts).map(|_| Vec::new());
+    }
+    if cli.view_mode == ViewMode::Tmux {
+        return run_tmux_view(cli, &opts).map(|_| Vec::new());
+    }
+    if cli.badge_clear {
+        return badge_windows(&[], None, cli.dry_run, cli.yes).map(|_| Vec::new());
+    }
+    let (mut rows, mut panes) = collect_scored(cli, config, cache)?;
+    if let Some(threshold) = cli.alert_growth {
+        let grown = mark_growth(&mut rows, &mut panes, cache, threshold);
+        if cli.alert_notify && !grown.is_empty() {
+            notify_growth(&grown);
+        }
+    }
+    if cli.since_last {
+        // Looked up before this run's export lands next to (or over) it.
+        let template = cli.export_path.as_deref().unwrap_or_default();
+        match find_last_export(template) {
+            Some(path) => {
+                log_event(
+                    "info",
+                    &format!("comparing against {}", path.display()),
+                    None,
+                );
+                let baseline = load_export(&path)?;
+                apply_since_last(&mut rows, &mut panes, &baseline);
+                opts.since_last = true;
+            }
+            None => warn(format!(
+                "--since-last found no earlier export for '{template}'"
+            )),
+        }
+    }
+    if cli.percent || cli.cumulative {
+        apply_shares(&mut rows, &mut panes);
+    }
+    RUN_STATUS.lock().unwrap_or_else(|e| e.into_inner()).totals = Some((
+        rows.iter().map(|r| r.swap_bytes).sum(),
+        rows.iter().map(|r| r.physical_bytes).sum(),
+        rows.iter().map(|r| r.rss_bytes).sum(),
+    ));
+    if cli.mark_panes {
+        mark_panes(&panes, cli.dry_run, cli.yes)?;
+    }
+    if let Some(threshold) = cli.badge_windows {
+        badge_windows(&panes, Some(threshold), cli.dry_run, cli.yes)?;
+    }
+    let budgets = evaluate_budgets(&panes, &config.budgets);
+    if rows.iter().any(|r| r.severity == Some(Severity::Crit))
+        || panes.iter().any(|p| p.severity == Some(Severity::Crit))
+        || budgets.iter().any(|b| b.status == BudgetStatus::Over)
+    {
+        mark_breached();
+    }
+    if let Some(path) = &cli.record_path {
+        append_recording(path, unix_now(), &rows, cli.lock_wait)?;
+    }
+
+    match cli.view_mode {
+        _ if cli.group_by.is_some() => {
+            let expr = cli.group_by.as_deref().unwrap_or_default();
+            let mut keys = HashMap::new();
+            for socket in tmux_servers(&panes) {
+                keys.extend(
+                    pane_format_values(socket.as_deref(), expr)
+                        .map_err(|e| format!("failed to evaluate --group-by with tmux: {e}"))?,
+                );
+            }
+            let groups = group_panes(&panes, &rows, |pane| keys.get(&pane.tmux_target).cloned());
+            emit(cli, &opts, groups.len(), |fmt, o| {
+                render_groups(&groups, ("Group", "group"), expr, fmt, o)
+            })?
+        }
+        ViewMode::Project => {
+            let mut paths = HashMap::new();
+            for socket in tmux_servers(&panes) {
+                paths.extend(
+                    pane_format_values(socket.as_deref(), "#{pane_current_path}")
+                        .map_err(|e| format!("failed to read pane directories from tmux: {e}"))?,
+                );
+            }
+            let mut roots = HashMap::new();
+            let groups = group_panes(&panes, &rows, |pane| {
+                let path = paths.get(&pane.tmux_target)?;
+                Some(
+                    roots
+                        .entry(path.clone())
+                        .or_insert_with(|| project_root(std::path::Path::new(path)))
+                        .clone(),
+                )
+            });
+            emit(cli, &opts, groups.len(), |fmt, o| {
+                render_groups(
+                    &groups,
+                    ("Project", "project"),
+                    "pane directory (git repository root when inside one)",
+                    fmt,
+                    o,
+                )
+            })?
+        }
+        ViewMode::Session => {
+            let groups = session_groups(&panes, &rows);
+            emit(cli, &opts, groups.len(), |fmt, o| {
+                render_groups(&groups, ("Session", "session"), "tmux session", fmt, o)
+            })?
+        }
+        _ if renders_whole_report(cli) => {
+            let count = match cli.view_mode {
+                ViewMode::Pane => panes.len(),
+                _ => rows.len(),
+            };
+            emit(cli, &opts, count, |fmt, o| match (fmt, cli.view_mode) {
+                (OutputFormat::MdReport, _) => render_md_report(
+                    &rows,
+                    &panes,
+                    cli.history_target_bytes,
+                    (unix_now(), &short_hostname()),
+                    o,
+                ),
+                (OutputFormat::Svg, _) => render_pane_svg(&panes, unix_now()),
+                (_, ViewMode::Pane) => render_pane(&panes, fmt, o),
+                _ => render_process(&rows, fmt, o),
+            })?
+        }
+        ViewMode::Process => emit(cli, &opts, rows.len(), |fmt, o| {
+            render_process(&rows, fmt, o)
+        })?,
+        ViewMode::Pane => emit(cli, &opts, panes.len(), |fmt, o| match fmt {
+            OutputFormat::Table if cli.pane_detail => render_pane_table(&panes, Some(&rows), o),
+            _ => render_pane(&panes, fmt, o),
+        })?,
+        ViewMode::Budget => emit(cli, &opts, budgets.len(), |fmt, o| {
+            render_budget(&budgets, fmt, o)
+        })?,
+        ViewMode::Mappings | ViewMode::Tmux => {
+            unreachable!("mappings and tmux views return before collection")
+        }
+    }
+
+    if let Some(path) = &cli.policy_path {
+        let rules = load_policy(path)?;
+        let breaches = evaluate_policy(&rows, &panes, &rules);
+        apply_policy(&breaches, &rows, cli.dry_run, cli.yes)?;
+    }
+
+    Ok(rows)
+}
+
+const MAPPINGS_TOP: usize = 20;
+
+fn run_mappings(cli: &Cli, opts: &RenderOptions) -> Result<(), String> {
+    let pid = cli.pid.expect("checked in check_cli");
+    let mut mappings = process_mappings(pid)?;
+    mappings.truncate(MAPPINGS_TOP);
+    emit(cli, opts, mappings.len(), |fmt, o| {
+        render_mappings(&mappings, fmt, o)
+    })
+}
+
+// Flags rows that grew past the threshold since the previous tick and returns their
+// names for --alert-notify. The first tick only records a baseline.
+fn mark_growth(
+    rows: &mut [ProcRecord],
+    panes: &mut [PaneRecord],
+    cache: &mut CollectCache,
+    threshold: GrowthThreshold,
+) -> Vec<String> {
+    let mut grown = Vec::new();
+    let mut process_footprints = HashMap::new();
+    for row in rows.iter_mut() {
+        let now = footprint_bytes(row.swap_bytes, row.physical_bytes, row.rss_bytes);
+        let before = cache
+            .process_footprints
+            .get(&row.pid)
+            .filter(|(command, _)| *command == row.command)
+            .map(|(_, bytes)| *bytes);
+        let alert = before.is_some_and(|b| grew_past(b, now, threshold));
+        if alert {
+            grown.push(format!("{} (pid {})", row.command_name, row.pid));
+        }
+        row.growth_alert = Some(alert);
+        process_footprints.insert(row.pid, (row.command.clone(), now));
+    }
+    let mut pane_footprints = HashMap::new();
+    for pane in panes.iter_mut() {
+        let now = footprint_bytes(pane.swap_bytes, pane.physical_bytes, pane.rss_bytes);
+        let before = cache.pane_footprints.get(&pane.tmux_target).copied();
+        let alert = before.is_some_and(|b| grew_past(b, now, threshold));
+        if alert && pane.tmux_target != "?" {
+            grown.push(format!("pane {}", pane.tmux_target));
+        }
+        pane.growth_alert = Some(alert);
+        pane_footprints.insert(pane.tmux_target.clone(), now);
+    }
+    cache.process_footprints = process_footprints;
+    cache.pane_footprints = pane_footprints;
+    grown
+}
+
+fn grew_past(before: u64, now: u64, threshold: GrowthThreshold) -> bool {
+    let growth = now.saturating_sub(before);
+    match threshold {
+        GrowthThreshold::Percent(pct) => {
+            before > 0 && growth > 0 && growth as f64 * 100.0 / before as f64 >= pct
+        }
+        GrowthThreshold::Bytes(bytes) => growth > 0 && growth >= bytes,
+    }
+}
+
+// "10%", "50M", optionally followed by "/interval" (growth between two ticks).
+fn parse_growth_threshold(v: &str) -> Option<GrowthThreshold> {
+    let v = v.strip_suffix("/interval").unwrap_or(v).trim();
+    match v.strip_suffix('%') {
+        Some(pct) => pct
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|p| p.is_finite() && *p > 0.0)
+            .map(GrowthThreshold::Percent),
+        None => parse_size(v)
+            .ok()
+            .filter(|b| *b > 0)
+            .map(GrowthThreshold::Bytes),
+    }
+}
+
+// Desktop notification; a missing notifier only warns so watching goes on.
+fn notify_growth(grown: &[String]) {
+    notify(
+        &format!("Grew since the last tick: {}", grown.join(", ")),
+        "growth",
+    );
+}
+
+fn notify(message: &str, what: &str) {
+    let result = if cfg!(target_os = "macos") {
+        let script = format!(
+            "display notification \"{}\" with title \"opencode-tmux-mem\"",
+            message.replace('\\', "\\\\").replace('"', "\\\"")
+        );
+        run_cmd("osascript", &["-e", &script])
+    } else {
+        run_cmd("notify-send", &["opencode-tmux-mem", message])
+    };
+    if let Err(e) = result {
+        warn(format!("{what} notification failed: {e}"));
+    }
+}
+
+// The newest file whose path fits the --export template. Placeholders in
+// directories are matched too, so `archive/%Y-%m/report.json` still finds last
+// month's export on the first run of a new month.
+fn find_last_export(template: &str) -> Option<std::path::PathBuf> {
+    let mut candidates = vec![std::path::PathBuf::new()];
+    for part in std::path::Path::new(template).iter() {
+        let part = part.to_string_lossy();
+        if !part.contains('%') {
+            candidates
+                .iter_mut()
+                .for_each(|path| path.push(part.as_ref()));
+            continue;
+        }
+        candidates = candidates
+            .into_iter()
+            .flat_map(|dir| {
+                let listed = if dir.as_os_str().is_empty() {
+                    fs::read_dir(".")
+                } else {
+                    fs::read_dir(&dir)
+                };
+                listed
+                    .into_iter()
+                    .flatten()
+                    .flatten()
+                    .filter(|entry| template_matches(&part, &entry.file_name().to_string_lossy()))
+                    .map(move |entry| dir.join(entry.file_name()))
+            })
+            .collect();
+    }
+    candidates
+        .into_iter()
+        .filter_map(|path| {
+            let meta = fs::metadata(&path).ok().filter(fs::Metadata::is_file)?;
+            Some((meta.modified().ok()?, path))
+        })
+        .max()
+        .map(|(_, path)| path)
+}
+
+// Whether expand_path_template could have turned `template` into `name`.
+fn template_matches(template: &str, name: &str) -> bool {
+    let Some(rest) = template.strip_prefix('%') else {
+        return match template.chars().next() {
+            None => name.is_empty(),
+            Some(c) => name
+                .strip_prefix(c)
+                .is_some_and(|name| template_matches(&template[c.len_utf8()..], name)),
+        };
+    };
+    let Some(spec) = rest.chars().next() else {
+        return name == "%";
+    };
+    let rest = &rest[spec.len_utf8()..];
+    let digits = |n: usize| {
+        name.get(..n)
+            .is_some_and(|d| d.bytes().all(|b| b.is_ascii_digit()))
+            && template_matches(rest, &name[n..])
+    };
+    match spec {
+        'Y' => digits(4),
+        'm' | 'd' | 'H' | 'M' | 'S' => digits(2),
+        '%' => name
+            .strip_prefix('%')
+            .is_some_and(|name| template_matches(rest, name)),
+        // Variable width: try every split that keeps the placeholder plausible.
+        's' | 'h' => name
+            .char_indices()
+            .skip(1)
+            .map(|(i, _)| i)
+            .chain([name.len()])
+            .take_while(|&i| {
+                name[..i].chars().all(|c| {
+                    if spec == 's' {
+                        c.is_ascii_digit()
+                    } else {
+                        c != '/'
+                    }
+                })
+            })
+            .any(|i| template_matches(rest, &name[i..])),
+        // Not a placeholder: expand_path_template kept it as written.
+        _ => name
+            .strip_prefix('%')
+            .and_then(|name| name.strip_prefix(spec))
+            .is_some_and(|name| template_matches(rest, name)),
+    }
+}
+
+// Our JSON exports put one record per line, bare or inside --envelope's records;
+// anything else is read as one of our CSV exports.
+fn load_export(path: &std::path::Path) -> Result<Vec<HashMap<String, Field>>, String> {
+    let raw = fs::read_to_string(path)
+        .map_err(|e| format!("failed reading export '{}': {e}", path.display()))?;
+    let format = export_file_format(&path.to_string_lossy(), &raw)
+        .map_err(|e| format!("failed reading export '{}': {e}", path.display()))?;
+    if format == OutputFormat::Csv {
+        return parse_csv_export(&raw)
+            .map_err(|e| format!("failed reading export '{}': {e}", path.display()));
+    }
+    raw.lines()
+        .map(|line| line.trim().trim_end_matches(','))
+        .filter(|line| line.starts_with('{') && line.ends_with('}'))
+        .map(|line| {
+            parse_json_object(line)
+                .map_err(|e| format!("failed reading export '{}': {e}", path.display()))
+        })
+        .collect()
+}
+
+// The extension decides when it names a format, the first line otherwise. Only
+// JSON and CSV exports keep every field, so YAML and Markdown are turned away
+// rather than read as a CSV with one odd column.
+fn export_file_format(path: &str, raw: &str) -> Result<OutputFormat, String> {
+    let first = raw.trim_start().lines().next().unwrap_or_default();
+    let format = infer_format_from_path(path).unwrap_or(
+        if first.starts_with('[') || first.starts_with('{') {
+            OutputFormat::Json
+        } else if first.starts_with("---") {
+            OutputFormat::Yaml
+        } else if first.starts_with('|') || first.starts_with('#') {
+            OutputFormat::Markdown
+        } else {
+            OutputFormat::Csv
+        },
+    );
+    match format {
+        OutputFormat::Json | OutputFormat::Csv => Ok(format),
+        OutputFormat::Yaml => Err("YAML exports can't be read back; use JSON or CSV".to_string()),
+        OutputFormat::Markdown => {
+            Err("Markdown exports can't be read back; use JSON or CSV".to_string())
+        }
+        _ => Err("only JSON and CSV exports can be read back".to_string()),
+    }
+}
+
+// CSV loses the JSON types, so cells are guessed back: empty is null, digits are
+// numbers, everything else a string. That is enough for the byte columns and keys.
+fn parse_csv_export(raw: &str) -> Result<Vec<HashMap<String, Field>>, String> {
+    let mut lines = parse_csv(raw)?.into_iter();
+    let header = lines.next().unwrap_or_default();
+    lines
+        .enumerate()
+        .map(|(idx, cells)| {
+            if cells.len() != header.len() {
+                return Err(format!(
+                    "CSV row {} has {} cells, the header has {}",
+                    idx + 2,
+                    cells.len(),
+                    header.len()
+                ));
+            }
+            Ok(header
+                .iter()
+                .cloned()
+                .zip(cells.into_iter().map(|cell| {
+                    if cell.is_empty() {
+                        Field::Null
+                    } else if let Ok(v) = cell.parse::<u64>() {
+                        Field::Uint(v)
+                    } else if let Ok(v) = cell.parse::<i64>() {
+                        Field::Int(v)
+                    } else {
+                        Field::Str(cell)
+                    }
+                }))
+                .collect())
+        })
+        .collect()
+}
+
+// The reverse of escape_csv: quoted cells may hold commas, doubled quotes, newlines.
+fn parse_csv(raw: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut cell = String::new();
+    let mut chars = raw.chars().peekable();
+    let mut quoted = false;
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.next_if_eq(&'"').is_some() => cell.push('"'),
+            (true, '"') => quoted = false,
+            (true, c) => cell.push(c),
+            (false, '"') if cell.is_empty() => quoted = true,
+            (false, ',') => row.push(std::mem::take(&mut cell)),
+            (false, '\r') => {}
+            (false, '\n') => {
+                row.push(std::mem::take(&mut cell));
+                rows.push(std::mem::take(&mut row));
+            }
+            (false, c) => cell.push(c),
+        }
+    }
+    if quoted {
+        return Err("unterminated quoted CSV cell".to_string());
+    }
+    if !cell.is_empty() || !row.is_empty() {
+        row.push(cell);
+        rows.push(row);
+    }
+    Ok(rows)
+}
+
+// --percent: both views share the same totals, so a pane's share is the sum of
+// its processes'. A metric that is zero everywhere (physical without vmmap) has
+// no share.
+fn apply_shares(rows: &mut [ProcRecord], panes: &mut [PaneRecord]) {
+    let share = |v: u64, total: u64| (total > 0).then(|| v as f64 * 100.0 / total as f64);
+    let swap = rows.iter().map(|r| r.swap_bytes).sum::<u64>();
+    let physical = rows.iter().map(|r| r.physical_bytes).sum::<u64>();
+    for row in rows.iter_mut() {
+        row.swap_pct = share(row.swap_bytes, swap);
+        row.physical_pct = share(row.physical_bytes, physical);
+    }
+    for pane in panes.iter_mut() {
+        pane.swap_pct = share(pane.swap_bytes, swap);
+        pane.physical_pct = share(pane.physical_bytes, physical);
+    }
+}
+
+fn pct_cell(pct: Option<f64>) -> String {
+    pct.map_or("-".to_string(), |v| format!("{v:.1}%"))
+}
+
+// "Swap %" and "Physical %" follow their byte columns, wherever earlier opt-in
+// columns left them. Rows are sorted by swap, so the running total of its share
+// ("Cum. swap %") says how far down the table a given share of swap reaches;
+// subtotal rows (a None entry) leave it blank.
+fn insert_share_columns(
+    table: &mut TableLayout,
+    cells: Vec<Option<(Option<f64>, Option<f64>)>>,
+    opts: &RenderOptions,
+) {
+    let mut running = None;
+    let cumulative = cells
+        .iter()
+        .map(|row| match row {
+            Some((swap, _)) => {
+                running = swap.map(|v| running.unwrap_or(0.0) + v).or(running);
+                (pct_cell(running), None)
+            }
+            None => (String::new(), None),
+        })
+        .collect::<Vec<_>>();
+    let (swap, physical): (Vec<_>, Vec<_>) = cells
+        .into_iter()
+        .map(|row| {
+            let (swap, physical) = row.unwrap_or_default();
+            ((pct_cell(swap), None), (pct_cell(physical), None))
+        })
+        .unzip();
+    let mut columns = Vec::new();
+    if opts.percent {
+        columns.push(("Swap", "Swap %", swap));
+        columns.push(("Physical", "Physical %", physical));
+    }
+    if opts.cumulative {
+        columns.push((
+            if opts.percent { "Swap %" } else { "Swap" },
+            "Cum. swap %",
+            cumulative,
+        ));
+    }
+    for (column, header, cells) in columns {
+        if let Some(at) = table.header.iter().position(|h| *h == column) {
+            insert_table_column(table, at + 1, header, cells);
+        }
+    }
+}
+
+// Processes match on PID and command (a recycled PID counts as new), panes on
+// their target.
+fn apply_since_last(
+    rows: &mut [ProcRecord],
+    panes: &mut [PaneRecord],
+    baseline: &[HashMap<String, Field>],
+) {
+    let uint = |record: &HashMap<String, Field>, key: &str| match record.get(key) {
+        Some(Field::Uint(v)) => *v as i64,
+        _ => 0,
+    };
+    let text = |record: &HashMap<String, Field>, key: &str| match record.get(key) {
+        Some(Field::Str(v)) => Some(v.clone()),
+        _ => None,
+    };
+    let delta = |record: &HashMap<String, Field>, swap: u64, physical: u64, rss: u64| {
+        (
+            swap as i64 - uint(record, "swap_bytes"),
+            physical as i64 - uint(record, "physical_bytes"),
+            rss as i64 - uint(record, "rss_bytes"),
+        )
+    };
+    for row in rows {
+        row.since_last = baseline
+            .iter()
+            .find(|r| {
+                r.get("pid") == Some(&Field::Uint(row.pid as u64))
+                    && text(r, "command").as_deref() == Some(row.command.as_str())
+            })
+            .map(|r| delta(r, row.swap_bytes, row.physical_bytes, row.rss_bytes));
+    }
+    for pane in panes {
+        pane.since_last = baseline
+            .iter()
+            .find(|r| {
+                !r.contains_key("pid")
+                    && text(r, "tmux_target").as_deref() == Some(pane.tmux_target.as_str())
+            })
+            .map(|r| delta(r, pane.swap_bytes, pane.physical_bytes, pane.rss_bytes));
+    }
+}
+
+fn delta_fields(delta: Option<(i64, i64, i64)>) -> [(&'static str, Field); 3] {
+    let field =
+        |pick: fn((i64, i64, i64)) -> i64| delta.map_or(Field::Null, |d| Field::Int(pick(d)));
+    [
+        ("swap_delta_bytes", field(|d| d.0)),
+        ("physical_delta_bytes", field(|d| d.1)),
+        ("rss_delta_bytes", field(|d| d.2)),
+    ]
+}
+
+// Only a report that --since-last compared against an export carries the deltas.
+fn without_deltas(mut fields: Vec<(&'static str, Field)>) -> Vec<(&'static str, Field)> {
+    let deltas = delta_fields(None).map(|(name, _)| name);
+    fields.retain(|(name, _)| !deltas.contains(name));
+    fields
+}
+
+// Cells for the ΔSwap/ΔPhysical/ΔRSS columns; rows missing from the last export say "new".
+fn delta_cells(delta: Option<(i64, i64, i64)>) -> [String; 3] {
+    let Some((swap, physical, rss)) = delta else {
+        return ["new".to_string(), "new".to_string(), "new".to_string()];
+    };
+    [swap, physical, rss].map(|v| match v {
+        0 => "0 B".to_string(),
+        v if v < 0 => format!("-{}", human_bytes(v.unsigned_abs())),
+        v => format!("+{}", human_bytes(v as u64)),
+    })
+}
+
+const DELTA_HEADERS: [&str; 3] = ["ΔSwap", "ΔPhysical", "ΔRSS"];
+
+fn insert_delta_columns(table: &mut TableLayout, at: usize, cells: Vec<[String; 3]>) {
+    let mut columns: [Vec<(String, Option<&'static str>)>; 3] = Default::default();
+    for row in cells {
+        for (column, cell) in columns.iter_mut().zip(row) {
+            column.push((cell, None));
+        }
+    }
+    let [swap, physical, rss] = columns;
+    let [swap_header, physical_header, rss_header] = DELTA_HEADERS;
+    insert_table_column(table, at, rss_header, rss);
+    insert_table_column(table, at, physical_header, physical);
+    insert_table_column(table, at, swap_header, swap);
+}
+
+fn run_tmux_view(cli: &Cli, opts: &RenderOptions) -> Result<(), String> {
+    let tmux = collect_tmux(cli)?;
+    emit(cli, opts, tmux.len(), |fmt, o| render_tmux(&tmux, fmt, o))
+}
+
+// One server row (and its clients) per server the panes were listed from: the
+// default one, or each found by socket discovery.
+fn collect_tmux(cli: &Cli) -> Result<Vec<TmuxRecord>, String> {
+    let panes = list_tmux_panes().unwrap_or_default();
+    let mut servers = panes.iter().map(|p| p.socket.clone()).collect::<Vec<_>>();
+    servers.sort();
+    servers.dedup();
+    if servers.is_empty() {
+        servers.push(None);
+    }
+    let start = capture_start(cli.history_max_lines);
+    let mut rows = Vec::new();
+    for socket in servers {
+        let Some(server_pid) = tmux_cmd(socket.as_deref(), &["display-message", "-p", "#{pid}"])
+            .ok()
+            .and_then(|out| out.trim().parse::<i32>().ok())
+        else {
+            continue;
+        };
+        let (pane_history_bytes, pane_count) = if cli.no_history_bytes {
+            (None, None)
+        } else {
+            let on_server = panes
+                .iter()
+                .filter(|p| p.socket == socket)
+                .collect::<Vec<_>>();
+            let bytes = parallel_map(&on_server, cli.max_concurrent, |pane| {
+                capture_pane(&pane.target, pane.socket.as_deref(), &start).map_or(0, |out| {
+                    sampled_history_bytes(&out, pane.history_size, cli.history_max_lines)
+                })
+            });
+            (Some(bytes.iter().sum()), Some(on_server.len()))
+        };
+        let clients = match tmux_cmd(
+            socket.as_deref(),
+            &[
+                "list-clients",
+                "-F",
+                "#{client_pid}\t#{client_session}\t#{client_tty}\t#{client_flags}",
+            ],
+        ) {
+            Ok(raw) => parse_tmux_clients(&raw),
+            Err(e) => {
+                warn(format!("tmux clients unavailable: {e}"));
+                Vec::new()
+            }
+        };
+        rows.push(TmuxRecord {
+            kind: "server",
+            pid: server_pid,
+            pane_history_bytes,
+            pane_count,
+            ..TmuxRecord::default()
+        });
+        rows.extend(clients);
+    }
+    if rows.is_empty() {
+        return Err("no tmux server is running".to_string());
+    }
+    let cache = CollectCache::default();
+    let samples = parallel_map(&rows, cli.max_concurrent, |row| {
+        sample_process(cli, row.pid, &cache)
+    });
+    // Clients that detached mid-scan are dropped.
+    Ok(rows
+        .into_iter()
+        .zip(samples)
+        .filter_map(|(row, sample)| {
+            let sample = sample?;
+            Some(TmuxRecord {
+                swap_bytes: sample.swap_bytes,
+                physical_bytes: sample.physical_bytes,
+                rss_bytes: sample.rss_bytes,
+                vmmap_skipped: sample.vmmap_skipped || sample.vmmap.is_none(),
+                ..row
+            })
+        })
+        .collect())
+}
+
+// One attached client per line: pid, session, tty, flags. Monitor clients (our
+// own control client among them) are left out.
+fn parse_tmux_clients(raw: &str) -> Vec<TmuxRecord> {
+    raw.lines()
+        .filter_map(|line| {
+            let mut parts = line.split('\t');
+            let pid = parts.next()?.trim().parse::<i32>().ok()?;
+            let text = |part: Option<&str>| part.filter(|p| !p.is_empty()).map(str::to_string);
+            let (session, tty) = (text(parts.next()), text(parts.next()));
+            if is_monitor_client(parts.next().unwrap_or("")) {
+                return None;
+            }
+            Some(TmuxRecord {
+                kind: "client",
+                pid,
+                session,
+                tty,
+                ..TmuxRecord::default()
+            })
+        })
+        .collect()
+}
+
+fn run_tune(cli: &Cli) -> Result<(), String> {
+    let rows = collect_rows(cli, &mut CollectCache::default())?;
+    let panes = aggregate_by_pane(&rows);
+    let (actions, dry_run) = plan_tune(&panes, cli.history_target_bytes, cli.apply, cli.dry_run);
+    if actions.is_empty() {
+        log_event(
+            "info",
+            "tune: every session already fits the history target",
+            None,
+        );
+        return Ok(());
+    }
+
+    if !confirm_actions_stdin(&actions, dry_run, cli.yes)? {
+        return Ok(());
+    }
+    let failures = execute_actions(&actions, dry_run);
+    if !cli.apply && !cli.dry_run {
+        log_event(
+            "info",
+            "tune: re-run with --apply to change history-limit",
+            None,
+        );
+    }
+    if failures > 0 {
+        return Err(format!("tune failed for {failures} session(s)"));
+    }
+    Ok(())
+}
+
+// The history-limit changes tune would make, and whether they only print:
+// without --apply, tune previews exactly like --dry-run.
+fn plan_tune(
+    panes: &[PaneRecord],
+    target_bytes: u64,
+    apply: bool,
+    dry_run: bool,
+) -> (Vec<PlannedAction>, bool) {
+    let actions = advise_history_limits(panes, target_bytes)
+        .iter()
+        .filter_map(|advice| {
+            Some(PlannedAction {
+                action: "tune",
+                target: format!("session {}", advice.session),
+                program: "tmux",
+                args: advice_args(advice)?,
+                metric: "history_limit",
+                before: advice.current_limit.max(0) as u64,
+            })
+        })
+        .collect();
+    (actions, dry_run || !apply)
+}
+
+const DEFAULT_LISTEN: &str = "127.0.0.1:7878";
+// Requests this close together share one collection (the dashboard fetches two endpoints).
+const SERVE_REUSE: Duration = Duration::from_secs(2);
+const SERVE_POLL: Duration = Duration::from_millis(50);
+const DASHBOARD_HTML: &str = include_str!("dashboard.html");
+
+// One request at a time is plenty for a team dashboard and keeps collections from overlapping.
+fn run_serve(cli: &Cli, config: &Config) -> Result<(), String> {
+    let listener = TcpListener::bind(&cli.listen)
+        .map_err(|e| format!("failed to listen on {}: {e}", cli.listen))?;
+    // Non-blocking accept so SIGINT/SIGTERM are noticed between requests.
+    listener
+        .set_nonblocking(true)
+        .map_err(|e| format!("failed to configure listener: {e}"))?;
+    install_signal_handlers();
+    log_event("info", &format!("serving on http://{}/", cli.listen), None);
+    let mut cache = persistent_cache();
+    let mut last: Option<(Instant, Vec<ProcRecord>, Vec<PaneRecord>)> = None;
+    // Collections served so far and how long the latest took, for /metrics.
+    let mut collections = 0u64;
+    let mut last_collection: Option<Duration> = None;
+    while !interrupted() {
+        let mut stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(SERVE_POLL);
+                continue;
+            }
+            Err(_) => continue,
+        };
+        let _ = stream.set_nonblocking(false);
+        let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+        let path = match read_request_path(&stream) {
+            Ok(path) => path,
+            Err(status) => {
+                let _ = write_response(&mut stream, status, "text/plain", status);
+                continue;
+            }
+        };
+        let result = match path.as_str() {
+            "/" | "/index.html" => write_response(
+                &mut stream,
+                "200 OK",
+                "text/html; charset=utf-8",
+                DASHBOARD_HTML,
+            ),
+            "/api/version" => write_response(
+                &mut stream,
+                "200 OK",
+                "application/json",
+                &render_version(OutputFormat::Json),
+            ),
+            "/metrics" => write_response(
+                &mut stream,
+                "200 OK",
+                "text/plain; version=0.0.4",
+                &render_prometheus(&self_metrics(), collections, last_collection),
+            ),
+            "/api/processes" | "/api/panes" => {
+                if last
+                    .as_ref()
+                    .is_none_or(|(at, _, _)| at.elapsed() > SERVE_REUSE)
+                {
+                    let started = Instant::now();
+                    let collected = collect_scored(cli, config, &mut cache);
+                    collections += 1;
+                    last_collection = Some(started.elapsed());
+                    match collected {
+                        Ok((rows, panes)) => last = Some((Instant::now(), rows, panes)),
+                        Err(e) => {
+                            let _ = write_response(
+                                &mut stream,
+                                "500 Internal Server Error",
+                                "text/plain",
+                                &e,
+                            );
+                            continue;
+                        }
+                    }
+                }
+                let (_, rows, panes) = last.as_ref().expect("collected above");
+                let body = wrap_envelope(
+                    cli,
+                    OutputFormat::Json,
+                    if path == "/api/panes" {
+                        render_fields_json(panes, |p| {
+                            without_deltas(pin_schema(
+                                pane_fields(p),
+                                PANE_SCHEMA,
+                                cli.schema_version,
+                            ))
+                        })
+                    } else {
+                        render_fields_json(rows, |r| {
+                            without_deltas(pin_schema(
+                                process_fields(r),
+                                PROCESS_SCHEMA,
+                                cli.schema_version,
+                            ))
+                        })
+                    },
+                );
+                write_response(&mut stream, "200 OK", "application/json", &body)
+            }
+            _ => write_response(&mut stream, "404 Not Found", "text/plain", "404 Not Found"),
+        };
+        if let Err(e) = result {
+            warn(format!("failed answering {path}: {e}"));
+        }
+    }
+    Ok(())
+}
+
+// Prometheus text exposition of the server's own cost, so a scraper can alert
+// when the monitor starts weighing on the machine it watches.
+fn render_prometheus(
+    metrics: &SelfMetrics,
+    collections: u64,
+    last_collection: Option<Duration>,
+) -> String {
+    let mut samples = vec![
+        (
+            "collections_total",
+            "counter",
+            "Collections run to answer API requests.",
+            collections as f64,
+        ),
+        (
+            "subprocesses_total",
+            "counter",
+            "Subprocesses (tmux, ps, vmmap, lsof, ...) launched since start.",
+            metrics.subprocesses as f64,
+        ),
+        (
+            "uptime_seconds",
+            "gauge",
+            "Seconds since the server started.",
+            metrics.wall.as_secs_f64(),
+        ),
+    ];
+    if let Some(took) = last_collection {
+        samples.push((
+            "last_collection_seconds",
+            "gauge",
+            "Wall time of the most recent collection.",
+            took.as_secs_f64(),
+        ));
+    }
+    if let Some(bytes) = metrics.peak_rss_bytes {
+        samples.push((
+            "peak_rss_bytes",
+            "gauge",
+            "Peak resident set size of the server process.",
+            bytes as f64,
+        ));
+    }
+    let mut out = String::new();
+    for (name, kind, help, value) in samples {
+        let _ = writeln!(out, "# HELP opencode_tmux_mem_{name} {help}");
+        let _ = writeln!(out, "# TYPE opencode_tmux_mem_{name} {kind}");
+        let _ = writeln!(out, "opencode_tmux_mem_{name} {value}");
+    }
+    out
+}
+
+// Reads the request line and drains headers; only GET is served.
+fn read_request_path(stream: &TcpStream) -> Result<String, &'static str> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader
+        .read_line(&mut request_line)
+        .map_err(|_| "400 Bad Request")?;
+    let mut header = String::new();
+    while reader.read_line(&mut header).unwrap_or(0) > 2 {
+        header.clear();
+    }
+    parse_request_line(&request_line)
+}
+
+fn parse_request_line(line: &str) -> Result<String, &'static str> {
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err("400 Bad Request");
+    };
+    if method != "GET" {
+        return Err("405 Method Not Allowed");
+    }
+    Ok(target.split('?').next().unwrap_or(target).to_string())
+}
+
+fn write_response(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+const SERVICE_LABEL: &str = "io.github.dmoliveira.opencode-tmux-mem";
+const SERVICE_EVERY: &str = "5m";
+// install-service's own flags; everything else is passed on to the service.
+const SERVICE_OWN_FLAGS: &[&str] = &["--apply", "--dry-run", "-y", "--yes"];
+const SERVICE_OWN_OPTIONS: &[&str] = &["--service-manager", "--service-mode", "--key"];
+const SERVICE_PATH_OPTIONS: &[&str] = &[
+    "--record",
+    "--export",
+    "--export-append",
+    "--config",
+    "--policy",
+    "--db",
+    "--cwd",
+    "--open-file",
+];
+
+// Prints the unit; --apply writes it and loads it with launchctl or systemctl.
+fn run_install_service(cli: &Cli) -> Result<(), String> {
+    let exe = env::current_exe()
+        .map_err(|e| format!("failed locating the opencode-tmux-mem binary: {e}"))?;
+    let args = service_command(cli.service_mode, &cli.service_args, state_dir());
+    let path_env = env::var("PATH").unwrap_or_default();
+    let name = service_name(cli.service_mode);
+    let (unit, path, commands) = match cli.service_manager {
+        ServiceManager::Launchd => {
+            let home = env::var("HOME").map_err(|_| "HOME is not set".to_string())?;
+            let path = format!("{home}/Library/LaunchAgents/{SERVICE_LABEL}.{name}.plist");
+            let log = format!("{home}/Library/Logs/opencode-tmux-mem-{name}.log");
+            let unit = launchd_plist(
+                &format!("{SERVICE_LABEL}.{name}"),
+                &exe.to_string_lossy(),
+                &args,
+                &path_env,
+                cli.service_mode,
+                &log,
+            );
+            let load = vec!["load".to_string(), "-w".to_string(), path.clone()];
+            (unit, path, vec![("launchctl", load)])
+        }
+        ServiceManager::Systemd => {
+            let dir = match env::var("XDG_CONFIG_HOME") {
+                Ok(dir) if !dir.is_empty() => dir,
+                _ => format!(
+                    "{}/.config",
+                    env::var("HOME").map_err(|_| "HOME is not set".to_string())?
+                ),
+            };
+            let unit_name = format!("opencode-tmux-mem-{name}.service");
+            let path = format!("{dir}/systemd/user/{unit_name}");
+            let unit = systemd_unit(&exe.to_string_lossy(), &args, &path_env, cli.service_mode);
+            let user = |args: &[&str]| {
+                std::iter::once("--user")
+                    .chain(args.iter().copied())
+                    .map(str::to_string)
+                    .collect::<Vec<_>>()
+            };
+            let commands = vec![
+                ("systemctl", user(&["daemon-reload"])),
+                ("systemctl", user(&["enable", "--now", &unit_name])),
+            ];
+            (unit, path, commands)
+        }
+    };
+
+    if !cli.apply {
+        print!("{unit}");
+        log_event(
+            "info",
+            &format!("install-service: re-run with --apply to install it as {path}"),
+            None,
+        );
+        return Ok(());
+    }
+    let actions = commands
+        .into_iter()
+        .map(|(program, args)| PlannedAction {
+            action: "install-service",
+            target: path.clone(),
+            program,
+            args,
+            metric: "service",
+            before: 0,
+        })
+        .collect::<Vec<_>>();
+    if !confirm_actions_stdin(&actions, cli.dry_run, cli.yes)? {
+        return Ok(());
+    }
+    if cli.dry_run {
+        log_event("info", &format!("dry-run: write {path}"), None);
+    } else {
+        // The service's recording lives in the state dir, which may not exist yet.
+        for dir in [
+            std::path::Path::new(&path).parent(),
+            state_dir().as_deref().map(std::path::Path::new),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            fs::create_dir_all(dir)
+                .map_err(|e| format!("failed creating '{}': {e}", dir.display()))?;
+        }
+        write_atomic(&path, &unit).map_err(|e| format!("failed writing '{path}': {e}"))?;
+        log_event("info", &format!("wrote {path}"), None);
+    }
+    let failures = execute_actions(&actions, cli.dry_run);
+    if failures > 0 {
+        return Err(format!("install-service: {failures} command(s) failed"));
+    }
+    Ok(())
+}
+
+fn service_name(mode: ServiceMode) -> &'static str {
+    match mode {
+        ServiceMode::Record => "record",
+        ServiceMode::Serve => "serve",
+    }
+}
+
+// The flags install-service (or install-keybinding) was given, minus its own, with
+// relative paths made absolute because the service or popup starts elsewhere.
+fn forwarded_service_args(args: &[String], cwd: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        let arg = args[i].as_str();
+        if SERVICE_OWN_FLAGS.contains(&arg) {
+            i += 1;
+            continue;
+        }
+        if SERVICE_OWN_OPTIONS.contains(&arg) {
+            i += 2;
+            continue;
+        }
+        out.push(arg.to_string());
+        if SERVICE_PATH_OPTIONS.contains(&arg) {
+            if let Some(value) = args.get(i + 1) {
+                out.push(if value.starts_with('/') {
+                    value.clone()
+                } else {
+                    format!("{cwd}/{value}")
+                });
+                i += 1;
+            }
+        }
+        i += 1;
+    }
+    out
+}
+
+// Record mode defaults to --every 5m into the state dir unless the flags say otherwise.
+fn service_command(mode: ServiceMode, forwarded: &[String], state: Option<String>) -> Vec<String> {
+    let has = |flag: &str| forwarded.iter().any(|a| a == flag);
+    let mut args = Vec::new();
+    match mode {
+        ServiceMode::Serve => args.push("serve".to_string()),
+        ServiceMode::Record => args.push("report".to_string()),
+    }
+    args.extend(forwarded.iter().cloned());
+    if mode == ServiceMode::Record {
+        if !has("--every") {
+            args.extend(["--every".to_string(), SERVICE_EVERY.to_string()]);
+        }
+        if let (false, Some(state)) = (has("--record"), state) {
+            args.extend(["--record".to_string(), format!("{state}/mem.jsonl")]);
+        }
+    }
+    args
+}
+
+fn launchd_plist(
+    label: &str,
+    exe: &str,
+    args: &[String],
+    path_env: &str,
+    mode: ServiceMode,
+    log: &str,
+) -> String {
+    let mut program = format!("    <string>{}</string>\n", escape_html(exe));
+    for arg in args {
+        let _ = writeln!(program, "    <string>{}</string>", escape_html(arg));
+    }
+    // The recorder's report goes nowhere; only warnings are worth keeping.
+    let stdout = match mode {
+        ServiceMode::Record => "/dev/null",
+        ServiceMode::Serve => log,
+    };
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">
+<plist version=\"1.0\">
+<dict>
+  <key>Label</key>
+  <string>{}</string>
+  <key>ProgramArguments</key>
+  <array>
+{program}  </array>
+  <key>EnvironmentVariables</key>
+  <dict>
+    <key>PATH</key>
+    <string>{}</string>
+  </dict>
+  <key>RunAtLoad</key>
+  <true/>
+  <key>KeepAlive</key>
+  <true/>
+  <key>StandardOutPath</key>
+  <string>{}</string>
+  <key>StandardErrorPath</key>
+  <string>{}</string>
+</dict>
+</plist>
+",
+        escape_html(label),
+        escape_html(path_env),
+        escape_html(stdout),
+        escape_html(log),
+    )
+}
+
+fn systemd_unit(exe: &str, args: &[String], path_env: &str, mode: ServiceMode) -> String {
+    let exec = std::iter::once(exe)
+        .chain(args.iter().map(String::as_str))
+        .map(systemd_quote)
+        .collect::<Vec<_>>()
+        .join(" ");
+    let stdout = match mode {
+        ServiceMode::Record => "StandardOutput=null\n",
+        ServiceMode::Serve => "",
+    };
+    format!(
+        "[Unit]
+Description=opencode-tmux-mem ({})
+
+[Service]
+ExecStart={exec}
+Environment={}
+Restart=on-failure
+RestartSec=30
+{stdout}
+[Install]
+WantedBy=default.target
+",
+        service_name(mode),
+        systemd_quote(&format!("PATH={path_env}")),
+    )
+}
+
+// systemd expands %specifiers and $variables, so both are escaped, not just quotes.
+fn systemd_quote(s: &str) -> String {
+    let escaped = s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%")
+        .replace('$', "$$");
+    format!("\"{escaped}\"")
+}
+
+fn run_doctor(cli: &Cli) -> Result<(), String> {
+    let checks = doctor_checks(cli);
+    emit(cli, &render_options(cli), checks.len(), |fmt, o| {
+        render_doctor(&checks, fmt, o)
+    })?;
+    match checks
+        .iter()
+        .filter(|c| c.status == CheckStatus::Fail)
+        .count()
+    {
+        0 => Ok(()),
+        n => Err(format!("doctor found {n} problem(s)")),
+    }
+}
+
+fn check(name: &'static str, status: CheckStatus, detail: String, hint: &str) -> DoctorCheck {
+    DoctorCheck {
+        name,
+        status,
+        detail,
+        hint: hint.to_string(),
+    }
+}
+
+// A tool counts as present when it runs at all; exit status is the caller's business.
+fn tool_runs(program: &str, args: &[&str]) -> io::Result<Output> {
+    command_output(program, args)
+}
+
+fn doctor_checks(cli: &Cli) -> Vec<DoctorCheck> {
+    use CheckStatus::{Fail, Pass, Warn};
+    let mut checks = Vec::new();
+
+    checks.push(match run_cmd("tmux", &["-V"]) {
+        Ok(version) => check("tmux", Pass, version.trim().to_string(), ""),
+        Err(e) => check(
+            "tmux",
+            Fail,
+            e.to_string(),
+            "install tmux (brew install tmux / apt install tmux)",
+        ),
+    });
+    checks.push(match list_tmux_panes() {
+        Ok(panes) => {
+            let mut sessions = panes
+                .iter()
+                .map(|p| target_session(&p.target))
+                .collect::<Vec<_>>();
+            sessions.sort_unstable();
+            sessions.dedup();
+            let mut sockets = panes
+                .iter()
+                .filter_map(|p| p.socket.as_deref())
+                .collect::<Vec<_>>();
+            sockets.dedup();
+            let mut detail = format!("{} pane(s) in {} session(s)", panes.len(), sessions.len());
+            if !sockets.is_empty() {
+                let _ = write!(detail, " via {}", sockets.join(", "));
+            }
+            check("tmux server", Pass, detail, "")
+        }
+        Err(e) => check(
+            "tmux server",
+            Warn,
+            e.to_string().trim().to_string(),
+            "start tmux, or run from the same user and TMUX_TMPDIR as the server; without it processes have no pane",
+        ),
+    });
+    for (program, args, hint) in [
+        (
+            "pgrep",
+            &["-x", "opencode-tmux-mem-doctor"][..],
+            "install procps (Linux); pgrep ships with macOS",
+        ),
+        (
+            "ps",
+            &["-p", "1", "-o", "pid="][..],
+            "install procps (Linux); ps ships with macOS",
+        ),
+    ] {
+        checks.push(match tool_runs(program, args) {
+            Ok(_) => check(program, Pass, "found".to_string(), ""),
+            Err(e) => check(program, Fail, e.to_string(), hint),
+        });
+    }
+
+    let vmmap = tool_runs("vmmap", &["--help"]).is_ok();
+    let proc_fs = std::path::Path::new("/proc/self/status").exists();
+    checks.push(match (vmmap, proc_fs) {
+        (true, _) => check("memory source", Pass, "vmmap".to_string(), ""),
+        (false, true) => check(
+            "memory source",
+            Pass,
+            "/proc (no vmmap: Swap and Physical read 0, RSS is exact)".to_string(),
+            "",
+        ),
+        (false, false) => check(
+            "memory source",
+            Fail,
+            "neither vmmap nor /proc is available".to_string(),
+            "on macOS install the Xcode command line tools (xcode-select --install)",
+        ),
+    });
+
+    let mut ppid_cache = HashMap::new();
+    let sample = discover_pids(cli).ok().and_then(|pids| {
+        pids.into_iter()
+            .find(|&pid| !is_self_or_child(pid, &mut ppid_cache))
+    });
+    checks.push(match sample {
+        None => check(
+            "sample PID",
+            Warn,
+            format!(
+                "no process matches {}",
+                cli.process_patterns
+                    .iter()
+                    .map(|(_, pattern)| format!("'{pattern}'"))
+                    .chain(cli.ports.iter().map(|port| format!("port {port}")))
+                    .collect::<Vec<_>>()
+                    .join(" or ")
+            ),
+            "start the app or pass --process / --match-mode full",
+        ),
+        Some(pid) if vmmap => match vmmap_memory(pid) {
+            Ok(_) => check("sample PID", Pass, format!("vmmap read PID {pid}"), ""),
+            Err(e) => check(
+                "sample PID",
+                Fail,
+                format!("vmmap failed on PID {pid}: {}", e.to_string().trim()),
+                "run as the process owner, or with sudo for other users' processes",
+            ),
+        },
+        Some(pid) => match fs::read_to_string(format!("/proc/{pid}/status")) {
+            Ok(_) => check("sample PID", Pass, format!("/proc read PID {pid}"), ""),
+            Err(e) => check(
+                "sample PID",
+                Fail,
+                format!("/proc/{pid}/status: {e}"),
+                "run as the process owner, or with sudo for other users' processes",
+            ),
+        },
+    });
+    checks
+}
+
+fn run_leaks(cli: &Cli) -> Result<(), String> {
+    let path = cli.db_path.as_deref().expect("checked in check_cli");
+    let samples = load_recording(path)?;
+    let leaks = detect_leaks(&samples, cli.leak_threshold_bytes);
+    emit(cli, &render_options(cli), leaks.len(), |fmt, o| {
+        render_leaks(&leaks, fmt, o)
+    })
+}
+
+const DEFAULT_VERIFY_TOLERANCE_PCT: f64 = 10.0;
+
+// RSS counts shared pages and misses compressed memory, so it is shown for
+// context only; the check is whether vmmap and footprint(1), which both report
+// the kernel's physical footprint, agree.
+fn run_verify(cli: &Cli) -> Result<(), String> {
+    let mut pids = discover_pids(cli).map_err(|e| format!("failed to discover processes: {e}"))?;
+    if !cli.include_self {
+        let mut ppid_cache = HashMap::new();
+        pids.retain(|&pid| !is_self_or_child(pid, &mut ppid_cache));
+    }
+    let mut rows = parallel_map(&pids, cli.max_concurrent, |&pid| {
+        verify_process(
+            pid,
+            ps_command(pid, cli.wide).unwrap_or_else(|_| "<unavailable>".to_string()),
+            ps_rss_bytes(pid).unwrap_or(0),
+            vmmap_memory(pid).ok().map(|v| v.physical_bytes),
+            footprint_tool(pid),
+            cli.verify_tolerance_pct,
+        )
+    });
+    rows.sort_by(|a, b| {
+        (b.status == "mismatch")
+            .cmp(&(a.status == "mismatch"))
+            .then_with(|| {
+                b.spread_pct
+                    .unwrap_or(0.0)
+                    .total_cmp(&a.spread_pct.unwrap_or(0.0))
+            })
+            .then_with(|| a.pid.cmp(&b.pid))
+    });
+    let tolerance = cli.verify_tolerance_pct;
+    emit(cli, &render_options(cli), rows.len(), |fmt, o| {
+        render_verify(&rows, tolerance, fmt, o)
+    })
+}
+
+fn verify_process(
+    pid: i32,
+    command: String,
+    rss_bytes: u64,
+    vmmap_bytes: Option<u64>,
+    footprint_tool_bytes: Option<u64>,
+    tolerance_pct: f64,
+) -> VerifyRecord {
+    let spread_pct = match (vmmap_bytes, footprint_tool_bytes) {
+        (Some(a), Some(b)) if a.max(b) > 0 => Some(a.abs_diff(b) as f64 * 100.0 / a.max(b) as f64),
+        (Some(_), Some(_)) => Some(0.0),
+        _ => None,
+    };
+    VerifyRecord {
+        pid,
+        command,
+        rss_bytes,
+        vmmap_bytes,
+        footprint_tool_bytes,
+        spread_pct,
+        status: match spread_pct {
+            None => "unverified",
+            Some(pct) if pct > tolerance_pct => "mismatch",
+            Some(_) => "ok",
+        },
+    }
+}
+
+// footprint(1) ships with macOS 10.15+; elsewhere the command is simply missing.
+fn footprint_tool(pid: i32) -> Option<u64> {
+    run_cmd("footprint", &["-p", &pid.to_string()])
+        .ok()
+        .and_then(|raw| parse_footprint_tool(&raw))
+}
+
+// "opencode [4242]: 64-bit    Footprint: 312 MB (16384 bytes per page)"
+fn parse_footprint_tool(raw: &str) -> Option<u64> {
+    let (_, rest) = raw.split_once("Footprint:")?;
+    let mut tokens = rest.split_whitespace();
+    let value = tokens.next()?.parse::<f64>().ok()?;
+    let unit = match tokens.next()? {
+        "B" => 1.0,
+        "KB" => 1024.0,
+        "MB" => 1024.0 * 1024.0,
+        "GB" => 1024.0 * 1024.0 * 1024.0,
+        _ => return None,
+    };
+    Some((value * unit) as u64)
+}
+
+fn run_history(cli: &Cli) -> Result<(), String> {
+    let samples = load_recording(cli.db_path.as_deref().unwrap_or_default())?;
+    if cli.per_process {
+        let trends = process_trends(&samples);
+        return emit(cli, &render_options(cli), trends.len(), |fmt, o| {
+            render_process_trends(&trends, fmt, o)
+        });
+    }
+    let trends = pane_trends(&samples);
+    emit(cli, &render_options(cli), trends.len(), |fmt, o| {
+        render_trends(&trends, fmt, o)
+    })
+}
+
+fn snapshot_dir() -> Result<String, String> {
+    state_dir()
+        .map(|dir| format!("{dir}/snapshots"))
+        .ok_or_else(|| "cannot locate a state directory: set HOME or XDG_STATE_HOME".to_string())
+}
+
+fn run_snapshot(cli: &Cli, config: &Config) -> Result<(), String> {
+    let dir = snapshot_dir()?;
+    let path = |name: &str| format!("{dir}/{name}.json");
+    match cli.snapshot.as_ref().expect("parsed with the command") {
+        SnapshotCommand::Save(name) => {
+            let path = path(name);
+            if std::path::Path::new(&path).exists() {
+                return Err(format!(
+                    "snapshot '{name}' already exists; pick another name or remove {path}"
+                ));
+            }
+            let (rows, _) = collect_scored(cli, config, &mut CollectCache::default())?;
+            let body = render_fields_json(&rows, process_fields);
+            fs::create_dir_all(&dir).map_err(|e| format!("failed creating '{dir}': {e}"))?;
+            write_atomic(
+                &path,
+                &envelope(&envelope_meta(cli), None, OutputFormat::Json, body),
+            )
+            .map_err(|e| format!("failed writing snapshot '{path}': {e}"))?;
+            println!(
+                "saved snapshot '{name}' ({} processes) to {path}",
+                rows.len()
+            );
+            Ok(())
+        }
+        SnapshotCommand::List => {
+            let mut snapshots = Vec::new();
+            // A missing directory just means nothing was saved yet.
+            for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+                let file = entry.file_name().to_string_lossy().to_string();
+                let Some(name) = file.strip_suffix(".json") else {
+                    continue;
+                };
+                match load_export_meta(&entry.path()) {
+                    Ok((meta, records)) => snapshots.push(snapshot_summary(name, &meta, &records)),
+                    Err(e) => warn(e),
+                }
+            }
+            snapshots.sort_by(|a, b| a.saved_at.cmp(&b.saved_at).then(a.name.cmp(&b.name)));
+            emit(cli, &render_options(cli), snapshots.len(), |fmt, o| {
+                render_snapshots(&snapshots, fmt, o)
+            })
+        }
+        SnapshotCommand::Diff(a, b) => {
+            let (meta_a, before) = load_export_meta(std::path::Path::new(&path(a)))?;
+            let (meta_b, after) = load_export_meta(std::path::Path::new(&path(b)))?;
+            let rows = diff_snapshots(&before, &after);
+            let summaries = [
+                snapshot_summary(a, &meta_a, &before),
+                snapshot_summary(b, &meta_b, &after),
+            ];
+            emit(cli, &render_options(cli), rows.len(), |fmt, o| {
+                render_snapshot_diff(&rows, &summaries, fmt, o)
+            })
+        }
+    }
+}
+
+// Snapshots and --envelope exports keep metadata on the "meta" line; bare exports have none.
+type ExportFile = (HashMap<String, Field>, Vec<HashMap<String, Field>>);
+
+fn load_export_meta(path: &std::path::Path) -> Result<ExportFile, String> {
+    let raw = fs::read_to_string(path)
+        .map_err(|e| format!("failed reading export '{}': {e}", path.display()))?;
+    let meta = raw
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("\"meta\":"))
+        .map(|line| parse_json_object(line.trim_end_matches(',')))
+        .transpose()
+        .map_err(|e| format!("failed reading export '{}': {e}", path.display()))?
+        .unwrap_or_default();
+    Ok((meta, load_export(path)?))
+}
+
+fn snapshot_summary(
+    name: &str,
+    meta: &HashMap<String, Field>,
+    records: &[HashMap<String, Field>],
+) -> SnapshotRecord {
+    let text = |key: &str| match meta.get(key) {
+        Some(Field::Str(v)) => v.clone(),
+        _ => "-".to_string(),
+    };
+    let sum = |key: &str| records.iter().map(|r| record_uint(r, key)).sum();
+    SnapshotRecord {
+        name: name.to_string(),
+        saved_at: text("generated_at"),
+        version: text("version"),
+        process_count: records.len(),
+        swap_bytes: sum("swap_bytes"),
+        physical_bytes: sum("physical_bytes"),
+        rss_bytes: sum("rss_bytes"),
+    }
+}
+
+fn record_uint(record: &HashMap<String, Field>, key: &str) -> u64 {
+    match record.get(key) {
+        Some(Field::Uint(v)) => *v,
+        _ => 0,
+    }
+}
+
+// PIDs change across an upgrade or restart, so snapshots compare pane by pane;
+// processes outside tmux share one "-" row.
+fn diff_snapshots(
+    before: &[HashMap<String, Field>],
+    after: &[HashMap<String, Field>],
+) -> Vec<SnapshotDiffRecord> {
+    let mut panes = Vec::<SnapshotDiffRecord>::new();
+    for (records, is_after) in [(before, false), (after, true)] {
+        for record in records {
+            let text = |key: &str| match record.get(key) {
+                Some(Field::Str(v)) => v.clone(),
+                _ => "-".to_string(),
+            };
+            let target = text("tmux_target");
+            let idx = match panes.iter().position(|p| p.tmux_target == target) {
+                Some(idx) => idx,
+                None => {
+                    panes.push(SnapshotDiffRecord {
+                        tmux_target: target,
+                        tmux_window_name: text("tmux_window"),
+                        ..SnapshotDiffRecord::default()
+                    });
+                    panes.len() - 1
+                }
+            };
+            let side = if is_after {
+                &mut panes[idx].after
+            } else {
+                &mut panes[idx].before
+            };
+            side.0 += 1;
+            side.1 += record_uint(record, "swap_bytes");
+            side.2 += record_uint(record, "physical_bytes");
+            side.3 += record_uint(record, "rss_bytes");
+        }
+    }
+    // Biggest change first, whichever direction.
+    panes.sort_by_key(|p| std::cmp::Reverse(snapshot_delta(p).2.unsigned_abs()));
+    panes
+}
+
+fn snapshot_delta(row: &SnapshotDiffRecord) -> (i64, i64, i64) {
+    (
+        row.after.1 as i64 - row.before.1 as i64,
+        row.after.2 as i64 - row.before.2 as i64,
+        row.after.3 as i64 - row.before.3 as i64,
+    )
+}
+
+fn run_merge(cli: &Cli) -> Result<(), String> {
+    if cli.merge_paths.is_empty() {
+        return Err("merge requires at least one export file".to_string());
+    }
+    let mut merged = Vec::<MergeRecord>::new();
+    for path in &cli.merge_paths {
+        let path = std::path::Path::new(path);
+        let (meta, records) = load_export_meta(path)?;
+        for record in merge_records(path, &meta, &records) {
+            // The same export passed twice (or copied between hosts' folders) counts
+            // once; without a host two files can't be told apart, so both stay.
+            if record.host != "-"
+                && merged
+                    .iter()
+                    .any(|m| m.host == record.host && m.timestamp == record.timestamp)
+            {
+                warn(format!(
+                    "{}: skipping duplicate of {} at {}",
+                    record.source, record.host, record.timestamp
+                ));
+                continue;
+            }
+            merged.push(record);
+        }
+    }
+    merged.sort_by(|a, b| (&a.host, &a.timestamp).cmp(&(&b.host, &b.timestamp)));
+    emit(cli, &render_options(cli), merged.len(), |fmt, o| {
+        render_merge(&merged, fmt, o)
+    })
+}
+
+// Host and time come from --envelope metadata; a recording's per-line "ts" splits
+// it into one row per sample. Without either the file's mtime stands in.
+fn merge_records(
+    path: &std::path::Path,
+    meta: &HashMap<String, Field>,
+    records: &[HashMap<String, Field>],
+) -> Vec<MergeRecord> {
+    let meta_text = |key: &str| match meta.get(key) {
+        Some(Field::Str(v)) => Some(v.clone()),
+        _ => None,
+    };
+    let host = meta_text("host").unwrap_or_else(|| "-".to_string());
+    let file_time = meta_text("generated_at").unwrap_or_else(|| {
+        let modified = fs::metadata(path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok());
+        format_utc_timestamp(modified.map_or(0, |d| d.as_secs()))
+    });
+    let mut out = Vec::<MergeRecord>::new();
+    let mut pane_rss = Vec::<HashMap<String, u64>>::new();
+    for record in records {
+        let timestamp = match record.get("ts") {
+            Some(Field::Uint(ts)) => format_utc_timestamp(*ts),
+            _ => file_time.clone(),
+        };
+        let idx = match out.iter().position(|m| m.timestamp == timestamp) {
+            Some(idx) => idx,
+            None => {
+                out.push(MergeRecord {
+                    host: host.clone(),
+                    timestamp,
+                    source: path.display().to_string(),
+                    ..MergeRecord::default()
+                });
+                pane_rss.push(HashMap::new());
+                out.len() - 1
+            }
+        };
+        let row = &mut out[idx];
+        // Pane exports carry their own process counts.
+        row.process_count += match record.get("process_count") {
+            Some(Field::Uint(n)) => *n,
+            _ => 1,
+        };
+        row.swap_bytes += record_uint(record, "swap_bytes");
+        row.physical_bytes += record_uint(record, "physical_bytes");
+        row.rss_bytes += record_uint(record, "rss_bytes");
+        if let Some(Field::Str(target)) = record.get("tmux_target") {
+            *pane_rss[idx].entry(target.clone()).or_default() += record_uint(record, "rss_bytes");
+        }
+    }
+    for (row, panes) in out.iter_mut().zip(pane_rss) {
+        row.pane_count = panes.len();
+        if let Some((target, rss)) = panes
+            .into_iter()
+            .max_by(|a, b| a.1.cmp(&b.1).then(b.0.cmp(&a.0)))
+        {
+            row.top_pane = Some(target);
+            row.top_pane_rss_bytes = rss;
+        }
+    }
+    out
+}
+
+fn run_advise(cli: &Cli) -> Result<(), String> {
+    let rows = collect_rows(cli, &mut CollectCache::default())?;
+    let panes = aggregate_by_pane(&rows);
+    let advice = advise_history_limits(&panes, cli.history_target_bytes);
+    emit(cli, &render_options(cli), advice.len(), |fmt, o| {
+        render_advice(&advice, fmt, o)
+    })
+}
+
+// Returns the action and how many arguments (after `snapshot`) it used.
+fn parse_snapshot_command(args: &[String]) -> Result<(SnapshotCommand, usize), String> {
+    let name = |idx: usize| -> Result<String, String> {
+        let name = args
+            .get(idx)
+            .filter(|n| !n.starts_with('-'))
+            .ok_or("snapshot save and diff require snapshot names")?;
+        // Names become file names in the snapshot directory.
+        if name.contains('/') || name.starts_with('.') {
+            return Err(format!("invalid snapshot name: {name}"));
+        }
+        Ok(name.clone())
+    };
+    match args.first().map(String::as_str) {
+        Some("save") => Ok((SnapshotCommand::Save(name(1)?), 2)),
+        Some("list") => Ok((SnapshotCommand::List, 1)),
+        Some("diff") => Ok((SnapshotCommand::Diff(name(1)?, name(2)?), 3)),
+        Some(other) if !other.starts_with('-') => Err(format!(
+            "unknown snapshot action: {other} (expected save, list, or diff)"
+        )),
+        _ => Err("snapshot requires an action: save <name>, list, or diff <a> <b>".to_string()),
+    }
+}
+
+fn parse_cli() -> Result<Cli, String> {
+    // Intentionally no external CLI crate: tiny binary, tiny dependency surface.
+    let mut process_patterns = Vec::<String>::new();
+    let mut ports = Vec::<u16>::new();
+    let mut cwd_paths = Vec::new();
+    let mut open_files = Vec::new();
+    let mut match_mode = MatchMode::Exact;
+    let mut ignore_case = false;
+    let mut view_mode = ViewMode::Process;
+    let mut stdout_format = OutputFormat::Table;
+    let mut export_path: Option<String> = None;
+    let mut export_format: Option<OutputFormat> = None;
+    let mut export_append = false;
+    let mut lock_wait = Duration::ZERO;
+    let mut no_history_bytes = false;
+    let mut config_path: Option<String> = None;
+    let mut policy_path: Option<String> = None;
+    let mut history_target_bytes = 8 * 1024 * 1024;
+    let mut apply = false;
+    let mut dry_run = false;
+    let mut yes = false;
+    let mut app: Option<AppProfile> = None;
+    let mut wide = false;
+    let mut max_command_width: Option<usize> = None;
+    let mut user: Option<String> = None;
+    let mut env_filters = Vec::new();
+    let mut color = ColorMode::Auto;
+    let mut table_style = TableStyle::Plain;
+    let mut yaml_style = YamlStyle::Sequence;
+    let mut quiet = false;
+    let mut max_concurrent = DEFAULT_MAX_CONCURRENT;
+    let mut retries = DEFAULT_RETRIES;
+    let mut watch: Option<Duration> = None;
+    let mut watch_adaptive = false;
+    let mut on_pressure = false;
+    let mut every: Option<Duration> = None;
+    let mut jitter = Duration::ZERO;
+    let mut cache_ttl = Duration::ZERO;
+    let mut vmmap_min_rss: Option<u64> = None;
+    let mut pid: Option<i32> = None;
+    let mut gpu = false;
+    let mut energy = false;
+    let mut disk_io = false;
+    let mut pageins = false;
+    let mut bars: Option<Metric> = None;
+    let mut percent = false;
+    let mut cumulative = false;
+    let mut pane_detail = false;
+    let mut per_process = false;
+    let mut record_path: Option<String> = None;
+    let mut db_path: Option<String> = None;
+    let mut leak_threshold_bytes = DEFAULT_LEAK_THRESHOLD;
+    let mut verify = false;
+    let mut verify_tolerance_pct = DEFAULT_VERIFY_TOLERANCE_PCT;
+    let mut listen = DEFAULT_LISTEN.to_string();
+    let mut schema_version: Option<u32> = None;
+    let mut status_json = false;
+    let mut capture_bundle: Option<String> = None;
+    let mut replay_bundle: Option<String> = None;
+    let mut log_format = LogFormat::Text;
+    let mut envelope = false;
+    let mut self_metrics = false;
+    let mut json_schema_url: Option<String> = None;
+    let mut mail: Option<String> = None;
+    let mut sendmail: Option<String> = None;
+    let mut service_manager = if cfg!(target_os = "macos") {
+        ServiceManager::Launchd
+    } else {
+        ServiceManager::Systemd
+    };
+    let mut service_mode = ServiceMode::Record;
+    let mut pids_display = PidsDisplay::default();
+    let mut include_shell = false;
+    let mut history_overhead = DEFAULT_HISTORY_OVERHEAD;
+    let mut history_max_lines: Option<u64> = None;
+    let mut alert_growth: Option<GrowthThreshold> = None;
+    let mut alert_notify = false;
+    let mut since_last = false;
+    let mut merge_paths = Vec::new();
+    let mut group_by: Option<String> = None;
+    let mut mark_panes = false;
+    let mut badge_windows: Option<u64> = None;
+    let mut badge_clear = false;
+    let mut binding_key = DEFAULT_BINDING_KEY.to_string();
+    let mut scope: Option<TmuxScope> = None;
+    let mut include_self = false;
+    let mut orphans_only = false;
+
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let mut command = Subcommand::Report;
+    let mut i = 0usize;
+    if let Some(first) = args.first() {
+        if !first.starts_with('-') {
+            command = match first.as_str() {
+                "report" => Subcommand::Report,
+                "advise" => Subcommand::Advise,
+                "tune" => Subcommand::Tune,
+                "leaks" => Subcommand::Leaks,
+                "serve" => Subcommand::Serve,
+                "doctor" => Subcommand::Doctor,
+                "version" => Subcommand::Version,
+                "install-service" => Subcommand::InstallService,
+                "snapshot" => Subcommand::Snapshot,
+                "merge" => Subcommand::Merge,
+                "install-hooks" => Subcommand::InstallHooks,
+                "plugin" => Subcommand::Plugin,
+                "install-keybinding" => Subcommand::InstallKeybinding,
+                _ => return Err(format!("unknown command: {first}\n\nUse --help for usage.")),
+            };
+            i = 1;
+        }
+    }
+    let mut snapshot = None;
+    if command == Subcommand::Snapshot {
+        let (action, used) = parse_snapshot_command(&args[1..])?;
+        snapshot = Some(action);
+        i += used;
+    }
+    while i < args.len() {
+        let arg = &args[i];
+        match arg.as_str() {
+            "--process" => {
+                i += 1;
+                process_patterns.push(args.get(i).ok_or("--process requires a value")?.to_string());
+            }
+            "--match-mode" => {
+                i += 1;
+                let v = args.get(i).ok_or("--match-mode requires a value")?;
+                match_mode = parse_match_mode(v)
+                    .ok_or_else(|| format!("unsupported --match-mode value: {v}"))?;
+            }
+            "--ignore-case" => ignore_case = true,
+            "--open-file" => {
+                i += 1;
+                let v = args.get(i).ok_or("--open-file requires a path")?;
+                open_files
+                    .push(fs::canonicalize(v).map_err(|e| format!("--open-file '{v}': {e}"))?);
+            }
+            "--cwd" => {
+                i += 1;
+                let v = args.get(i).ok_or("--cwd requires a path")?;
+                // Resolved like the kernel reports cwds: absolute, no symlinks.
+                cwd_paths.push(fs::canonicalize(v).map_err(|e| format!("--cwd '{v}': {e}"))?);
+            }
+            "--port" => {
+                i += 1;
+                let v = args.get(i).ok_or("--port requires a value")?;
+                ports.push(
+                    v.parse()
+                        .ok()
+                        .filter(|port| *port > 0)
+                        .ok_or_else(|| format!("invalid --port value: {v}"))?,
+                );
+            }
+            "--format" => {
+                i += 1;
+                let v = args.get(i).ok_or("--format requires a value")?;
+                stdout_format = parse_format(v)?;
+            }
+            "--view" => {
+                i += 1;
+                let v = args.get(i).ok_or("--view requires a value")?;
+                view_mode = parse_view_mode(v)?;
+            }
+            "--group-by" => {
+                i += 1;
+                let v = args.get(i).ok_or("--group-by requires a tmux format")?;
+                if !v.contains("#{") {
+                    return Err(format!(
+                        "--group-by expects a tmux format such as '#{{session_name}}', got: {v}"
+                    ));
+                }
+                group_by = Some(v.to_string());
+            }
+            "--export" => {
+                i += 1;
+                export_path = Some(
+                    args.get(i)
+                        .ok_or("--export requires a file path")?
+                        .to_string(),
+                );
+            }
+            "--export-append" => {
+                i += 1;
+                export_path = Some(
+                    args.get(i)
+                        .ok_or("--export-append requires a file path")?
+                        .to_string(),
+                );
+                export_append = true;
+            }
+            "--lock-wait" => {
+                i += 1;
+                let v = args.get(i).ok_or("--lock-wait requires a duration")?;
+                lock_wait = parse_duration(v).ok_or(format!("invalid --lock-wait value: {v}"))?;
+            }
+            "--export-format" => {
+                i += 1;
+                let v = args.get(i).ok_or("--export-format requires a value")?;
+                export_format = Some(parse_format(v)?);
+            }
+            "--no-history-bytes" => no_history_bytes = true,
+            "--porcelain" => stdout_format = OutputFormat::Porcelain,
+            "--status-json" => status_json = true,
+            "--log-format" => {
+                i += 1;
+                log_format = match args.get(i).map(String::as_str) {
+                    Some("text") => LogFormat::Text,
+                    Some("json") => LogFormat::Json,
+                    Some(v) => return Err(format!("invalid --log-format: {v} (text or json)")),
+                    None => return Err("--log-format requires text or json".to_string()),
+                };
+            }
+            "--capture-bundle" => {
+                i += 1;
+                capture_bundle = Some(
+                    args.get(i)
+                        .ok_or("--capture-bundle requires a directory")?
+                        .to_string(),
+                );
+            }
+            "--replay-bundle" => {
+                i += 1;
+                replay_bundle = Some(
+                    args.get(i)
+                        .ok_or("--replay-bundle requires a directory")?
+                        .to_string(),
+                );
+            }
+            "--envelope" => envelope = true,
+            "--self-metrics" => self_metrics = true,
+            "--json-schema-url" => {
+                i += 1;
+                json_schema_url = Some(
+                    args.get(i)
+                        .filter(|url| !url.is_empty())
+                        .ok_or("--json-schema-url requires a URL")?
+                        .to_string(),
+                );
+            }
+            "--mail" => {
+                i += 1;
+                let v = args.get(i).ok_or("--mail requires an address")?;
+                if v.starts_with('-') || !v.contains('@') {
+                    return Err(format!("--mail expects an email address, got: {v}"));
+                }
+                mail = Some(v.clone());
+            }
+            "--sendmail" => {
+                i += 1;
+                sendmail = Some(
+                    args.get(i)
+                        .filter(|cmd| !cmd.trim().is_empty())
+                        .ok_or("--sendmail requires a command")?
+                        .to_string(),
+                );
+            }
+            "--json" => stdout_format = OutputFormat::Json,
+            "--include-shell" => include_shell = true,
+            "--alert-growth" => {
+                i += 1;
+                let v = args.get(i).ok_or("--alert-growth requires a threshold")?;
+                alert_growth = Some(
+                    parse_growth_threshold(v)
+                        .ok_or(format!("invalid --alert-growth threshold: {v}"))?,
+                );
+            }
+            "--alert-notify" => alert_notify = true,
+            "--since-last" => since_last = true,
+            "--history-overhead" => {
+                i += 1;
+                let v = args.get(i).ok_or("--history-overhead requires a factor")?;
+                history_overhead = v
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|f| f.is_finite() && *f > 0.0)
+                    .ok_or(format!("invalid --history-overhead factor: {v}"))?;
+            }
+            "--history-max-lines" => {
+                i += 1;
+                let v = args.get(i).ok_or("--history-max-lines requires a value")?;
+                history_max_lines = Some(
+                    v.parse::<u64>()
+                        .ok()
+                        .filter(|n| *n > 0)
+                        .ok_or(format!("invalid --history-max-lines value: {v}"))?,
+                );
+            }
+            "--record" => {
+                i += 1;
+                record_path = Some(
+                    args.get(i)
+                        .ok_or("--record requires a file path")?
+                        .to_string(),
+                );
+            }
+            "--db" => {
+                i += 1;
+                db_path = Some(args.get(i).ok_or("--db requires a file path")?.to_string());
+            }
+            "--schema-version" => {
+                i += 1;
+                let v = args.get(i).ok_or("--schema-version requires a number")?;
+                schema_version = Some(
+                    v.parse::<u32>()
+                        .ok()
+                        .filter(|n| (1..=SCHEMA_VERSION).contains(n))
+                        .ok_or(format!(
+                            "unsupported --schema-version {v} (this build supports 1-{SCHEMA_VERSION})"
+                        ))?,
+                );
+            }
+            "--listen" => {
+                i += 1;
+                listen = args
+                    .get(i)
+                    .ok_or("--listen requires an address")?
+                    .to_string();
+            }
+            "--leak-threshold" => {
+                i += 1;
+                let v = args.get(i).ok_or("--leak-threshold requires a size")?;
+                leak_threshold_bytes = parse_size(v)?;
+            }
+            "--verify" => verify = true,
+            "--verify-tolerance" => {
+                i += 1;
+                let v = args
+                    .get(i)
+                    .ok_or("--verify-tolerance requires a percentage")?;
+                verify_tolerance_pct = v
+                    .trim()
+                    .trim_end_matches('%')
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|pct| pct.is_finite() && *pct >= 0.0)
+                    .ok_or(format!("invalid --verify-tolerance: {v}"))?;
+            }
+            "--config" => {
+                i += 1;
+                config_path = Some(
+                    args.get(i)
+                        .ok_or("--config requires a file path")?
+                        .to_string(),
+                );
+            }
+            "--policy" => {
+                i += 1;
+                policy_path = Some(
+                    args.get(i)
+                        .ok_or("--policy requires a file path")?
+                        .to_string(),
+                );
+            }
+            "--history-target" => {
+                i += 1;
+                let v = args.get(i).ok_or("--history-target requires a size")?;
+                history_target_bytes = parse_size(v)?;
+            }
+            "--apply" => apply = true,
+            "--dry-run" => dry_run = true,
+            "--mark-panes" => mark_panes = true,
+            "--badge-windows" => {
+                i += 1;
+                let v = args.get(i).ok_or("--badge-windows requires a size")?;
+                badge_windows = Some(parse_size(v)?);
+            }
+            "--badge-clear" => badge_clear = true,
+            "--include-self" => include_self = true,
+            "--orphans-only" => orphans_only = true,
+            "--current-session" | "--current-window" | "--current-pane" | "--all-sessions" => {
+                let next = match arg.as_str() {
+                    "--current-session" => TmuxScope::Session,
+                    "--current-window" => TmuxScope::Window,
+                    "--current-pane" => TmuxScope::Pane,
+                    _ => TmuxScope::All,
+                };
+                if scope.is_some_and(|s| s != next) {
+                    return Err("--current-session, --current-window, --current-pane, and --all-sessions are exclusive".to_string());
+                }
+                scope = Some(next);
+            }
+            "--key" => {
+                i += 1;
+                binding_key = args
+                    .get(i)
+                    .filter(|k| !k.is_empty())
+                    .ok_or("--key requires a tmux key, e.g. M or C-m")?
+                    .to_string();
+            }
+            "-y" | "--yes" => yes = true,
+            "-q" | "--quiet" => quiet = true,
+            "--watch" => {
+                i += 1;
+                let v = args.get(i).ok_or("--watch requires an interval")?;
+                watch = Some(
+                    parse_duration(v)
+                        .filter(|d| !d.is_zero())
+                        .ok_or(format!("invalid --watch interval: {v}"))?,
+                );
+            }
+            "--watch-adaptive" => watch_adaptive = true,
+            "--on-pressure" => on_pressure = true,
+            "--pids" => {
+                i += 1;
+                let v = args.get(i).ok_or("--pids requires a value")?;
+                pids_display = match v.to_ascii_lowercase().as_str() {
+                    "summary" => PidsDisplay::Summary,
+                    "full" => PidsDisplay::Full,
+                    "none" => PidsDisplay::None,
+                    _ => return Err(format!("unsupported --pids value: {v}")),
+                };
+            }
+            "--service-manager" => {
+                i += 1;
+                let v = args.get(i).ok_or("--service-manager requires a value")?;
+                service_manager = match v.to_ascii_lowercase().as_str() {
+                    "launchd" => ServiceManager::Launchd,
+                    "systemd" => ServiceManager::Systemd,
+                    _ => return Err(format!("unsupported --service-manager value: {v}")),
+                };
+            }
+            "--service-mode" => {
+                i += 1;
+                let v = args.get(i).ok_or("--service-mode requires a value")?;
+                service_mode = match v.to_ascii_lowercase().as_str() {
+                    "record" => ServiceMode::Record,
+                    "serve" => ServiceMode::Serve,
+                    _ => return Err(format!("unsupported --service-mode value: {v}")),
+                };
+            }
+            "--every" => {
+                i += 1;
+                let v = args.get(i).ok_or("--every requires an interval")?;
+                every = Some(
+                    parse_duration(v)
+                        .filter(|d| !d.is_zero())
+                        .ok_or(format!("invalid --every interval: {v}"))?,
+                );
+            }
+            "--jitter" => {
+                i += 1;
+                let v = args.get(i).ok_or("--jitter requires a duration")?;
+                jitter = parse_duration(v).ok_or(format!("invalid --jitter value: {v}"))?;
+            }
+            "--cache-ttl" => {
+                i += 1;
+                let v = args.get(i).ok_or("--cache-ttl requires a duration")?;
+                cache_ttl = parse_duration(v).ok_or(format!("invalid --cache-ttl value: {v}"))?;
+            }
+            "--pid" => {
+                i += 1;
+                let v = args.get(i).ok_or("--pid requires a process id")?;
+                pid = Some(
+                    v.parse::<i32>()
+                        .ok()
+                        .filter(|n| *n > 0)
+                        .ok_or(format!("invalid --pid value: {v}"))?,
+                );
+            }
+            "--vmmap-min-rss" => {
+                i += 1;
+                let v = args.get(i).ok_or("--vmmap-min-rss requires a size")?;
+                vmmap_min_rss = Some(parse_size(v)?);
+            }
+            "--max-concurrent" => {
+                i += 1;
+                let v = args.get(i).ok_or("--max-concurrent requires a value")?;
+                max_concurrent = v
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|n| *n > 0)
+                    .ok_or(format!("invalid --max-concurrent value: {v}"))?;
+            }
+            "--retries" => {
+                i += 1;
+                let v = args.get(i).ok_or("--retries requires a count")?;
+                retries = v
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|n| *n <= MAX_RETRIES)
+                    .ok_or(format!("invalid --retries value: {v} (0-{MAX_RETRIES})"))?;
+            }
+            "--app" => {
+                i += 1;
+                let v = args.get(i).ok_or("--app requires a profile name")?;
+                app = Some(match v.to_ascii_lowercase().as_str() {
+                    "opencode" => AppProfile::Opencode,
+                    _ => return Err(format!("unsupported --app profile: {v}")),
+                });
+            }
+            "--env" => {
+                i += 1;
+                let v = args.get(i).ok_or("--env requires KEY=VALUE")?;
+                env_filters.push(
+                    v.split_once('=')
+                        .filter(|(key, _)| !key.is_empty())
+                        .map(|(key, value)| (key.to_string(), value.to_string()))
+                        .ok_or(format!("invalid --env filter: {v} (expected KEY=VALUE)"))?,
+                );
+            }
+            "--user" => {
+                i += 1;
+                user = Some(
+                    args.get(i)
+                        .ok_or("--user requires a user name")?
+                        .to_string(),
+                );
+            }
+            "--color" => {
+                i += 1;
+                let v = args.get(i).ok_or("--color requires a value")?;
+                color = match v.to_ascii_lowercase().as_str() {
+                    "auto" => ColorMode::Auto,
+                    "always" => ColorMode::Always,
+                    "never" => ColorMode::Never,
+                    _ => return Err(format!("unsupported --color value: {v}")),
+                };
+            }
+            "--table-style" => {
+                i += 1;
+                let v = args.get(i).ok_or("--table-style requires a value")?;
+                table_style = match v.to_ascii_lowercase().as_str() {
+                    "plain" => TableStyle::Plain,
+                    "ascii" => TableStyle::Ascii,
+                    "unicode" => TableStyle::Unicode,
+                    "compact" => TableStyle::Compact,
+                    _ => return Err(format!("unsupported --table-style value: {v}")),
+                };
+            }
+            "--yaml-style" => {
+                i += 1;
+                let v = args.get(i).ok_or("--yaml-style requires a value")?;
+                yaml_style = match v.to_ascii_lowercase().as_str() {
+                    "sequence" => YamlStyle::Sequence,
+                    "document" => YamlStyle::Document,
+                    _ => return Err(format!("unsupported --yaml-style value: {v}")),
+                };
+            }
+            "--wide" => wide = true,
+            "--gpu" => gpu = true,
+            "--energy" => energy = true,
+            "--disk-io" => disk_io = true,
+            "--pageins" => pageins = true,
+            "--bars" => bars = Some(bars.unwrap_or(Metric::Rss)),
+            "--percent" => percent = true,
+            "--cumulative" => cumulative = true,
+            "--pane-detail" => pane_detail = true,
+            "--bars-metric" => {
+                i += 1;
+                let v = args.get(i).ok_or("--bars-metric requires a metric")?;
+                bars = Some(parse_metric(v)?);
+            }
+            "--per-process" => per_process = true,
+            "--max-command-width" => {
+                i += 1;
+                let v = args.get(i).ok_or("--max-command-width requires a value")?;
+                max_command_width = Some(
+                    v.parse::<usize>()
+                        .ok()
+                        .filter(|n| *n > 0)
+                        .ok_or(format!("invalid --max-command-width value: {v}"))?,
+                );
+            }
+            "-h" | "--help" => {
+                print_help();
+                std::process::exit(0);
+            }
+            "--help-json" => {
+                print!("{}", render_help_json());
+                std::process::exit(0);
+            }
+            "-V" | "--version" => {
+                print!("{}", render_version(OutputFormat::Table));
+                std::process::exit(0);
+            }
+            path if command == Subcommand::Merge && !path.starts_with('-') => {
+                merge_paths.push(path.to_string());
+            }
+            _ => return Err(format!("unknown argument: {arg}\n\nUse --help for usage.")),
+        }
+        i += 1;
+    }
+
+    Ok(Cli {
+        command,
+        // Other selectors replace the default pattern rather than add to it.
+        process_patterns: if process_patterns.is_empty()
+            && !(ports.is_empty() && cwd_paths.is_empty() && open_files.is_empty())
+        {
+            Vec::new()
+        } else if process_patterns.is_empty() {
+            vec![(match_mode, "opencode".to_string())]
+        } else {
+            process_patterns
+                .iter()
+                .map(|raw| split_pattern_mode(raw, match_mode))
+                .collect()
+        },
+        ports,
+        cwd_paths,
+        open_files,
+        ignore_case,
+        view_mode,
+        stdout_format,
+        export_path,
+        export_format,
+        export_append,
+        lock_wait,
+        no_history_bytes,
+        config_path,
+        policy_path,
+        history_target_bytes,
+        apply,
+        dry_run,
+        yes,
+        app,
+        wide,
+        max_command_width,
+        user,
+        env_filters,
+        color,
+        table_style,
+        yaml_style,
+        quiet,
+        max_concurrent,
+        retries,
+        watch,
+        watch_adaptive,
+        on_pressure,
+        every,
+        jitter,
+        cache_ttl,
+        vmmap_min_rss,
+        pid,
+        gpu,
+        energy,
+        disk_io,
+        pageins,
+        bars,
+        percent,
+        cumulative,
+        pane_detail,
+        per_process,
+        record_path,
+        db_path,
+        leak_threshold_bytes,
+        verify,
+        verify_tolerance_pct,
+        listen,
+        schema_version,
+        status_json,
+        capture_bundle,
+        replay_bundle,
+        log_format,
+        envelope,
+        self_metrics,
+        json_schema_url,
+        mail,
+        sendmail,
+        header_labels: Vec::new(),
+        service_manager,
+        service_mode,
+        pids_display,
+        include_shell,
+        history_overhead,
+        history_max_lines,
+        alert_growth,
+        alert_notify,
+        since_last,
+        snapshot,
+        merge_paths,
+        group_by,
+        mark_panes,
+        badge_windows,
+        badge_clear,
+        binding_key,
+        scope,
+        tmux_scope: None,
+        include_self,
+        orphans_only,
+        service_args: if matches!(
+            command,
+            Subcommand::InstallService | Subcommand::InstallKeybinding
+        ) {
+            let cwd = env::current_dir()
+                .map(|dir| dir.to_string_lossy().to_string())
+                .unwrap_or_default();
+            forwarded_service_args(&args[1..], &cwd)
+        } else {
+            Vec::new()
+        },
+    })
+}
+
+// One row of the Options section; `usage` is the flag (or "-y, --yes" pair) plus
+// its value placeholder, exactly as help prints it.
+struct HelpOption {
+    usage: &'static str,
+    choices: &'static [&'static str],
+    default: Option<&'static str>,
+    repeatable: bool,
+    help: &'static [&'static str],
+}
+
+const HELP_USAGE: &[&str] = &[
+    "opencode-tmux-mem [report] [options]",
+    "opencode-tmux-mem report --db <file> [--format html]",
+    "opencode-tmux-mem advise [options]",
+    "opencode-tmux-mem tune [--apply] [--dry-run] [options]",
+    "opencode-tmux-mem leaks --db <file> [--leak-threshold <size>]",
+    "opencode-tmux-mem serve [--listen <addr>] [options]",
+    "opencode-tmux-mem doctor [--process <pattern>]",
+    "opencode-tmux-mem version [--json]",
+    "opencode-tmux-mem install-service [--service-mode record|serve] [--apply] [options]",
+    "opencode-tmux-mem install-hooks [--apply]",
+    "opencode-tmux-mem plugin [--process <pattern>] [--cache-ttl <duration>]",
+    "opencode-tmux-mem install-keybinding [--key <key>] [--apply] [options]",
+    "opencode-tmux-mem snapshot save <name> | list | diff <a> <b>",
+    "opencode-tmux-mem merge <export>... [--format <fmt>]",
+];
+
+const HELP_COMMANDS: &[(&str, &[&str])] = &[
+    (
+        "report",
+        &["Live report (default); with --db, per-pane history"],
+    ),
+    ("advise", &["Suggest per-session tmux history-limit values"]),
+    ("tune", &["Apply the advised history-limit values"]),
+    (
+        "leaks",
+        &["Find processes and panes that keep growing in a recording"],
+    ),
+    (
+        "serve",
+        &["Browser dashboard plus /api/processes and /api/panes"],
+    ),
+    (
+        "doctor",
+        &["Check tools, tmux, and memory access; print fixes"],
+    ),
+    (
+        "version",
+        &["Version, git commit, build date, and features"],
+    ),
+    (
+        "install-service",
+        &["Print (or --apply) a launchd/systemd unit for record/serve"],
+    ),
+    (
+        "install-keybinding",
+        &[
+            "Bind prefix + key (default M) to a --watch popup using",
+            "the given options",
+        ],
+    ),
+    (
+        "plugin",
+        &["One fast line for tmux's status-right, e.g. 2.1G (3)"],
+    ),
+    (
+        "install-hooks",
+        &[
+            "tmux hooks that make an --every daemon collect on",
+            "pane splits, new windows, and exits",
+        ],
+    ),
+    (
+        "snapshot",
+        &["Save named snapshots, list them, and diff two by pane"],
+    ),
+    (
+        "merge",
+        &["One row per host and time from several JSON exports"],
+    ),
+];
+
+// Options in help order; print_help and --help-json both read this table, so a
+// new flag only needs its match arm in parse_cli and a row here.
+const HELP_OPTIONS: &[HelpOption] = &[
+    HelpOption {
+        usage: "--process <pattern>",
+        choices: &[],
+        default: Some("opencode"),
+        repeatable: true,
+        help: &[
+            "Process pattern (default: opencode); repeat to match",
+            "any of several, each optionally prefixed with its",
+            "mode (exact:, full:, regex:, glob:, full-word:)",
+        ],
+    },
+    HelpOption {
+        usage: "--match-mode <exact|full|glob|full-word>",
+        choices: &["exact", "full", "regex", "glob", "full-word"],
+        default: Some("exact"),
+        repeatable: false,
+        help: &[
+            "PID scan mode (default: exact); glob takes * and ?,",
+            "full-word only matches whole command-line words",
+        ],
+    },
+    HelpOption {
+        usage: "--port <n>",
+        choices: &[],
+        default: None,
+        repeatable: true,
+        help: &[
+            "Also report processes listening on this TCP port",
+            "(repeatable; without --process, only those)",
+        ],
+    },
+    HelpOption {
+        usage: "--cwd <dir>",
+        choices: &[],
+        default: None,
+        repeatable: true,
+        help: &[
+            "Also report processes working under this directory",
+            "(repeatable; without --process, only those)",
+        ],
+    },
+    HelpOption {
+        usage: "--open-file <path>",
+        choices: &[],
+        default: None,
+        repeatable: true,
+        help: &[
+            "Also report processes holding this file open",
+            "(repeatable; without --process, only those)",
+        ],
+    },
+    HelpOption {
+        usage: "--ignore-case",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Match --process case-insensitively (pgrep -i)"],
+    },
+    HelpOption {
+        usage: "--user <name>",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Only processes owned by this user"],
+    },
+    HelpOption {
+        usage: "--env <KEY=VALUE>",
+        choices: &[],
+        default: None,
+        repeatable: true,
+        help: &["Only processes whose environment has this variable"],
+    },
+    HelpOption {
+        usage: "--include-self",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &[
+            "Keep this tool's own processes when the pattern",
+            "matches them (dropped by default)",
+        ],
+    },
+    HelpOption {
+        usage: "--current-session",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Only panes in the tmux session this runs in"],
+    },
+    HelpOption {
+        usage: "--current-window",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Only panes in the tmux window this runs in"],
+    },
+    HelpOption {
+        usage: "--current-pane",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Only the tmux pane this runs in"],
+    },
+    HelpOption {
+        usage: "--all-sessions",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Override current_session: true from the config"],
+    },
+    HelpOption {
+        usage: "--orphans-only",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &[
+            "Only processes outside every tmux pane (leftovers",
+            "from closed panes or detached launches)",
+        ],
+    },
+    HelpOption {
+        usage: "--view <process|pane|budget|mappings|tmux|project|session>",
+        choices: &[
+            "process", "pane", "budget", "mappings", "tmux", "project", "session",
+        ],
+        default: Some("process"),
+        repeatable: false,
+        help: &["Output view mode (default: process)"],
+    },
+    HelpOption {
+        usage: "--pid <pid>",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Process for --view mappings"],
+    },
+    HelpOption {
+        usage: "--pids <summary|full|none>",
+        choices: &["summary", "full", "none"],
+        default: Some("summary"),
+        repeatable: false,
+        help: &[
+            "Pane view PIDs column: first 3 and +n more (default),",
+            "every PID, or no column",
+        ],
+    },
+    HelpOption {
+        usage: "--include-shell",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Add each pane's root shell RSS to the pane view totals"],
+    },
+    HelpOption {
+        usage: "--json",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Shorthand for --format json"],
+    },
+    HelpOption {
+        usage: "--format <fmt>",
+        choices: &[
+            "table",
+            "json",
+            "csv",
+            "yaml",
+            "markdown",
+            "html",
+            "md-report",
+            "svg",
+        ],
+        default: Some("table"),
+        repeatable: false,
+        help: &[
+            "table|json|csv|yaml|markdown (default: table);",
+            "html with report --db",
+            "md-report: full Markdown report with recommendations",
+            "svg: bar chart of memory per pane (or --export *.svg)",
+        ],
+    },
+    HelpOption {
+        usage: "--porcelain",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Stable tab-separated process/pane output for scripts"],
+    },
+    HelpOption {
+        usage: "--envelope",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Wrap JSON/YAML records with build and run metadata"],
+    },
+    HelpOption {
+        usage: "--json-schema-url <url>",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Add a \"$schema\" key to JSON envelopes"],
+    },
+    HelpOption {
+        usage: "--self-metrics",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Add the run's own cost to envelope metadata"],
+    },
+    HelpOption {
+        usage: "--mail <address>",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Mail the html/markdown report through sendmail"],
+    },
+    HelpOption {
+        usage: "--sendmail <command>",
+        choices: &[],
+        default: Some(DEFAULT_SENDMAIL),
+        repeatable: false,
+        help: &["Delivery command for --mail (default: sendmail)"],
+    },
+    HelpOption {
+        usage: "--export <path>",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &[
+            "Export to file; %Y %m %d %H %M %S (UTC, not local time),",
+            "%s, %h expand, %% is a literal %",
+        ],
+    },
+    HelpOption {
+        usage: "--schema-version <n>",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Pin JSON/CSV/YAML process and pane fields to version n"],
+    },
+    HelpOption {
+        usage: "--export-format <fmt>",
+        choices: &[
+            "table",
+            "json",
+            "csv",
+            "yaml",
+            "markdown",
+            "html",
+            "md-report",
+            "svg",
+        ],
+        default: None,
+        repeatable: false,
+        help: &["Export format override"],
+    },
+    HelpOption {
+        usage: "--export-append <path>",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Append to an export file (csv, yaml, markdown, table)"],
+    },
+    HelpOption {
+        usage: "--mark-panes",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &[
+            "Color the tmux borders of warn/crit panes (severity",
+            "thresholds); cleared again once they recover",
+        ],
+    },
+    HelpOption {
+        usage: "--badge-windows <size>",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &[
+            "Append a memory badge like ' [2.1G]' to the names of",
+            "tmux windows using at least this much",
+        ],
+    },
+    HelpOption {
+        usage: "--badge-clear",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Remove those badges from every window and exit"],
+    },
+    HelpOption {
+        usage: "--group-by <format>",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Add up panes by a tmux format, e.g. '#{session_name}'"],
+    },
+    HelpOption {
+        usage: "--since-last",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &[
+            "Add change columns against the newest earlier JSON or",
+            "CSV --export matching the path template",
+        ],
+    },
+    HelpOption {
+        usage: "--lock-wait <duration>",
+        choices: &[],
+        default: Some("0"),
+        repeatable: false,
+        help: &[
+            "Wait this long for a locked --record or --export-append",
+            "file instead of failing (default: 0)",
+        ],
+    },
+    HelpOption {
+        usage: "--no-history-bytes",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Skip tmux capture-pane byte estimation"],
+    },
+    HelpOption {
+        usage: "--history-overhead <factor>",
+        choices: &[],
+        default: Some("3"),
+        repeatable: false,
+        help: &["tmux storage per captured byte, for pane_history_estimated_bytes"],
+    },
+    HelpOption {
+        usage: "--history-max-lines <n>",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &[
+            "Capture only the newest n history lines per pane and",
+            "extrapolate the rest from history_size",
+        ],
+    },
+    HelpOption {
+        usage: "--gpu",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Add a GPU memory column (nvidia-smi, or Metal via vmmap)"],
+    },
+    HelpOption {
+        usage: "--energy",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Add an energy impact column (macOS top -stats power)"],
+    },
+    HelpOption {
+        usage: "--disk-io",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Add disk read/write columns (bytes since start)"],
+    },
+    HelpOption {
+        usage: "--pageins",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Add a pageins column (pages read back from disk)"],
+    },
+    HelpOption {
+        usage: "--percent",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Add each row's share of total swap and physical"],
+    },
+    HelpOption {
+        usage: "--cumulative",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Add a running total of the swap share to tables"],
+    },
+    HelpOption {
+        usage: "--pane-detail",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["List each pane's processes under it in the pane table"],
+    },
+    HelpOption {
+        usage: "--bars",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Draw a proportional bar next to RSS in tables"],
+    },
+    HelpOption {
+        usage: "--bars-metric <metric>",
+        choices: &["swap", "physical", "rss", "history", "history_lines"],
+        default: Some("rss"),
+        repeatable: false,
+        help: &["Bar a different column: swap|physical|rss|history|history_lines"],
+    },
+    HelpOption {
+        usage: "--vmmap-min-rss <size>",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Skip vmmap for processes with less RSS (ps data only)"],
+    },
+    HelpOption {
+        usage: "--watch <interval>",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Repeat the report, e.g. 5s or 1m"],
+    },
+    HelpOption {
+        usage: "--on-pressure",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Sleep until the OS reports memory pressure, then report"],
+    },
+    HelpOption {
+        usage: "--watch-adaptive",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &[
+            "Stretch the --watch interval while memory is stable,",
+            "shrink it when memory grows fast",
+        ],
+    },
+    HelpOption {
+        usage: "--service-mode <mode>",
+        choices: &["record", "serve"],
+        default: Some("record"),
+        repeatable: false,
+        help: &["record|serve for install-service (default: record)"],
+    },
+    HelpOption {
+        usage: "--service-manager <mgr>",
+        choices: &["launchd", "systemd"],
+        default: Some(if cfg!(target_os = "macos") {
+            "launchd"
+        } else {
+            "systemd"
+        }),
+        repeatable: false,
+        help: &["launchd|systemd (default: launchd on macOS)"],
+    },
+    HelpOption {
+        usage: "--key <key>",
+        choices: &[],
+        default: Some(DEFAULT_BINDING_KEY),
+        repeatable: false,
+        help: &["tmux key install-keybinding binds (default: M)"],
+    },
+    HelpOption {
+        usage: "--alert-growth <threshold>",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &[
+            "Highlight rows growing this much per watch tick, e.g.",
+            "10%/interval or 50M",
+        ],
+    },
+    HelpOption {
+        usage: "--alert-notify",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Also send a desktop notification for --alert-growth"],
+    },
+    HelpOption {
+        usage: "--every <interval>",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Keep running and collect/export on a cadence, e.g. 5m"],
+    },
+    HelpOption {
+        usage: "--jitter <duration>",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Random extra delay of up to this much per --every run"],
+    },
+    HelpOption {
+        usage: "--record <path>",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Append every report's process rows to a JSONL file"],
+    },
+    HelpOption {
+        usage: "--db <path>",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Recording read by leaks and report"],
+    },
+    HelpOption {
+        usage: "--per-process",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &[
+            "report --db: one row per process, ranked by",
+            "footprint per hour of uptime",
+        ],
+    },
+    HelpOption {
+        usage: "--leak-threshold <size>",
+        choices: &[],
+        default: Some("50M"),
+        repeatable: false,
+        help: &["Growth per hour leaks reports (default: 50M)"],
+    },
+    HelpOption {
+        usage: "--verify",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Cross-check RSS, vmmap, and footprint(1) per process"],
+    },
+    HelpOption {
+        usage: "--verify-tolerance <pct>",
+        choices: &[],
+        default: Some("10%"),
+        repeatable: false,
+        help: &["Footprint disagreement --verify flags (default: 10%)"],
+    },
+    HelpOption {
+        usage: "--listen <addr>",
+        choices: &[],
+        default: Some(DEFAULT_LISTEN),
+        repeatable: false,
+        help: &["Address serve binds (default: 127.0.0.1:7878)"],
+    },
+    HelpOption {
+        usage: "--cache-ttl <duration>",
+        choices: &[],
+        default: Some("0"),
+        repeatable: false,
+        help: &[
+            "Reuse vmmap and pane history results across watch ticks",
+            "for this long; ps data refreshes every tick (default: 0)",
+        ],
+    },
+    HelpOption {
+        usage: "--max-concurrent <n>",
+        choices: &[],
+        default: Some("4"),
+        repeatable: false,
+        help: &["Parallel vmmap/ps/capture-pane subprocesses (default: 4)"],
+    },
+    HelpOption {
+        usage: "--retries <n>",
+        choices: &[],
+        default: Some("2"),
+        repeatable: false,
+        help: &[
+            "Retry a tmux/ps/vmmap call that fails transiently,",
+            "backing off from 50ms (default: 2)",
+        ],
+    },
+    HelpOption {
+        usage: "--app <opencode>",
+        choices: &["opencode"],
+        default: None,
+        repeatable: false,
+        help: &["Group known helper processes under their app"],
+    },
+    HelpOption {
+        usage: "--wide",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Full command lines, never truncated"],
+    },
+    HelpOption {
+        usage: "--max-command-width <n>",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Truncate commands in table views with an ellipsis"],
+    },
+    HelpOption {
+        usage: "--color <auto|always|never>",
+        choices: &["auto", "always", "never"],
+        default: Some("auto"),
+        repeatable: false,
+        help: &["Highlight table output (default: auto; honors NO_COLOR)"],
+    },
+    HelpOption {
+        usage: "--table-style <style>",
+        choices: &["plain", "ascii", "unicode", "compact"],
+        default: Some("plain"),
+        repeatable: false,
+        help: &["plain|ascii|unicode|compact (default: plain, tab-separated)"],
+    },
+    HelpOption {
+        usage: "--yaml-style <style>",
+        choices: &["sequence", "document"],
+        default: Some("sequence"),
+        repeatable: false,
+        help: &["sequence|document (default: sequence, a bare record list)"],
+    },
+    HelpOption {
+        usage: "--config <path>",
+        choices: &[],
+        default: Some("~/.config/opencode-tmux-mem/config.yaml"),
+        repeatable: false,
+        help: &["Config file (default: ~/.config/opencode-tmux-mem/config.yaml)"],
+    },
+    HelpOption {
+        usage: "--policy <path>",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Evaluate policy rules after collection"],
+    },
+    HelpOption {
+        usage: "--history-target <size>",
+        choices: &[],
+        default: Some("8M"),
+        repeatable: false,
+        help: &["Per-pane history bytes advise aims for (default: 8M)"],
+    },
+    HelpOption {
+        usage: "--apply",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Let tune change tmux options (default: preview)"],
+    },
+    HelpOption {
+        usage: "--dry-run",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &[
+            "Print mutating commands (kill, clear-history, tune)",
+            "instead of running them",
+        ],
+    },
+    HelpOption {
+        usage: "-y, --yes",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Skip the confirmation prompt for actions"],
+    },
+    HelpOption {
+        usage: "-q, --quiet",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["No progress line on stderr during long scans"],
+    },
+    HelpOption {
+        usage: "--status-json",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["End stderr with a one-line JSON summary of the run"],
+    },
+    HelpOption {
+        usage: "--log-format <text|json>",
+        choices: &["text", "json"],
+        default: Some("text"),
+        repeatable: false,
+        help: &[
+            "Stderr diagnostics as text (default) or one JSON",
+            "object per event, for journald/vector",
+        ],
+    },
+    HelpOption {
+        usage: "--capture-bundle <dir>",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Save every external command's output to dir"],
+    },
+    HelpOption {
+        usage: "--replay-bundle <dir>",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Run from a --capture-bundle dir instead of the system"],
+    },
+    HelpOption {
+        usage: "-V, --version",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Show version"],
+    },
+    HelpOption {
+        usage: "-h, --help",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Show help"],
+    },
+    HelpOption {
+        usage: "--help-json",
+        choices: &[],
+        default: None,
+        repeatable: false,
+        help: &["Describe every option as JSON, for wrapper UIs"],
+    },
+];
+
+fn print_help() {
+    print!("{}", help_text());
+}
+
+fn help_text() -> String {
+    let mut out = "opencode-tmux-mem 🔍\n\nUsage:\n".to_string();
+    for line in HELP_USAGE {
+        let _ = writeln!(out, "  {line}");
+    }
+    out.push_str("\nCommands:\n");
+    for (name, help) in HELP_COMMANDS {
+        push_help_entry(&mut out, name, help);
+    }
+    out.push_str("\nOptions:\n");
+    for option in HELP_OPTIONS {
+        push_help_entry(&mut out, option.usage, option.help);
+    }
+    out
+}
+
+// Descriptions start at column 30; a longer usage gets a line of its own.
+fn push_help_entry(out: &mut String, usage: &str, help: &[&str]) {
+    let mut lines = help.iter();
+    match lines.next() {
+        Some(first) if usage.len() <= 27 => {
+            let _ = writeln!(out, "  {usage:<27} {first}");
+        }
+        first => {
+            let _ = writeln!(out, "  {usage}");
+            if let Some(line) = first {
+                let _ = writeln!(out, "{:30}{line}", "");
+            }
+        }
+    }
+    for line in lines {
+        let _ = writeln!(out, "{:30}{line}", "");
+    }
+}
+
+// --help-json: the same tables as data, for wrapper UIs that build their forms
+// from it. `value` is the placeholder name, null for plain switches.
+fn render_help_json() -> String {
+    let strings = |items: &[&str]| Field::StrList(items.iter().map(|s| s.to_string()).collect());
+    let mut out = format!(
+        "{{\n  \"tool\": \"{}\",\n  \"version\": \"{VERSION}\",\n  \"usage\": {},\n  \"commands\": [\n",
+        env!("CARGO_PKG_NAME"),
+        json_field(&strings(HELP_USAGE)),
+    );
+    let commands = HELP_COMMANDS
+        .iter()
+        .map(|(name, help)| {
+            json_object(&[
+                ("name", Field::Str(name.to_string())),
+                ("description", Field::Str(help.join(" "))),
+            ])
+        })
+        .collect::<Vec<_>>();
+    let _ = writeln!(out, "    {}", commands.join(",\n    "));
+    out.push_str("  ],\n  \"options\": [\n");
+    let options = HELP_OPTIONS
+        .iter()
+        .map(|option| {
+            let (flags, value) = option
+                .usage
+                .split_once(' ')
+                .filter(|(_, rest)| rest.starts_with('<'))
+                .map_or((option.usage, None), |(flag, value)| {
+                    (flag, Some(value.trim_matches(['<', '>'])))
+                });
+            json_object(&[
+                ("flags", strings(&flags.split(", ").collect::<Vec<_>>())),
+                (
+                    "value",
+                    value.map_or(Field::Null, |v| Field::Str(v.to_string())),
+                ),
+                ("choices", strings(option.choices)),
+                (
+                    "default",
+                    option
+                        .default
+                        .map_or(Field::Null, |d| Field::Str(d.to_string())),
+                ),
+                ("repeatable", Field::Bool(option.repeatable)),
+                ("description", Field::Str(option.help.join(" "))),
+            ])
+        })
+        .collect::<Vec<_>>();
+    let _ = writeln!(out, "    {}", options.join(",\n    "));
+    out.push_str("  ]\n}\n");
+    out
+}
+
+fn parse_format(v: &str) -> Result<OutputFormat, String> {
+    match v.to_ascii_lowercase().as_str() {
+        "table" => Ok(OutputFormat::Table),
+        "json" => Ok(OutputFormat::Json),
+        "csv" => Ok(OutputFormat::Csv),
+        "yaml" | "yml" => Ok(OutputFormat::Yaml),
+        "markdown" | "md" => Ok(OutputFormat::Markdown),
+        "html" => Ok(OutputFormat::Html),
+        "md-report" => Ok(OutputFormat::MdReport),
+        "svg" => Ok(OutputFormat::Svg),
+        _ => Err(format!("unsupported format: {v}")),
+    }
+}
+
+fn parse_view_mode(v: &str) -> Result<ViewMode, String> {
+    match v.to_ascii_lowercase().as_str() {
+        "process" => Ok(ViewMode::Process),
+        "pane" => Ok(ViewMode::Pane),
+        "budget" => Ok(ViewMode::Budget),
+        "mappings" => Ok(ViewMode::Mappings),
+        "tmux" => Ok(ViewMode::Tmux),
+        "project" => Ok(ViewMode::Project),
+        "session" => Ok(ViewMode::Session),
+        _ => Err(format!("unsupported view mode: {v}")),
+    }
+}
+
+fn infer_format_from_path(path: &str) -> Option<OutputFormat> {
+    let lower = path.to_ascii_lowercase();
+    if lower.ends_with(".json") {
+        Some(OutputFormat::Json)
+    } else if lower.ends_with(".csv") {
+        Some(OutputFormat::Csv)
+    } else if lower.ends_with(".yaml") || lower.ends_with(".yml") {
+        Some(OutputFormat::Yaml)
+    } else if lower.ends_with(".md") || lower.ends_with(".markdown") {
+        Some(OutputFormat::Markdown)
+    } else if lower.ends_with(".html") || lower.ends_with(".htm") {
+        Some(OutputFormat::Html)
+    } else if lower.ends_with(".svg") {
+        Some(OutputFormat::Svg)
+    } else {
+        None
+    }
+}
+
+fn default_config_path() -> Option<String> {
+    if let Ok(dir) = env::var("XDG_CONFIG_HOME") {
+        if !dir.is_empty() {
+            return Some(format!("{dir}/opencode-tmux-mem/config.yaml"));
+        }
+    }
+    env::var("HOME")
+        .ok()
+        .map(|home| format!("{home}/.config/opencode-tmux-mem/config.yaml"))
+}
+
+fn load_config(explicit: Option<&str>) -> Result<Config, String> {
+    // An explicit --config must exist; the default location is optional.
+    let (path, required) = match explicit {
+        Some(p) => (p.to_string(), true),
+        None => match default_config_path() {
+            Some(p) => (p, false),
+            None => return Ok(Config::default()),
+        },
+    };
+    let raw = match fs::read_to_string(&path) {
+        Ok(v) => v,
+        Err(e) if !required && e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(e) => return Err(format!("failed reading config file '{path}': {e}")),
+    };
+    parse_config(&raw).map_err(|e| format!("invalid config file '{path}': {e}"))
+}
+
+fn parse_config(raw: &str) -> Result<Config, String> {
+    let mut config = Config::default();
+    let doc = parse_yaml(raw)?;
+    let YamlValue::Map(entries) = doc else {
+        return Ok(config);
+    };
+    for (key, value) in entries {
+        match key.as_str() {
+            "budgets" => config.budgets = parse_budgets(&value)?,
+            "severity" => config.severity = parse_severity(&value)?,
+            "json_schema_url" => match value {
+                YamlValue::Scalar(url) if !url.is_empty() => config.json_schema_url = Some(url),
+                _ => return Err("json_schema_url must be a URL".to_string()),
+            },
+            "sendmail" => match value {
+                YamlValue::Scalar(cmd) if !cmd.trim().is_empty() => config.sendmail = Some(cmd),
+                _ => return Err("sendmail must be a command".to_string()),
+            },
+            "headers" => config.headers = parse_headers(&value)?,
+            "current_session" => {
+                config.current_session = match value {
+                    YamlValue::Scalar(v) if v == "true" => true,
+                    YamlValue::Scalar(v) if v == "false" => false,
+                    _ => return Err("current_session must be true or false".to_string()),
+                }
+            }
+            _ => return Err(format!("unknown config key: {key}")),
+        }
+    }
+    Ok(config)
+}
+
+fn parse_budgets(value: &YamlValue) -> Result<Vec<Budget>, String> {
+    let YamlValue::Map(entries) = value else {
+        return Err("budgets must map window-name globs to limits".to_string());
+    };
+    let mut budgets = Vec::new();
+    for (glob, limits) in entries {
+        let YamlValue::Map(limit_entries) = limits else {
+            return Err(format!("budget '{glob}' must map metrics to limits"));
+        };
+        let mut parsed = Vec::new();
+        for (metric, limit) in limit_entries {
+            let metric = parse_metric(metric)?;
+            let YamlValue::Scalar(raw) = limit else {
+                return Err(format!("budget '{glob}' has a non-scalar limit"));
+            };
+            let limit =
+                parse_metric_limit(metric, raw).map_err(|e| format!("budget '{glob}': {e}"))?;
+            parsed.push((metric, limit));
+        }
+        budgets.push(Budget {
+            window_glob: glob.clone(),
+            limits: parsed,
+        });
+    }
+    Ok(budgets)
+}
+
+fn parse_headers(value: &YamlValue) -> Result<Vec<(String, String)>, String> {
+    let YamlValue::Map(entries) = value else {
+        return Err("headers must map column names to labels".to_string());
+    };
+    entries
+        .iter()
+        .map(|(column, label)| match label {
+            YamlValue::Scalar(label) if !label.trim().is_empty() => {
+                Ok((header_key(column), label.clone()))
+            }
+            _ => Err(format!("header '{column}' must have a non-empty label")),
+        })
+        .collect()
+}
+
+// "Tmux window.pane" -> "tmux_window_pane" and "Swap %" -> "swap_pct", so
+// config keys can follow either the table headings or the CSV field names.
+fn header_key(name: &str) -> String {
+    name.to_lowercase()
+        .replace('%', " pct ")
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join("_")
+}
+
+// CSV names byte columns `swap_bytes`, so the key `swap` covers both spellings.
+fn header_label<'a>(labels: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    let key = header_key(name);
+    let short = key.strip_suffix("_bytes").unwrap_or(&key);
+    labels
+        .iter()
+        .find(|(k, _)| *k == key || k == short)
+        .map(|(_, label)| label.as_str())
+}
+
+// Tables relabel in render_layout; CSV and Markdown headers are rewritten here,
+// once, for every report that goes through emit.
+fn relabel_headers(fmt: OutputFormat, body: String, labels: &[(String, String)]) -> String {
+    if labels.is_empty() {
+        return body;
+    }
+    match fmt {
+        OutputFormat::Csv => match body.split_once('\n') {
+            Some((header, rest)) => {
+                let header = header
+                    .split(',')
+                    .map(|name| header_label(labels, name).map_or(name.to_string(), escape_csv))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                format!("{header}\n{rest}")
+            }
+            None => body,
+        },
+        OutputFormat::Markdown | OutputFormat::MdReport => {
+            let lines = body.split_inclusive('\n').collect::<Vec<_>>();
+            let mut out = String::with_capacity(body.len());
+            for (i, line) in lines.iter().enumerate() {
+                let is_header = lines.get(i + 1).is_some_and(|next| {
+                    next.starts_with('|')
+                        && next.contains("---")
+                        && next
+                            .trim_end()
+                            .chars()
+                            .all(|c| matches!(c, '|' | '-' | ':' | ' '))
+                });
+                if !is_header {
+                    out.push_str(line);
+                    continue;
+                }
+                let cells = line.trim().trim_matches('|').split('|').map(|cell| {
+                    let cell = cell.trim();
+                    header_label(labels, cell).map_or(cell.to_string(), |l| l.replace('|', "\\|"))
+                });
+                let _ = writeln!(out, "| {} |", cells.collect::<Vec<_>>().join(" | "));
+            }
+            out
+        }
+        _ => body,
+    }
+}
+
+fn parse_severity(value: &YamlValue) -> Result<Vec<SeverityThreshold>, String> {
+    let YamlValue::Map(entries) = value else {
+        return Err("severity must map metrics to warn/crit thresholds".to_string());
+    };
+    let mut out = Vec::new();
+    for (metric_raw, levels) in entries {
+        let metric = parse_metric(metric_raw)?;
+        let YamlValue::Map(level_entries) = levels else {
+            return Err(format!(
+                "severity '{metric_raw}' must map warn/crit to limits"
+            ));
+        };
+        let mut threshold = SeverityThreshold {
+            metric,
+            warn: None,
+            crit: None,
+        };
+        for (level, limit) in level_entries {
+            let YamlValue::Scalar(raw) = limit else {
+                return Err(format!("severity '{metric_raw}' has a non-scalar limit"));
+            };
+            let limit = parse_metric_limit(metric, raw)
+                .map_err(|e| format!("severity '{metric_raw}': {e}"))?;
+            match level.as_str() {
+                "warn" => threshold.warn = Some(limit),
+                "crit" => threshold.crit = Some(limit),
+                _ => {
+                    return Err(format!(
+                        "severity '{metric_raw}' has unknown level: {level}"
+                    ))
+                }
+            }
+        }
+        out.push(threshold);
+    }
+    Ok(out)
+}
+
+fn load_policy(path: &str) -> Result<Vec<PolicyRule>, String> {
+    let raw = fs::read_to_string(path)
+        .map_err(|e| format!("failed reading policy file '{path}': {e}"))?;
+    parse_policy(&raw).map_err(|e| format!("invalid policy file '{path}': {e}"))
+}
+
+fn parse_policy(raw: &str) -> Result<Vec<PolicyRule>, String> {
+    let doc = parse_yaml(raw)?;
+    let rules = match &doc {
+        YamlValue::Map(entries) => match entries.iter().find(|(k, _)| k == "rules") {
+            Some((_, YamlValue::List(items))) => items.clone(),
+            Some(_) => return Err("rules must be a list".to_string()),
+            None => Vec::new(),
+        },
+        YamlValue::List(items) => items.clone(),
+        YamlValue::Scalar(_) => return Err("expected a list of rules".to_string()),
+    };
+
+    let mut out = Vec::new();
+    for (idx, item) in rules.iter().enumerate() {
+        let YamlValue::Map(fields) = item else {
+            return Err(format!("rule #{} must be a mapping", idx + 1));
+        };
+        let get = |key: &str| {
+            fields.iter().find_map(|(k, v)| match v {
+                YamlValue::Scalar(s) if k == key => Some(s.clone()),
+                _ => None,
+            })
+        };
+        let name = get("name").unwrap_or_else(|| format!("rule-{}", idx + 1));
+        let metric = parse_metric(&get("metric").ok_or(format!("rule '{name}' needs a metric"))?)?;
+        let limit_raw = get("limit").ok_or(format!("rule '{name}' needs a limit"))?;
+        let limit =
+            parse_metric_limit(metric, &limit_raw).map_err(|e| format!("rule '{name}': {e}"))?;
+        let action = match get("action").as_deref().unwrap_or("warn") {
+            "warn" => PolicyAction::Warn,
+            "exit" => PolicyAction::Exit,
+            "kill" => PolicyAction::Kill,
+            "clear-history" => PolicyAction::ClearHistory,
+            other => return Err(format!("rule '{name}' has unsupported action: {other}")),
+        };
+        for (key, _) in fields {
+            if !matches!(
+                key.as_str(),
+                "name" | "session" | "window" | "process" | "metric" | "limit" | "action"
+            ) {
+                return Err(format!("rule '{name}' has unknown key: {key}"));
+            }
+        }
+        out.push(PolicyRule {
+            name,
+            session: get("session"),
+            window: get("window"),
+            process: get("process"),
+            metric,
+            limit,
+            action,
+        });
+    }
+    Ok(out)
+}
+
+fn parse_metric(v: &str) -> Result<Metric, String> {
+    match v.to_ascii_lowercase().as_str() {
+        "swap" => Ok(Metric::Swap),
+        "physical" => Ok(Metric::Physical),
+        "rss" => Ok(Metric::Rss),
+        "history" | "history_bytes" => Ok(Metric::HistoryBytes),
+        "history_lines" => Ok(Metric::HistoryLines),
+        _ => Err(format!("unsupported metric: {v}")),
+    }
+}
+
+fn parse_metric_limit(metric: Metric, raw: &str) -> Result<u64, String> {
+    match metric {
+        Metric::HistoryLines => raw
+            .trim()
+            .parse::<u64>()
+            .map_err(|_| format!("invalid line count: {raw}")),
+        _ => parse_size(raw),
+    }
+}
+
+fn metric_name(metric: Metric) -> &'static str {
+    match metric {
+        Metric::Swap => "swap",
+        Metric::Physical => "physical",
+        Metric::Rss => "rss",
+        Metric::HistoryBytes => "history_bytes",
+        Metric::HistoryLines => "history_lines",
+    }
+}
+
+// Accepts 500ms, 30s, 5m, 1h or bare seconds.
+fn parse_duration(v: &str) -> Option<Duration> {
+    let t = v.trim();
+    let split = t.find(|c: char| !c.is_ascii_digit()).unwrap_or(t.len());
+    let (num, unit) = t.split_at(split);
+    let n = num.parse::<u64>().ok()?;
+    match unit {
+        "ms" => Some(Duration::from_millis(n)),
+        "" | "s" => Some(Duration::from_secs(n)),
+        "m" => Some(Duration::from_secs(n * 60)),
+        "h" => Some(Duration::from_secs(n * 3600)),
+        _ => None,
+    }
+}
+
+// Accepts plain bytes or compact sizes like 512M, 1.5G, 2GB or 2GiB.
+fn parse_size(v: &str) -> Result<u64, String> {
+    let t = v.trim();
+    let t = t
+        .strip_suffix("iB")
+        .or_else(|| t.strip_suffix("ib"))
+        .or_else(|| {
+            let stripped = t.strip_suffix(['B', 'b'])?;
+            stripped
+                .ends_with(|c: char| c.is_ascii_alphabetic())
+                .then_some(stripped)
+        })
+        .unwrap_or(t);
+    let numeric = t.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+    let unit_ok = matches!(
+        t[numeric.len()..].to_ascii_uppercase().as_str(),
+        "" | "B" | "K" | "M" | "G" | "T"
+    );
+    if numeric.is_empty() || numeric.parse::<f64>().is_err() || !unit_ok {
+        return Err(format!("invalid size: {v}"));
+    }
+    Ok(parse_compact_bytes(t))
+}
+
+// Shell-style wildcard match supporting `*` and `?`.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let p = pattern.chars().collect::<Vec<_>>();
+    let t = text.chars().collect::<Vec<_>>();
+    let (mut pi, mut ti) = (0usize, 0usize);
+    let mut star: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if let Some((sp, st)) = star {
+            pi = sp + 1;
+            ti = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|c| *c == '*')
+}
+
+fn parse_yaml(raw: &str) -> Result<YamlValue, String> {
+    let mut lines = Vec::<(usize, String)>::new();
+    for line in raw.lines() {
+        let stripped = strip_yaml_comment(line);
+        if stripped.trim().is_empty() || stripped.trim() == "---" {
+            continue;
+        }
+        if stripped.starts_with('\t') {
+            return Err("tabs are not allowed for indentation".to_string());
+        }
+        let indent = stripped.len() - stripped.trim_start().len();
+        lines.push((indent, stripped.trim().to_string()));
+    }
+    if lines.is_empty() {
+        return Ok(YamlValue::Map(Vec::new()));
+    }
+    let mut idx = 0usize;
+    let indent = lines[0].0;
+    let value = parse_yaml_block(&mut lines, &mut idx, indent)?;
+    if idx < lines.len() {
+        return Err(format!("unexpected indentation near '{}'", lines[idx].1));
+    }
+    Ok(value)
+}
+
+fn strip_yaml_comment(line: &str) -> &str {
+    let mut quote: Option<char> = None;
+    let mut prev = ' ';
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '#' && prev.is_whitespace() => return line[..i].trim_end(),
+            None => {}
+        }
+        prev = c;
+    }
+    line.trim_end()
+}
+
+fn parse_yaml_block(
+    lines: &mut [(usize, String)],
+    idx: &mut usize,
+    indent: usize,
+) -> Result<YamlValue, String> {
+    if lines[*idx].1 == "-" || lines[*idx].1.starts_with("- ") {
+        let mut items = Vec::new();
+        while *idx < lines.len() && lines[*idx].0 == indent {
+            let text = lines[*idx].1.clone();
+            let Some(rest) = text.strip_prefix('-') else {
+                break;
+            };
+            let rest = rest.trim_start();
+            if rest.is_empty() {
+                *idx += 1;
+                if *idx < lines.len() && lines[*idx].0 > indent {
+                    let child = lines[*idx].0;
+                    items.push(parse_yaml_block(lines, idx, child)?);
+                } else {
+                    items.push(YamlValue::Scalar(String::new()));
+                }
+            } else if split_yaml_key(rest).is_some() {
+                // `- key: value` starts an inline mapping; re-read it one level deeper.
+                let child = indent + (text.len() - rest.len());
+                lines[*idx] = (child, rest.to_string());
+                items.push(parse_yaml_block(lines, idx, child)?);
+            } else {
+                items.push(parse_yaml_scalar(rest));
+                *idx += 1;
+            }
+        }
+        return Ok(YamlValue::List(items));
+    }
+
+    let mut entries = Vec::new();
+    while *idx < lines.len() && lines[*idx].0 == indent {
+        let text = lines[*idx].1.clone();
+        let Some((key, value)) = split_yaml_key(&text) else {
+            return Err(format!("expected 'key: value', got '{text}'"));
+        };
+        *idx += 1;
+        if !value.is_empty() {
+            entries.push((key, parse_yaml_scalar(&value)));
+            continue;
+        }
+        let nested = *idx < lines.len()
+            && (lines[*idx].0 > indent
+                || (lines[*idx].0 == indent && lines[*idx].1.starts_with('-')));
+        if nested {
+            let child = lines[*idx].0;
+            entries.push((key, parse_yaml_block(lines, idx, child)?));
+        } else {
+            entries.push((key, YamlValue::Scalar(String::new())));
+        }
+    }
+    Ok(YamlValue::Map(entries))
+}
+
+fn split_yaml_key(text: &str) -> Option<(String, String)> {
+    let (key, value) = if let Some(key) = text.strip_suffix(':') {
+        (key, "")
+    } else {
+        let pos = text.find(": ")?;
+        (&text[..pos], text[pos + 2..].trim())
+    };
+    let key = match parse_yaml_scalar(key) {
+        YamlValue::Scalar(k) if !k.is_empty() => k,
+        _ => return None,
+    };
+    Some((key, value.to_string()))
+}
+
+fn parse_yaml_scalar(v: &str) -> YamlValue {
+    let t = v.trim();
+    if t.len() >= 2
+        && ((t.starts_with('"') && t.ends_with('"')) || (t.starts_with('\'') && t.ends_with('\'')))
+    {
+        return YamlValue::Scalar(t[1..t.len() - 1].replace("\\\"", "\""));
+    }
+    if t.starts_with('[') && t.ends_with(']') {
+        let inner = t[1..t.len() - 1].trim();
+        if inner.is_empty() {
+            return YamlValue::List(Vec::new());
+        }
+        return YamlValue::List(inner.split(',').map(parse_yaml_scalar).collect());
+    }
+    YamlValue::Scalar(t.to_string())
+}
+
+fn run_cmd(program: &str, args: &[&str]) -> io::Result<String> {
+    let out = command_output(program, args)?;
+    if !out.status.success() {
+        return Err(io::Error::other(format!(
+            "command failed: {} {} => {}",
+            program,
+            args.join(" "),
+            String::from_utf8_lossy(&out.stderr)
+        )));
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).to_string())
+}
+
+// Commands whose output feeds a report run through here, so a bundle can record
+// or replay them. Interactive ones (mail, the tmux control client) don't.
+fn command_output(program: &str, args: &[&str]) -> io::Result<Output> {
+    bundled(bundle_key(program, args), || {
+        subprocess(program).args(args).output()
+    })
+}
+
+// /proc files (and scans of /proc) that feed a report, recorded in the bundle as
+// `< path` with the contents as stdout. macOS proc_pid_rusage reads go here too.
+fn read_proc(path: &str, read: impl FnOnce() -> io::Result<Vec<u8>>) -> io::Result<Vec<u8>> {
+    let out = bundled(escape_bundle_line(&format!("< {path}")), || {
+        read().map(|stdout| Output {
+            status: std::process::ExitStatus::from_raw(0),
+            stdout,
+            stderr: Vec::new(),
+        })
+    })?;
+    Ok(out.stdout)
+}
+
+fn read_proc_string(path: &str) -> io::Result<String> {
+    read_proc(path, || fs::read(path)).map(|raw| String::from_utf8_lossy(&raw).into_owned())
+}
+
+fn bundled(line: String, live: impl FnOnce() -> io::Result<Output>) -> io::Result<Output> {
+    match BUNDLE.get() {
+        None => live(),
+        Some(Bundle::Capture { dir, next }) => {
+            let out = live();
+            if let Err(e) = capture_output(dir, next, &line, &out) {
+                warn(format!("--capture-bundle could not save '{line}': {e}"));
+            }
+            out
+        }
+        Some(Bundle::Replay(entries)) => {
+            let mut entries = entries.lock().unwrap_or_else(|e| e.into_inner());
+            // Repeated commands (one per --every tick) replay in order; the last
+            // output then stands for any further calls.
+            let entry = match entries.get_mut(&line) {
+                Some(queue) if queue.len() > 1 => queue.pop_front(),
+                Some(queue) => queue.front().cloned(),
+                None => None,
+            }
+            .ok_or_else(|| io::Error::other(format!("not in the replay bundle: {line}")))?;
+            bundle_output(entry)
+        }
+    }
+}
+
+// Sets up BUNDLE before the run; a capture directory gets run.txt with the
+// version and arguments, for whoever reads the bug report.
+fn open_bundle(cli: &Cli) -> Result<(), String> {
+    let bundle = if let Some(dir) = &cli.capture_bundle {
+        fs::create_dir_all(dir)
+            .map_err(|e| format!("failed to create --capture-bundle {dir}: {e}"))?;
+        let args = env::args().skip(1).collect::<Vec<_>>();
+        let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+        fs::write(
+            std::path::Path::new(dir).join("run.txt"),
+            format!(
+                "{VERSION} {}\n{}\n",
+                env::consts::OS,
+                shell_command_line("opencode-tmux-mem", &args)
+            ),
+        )
+        .and_then(|_| fs::write(std::path::Path::new(dir).join("index.tsv"), ""))
+        .map_err(|e| format!("failed to write --capture-bundle {dir}: {e}"))?;
+        Bundle::Capture {
+            dir: dir.into(),
+            next: Mutex::new(0),
+        }
+    } else if let Some(dir) = &cli.replay_bundle {
+        Bundle::Replay(Mutex::new(
+            load_bundle(std::path::Path::new(dir))
+                .map_err(|e| format!("failed to read --replay-bundle {dir}: {e}"))?,
+        ))
+    } else {
+        return Ok(());
+    };
+    let _ = BUNDLE.set(bundle);
+    Ok(())
+}
+
+// index.tsv has one line per command: sequence number, exit code (or "missing",
+// "failed" when it never started), and the command line; the sequence number
+// names its .stdout and .stderr files.
+fn capture_output(
+    dir: &std::path::Path,
+    next: &Mutex<usize>,
+    line: &str,
+    out: &io::Result<Output>,
+) -> io::Result<()> {
+    let mut next = next.lock().unwrap_or_else(|e| e.into_inner());
+    let seq = *next;
+    *next += 1;
+    let (status, stdout, stderr) = match out {
+        Ok(out) => (
+            out.status.code().unwrap_or(-1).to_string(),
+            out.stdout.as_slice(),
+            out.stderr.clone(),
+        ),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            ("missing".to_string(), &[][..], e.to_string().into_bytes())
+        }
+        Err(e) => ("failed".to_string(), &[][..], e.to_string().into_bytes()),
+    };
+    fs::write(dir.join(format!("{seq}.stdout")), stdout)?;
+    fs::write(dir.join(format!("{seq}.stderr")), stderr)?;
+    let mut index = fs::OpenOptions::new()
+        .append(true)
+        .open(dir.join("index.tsv"))?;
+    writeln!(index, "{seq}\t{status}\t{line}")
+}
+
+// The command line as index.tsv stores it: tabs and newlines (tmux formats have
+// them) escaped so each command stays on one line.
+fn bundle_key(program: &str, args: &[&str]) -> String {
+    escape_bundle_line(&shell_command_line(program, args))
+}
+
+fn escape_bundle_line(line: &str) -> String {
+    line.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn load_bundle(dir: &std::path::Path) -> io::Result<HashMap<String, VecDeque<BundleEntry>>> {
+    let index = fs::read_to_string(dir.join("index.tsv"))?;
+    let mut entries = HashMap::<String, VecDeque<BundleEntry>>::new();
+    for line in index.lines() {
+        let mut parts = line.splitn(3, '\t');
+        let (Some(seq), Some(status), Some(command)) = (parts.next(), parts.next(), parts.next())
+        else {
+            continue;
+        };
+        let entry = BundleEntry {
+            exit_code: status.parse().ok(),
+            missing: status == "missing",
+            stdout: fs::read(dir.join(format!("{seq}.stdout")))?,
+            stderr: fs::read(dir.join(format!("{seq}.stderr")))?,
+        };
+        entries
+            .entry(command.to_string())
+            .or_default()
+            .push_back(entry);
+    }
+    Ok(entries)
+}
+
+fn bundle_output(entry: BundleEntry) -> io::Result<Output> {
+    let Some(code) = entry.exit_code else {
+        let message = String::from_utf8_lossy(&entry.stderr).into_owned();
+        return Err(if entry.missing {
+            io::Error::new(io::ErrorKind::NotFound, message)
+        } else {
+            io::Error::other(message)
+        });
+    };
+    Ok(Output {
+        status: std::process::ExitStatus::from_raw((code & 0xff) << 8),
+        stdout: entry.stdout,
+        stderr: entry.stderr,
+    })
+}
+
+// Every state-changing external command goes through here so --dry-run covers it.
+fn run_action(dry_run: bool, program: &str, args: &[&str]) -> io::Result<()> {
+    if dry_run {
+        log_event(
+            "info",
+            &format!("dry-run: {}", shell_command_line(program, args)),
+            None,
+        );
+        return Ok(());
+    }
+    run_cmd(program, args).map(|_| ())
+}
+
+fn shell_command_line(program: &str, args: &[&str]) -> String {
+    let mut out = shell_quote(program);
+    for arg in args {
+        out.push(' ');
+        out.push_str(&shell_quote(arg));
+    }
+    out
+}
+
+fn shell_quote(s: &str) -> String {
+    let safe = !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=,@%+".contains(c));
+    if safe {
+        s.to_string()
+    } else {
+        format!("'{}'", s.replace('\'', "'\\''"))
+    }
+}
+
+// The session, window, or pane this command runs in ($TMUX_PANE), rather than
+// whichever client tmux considers current, in the same form as pane targets.
+fn current_tmux_target(scope: TmuxScope) -> Result<String, String> {
+    let (flag, format) = match scope {
+        TmuxScope::All => unreachable!("unscoped runs skip the lookup"),
+        TmuxScope::Session => ("--current-session", "#{session_name}"),
+        TmuxScope::Window => ("--current-window", "#{session_name}:#{window_index}"),
+        TmuxScope::Pane => (
+            "--current-pane",
+            "#{session_name}:#{window_index}.#{pane_index}",
+        ),
+    };
+    if env::var_os("TMUX").is_none() {
+        return Err(format!("{flag} only works inside tmux ($TMUX is not set)"));
+    }
+    // $TMUX is "<socket>,<server pid>,<session>": ask the server this pane is on.
+    let tmux = env::var("TMUX").unwrap_or_default();
+    let socket = tmux.split(',').next().filter(|s| !s.is_empty());
+    let pane = env::var("TMUX_PANE").unwrap_or_default();
+    let mut args = vec!["display-message", "-p"];
+    if !pane.is_empty() {
+        args.extend(["-t", pane.as_str()]);
+    }
+    args.push(format);
+    tmux_cmd(socket, &args)
+        .ok()
+        .map(|out| out.trim().to_string())
+        .filter(|target| !target.is_empty())
+        .ok_or_else(|| format!("{flag}: could not resolve the current tmux target"))
+}
+
+// Targets are "session:window.pane" and tmux forbids ':' and '.' in session
+// names, so the scope's own shape says whether it names a session, a window, or a pane.
+fn in_tmux_scope(target: &str, scope: Option<&str>) -> bool {
+    scope.is_none_or(|scope| {
+        let next = if scope.contains(':') { '.' } else { ':' };
+        target == scope
+            || target
+                .strip_prefix(scope)
+                .is_some_and(|rest| rest.starts_with(next))
+    })
+}
+
+// A control-mode client with no-output displays nothing: our own pane queries
+// (see open_tmux_control) or another monitoring daemon.
+fn is_monitor_client(flags: &str) -> bool {
+    let flags = flags.split(',').collect::<Vec<_>>();
+    flags.contains(&"control-mode") && flags.contains(&"no-output")
+}
+
+const LIST_PANES_FORMAT: &str = "#{session_name}:#{window_index}.#{pane_index}\t#{window_name}\t#{pane_pid}\t#{history_size}\t#{history_limit}\t#{session_created}\t#{session_attached}";
+
+// With no default server (tmux started with -L/-S, or another TMUX_TMPDIR), the
+// panes of every live server found by socket discovery are used instead.
+fn list_tmux_panes() -> io::Result<Vec<PaneInfo>> {
+    let err = match run_cmd("tmux", &["list-panes", "-a", "-F", LIST_PANES_FORMAT]) {
+        Ok(raw) => return Ok(parse_tmux_panes(&raw, None)),
+        Err(e) => e,
+    };
+    let mut panes = Vec::new();
+    for socket in tmux_sockets_in(&tmux_socket_dirs()) {
+        if let Ok(raw) = tmux_cmd(
+            Some(&socket),
+            &["list-panes", "-a", "-F", LIST_PANES_FORMAT],
+        ) {
+            panes.extend(parse_tmux_panes(&raw, Some(&socket)));
+        }
+    }
+    if panes.is_empty() {
+        return Err(err);
+    }
+    Ok(panes)
+}
+
+// A tmux command against the server at `socket`, or the default one for None.
+fn tmux_cmd(socket: Option<&str>, args: &[&str]) -> io::Result<String> {
+    match socket {
+        Some(socket) => run_cmd("tmux", &[&["-S", socket], args].concat()),
+        None => run_cmd("tmux", args),
+    }
+}
+
+// The servers the reported panes live on. With none in tmux (or --badge-clear),
+// whichever servers list_tmux_panes reaches; the default one if it reaches none.
+fn tmux_servers(panes: &[PaneRecord]) -> Vec<Option<String>> {
+    let mut servers = panes
+        .iter()
+        .filter(|p| p.tmux_target != "?")
+        .map(|p| p.tmux_socket.clone())
+        .collect::<Vec<_>>();
+    if servers.is_empty() {
+        servers = list_tmux_panes()
+            .map(|panes| panes.into_iter().map(|p| p.socket).collect())
+            .unwrap_or_default();
+    }
+    servers.sort();
+    servers.dedup();
+    if servers.is_empty() {
+        servers.push(None);
+    }
+    servers
+}
+
+// Prefixes tmux actions planned from one server's listing with its -S socket.
+fn on_tmux_server(actions: &mut [PlannedAction], socket: Option<&str>) {
+    if let Some(socket) = socket {
+        for action in actions {
+            action
+                .args
+                .splice(0..0, ["-S".to_string(), socket.to_string()]);
+        }
+    }
+}
+
+// Replies in command order, or None when the cache has no control client (a
+// single run) or it failed; callers then fork tmux as usual. A client that fails
+// is dropped and reopened on the next query.
+fn tmux_control_query(
+    cache: &mut CollectCache,
+    commands: &[Vec<&str>],
+) -> Option<Vec<Result<String, String>>> {
+    if !cache.persistent_tmux || commands.is_empty() {
+        return None;
+    }
+    if cache.tmux_control.is_none() {
+        cache.tmux_control = open_tmux_control().ok();
+    }
+    let control = cache.tmux_control.as_mut()?;
+    let script = commands
+        .iter()
+        .map(|args| {
+            let words = args.iter().map(|a| shell_quote(a)).collect::<Vec<_>>();
+            format!("{}\n", words.join(" "))
+        })
+        .collect::<String>();
+    let replies = control
+        .stdin
+        .write_all(script.as_bytes())
+        .and_then(|_| control.stdin.flush())
+        .and_then(|_| read_control_replies(&mut control.stdout, commands.len()));
+    match replies {
+        Ok(replies) => Some(replies),
+        Err(_) => {
+            if let Some(mut control) = cache.tmux_control.take() {
+                let _ = control.child.kill();
+                let _ = control.child.wait();
+            }
+            None
+        }
+    }
+}
+
+// A control client must be attached to some session, or tmux drops it once its
+// first command is done. It gets a session of its own, one pane running `cat`,
+// that tmux destroys when the client goes away, so the user's sessions never
+// count it as attached and their client-attached hooks never see it. Pane
+// listings leave the session out. tmux before 3.2 rejects -f and exits, which
+// the first query sees as EOF.
+const TMUX_CONTROL_SESSION_PREFIX: &str = "opencode-tmux-mem-control-";
+
+fn open_tmux_control() -> io::Result<TmuxControl> {
+    let session = format!("{TMUX_CONTROL_SESSION_PREFIX}{}", std::process::id());
+    let mut child = subprocess("tmux")
+        .args(["-C", "new-session", "-s", &session])
+        .args(["-f", "no-output,ignore-size", "cat"])
+        .args([";", "set-option", "destroy-unattached", "on"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null())
+        .spawn()?;
+    let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err(io::Error::other("tmux control client has no pipes"));
+    };
+    Ok(TmuxControl {
+        child,
+        stdin,
+        stdout: BufReader::new(TimedStdout(stdout)),
+    })
+}
+
+// A server that stops answering would hang the tick; after this long without a
+// line the client is dropped and the queries fork tmux instead.
+const TMUX_CONTROL_TIMEOUT: Duration = Duration::from_secs(5);
+
+const POLLIN: i16 = 1;
+
+// The control client's stdout, with TMUX_CONTROL_TIMEOUT as a read timeout.
+#[derive(Debug)]
+struct TimedStdout(std::process::ChildStdout);
+
+impl io::Read for TimedStdout {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut fd = PollFd {
+            fd: self.0.as_raw_fd(),
+            events: POLLIN,
+            revents: 0,
+        };
+        let timeout = TMUX_CONTROL_TIMEOUT.as_millis() as i32;
+        loop {
+            // SAFETY: `fd` is one valid pollfd that outlives the call.
+            match unsafe { poll(&mut fd, 1, timeout) } {
+                0 => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "tmux control client stopped answering",
+                    ))
+                }
+                n if n > 0 => return self.0.read(buf),
+                _ => {
+                    let e = io::Error::last_os_error();
+                    if e.kind() != io::ErrorKind::Interrupted {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Each reply is a block from `%begin <time> <number> <flags>` to a matching
+// %end (success) or %error line. Blocks with flags 0 (the attach itself) and
+// notifications such as %session-changed between blocks are skipped.
+fn read_control_replies(
+    reader: &mut impl BufRead,
+    count: usize,
+) -> io::Result<Vec<Result<String, String>>> {
+    let mut replies = Vec::with_capacity(count);
+    let mut block: Option<(String, String)> = None;
+    let mut raw = Vec::new();
+    while replies.len() < count {
+        raw.clear();
+        if reader.read_until(b'\n', &mut raw)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "tmux control client exited",
+            ));
+        }
+        let line = String::from_utf8_lossy(&raw);
+        let line = line.strip_suffix('\n').unwrap_or(&line);
+        let Some((guard, body)) = &mut block else {
+            if let Some(rest) = line.strip_prefix("%begin ") {
+                block = Some((rest.to_string(), String::new()));
+            }
+            continue;
+        };
+        let closing = line
+            .strip_prefix("%end ")
+            .map(|rest| (rest, true))
+            .or_else(|| line.strip_prefix("%error ").map(|rest| (rest, false)));
+        match closing {
+            Some((rest, ok)) if rest == guard.as_str() => {
+                if rest.split_whitespace().nth(2) == Some("1") {
+                    let body = std::mem::take(body);
+                    replies.push(if ok {
+                        Ok(body)
+                    } else {
+                        Err(body.trim().to_string())
+                    });
+                }
+                block = None;
+            }
+            _ => {
+                body.push_str(line);
+                body.push('\n');
+            }
+        }
+    }
+    Ok(replies)
+}
+
+extern "C" {
+    fn getuid() -> u32;
+}
+
+// Where tmux creates sockets: $TMUX_TMPDIR/tmux-<uid>, then the /tmp default.
+fn tmux_socket_dirs() -> Vec<String> {
+    // SAFETY: getuid has no preconditions and cannot fail.
+    let uid = unsafe { getuid() };
+    let mut dirs = env::var("TMUX_TMPDIR")
+        .ok()
+        .filter(|d| !d.is_empty())
+        .map(|d| format!("{}/tmux-{uid}", d.trim_end_matches('/')))
+        .into_iter()
+        .collect::<Vec<_>>();
+    let fallback = format!("/tmp/tmux-{uid}");
+    if !dirs.contains(&fallback) {
+        dirs.push(fallback);
+    }
+    dirs
+}
+
+// Stale sockets of dead servers are still listed; list-panes just fails on them.
+fn tmux_sockets_in(dirs: &[String]) -> Vec<String> {
+    use std::os::unix::fs::FileTypeExt;
+    let mut sockets = dirs
+        .iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_socket()))
+        .map(|entry| entry.path().display().to_string())
+        .collect::<Vec<_>>();
+    sockets.sort();
+    sockets
+}
+
+fn parse_tmux_panes(raw: &str, socket: Option<&str>) -> Vec<PaneInfo> {
+    let mut panes = Vec::new();
+    for line in raw.lines() {
+        let mut parts = line.split('\t');
+        let target = parts.next().unwrap_or("").to_string();
+        // Reports show the name without our own --badge-windows suffix.
+        let window_name = strip_window_badge(parts.next().unwrap_or("")).to_string();
+        let pane_pid = parts
+            .next()
+            .unwrap_or("0")
+            .trim()
+            .parse::<i32>()
+            .unwrap_or(0);
+        let history_size = parts
+            .next()
+            .unwrap_or("0")
+            .trim()
+            .parse::<i64>()
+            .unwrap_or(0);
+        let history_limit = parts
+            .next()
+            .unwrap_or("0")
+            .trim()
+            .parse::<i64>()
+            .unwrap_or(0);
+        let session_created = parts.next().and_then(|v| v.trim().parse::<u64>().ok());
+        // session_attached counts the clients showing the session.
+        let session_attached = parts
+            .next()
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .map(|clients| clients > 0);
+        let control_session = target.starts_with(TMUX_CONTROL_SESSION_PREFIX);
+        if !target.is_empty() && pane_pid > 0 && !control_session {
+            panes.push(PaneInfo {
+                target,
+                window_name,
+                pane_pid,
+                history_size,
+                history_limit,
+                session_created,
+                session_attached,
+                socket: socket.map(str::to_string),
+            });
+        }
+    }
+    panes
+}
+
+// Evaluates a tmux format for every pane of one server, keyed by target like PaneInfo.
+fn pane_format_values(socket: Option<&str>, format: &str) -> io::Result<HashMap<String, String>> {
+    let raw = tmux_cmd(
+        socket,
+        &[
+            "list-panes",
+            "-a",
+            "-F",
+            &format!("#{{session_name}}:#{{window_index}}.#{{pane_index}}\t{format}"),
+        ],
+    )?;
+    Ok(raw
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .map(|(target, value)| (target.to_string(), value.to_string()))
+        .collect())
+}
+
+// Processes outside tmux, and panes the key leaves empty, share a "-" group.
+fn group_panes(
+    panes: &[PaneRecord],
+    rows: &[ProcRecord],
+    mut key_of: impl FnMut(&PaneRecord) -> Option<String>,
+) -> Vec<GroupRecord> {
+    let command_of = rows
+        .iter()
+        .map(|r| (r.pid, r.command_name.as_str()))
+        .collect::<HashMap<_, _>>();
+    let mut groups = HashMap::<String, GroupRecord>::new();
+    for pane in panes {
+        let key = key_of(pane)
+            .filter(|k| !k.is_empty())
+            .unwrap_or_else(|| "-".to_string());
+        let group = groups.entry(key.clone()).or_insert_with(|| GroupRecord {
+            key,
+            ..GroupRecord::default()
+        });
+        group.pane_count += 1;
+        group.process_count += pane.process_count;
+        group.swap_bytes = group.swap_bytes.saturating_add(pane.swap_bytes);
+        group.physical_bytes = group.physical_bytes.saturating_add(pane.physical_bytes);
+        group.rss_bytes = group.rss_bytes.saturating_add(pane.rss_bytes);
+        group.pane_history_bytes = group
+            .pane_history_bytes
+            .saturating_add(pane.pane_history_bytes);
+        for name in pane.pids.iter().filter_map(|pid| command_of.get(pid)) {
+            match group.commands.iter_mut().find(|(n, _)| n == name) {
+                Some((_, count)) => *count += 1,
+                None => group.commands.push((name.to_string(), 1)),
+            }
+        }
+    }
+    let mut groups = groups.into_values().collect::<Vec<_>>();
+    for group in &mut groups {
+        group
+            .commands
+            .sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    }
+    groups.sort_by(|a, b| {
+        footprint_bytes(b.swap_bytes, b.physical_bytes, b.rss_bytes)
+            .cmp(&footprint_bytes(
+                a.swap_bytes,
+                a.physical_bytes,
+                a.rss_bytes,
+            ))
+            .then_with(|| a.key.cmp(&b.key))
+    });
+    groups
+}
+
+// Panes added up per tmux session, each carrying its session's age and attach state.
+fn session_groups(panes: &[PaneRecord], rows: &[ProcRecord]) -> Vec<GroupRecord> {
+    let session_of = |pane: &PaneRecord| {
+        pane.tmux_target
+            .split_once(':')
+            .map(|(session, _)| session.to_string())
+    };
+    let mut groups = group_panes(panes, rows, session_of);
+    for group in &mut groups {
+        if let Some(pane) = panes
+            .iter()
+            .find(|p| session_of(p).as_deref() == Some(group.key.as_str()))
+        {
+            group.session_created = pane.session_created;
+            group.session_attached = pane.session_attached;
+        }
+    }
+    groups
+}
+
+fn session_age_field(created: Option<u64>) -> Field {
+    created.map_or(Field::Null, |c| Field::Uint(unix_now().saturating_sub(c)))
+}
+
+fn session_age_cell(created: Option<u64>) -> String {
+    created.map_or("-".to_string(), |c| {
+        human_duration(unix_now().saturating_sub(c))
+    })
+}
+
+fn session_attached_cell(attached: Option<bool>) -> String {
+    match attached {
+        Some(true) => "yes",
+        Some(false) => "no",
+        None => "-",
+    }
+    .to_string()
+}
+
+// Set on a window we badged while tmux was still naming it after its command,
+// so stripping the badge can hand the name back to `automatic-rename`.
+const WINDOW_AUTONAME_OPTION: &str = "@opencode-tmux-mem-autoname";
+
+// One tmux window as --badge-windows sees it.
+#[derive(Debug, Clone, Default)]
+struct BadgeWindow {
+    target: String,
+    name: String,
+    automatic_rename: bool,
+    autoname_saved: bool,
+}
+
+// Renames only windows whose badge changes; `threshold` None strips every badge.
+fn badge_windows(
+    panes: &[PaneRecord],
+    threshold: Option<u64>,
+    dry_run: bool,
+    yes: bool,
+) -> Result<(), String> {
+    let mut actions = Vec::new();
+    for socket in tmux_servers(panes) {
+        let raw = tmux_cmd(
+            socket.as_deref(),
+            &[
+                "list-windows",
+                "-a",
+                "-F",
+                &format!(
+                    "#{{session_name}}:#{{window_index}}\t#{{automatic-rename}}\t#{{{WINDOW_AUTONAME_OPTION}}}\t#{{window_name}}"
+                ),
+            ],
+        )
+        .map_err(|e| format!("failed to list tmux windows: {e}"))?;
+        let windows = raw
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.splitn(4, '\t');
+                Some(BadgeWindow {
+                    target: fields.next()?.to_string(),
+                    automatic_rename: fields.next()? == "1",
+                    autoname_saved: !fields.next()?.is_empty(),
+                    name: fields.next()?.to_string(),
+                })
+            })
+            .collect::<Vec<_>>();
+        let on_server = panes
+            .iter()
+            .filter(|p| p.tmux_socket == socket)
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut planned = window_badge_actions(&on_server, &windows, threshold);
+        on_tmux_server(&mut planned, socket.as_deref());
+        actions.extend(planned);
+    }
+    if confirm_actions_stdin(&actions, dry_run, yes)? {
+        execute_actions(&actions, dry_run);
+    }
+    Ok(())
+}
+
+// One chained tmux command per window whose badge changes. Renaming turns
+// `automatic-rename` off, so the first badge remembers it was on and the last
+// one turns it back on.
+fn window_badge_actions(
+    panes: &[PaneRecord],
+    windows: &[BadgeWindow],
+    threshold: Option<u64>,
+) -> Vec<PlannedAction> {
+    let mut actions = Vec::new();
+    for window in windows {
+        let target = window.target.as_str();
+        let footprint = panes
+            .iter()
+            .filter(|p| p.tmux_target.rsplit_once('.').map(|(w, _)| w) == Some(target))
+            .map(|p| footprint_bytes(p.swap_bytes, p.physical_bytes, p.rss_bytes))
+            .sum::<u64>();
+        let clean = strip_window_badge(&window.name);
+        let badged = matches!(threshold, Some(limit) if footprint > 0 && footprint >= limit);
+        let wanted = if badged {
+            format!("{clean} [{}]", compact_bytes(footprint))
+        } else {
+            clean.to_string()
+        };
+        if wanted == window.name {
+            continue;
+        }
+        let mut args = ["rename-window", "-t", target, &wanted]
+            .map(String::from)
+            .to_vec();
+        if badged && window.automatic_rename && !window.autoname_saved {
+            args.extend(
+                [
+                    ";",
+                    "set-window-option",
+                    "-t",
+                    target,
+                    WINDOW_AUTONAME_OPTION,
+                    "on",
+                ]
+                .map(String::from),
+            );
+        } else if !badged && window.autoname_saved {
+            for option in ["automatic-rename", WINDOW_AUTONAME_OPTION] {
+                args.extend(
+                    [";", "set-window-option", "-u", "-t", target, option].map(String::from),
+                );
+            }
+        }
+        actions.push(PlannedAction {
+            action: if badged {
+                "badge-window"
+            } else {
+                "clear-badge"
+            },
+            target: format!("window {target}"),
+            program: "tmux",
+            args,
+            metric: "footprint",
+            before: footprint,
+        });
+    }
+    actions
+}
+
+// Drops a trailing " [2.1G]"-style badge; other bracketed suffixes stay.
+fn strip_window_badge(name: &str) -> &str {
+    let Some((clean, badge)) = name.rsplit_once(" [") else {
+        return name;
+    };
+    let is_badge = badge.strip_suffix(']').is_some_and(|size| {
+        size.strip_suffix(['B', 'K', 'M', 'G', 'T'])
+            .is_some_and(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit() || c == '.'))
+    });
+    if is_badge {
+        clean
+    } else {
+        name
+    }
+}
+
+// A pane user option remembers which level we marked, so marks from an earlier
+// run (or tick) are cleared once the pane recovers or its processes exit.
+const PANE_MARK_OPTION: &str = "@opencode-tmux-mem-mark";
+const PANE_BORDER_OPTIONS: [&str; 2] = ["pane-border-style", "pane-active-border-style"];
+
+fn mark_panes(panes: &[PaneRecord], dry_run: bool, yes: bool) -> Result<(), String> {
+    let mut actions = Vec::new();
+    for socket in tmux_servers(panes) {
+        let marked = pane_format_values(socket.as_deref(), &format!("#{{{PANE_MARK_OPTION}}}"))
+            .map_err(|e| format!("failed to read pane marks from tmux: {e}"))?;
+        let on_server = panes
+            .iter()
+            .filter(|p| p.tmux_socket == socket)
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut planned = pane_mark_actions(&on_server, &marked);
+        on_tmux_server(&mut planned, socket.as_deref());
+        actions.extend(planned);
+    }
+    if confirm_actions_stdin(&actions, dry_run, yes)? {
+        execute_actions(&actions, dry_run);
+    }
+    Ok(())
+}
+
+// One chained tmux command per pane whose mark changes; `marked` maps every
+// pane to its current mark ("" when unmarked).
+fn pane_mark_actions(panes: &[PaneRecord], marked: &HashMap<String, String>) -> Vec<PlannedAction> {
+    let mut targets = marked.keys().collect::<Vec<_>>();
+    targets.sort();
+    let mut actions = Vec::new();
+    for target in targets {
+        let wanted = panes
+            .iter()
+            .find(|p| &p.tmux_target == target)
+            .and_then(|p| p.severity)
+            .filter(|s| *s > Severity::Ok);
+        let wanted_name = wanted.map_or("", severity_name);
+        if marked[target] == wanted_name {
+            continue;
+        }
+        let mut args = Vec::new();
+        for option in PANE_BORDER_OPTIONS.iter().chain([&PANE_MARK_OPTION]) {
+            if !args.is_empty() {
+                args.push(";".to_string());
+            }
+            args.extend(["set-option", "-p"].map(String::from));
+            match wanted {
+                Some(severity) => {
+                    let value = match (*option, severity) {
+                        (PANE_MARK_OPTION, _) => wanted_name,
+                        (_, Severity::Crit) => "fg=red",
+                        _ => "fg=yellow",
+                    };
+                    args.extend(["-t", target, option, value].map(String::from));
+                }
+                None => args.extend(["-u", "-t", target, option].map(String::from)),
+            }
+        }
+        actions.push(PlannedAction {
+            action: if wanted.is_some() {
+                "mark-pane"
+            } else {
+                "unmark-pane"
+            },
+            target: format!("pane {target}"),
+            program: "tmux",
+            args,
+            metric: "pane_mark",
+            before: 0,
+        });
+    }
+    actions
+}
+
+// The nearest enclosing git checkout (a `.git` directory, or file for worktrees),
+// else the directory itself.
+fn project_root(dir: &std::path::Path) -> String {
+    dir.ancestors()
+        .find(|d| d.join(".git").exists())
+        .unwrap_or(dir)
+        .to_string_lossy()
+        .to_string()
+}
+
+fn parse_match_mode(raw: &str) -> Option<MatchMode> {
+    match raw.to_ascii_lowercase().as_str() {
+        "exact" => Some(MatchMode::Exact),
+        "full" | "regex" => Some(MatchMode::Full),
+        "glob" => Some(MatchMode::Glob),
+        "full-word" => Some(MatchMode::FullWord),
+        _ => None,
+    }
+}
+
+// "glob:*lsp*" carries its own mode; anything else uses --match-mode. Only the
+// known mode names count as a prefix, so "node:inspect" stays one pattern.
+fn split_pattern_mode(raw: &str, default: MatchMode) -> (MatchMode, String) {
+    raw.split_once(':')
+        .and_then(|(prefix, pattern)| Some((parse_match_mode(prefix)?, pattern.to_string())))
+        .unwrap_or_else(|| (default, raw.to_string()))
+}
+
+// The union of every selector's matches: --process, --port, --cwd, --open-file.
+fn discover_pids(cli: &Cli) -> io::Result<Vec<i32>> {
+    let mut pids = Vec::new();
+    for (mode, pattern) in &cli.process_patterns {
+        pids.extend(pgrep_pattern(
+            pattern,
+            *mode,
+            cli.user.as_deref(),
+            cli.ignore_case,
+        )?);
+    }
+    for port in &cli.ports {
+        pids.extend(listening_pids(*port)?);
+    }
+    if !cli.cwd_paths.is_empty() {
+        pids.extend(
+            process_cwds()?
+                .into_iter()
+                .filter(|(_, cwd)| cli.cwd_paths.iter().any(|dir| cwd.starts_with(dir)))
+                .map(|(pid, _)| pid),
+        );
+    }
+    for path in &cli.open_files {
+        pids.extend(file_holder_pids(path)?);
+    }
+    pids.sort_unstable();
+    pids.dedup();
+    if !cli.env_filters.is_empty() {
+        let keys = cli
+            .env_filters
+            .iter()
+            .map(|(key, _)| key.as_str())
+            .collect::<Vec<_>>();
+        pids.retain(|&pid| match process_environ(pid, &keys) {
+            Some(environ) => env_matches(&environ, &cli.env_filters),
+            None => {
+                warn(format!(
+                    "cannot read the environment of PID {pid}; --env leaves it out"
+                ));
+                false
+            }
+        });
+    }
+    Ok(pids)
+}
+
+// A process's environment as KEY=VALUE entries: /proc/<pid>/environ on Linux,
+// `ps eww` on macOS. ps appends the variables to the command line, split on
+// spaces, so there a value containing a space can't match, and the command's own
+// words (counted from a plain `ps -o command=`) are skipped so an argument that
+// looks like KEY=VALUE is not taken for the environment. None when it can't be
+// read (another user's process without root). Only the variables named in `keys`
+// are kept, so a --capture-bundle never holds the rest of the environment.
+fn process_environ(pid: i32, keys: &[&str]) -> Option<Vec<String>> {
+    let wanted = |entry: &[u8]| {
+        keys.iter().any(|key| {
+            entry
+                .strip_prefix(key.as_bytes())
+                .is_some_and(|v| v.starts_with(b"="))
+        })
+    };
+    let path = format!("/proc/{pid}/environ");
+    let read = || {
+        Ok(fs::read(&path)?
+            .split(|b| *b == 0)
+            .filter(|entry| wanted(entry))
+            .flat_map(|entry| entry.iter().copied().chain([0]))
+            .collect())
+    };
+    if let Ok(raw) = read_proc(&path, read) {
+        return Some(
+            raw.split(|b| *b == 0)
+                .filter(|entry| !entry.is_empty())
+                .map(|entry| String::from_utf8_lossy(entry).into_owned())
+                .collect(),
+        );
+    }
+    let pid = pid.to_string();
+    let args = ["eww", "-p", &pid, "-o", "command="];
+    let out = bundled(bundle_key("ps", &args), || {
+        let argv = subprocess("ps")
+            .args(["-ww", "-p", &pid, "-o", "command="])
+            .output()?;
+        let mut out = subprocess("ps").args(args).output()?;
+        out.stdout = environ_words(&out.stdout, &argv.stdout)
+            .filter(|word| wanted(word))
+            .collect::<Vec<_>>()
+            .join(&b' ');
+        Ok(out)
+    })
+    .ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    Some(
+        String::from_utf8_lossy(&out.stdout)
+            .split_whitespace()
+            .map(str::to_string)
+            .collect(),
+    )
+}
+
+// The words `ps eww` prints after the command's own.
+fn environ_words<'a>(eww: &'a [u8], command: &[u8]) -> impl Iterator<Item = &'a [u8]> {
+    let skip = command
+        .split(u8::is_ascii_whitespace)
+        .filter(|word| !word.is_empty())
+        .count();
+    eww.split(u8::is_ascii_whitespace)
+        .filter(|word| !word.is_empty())
+        .skip(skip)
+}
+
+fn env_matches(environ: &[String], filters: &[(String, String)]) -> bool {
+    filters.iter().all(|(key, value)| {
+        environ
+            .iter()
+            .any(|entry| entry.split_once('=') == Some((key.as_str(), value.as_str())))
+    })
+}
+
+// PIDs printed by `lsof -t`; exit status 1 just means nothing matched.
+fn lsof_pids(args: &[&str]) -> io::Result<Vec<i32>> {
+    let out = command_output("lsof", args)?;
+    if !out.status.success() && !out.stderr.is_empty() {
+        return Err(io::Error::other(format!(
+            "lsof {} => {}",
+            args.join(" "),
+            String::from_utf8_lossy(&out.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .filter_map(|line| line.trim().parse().ok())
+        .collect())
+}
+
+// TCP listeners on `port`: lsof where installed, otherwise ss (Linux).
+fn listening_pids(port: u16) -> io::Result<Vec<i32>> {
+    match lsof_pids(&["-nP", "-t", &format!("-iTCP:{port}"), "-sTCP:LISTEN"]) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let raw = run_cmd("ss", &["-Hltnp", "sport", "=", &format!(":{port}")])?;
+            Ok(parse_ss_pids(&raw))
+        }
+        result => result,
+    }
+}
+
+// Every process's working directory that we may read: /proc on Linux, lsof
+// elsewhere. Other users' processes are silently missing without root.
+fn process_cwds() -> io::Result<Vec<(i32, std::path::PathBuf)>> {
+    if cfg!(target_os = "linux") {
+        use std::os::unix::ffi::OsStrExt;
+        // One "pid\0cwd\0" pair per process, so a bundle can hold the whole scan.
+        let raw = read_proc("/proc/*/cwd", || {
+            let mut out = Vec::new();
+            for entry in fs::read_dir("/proc")?.flatten() {
+                let Some(pid) = entry
+                    .file_name()
+                    .to_str()
+                    .and_then(|p| p.parse::<i32>().ok())
+                else {
+                    continue;
+                };
+                if let Ok(cwd) = fs::read_link(entry.path().join("cwd")) {
+                    out.extend_from_slice(format!("{pid}\0").as_bytes());
+                    out.extend_from_slice(cwd.as_os_str().as_bytes());
+                    out.push(0);
+                }
+            }
+            Ok(out)
+        })?;
+        let fields = raw.split(|b| *b == 0).collect::<Vec<_>>();
+        return Ok(fields
+            .chunks_exact(2)
+            .filter_map(|pair| {
+                let pid = std::str::from_utf8(pair[0]).ok()?.parse().ok()?;
+                Some((pid, std::ffi::OsStr::from_bytes(pair[1]).into()))
+            })
+            .collect());
+    }
+    let out = command_output("lsof", &["-a", "-d", "cwd", "-F", "pn"])?;
+    Ok(parse_lsof_fields(&String::from_utf8_lossy(&out.stdout))
+        .into_iter()
+        .map(|(pid, path)| (pid, path.into()))
+        .collect())
+}
+
+// Processes with `path` open: lsof where installed, otherwise the /proc fd links.
+fn file_holder_pids(path: &std::path::Path) -> io::Result<Vec<i32>> {
+    let name = path.to_string_lossy();
+    match lsof_pids(&["-t", "--", &name]) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound && cfg!(target_os = "linux") => {
+            let raw = read_proc(&format!("/proc/*/fd {}", shell_quote(&name)), || {
+                let mut out = String::new();
+                for entry in fs::read_dir("/proc")?.flatten() {
+                    let Some(pid) = entry
+                        .file_name()
+                        .to_str()
+                        .and_then(|p| p.parse::<i32>().ok())
+                    else {
+                        continue;
+                    };
+                    let Ok(mut fds) = fs::read_dir(entry.path().join("fd")) else {
+                        continue;
+                    };
+                    if fds.any(|fd| {
+                        fd.is_ok_and(|fd| fs::read_link(fd.path()).is_ok_and(|t| t == path))
+                    }) {
+                        let _ = writeln!(out, "{pid}");
+                    }
+                }
+                Ok(out.into_bytes())
+            })?;
+            Ok(String::from_utf8_lossy(&raw)
+                .lines()
+                .filter_map(|line| line.parse().ok())
+                .collect())
+        }
+        result => result,
+    }
+}
+
+// `lsof -F pn`: a "p<pid>" line followed by one "n<name>" line per file.
+fn parse_lsof_fields(raw: &str) -> Vec<(i32, String)> {
+    let mut out = Vec::new();
+    let mut pid = None;
+    for line in raw.lines() {
+        if let Some(v) = line.strip_prefix('p') {
+            pid = v.parse().ok();
+        } else if let (Some(name), Some(pid)) = (line.strip_prefix('n'), pid) {
+            out.push((pid, name.to_string()));
+        }
+    }
+    out
+}
+
+// ss -p lists owners as users:(("node",pid=4242,fd=21),...).
+fn parse_ss_pids(raw: &str) -> Vec<i32> {
+    let mut pids = raw
+        .split("pid=")
+        .skip(1)
+        .filter_map(|rest| {
+            rest.split(|c: char| !c.is_ascii_digit())
+                .next()?
+                .parse()
+                .ok()
+        })
+        .collect::<Vec<_>>();
+    pids.sort_unstable();
+    pids.dedup();
+    pids
+}
+
+fn pgrep_pattern(
+    pattern: &str,
+    match_mode: MatchMode,
+    user: Option<&str>,
+    ignore_case: bool,
+) -> io::Result<Vec<i32>> {
+    let args = pgrep_args(pattern, match_mode, user, ignore_case);
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    let out = command_output("pgrep", &args)?;
+    if !out.status.success() && out.status.code() == Some(1) {
+        return Ok(Vec::new());
+    }
+    if !out.status.success() {
+        return Err(io::Error::other(
+            String::from_utf8_lossy(&out.stderr).to_string(),
+        ));
+    }
+    let mut pids = Vec::new();
+    for line in String::from_utf8_lossy(&out.stdout).lines() {
+        if let Ok(pid) = line.trim().parse::<i32>() {
+            pids.push(pid);
+        }
+    }
+    pids.sort_unstable();
+    pids.dedup();
+    Ok(pids)
+}
+
+fn pgrep_args(
+    pattern: &str,
+    match_mode: MatchMode,
+    user: Option<&str>,
+    ignore_case: bool,
+) -> Vec<String> {
+    let mut args = Vec::new();
+    if ignore_case {
+        args.push("-i".to_string());
+    }
+    if let Some(user) = user {
+        args.extend(["-u".to_string(), user.to_string()]);
+    }
+    match match_mode {
+        MatchMode::Exact => args.extend(["-x".to_string(), pattern.to_string()]),
+        MatchMode::Full => args.extend(["-f".to_string(), pattern.to_string()]),
+        MatchMode::Glob => args.push(glob_regex(pattern)),
+        MatchMode::FullWord => args.extend(["-f".to_string(), full_word_regex(pattern)]),
+    }
+    args
+}
+
+// A word starts the command line, follows whitespace, or is the last component
+// of argv[0]'s path, and ends at whitespace or the end: `/usr/bin/opencode
+// --continue` matches "opencode", `less /var/log/opencode` and
+// `vim notes/opencode-ideas.md` do not.
+fn full_word_regex(pattern: &str) -> String {
+    format!("(^([^[:space:]]*/)?|[[:space:]])({pattern})([[:space:]]|$)")
+}
+
+// pgrep only speaks extended regexes, so a glob becomes an anchored one.
+fn glob_regex(glob: &str) -> String {
+    let mut regex = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            '.' | '^' | '$' | '+' | '(' | ')' | '[' | ']' | '{' | '}' | '|' | '\\' => {
+                regex.push('\\');
+                regex.push(c);
+            }
+            _ => regex.push(c),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+fn ps_process_table() -> io::Result<Vec<PsEntry>> {
+    let raw = run_cmd("ps", &["-A", "-o", "pid=,ppid=,command="])?;
+    Ok(parse_ps_table(&raw))
+}
+
+fn parse_ps_table(raw: &str) -> Vec<PsEntry> {
+    let mut entries = Vec::new();
+    for line in raw.lines() {
+        let mut parts = line.split_whitespace();
+        let (Some(pid), Some(ppid)) = (parts.next(), parts.next()) else {
+            continue;
+        };
+        let (Ok(pid), Ok(ppid)) = (pid.parse::<i32>(), ppid.parse::<i32>()) else {
+            continue;
+        };
+        entries.push(PsEntry {
+            pid,
+            ppid,
+            command: parts.collect::<Vec<_>>().join(" "),
+        });
+    }
+    entries
+}
+
+// Executable globs for the child processes an app typically spawns.
+fn app_helper_globs(app: AppProfile) -> &'static [&'static str] {
+    match app {
+        AppProfile::Opencode => &[
+            "node",
+            "bun",
+            "deno",
+            "rg",
+            "esbuild",
+            "rust-analyzer",
+            "gopls",
+            "clangd",
+            "zls",
+            "tsserver*",
+            "pyright*",
+            "*language-server*",
+            "*langserver*",
+            "*-lsp",
+        ],
+    }
+}
+
+// Returns (helper pid, app pid) for helpers found below each matched app process.
+fn app_helper_pids(app: AppProfile, app_pids: &[i32], table: &[PsEntry]) -> Vec<(i32, i32)> {
+    let mut children = HashMap::<i32, Vec<&PsEntry>>::new();
+    for entry in table {
+        children.entry(entry.ppid).or_default().push(entry);
+    }
+
+    let globs = app_helper_globs(app);
+    let mut out = Vec::new();
+    for &root in app_pids {
+        let mut stack = vec![root];
+        while let Some(cur) = stack.pop() {
+            for child in children.get(&cur).map(Vec::as_slice).unwrap_or_default() {
+                // A nested app instance is reported as its own group.
+                if app_pids.contains(&child.pid) {
+                    continue;
+                }
+                if globs
+                    .iter()
+                    .any(|g| command_matches_glob(g, &child.command))
+                    && !out.iter().any(|(pid, _)| *pid == child.pid)
+                {
+                    out.push((child.pid, root));
+                }
+                stack.push(child.pid);
+            }
+        }
+    }
+    out
+}
+
+// Executable name prefixes of well-known language servers and their language.
+const LANGUAGE_SERVERS: &[(&str, &str)] = &[
+    ("rust-analyzer", "rust"),
+    ("tsserver", "typescript"),
+    ("typescript-language-server", "typescript"),
+    ("vtsls", "typescript"),
+    ("gopls", "go"),
+    ("pyright", "python"),
+    ("basedpyright", "python"),
+    ("pylsp", "python"),
+    ("jedi-language-server", "python"),
+    ("clangd", "c"),
+    ("lua-language-server", "lua"),
+    ("zls", "zig"),
+    ("jdtls", "java"),
+];
+
+// Login and interactive shells; one at the pane root is the pane's own shell.
+const SHELLS: &[&str] = &["sh", "bash", "zsh", "fish", "dash", "ksh", "tcsh", "nu"];
+
+// An interactive shell at the pane root is `shell`; the app the pane runs (its root,
+// a script the root shell runs, or a direct child) is `app`; --app helpers and
+// anything deeper are `helper`.
+fn process_tag(depth: usize, command: &str, app_helper: bool) -> &'static str {
+    let interactive_shell = SHELLS.contains(&command_name(command).as_str())
+        && command
+            .split_whitespace()
+            .skip(1)
+            .all(|a| a.starts_with('-'));
+    match depth {
+        _ if app_helper => "helper",
+        0 if interactive_shell => "shell",
+        0 | 1 => "app",
+        _ => "helper",
+    }
+}
+
+// Interpreters whose first argument is the real program (e.g. `node tsserver.js`).
+const INTERPRETERS: &[&str] = &["node", "bun", "deno", "python", "python3", "java"];
+
+fn process_role(command: &str) -> Option<String> {
+    let exe = command_name(command);
+    let mut tokens = command
+        .split_whitespace()
+        .skip(1)
+        .map(|t| t.rsplit('/').next().unwrap_or(t));
+    let exe = exe.as_str();
+    let program = if INTERPRETERS.contains(&exe) {
+        tokens.find(|t| !t.starts_with('-')).unwrap_or(exe)
+    } else {
+        exe
+    };
+    LANGUAGE_SERVERS
+        .iter()
+        .find(|(prefix, _)| program.starts_with(prefix))
+        .map(|(_, lang)| format!("lsp:{lang}"))
+}
+
+fn ps_command(pid: i32, wide: bool) -> io::Result<String> {
+    // `-ww` lifts any column-width cap so the full argv comes back.
+    let mut args = vec![
+        "-p".to_string(),
+        pid.to_string(),
+        "-o".to_string(),
+        "command=".to_string(),
+    ];
+    if wide {
+        args.insert(0, "-ww".to_string());
+    }
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    Ok(run_cmd("ps", &args)?.trim().to_string())
+}
+
+fn ps_user(pid: i32) -> io::Result<String> {
+    Ok(run_cmd("ps", &["-p", &pid.to_string(), "-o", "user="])?
+        .trim()
+        .to_string())
+}
+
+// One process's columns from the `ps -A` call each collection makes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct PsCounters {
+    started_at: u64,
+    // (minor, major); major faults are what --pageins shows.
+    faults: Option<(u64, u64)>,
+}
+
+// `etime` is the one elapsed-time column both procps and BSD ps have; `majflt`
+// is the pageins counter on both (macOS ps documents `pagein` as the same).
+fn process_counters(faults: bool) -> io::Result<HashMap<i32, PsCounters>> {
+    let columns = if faults {
+        "pid=,etime=,minflt=,majflt="
+    } else {
+        "pid=,etime="
+    };
+    let raw = run_cmd("ps", &["-A", "-o", columns])?;
+    Ok(parse_ps_counters(&raw, unix_now()))
+}
+
+fn parse_ps_counters(raw: &str, now: u64) -> HashMap<i32, PsCounters> {
+    raw.lines()
+        .filter_map(|line| {
+            let mut cols = line.split_whitespace();
+            let pid = cols.next()?.parse::<i32>().ok()?;
+            let started_at = now.saturating_sub(parse_etime(cols.next()?)?);
+            let mut count = || cols.next().and_then(|n| n.parse::<u64>().ok());
+            let faults = count().zip(count());
+            Some((pid, PsCounters { started_at, faults }))
+        })
+        .collect()
+}
+
+// "[[dd-]hh:]mm:ss" to seconds.
+fn parse_etime(raw: &str) -> Option<u64> {
+    let (days, clock) = match raw.split_once('-') {
+        Some((days, clock)) => (days.parse::<u64>().ok()?, clock),
+        None => (0, raw),
+    };
+    let mut secs = 0u64;
+    let parts = clock.split(':').collect::<Vec<_>>();
+    if !(2..=3).contains(&parts.len()) {
+        return None;
+    }
+    for part in parts {
+        secs = secs * 60 + part.parse::<u64>().ok()?;
+    }
+    Some(days * 86_400 + secs)
+}
+
+fn ps_rss_bytes(pid: i32) -> io::Result<u64> {
+    let raw = run_cmd("ps", &["-p", &pid.to_string(), "-o", "rss="])?;
+    let kb = raw.trim().parse::<u64>().unwrap_or(0);
+    Ok(kb.saturating_mul(1024))
+}
+
+// Counters only grow for one process; a recycled PID starts over and reads as zero.
+fn fault_delta(previous: Option<(u64, u64)>, current: Option<(u64, u64)>) -> Option<(u64, u64)> {
+    let (prev_minor, prev_major) = previous?;
+    let (minor, major) = current?;
+    Some((
+        minor.saturating_sub(prev_minor),
+        major.saturating_sub(prev_major),
+    ))
+}
+
+// (read, written) bytes that reached storage. Linux needs the same user (or
+// root) to read /proc/<pid>/io; None when it can't be read.
+fn process_disk_io(pid: i32) -> Option<(u64, u64)> {
+    if let Ok(raw) = read_proc_string(&format!("/proc/{pid}/io")) {
+        return parse_proc_io(&raw);
+    }
+    rusage_disk_io(pid)
+}
+
+fn parse_proc_io(raw: &str) -> Option<(u64, u64)> {
+    let value = |key: &str| {
+        raw.lines()
+            .find_map(|line| line.strip_prefix(key)?.trim().parse::<u64>().ok())
+    };
+    Some((value("read_bytes:")?, value("write_bytes:")?))
+}
+
+#[cfg(target_os = "macos")]
+extern "C" {
+    fn proc_pid_rusage(pid: i32, flavor: i32, buffer: *mut u64) -> i32;
+}
+
+// rusage_info_v2: a 16-byte UUID, then u64 counters ending in
+// ri_diskio_bytesread and ri_diskio_byteswritten.
+#[cfg(target_os = "macos")]
+fn rusage_disk_io(pid: i32) -> Option<(u64, u64)> {
+    const RUSAGE_INFO_V2: i32 = 2;
+    // Bundled as `< proc_pid_rusage <pid>` with "read written" as its contents.
+    let raw = read_proc(&format!("proc_pid_rusage {pid}"), || {
+        let mut info = [0u64; 20];
+        // SAFETY: the buffer is larger than rusage_info_v2 and outlives the call.
+        if unsafe { proc_pid_rusage(pid, RUSAGE_INFO_V2, info.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(format!("{} {}", info[18], info[19]).into_bytes())
+    })
+    .ok()?;
+    let raw = String::from_utf8_lossy(&raw);
+    let mut counters = raw.split_whitespace().map(|n| n.parse::<u64>().ok());
+    Some((counters.next()??, counters.next()??))
+}
+
+#[cfg(not(target_os = "macos"))]
+fn rusage_disk_io(_pid: i32) -> Option<(u64, u64)> {
+    None
+}
+
+fn pageins_cell(pageins: Option<u64>) -> String {
+    pageins.map_or_else(|| "-".to_string(), |n| n.to_string())
+}
+
+fn disk_io_cells(io: Option<(u64, u64)>) -> [String; 2] {
+    match io {
+        Some((read, written)) => [human_bytes(read), human_bytes(written)],
+        None => ["-".to_string(), "-".to_string()],
+    }
+}
+
+fn ps_ppid(pid: i32, cache: &mut HashMap<i32, i32>) -> i32 {
+    if let Some(v) = cache.get(&pid) {
+        return *v;
+    }
+    let ppid = run_cmd("ps", &["-p", &pid.to_string(), "-o", "ppid="])
+        .ok()
+        .and_then(|s| s.trim().parse::<i32>().ok())
+        .unwrap_or(0);
+    cache.insert(pid, ppid);
+    ppid
+}
+
+// A broad --match-mode full pattern can match this reporter or one of the
+// short-lived probes it spawns; neither belongs in the report.
+fn is_self_or_child(pid: i32, ppid_cache: &mut HashMap<i32, i32>) -> bool {
+    let own = std::process::id() as i32;
+    pid == own || ps_ppid(pid, ppid_cache) == own
+}
+
+// The owning pane plus how many parent hops away its root is (0: the root itself).
+fn find_owning_pane(
+    pid: i32,
+    pane_by_pid: &HashMap<i32, PaneInfo>,
+    ppid_cache: &mut HashMap<i32, i32>,
+) -> Option<(PaneInfo, usize)> {
+    // Follow parent PID chain until we hit a pane root PID.
+    let mut cur = pid;
+    let mut guard = 0usize;
+    while cur > 0 && guard < 512 {
+        if let Some(pane) = pane_by_pid.get(&cur) {
+            return Some((pane.clone(), guard));
+        }
+        cur = ps_ppid(cur, ppid_cache);
+        guard += 1;
+    }
+    None
+}
+
+fn vmmap_memory(pid: i32) -> io::Result<VmmapSummary> {
+    let raw = run_cmd("vmmap", &["-summary", &pid.to_string()])?;
+    Ok(parse_vmmap_summary(&raw))
+}
+
+fn parse_vmmap_summary(raw: &str) -> VmmapSummary {
+    let mut swap_bytes = 0u64;
+    let mut physical_bytes = 0u64;
+    let mut gpu_bytes = 0u64;
+    let (mut anon_bytes, mut file_backed_bytes) = (0u64, 0u64);
+    let mut in_regions = false;
+
+    for line in raw.lines() {
+        let t = line.trim_start();
+        if t.starts_with("REGION TYPE") {
+            in_regions = true;
+            continue;
+        }
+        // Metal/IOKit allocations are their own region types; take their RESIDENT column.
+        if t.starts_with("IOAccelerator") || t.starts_with("IOSurface") {
+            let mut sizes = t
+                .split_whitespace()
+                .skip_while(|tok| !tok.starts_with(|c: char| c.is_ascii_digit()));
+            if let Some(resident) = sizes.nth(1) {
+                gpu_bytes += parse_compact_bytes(resident);
+            }
+        }
+        if t.starts_with("Physical footprint:") {
+            if let Some(v) = t.split(':').nth(1) {
+                let tok = v.split_whitespace().next().unwrap_or("0B");
+                physical_bytes = parse_compact_bytes(tok);
+            }
+        }
+        if t.starts_with("TOTAL") && !t.contains("minus reserved") {
+            let cols = t.split_whitespace().collect::<Vec<_>>();
+            if cols.len() >= 5 {
+                swap_bytes = parse_compact_bytes(cols[4]);
+            }
+            break;
+        }
+        if in_regions {
+            if let Some((kind, resident)) = vmmap_region_resident(t) {
+                match kind {
+                    "file" => file_backed_bytes += resident,
+                    _ => anon_bytes += resident,
+                }
+            }
+        }
+    }
+
+    VmmapSummary {
+        swap_bytes,
+        physical_bytes,
+        gpu_bytes,
+        anon_bytes,
+        file_backed_bytes,
+    }
+}
+
+// A summary row is the region type, then VIRTUAL and RESIDENT sizes (always with
+// a unit, unlike the number in a type such as "Memory Tag 253"). Mach-O segments
+// and mapped files are the regions `vmmap -wide` shows with a file path.
+fn vmmap_region_resident(row: &str) -> Option<(&'static str, u64)> {
+    let tokens = row.split_whitespace().collect::<Vec<_>>();
+    let first_size = tokens.iter().position(|tok| {
+        tok.starts_with(|c: char| c.is_ascii_digit())
+            && tok.ends_with(|c: char| c.is_ascii_alphabetic())
+    })?;
+    let resident = parse_compact_bytes(tokens.get(first_size + 1)?);
+    let region_type = tokens[..first_size].join(" ");
+    let kind = if region_type.starts_with("__") || region_type == "mapped file" {
+        "file"
+    } else {
+        "anon"
+    };
+    Some((kind, resident))
+}
+
+// top needs two samples: the first has no interval to measure power over.
+fn top_energy_impact() -> io::Result<HashMap<i32, f64>> {
+    let raw = run_cmd("top", &["-l", "2", "-s", "1", "-stats", "pid,power"])?;
+    Ok(parse_top_power(&raw))
+}
+
+// Each sample is a summary block, a "PID POWER" header, then one row per process;
+// only the rows after the last header count.
+fn parse_top_power(raw: &str) -> HashMap<i32, f64> {
+    let last = raw
+        .lines()
+        .rev()
+        .take_while(|line| !line.trim_start().starts_with("PID"))
+        .collect::<Vec<_>>();
+    last.into_iter()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let pid = parts.next()?.parse().ok()?;
+            let power = parts.next()?.parse().ok()?;
+            Some((pid, power))
+        })
+        .collect()
+}
+
+// Sum over the rows that could be read; None when none could.
+fn sum_disk_io<'a>(rows: impl IntoIterator<Item = &'a ProcRecord>) -> Option<(u64, u64)> {
+    rows.into_iter()
+        .filter_map(|r| r.disk_io)
+        .reduce(|(r1, w1), (r2, w2)| (r1 + r2, w1 + w2))
+}
+
+fn energy_cell(impact: Option<f64>) -> String {
+    impact.map_or_else(|| "-".to_string(), |v| format!("{v:.1}"))
+}
+
+// Per-PID VRAM from NVIDIA's driver; Err when nvidia-smi is missing or fails.
+fn nvidia_gpu_memory() -> io::Result<HashMap<i32, u64>> {
+    let raw = run_cmd(
+        "nvidia-smi",
+        &[
+            "--query-compute-apps=pid,used_memory",
+            "--format=csv,noheader,nounits",
+        ],
+    )?;
+    Ok(parse_nvidia_compute_apps(&raw))
+}
+
+// Lines are "<pid>, <MiB>"; a PID using several GPUs appears once per GPU.
+fn parse_nvidia_compute_apps(raw: &str) -> HashMap<i32, u64> {
+    let mut out = HashMap::new();
+    for line in raw.lines() {
+        let Some((pid, mib)) = line.split_once(',') else {
+            continue;
+        };
+        let (Ok(pid), Ok(mib)) = (pid.trim().parse::<i32>(), mib.trim().parse::<u64>()) else {
+            continue;
+        };
+        *out.entry(pid).or_insert(0) += mib * 1024 * 1024;
+    }
+    out
+}
+
+// Largest mappings first. Linux reads /proc/<pid>/smaps; macOS parses `vmmap -wide`.
+fn process_mappings(pid: i32) -> Result<Vec<MappingRecord>, String> {
+    let smaps_path = format!("/proc/{pid}/smaps");
+    let regions = if fs::metadata("/proc/self/smaps").is_ok() {
+        let raw = read_proc_string(&smaps_path)
+            .map_err(|e| format!("failed reading {smaps_path}: {e}"))?;
+        parse_smaps(&raw)
+    } else {
+        let raw = run_cmd("vmmap", &["-wide", &pid.to_string()])
+            .map_err(|e| format!("failed to run vmmap for pid {pid}: {e}"))?;
+        parse_vmmap_regions(&raw)
+    };
+
+    // Libraries and allocator zones show up as many regions; one row per name reads better.
+    let mut by_name = Vec::<MappingRecord>::new();
+    for region in regions {
+        match by_name
+            .iter_mut()
+            .find(|m| m.name == region.name && m.kind == region.kind)
+        {
+            Some(m) => {
+                m.regions += 1;
+                m.size_bytes += region.size_bytes;
+                m.resident_bytes += region.resident_bytes;
+                m.swap_bytes += region.swap_bytes;
+            }
+            None => by_name.push(region),
+        }
+    }
+    by_name.sort_by(|a, b| {
+        (b.resident_bytes + b.swap_bytes)
+            .cmp(&(a.resident_bytes + a.swap_bytes))
+            .then_with(|| b.size_bytes.cmp(&a.size_bytes))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    Ok(by_name)
+}
+
+// Resident (anonymous, file-backed) bytes. Linux has them in /proc/<pid>/status
+// (shmem counts as file-backed); elsewhere they come from the vmmap summary the
+// sample already has, so no second vmmap runs.
+fn anon_file_bytes(pid: i32, vmmap: Option<&VmmapSummary>) -> Option<(u64, u64)> {
+    if let Ok(raw) = read_proc_string(&format!("/proc/{pid}/status")) {
+        return parse_status_anon_file(&raw);
+    }
+    vmmap.map(|v| (v.anon_bytes, v.file_backed_bytes))
+}
+
+fn parse_status_anon_file(raw: &str) -> Option<(u64, u64)> {
+    let kb = |key: &str| {
+        raw.lines()
+            .find_map(|l| l.strip_prefix(key))
+            .and_then(|v| v.split_whitespace().next()?.parse::<u64>().ok())
+            .map(|v| v * 1024)
+    };
+    let anon = kb("RssAnon:")?;
+    let file = kb("RssFile:")? + kb("RssShmem:").unwrap_or(0);
+    Some((anon, file))
+}
+
+fn mapping_kind(name: &str) -> &'static str {
+    if name.starts_with('/') {
+        "file"
+    } else {
+        "anon"
+    }
+}
+
+fn parse_smaps(raw: &str) -> Vec<MappingRecord> {
+    let mut out = Vec::<MappingRecord>::new();
+    for line in raw.lines() {
+        let mut cols = line.split_whitespace();
+        let Some(first) = cols.next() else {
+            continue;
+        };
+        if first.contains('-') && first.chars().all(|c| c.is_ascii_hexdigit() || c == '-') {
+            let name = cols.skip(4).collect::<Vec<_>>().join(" ");
+            let name = if name.is_empty() {
+                "[anon]".to_string()
+            } else {
+                name
+            };
+            out.push(MappingRecord {
+                kind: mapping_kind(&name),
+                name,
+                regions: 1,
+                ..MappingRecord::default()
+            });
+            continue;
+        }
+        let Some(current) = out.last_mut() else {
+            continue;
+        };
+        let kb = cols.next().and_then(|v| v.parse::<u64>().ok()).unwrap_or(0) * 1024;
+        match first {
+            "Size:" => current.size_bytes = kb,
+            "Rss:" => current.resident_bytes = kb,
+            "Swap:" => current.swap_bytes = kb,
+            _ => {}
+        }
+    }
+    out
+}
+
+// Region lines look like:
+// MALLOC_SMALL  7f8000000-7f8800000  [ 8192K 1234K 1234K 0K] rw-/rwx SM=PRV  DefaultMallocZone
+fn parse_vmmap_regions(raw: &str) -> Vec<MappingRecord> {
+    let mut out = Vec::new();
+    for line in raw.lines() {
+        let (Some(open), Some(close)) = (line.find('['), line.find(']')) else {
+            continue;
+        };
+        if close < open {
+            continue;
+        }
+        let sizes = line[open + 1..close].split_whitespace().collect::<Vec<_>>();
+        if sizes.len() < 4 || !sizes[0].starts_with(|c: char| c.is_ascii_digit()) {
+            continue;
+        }
+        let mut head = line[..open].split_whitespace().collect::<Vec<_>>();
+        head.pop(); // start-end address range
+        let region_type = head.join(" ");
+        let detail = line[close + 1..]
+            .split_once("SM=")
+            .map(|(_, rest)| {
+                rest.split_once(char::is_whitespace)
+                    .map_or("", |(_, d)| d)
+                    .trim()
+            })
+            .unwrap_or("");
+        let name = if detail.is_empty() {
+            region_type
+        } else {
+            detail.to_string()
+        };
+        out.push(MappingRecord {
+            kind: mapping_kind(&name),
+            name,
+            regions: 1,
+            size_bytes: parse_compact_bytes(sizes[0]),
+            resident_bytes: parse_compact_bytes(sizes[1]),
+            swap_bytes: parse_compact_bytes(sizes[3]),
+        });
+    }
+    out
+}
+
+// tmux keeps every cell as a packed grid entry (5 bytes, more for UTF-8 and RGB
+// cells stored out of line) plus a header per line, where capture-pane yields about
+// one byte per ASCII cell. Trailing blanks are not stored, so the real cost lands
+// around 2-3x the captured text; the default errs toward not underestimating.
+const DEFAULT_HISTORY_OVERHEAD: f64 = 3.0;
+
+fn history_estimate(captured: u64, overhead: f64) -> u64 {
+    (captured as f64 * overhead).round() as u64
+}
+
+fn capture_pane(target: &str, socket: Option<&str>, start: &str) -> io::Result<String> {
+    tmux_cmd(
+        socket,
+        &["capture-pane", "-p", "-S", start, "-E", "-", "-t", target],
+    )
+}
+
+// capture-pane -S: the whole history, or with --history-max-lines only its newest lines.
+fn capture_start(max_lines: Option<u64>) -> String {
+    max_lines.map_or("-".to_string(), |n| format!("-{n}"))
+}
+
+// A capture cut short by --history-max-lines stands in for the history lines it
+// skipped: they are assumed to average the same bytes per line as the sample.
+fn sampled_history_bytes(captured: &str, history_size: i64, max_lines: Option<u64>) -> u64 {
+    let bytes = captured.len() as u64;
+    let history = u64::try_from(history_size).unwrap_or(0);
+    let skipped = max_lines.map_or(0, |max| history.saturating_sub(max));
+    let lines = captured.lines().count() as u64;
+    if skipped == 0 || lines == 0 {
+        return bytes;
+    }
+    (u128::from(bytes) * u128::from(lines + skipped) / u128::from(lines)) as u64
+}
+
+fn parse_compact_bytes(token: &str) -> u64 {
+    let t = token.trim();
+    if t.is_empty() {
+        return 0;
+    }
+    if let Ok(v) = t.parse::<u64>() {
+        return v;
+    }
+
+    let (num, unit) = t.split_at(t.len().saturating_sub(1));
+    let n = num.parse::<f64>().unwrap_or(0.0);
+    let m = match unit.to_ascii_uppercase().as_str() {
+        "B" => 1.0,
+        "K" => 1024.0,
+        "M" => 1024.0 * 1024.0,
+        "G" => 1024.0 * 1024.0 * 1024.0,
+        "T" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        _ => 1.0,
+    };
+    (n * m) as u64
+}
+
+// Short sizes for tight spots like window names: "2.1G", "512M", "900K".
+fn compact_bytes(bytes: u64) -> String {
+    let units = ["B", "K", "M", "G", "T"];
+    let mut v = bytes as f64;
+    let mut i = 0usize;
+    while v >= 1024.0 && i < units.len() - 1 {
+        v /= 1024.0;
+        i += 1;
+    }
+    if i > 0 && v < 9.95 {
+        format!("{v:.1}{}", units[i])
+    } else {
+        format!("{v:.0}{}", units[i])
+    }
+}
+
+fn human_bytes(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+    let mut v = bytes as f64;
+    let mut i = 0usize;
+    while v >= 1024.0 && i < units.len() - 1 {
+        v /= 1024.0;
+        i += 1;
+    }
+    if i == 0 {
+        format!("{} {}", bytes, units[i])
+    } else {
+        format!("{v:.2} {}", units[i])
+    }
+}
+
+fn render_options(cli: &Cli) -> RenderOptions {
+    RenderOptions {
+        max_command_width: if cli.wide {
+            None
+        } else {
+            cli.max_command_width
+        },
+        color: use_color(
+            cli.color,
+            cli.stdout_format,
+            env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()),
+            io::stdout().is_terminal(),
+        ),
+        gpu: cli.gpu,
+        energy: cli.energy,
+        disk_io: cli.disk_io,
+        pageins: cli.pageins,
+        bars: cli.bars,
+        percent: cli.percent,
+        cumulative: cli.cumulative,
+        watch: cli.watch.is_some(),
+        table_style: cli.table_style,
+        yaml_style: cli.yaml_style,
+        term_width: if cli.wide || cli.stdout_format != OutputFormat::Table {
+            None
+        } else {
+            terminal_width()
+        },
+        schema_version: cli.schema_version,
+        // Set by run_report once an earlier export is loaded.
+        since_last: false,
+        pids: cli.pids_display,
+        header_labels: cli.header_labels.clone(),
+    }
+}
+
+// COLUMNS wins when exported; otherwise ask the tty (stdout may be piped).
+fn terminal_width() -> Option<usize> {
+    if let Some(cols) = env::var("COLUMNS")
+        .ok()
+        .and_then(|v| v.trim().parse::<usize>().ok())
+        .filter(|n| *n > 0)
+    {
+        return Some(cols);
+    }
+    if !io::stdout().is_terminal() {
+        return None;
+    }
+    let tty = fs::File::open("/dev/tty").ok()?;
+    let out = subprocess("stty").arg("size").stdin(tty).output().ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&out.stdout)
+        .split_whitespace()
+        .nth(1)?
+        .parse::<usize>()
+        .ok()
+        .filter(|n| *n > 0)
+}
+
+// Color is only for tables on a terminal; NO_COLOR (https://no-color.org) wins in auto mode.
+fn use_color(mode: ColorMode, fmt: OutputFormat, no_color: bool, tty: bool) -> bool {
+    if fmt != OutputFormat::Table {
+        return false;
+    }
+    match mode {
+        ColorMode::Always => true,
+        ColorMode::Never => false,
+        ColorMode::Auto => !no_color && tty,
+    }
+}
+
+const BOLD: &str = "1";
+const DIM: &str = "2";
+const RED: &str = "31";
+const GREEN: &str = "32";
+const YELLOW: &str = "33";
+
+fn paint(s: &str, code: &str, on: bool) -> String {
+    if on {
+        format!("\x1b[{code}m{s}\x1b[0m")
+    } else {
+        s.to_string()
+    }
+}
+
+// Swap is the number to act on, so any non-zero value stands out.
+fn swap_style(bytes: u64) -> &'static str {
+    if bytes > 0 {
+        YELLOW
+    } else {
+        DIM
+    }
+}
+
+fn render_process(rows: &[ProcRecord], fmt: OutputFormat, opts: &RenderOptions) -> String {
+    let pinned = |r: &ProcRecord| {
+        let fields = pin_schema(process_fields(r), PROCESS_SCHEMA, opts.schema_version);
+        if opts.since_last {
+            fields
+        } else {
+            without_deltas(fields)
+        }
+    };
+    match fmt {
+        OutputFormat::Table => render_table(rows, opts),
+        OutputFormat::Json => render_fields_json(rows, pinned),
+        OutputFormat::Csv => render_fields_csv(rows, pinned),
+        OutputFormat::Yaml => render_fields_yaml(rows, pinned, opts),
+        OutputFormat::Markdown => render_markdown(rows, opts),
+        OutputFormat::Html => unreachable!("html is only rendered by report --db"),
+        OutputFormat::Porcelain => render_process_porcelain(rows),
+        OutputFormat::MdReport | OutputFormat::Svg => {
+            unreachable!("md-report and svg are rendered in run_report")
+        }
+    }
+}
+
+fn render_pane(rows: &[PaneRecord], fmt: OutputFormat, opts: &RenderOptions) -> String {
+    let pinned = |p: &PaneRecord| {
+        let fields = pin_schema(pane_fields(p), PANE_SCHEMA, opts.schema_version);
+        if opts.since_last {
+            fields
+        } else {
+            without_deltas(fields)
+        }
+    };
+    match fmt {
+        OutputFormat::Table => render_pane_table(rows, None, opts),
+        OutputFormat::Json => render_fields_json(rows, pinned),
+        OutputFormat::Csv => render_fields_csv(rows, pinned),
+        OutputFormat::Yaml => render_fields_yaml(rows, pinned, opts),
+        OutputFormat::Markdown => render_pane_markdown(rows, opts),
+        OutputFormat::Html => unreachable!("html is only rendered by report --db"),
+        OutputFormat::Porcelain => render_pane_porcelain(rows),
+        OutputFormat::MdReport | OutputFormat::Svg => {
+            unreachable!("md-report and svg are rendered in run_report")
+        }
+    }
+}
+
+// Porcelain contract: one tab-separated line per row, no header or totals, raw
+// integers, "-" for unknown values, and the free-text column last. Never reorder
+// these columns; new ones may only be appended before the command.
+fn render_process_porcelain(rows: &[ProcRecord]) -> String {
+    let mut out = String::new();
+    for row in rows {
+        let _ = writeln!(
+            out,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            row.pid,
+            porcelain_text(&row.tmux_target),
+            porcelain_text(&row.tmux_window_name),
+            row.swap_bytes,
+            row.physical_bytes,
+            row.rss_bytes,
+            row.pane_history_bytes,
+            porcelain_count(row.pane_history_size),
+            porcelain_count(row.pane_history_limit),
+            porcelain_text(&row.command),
+        );
+    }
+    out
+}
+
+fn render_pane_porcelain(rows: &[PaneRecord]) -> String {
+    let mut out = String::new();
+    for row in rows {
+        let pids = row
+            .pids
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(",");
+        let _ = writeln!(
+            out,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            porcelain_text(&row.tmux_target),
+            porcelain_text(&row.tmux_window_name),
+            row.process_count,
+            row.swap_bytes,
+            row.physical_bytes,
+            row.rss_bytes,
+            row.pane_history_bytes,
+            porcelain_count(row.pane_history_size),
+            porcelain_count(row.pane_history_limit),
+            if pids.is_empty() {
+                "-".to_string()
+            } else {
+                pids
+            },
+        );
+    }
+    out
+}
+
+// Tabs and newlines would break the line/field contract; "?" is the unknown-pane marker.
+fn porcelain_text(s: &str) -> String {
+    if s.is_empty() || s == "?" {
+        return "-".to_string();
+    }
+    s.replace(['\t', '\n', '\r'], " ")
+}
+
+fn porcelain_count(v: i64) -> String {
+    if v < 0 {
+        "-".to_string()
+    } else {
+        v.to_string()
+    }
+}
+
+fn is_top_level_app(row: &ProcRecord) -> bool {
+    row.tag == Some("app") && row.app_parent_pid.is_none()
+}
+
+// Two top-level apps in one pane usually mean an accidental double launch, which
+// silently doubles the pane's footprint; both get flagged, with one warning per pane.
+fn flag_duplicate_instances(
+    rows: &mut [ProcRecord],
+    panes: &[PaneRecord],
+    warned: &mut Vec<Vec<i32>>,
+) {
+    let mut current = Vec::new();
+    for pane in panes
+        .iter()
+        .filter(|p| p.app_instances > 1 && p.tmux_target != "?")
+    {
+        let mut pids = Vec::new();
+        for row in rows
+            .iter_mut()
+            .filter(|r| r.tmux_target == pane.tmux_target && is_top_level_app(r))
+        {
+            row.duplicate_instance = true;
+            pids.push(row.pid);
+        }
+        pids.sort_unstable();
+        if !warned.contains(&pids) {
+            warn(format!(
+                "pane {} runs {} top-level app processes (pids {}); likely a double launch",
+                pane.tmux_target,
+                pane.app_instances,
+                pids.iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        current.push(pids);
+    }
+    *warned = current;
+}
+
+fn aggregate_by_pane(rows: &[ProcRecord]) -> Vec<PaneRecord> {
+    let mut by_pane = HashMap::<String, PaneRecord>::new();
+    for row in rows {
+        let entry = by_pane
+            .entry(row.tmux_target.clone())
+            .or_insert_with(|| PaneRecord {
+                tmux_target: row.tmux_target.clone(),
+                tmux_socket: row.tmux_socket.clone(),
+                tmux_window_name: row.tmux_window_name.clone(),
+                process_count: 0,
+                pids: Vec::new(),
+                swap_bytes: 0,
+                physical_bytes: 0,
+                rss_bytes: 0,
+                pane_history_size: row.pane_history_size,
+                pane_history_limit: row.pane_history_limit,
+                pane_history_bytes: row.pane_history_bytes,
+                pane_history_estimated_bytes: row.pane_history_estimated_bytes,
+                history_measured: row.history_measured,
+                session_created: row.session_created,
+                session_attached: row.session_attached,
+                shell_rss_bytes: None,
+                severity: None,
+                growth_alert: None,
+                since_last: None,
+                swap_pct: None,
+                physical_pct: None,
+                app_instances: 0,
+            });
+
+        entry.process_count += 1;
+        entry.pids.push(row.pid);
+        if is_top_level_app(row) {
+            entry.app_instances += 1;
+        }
+        entry.swap_bytes = entry.swap_bytes.saturating_add(row.swap_bytes);
+        entry.physical_bytes = entry.physical_bytes.saturating_add(row.physical_bytes);
+        entry.rss_bytes = entry.rss_bytes.saturating_add(row.rss_bytes);
+
+        // Keep the richest pane history metadata and avoid double-counting bytes.
+        if row.pane_history_size > entry.pane_history_size {
+            entry.pane_history_size = row.pane_history_size;
+        }
+        if row.pane_history_limit > entry.pane_history_limit {
+            entry.pane_history_limit = row.pane_history_limit;
+        }
+        if row.pane_history_bytes > entry.pane_history_bytes {
+            entry.pane_history_bytes = row.pane_history_bytes;
+            entry.pane_history_estimated_bytes = row.pane_history_estimated_bytes;
+        }
+    }
+
+    let mut pane_rows = by_pane.into_values().collect::<Vec<_>>();
+    pane_rows.sort_by(|a, b| {
+        b.swap_bytes
+            .cmp(&a.swap_bytes)
+            .then_with(|| b.physical_bytes.cmp(&a.physical_bytes))
+            .then_with(|| a.tmux_target.cmp(&b.tmux_target))
+    });
+    pane_rows
+}
+
+// RSS of each reported pane's root shell (tmux's pane_pid). A pane whose root
+// process was itself matched counts zero, so nothing is added twice.
+fn pane_shell_rss(cli: &Cli, rows: &[ProcRecord]) -> HashMap<String, u64> {
+    let panes = match list_tmux_panes() {
+        Ok(panes) => panes,
+        // As in collect_rows: no tmux or no server is not missing data.
+        Err(e) if !transient_tmux_error(&e) => {
+            warn(format!("pane shells unavailable: {e}"));
+            return HashMap::new();
+        }
+        Err(e) => {
+            warn_partial(format!("pane shells unavailable: {e}"));
+            return HashMap::new();
+        }
+    };
+    let shells = panes
+        .into_iter()
+        .filter(|pane| {
+            rows.iter()
+                .any(|r| r.tmux_target == pane.target && r.tmux_socket == pane.socket)
+        })
+        .collect::<Vec<_>>();
+    let rss = parallel_map(&shells, cli.max_concurrent, |pane| {
+        if rows.iter().any(|r| r.pid == pane.pane_pid) {
+            0
+        } else {
+            ps_rss_bytes(pane.pane_pid).unwrap_or(0)
+        }
+    });
+    shells
+        .into_iter()
+        .map(|pane| pane.target)
+        .zip(rss)
+        .collect()
+}
+
+fn add_shell_memory(panes: &mut [PaneRecord], shells: &HashMap<String, u64>) {
+    for pane in panes {
+        if let Some(&rss) = shells.get(&pane.tmux_target) {
+            pane.shell_rss_bytes = Some(rss);
+            pane.rss_bytes = pane.rss_bytes.saturating_add(rss);
+        }
+    }
+}
+
+fn pane_metric(pane: &PaneRecord, metric: Metric) -> u64 {
+    match metric {
+        Metric::Swap => pane.swap_bytes,
+        Metric::Physical => pane.physical_bytes,
+        Metric::Rss => pane.rss_bytes,
+        Metric::HistoryBytes => pane.pane_history_bytes,
+        Metric::HistoryLines => pane.pane_history_size.max(0) as u64,
+    }
+}
+
+fn process_metric(row: &ProcRecord, metric: Metric) -> u64 {
+    match metric {
+        Metric::Swap => row.swap_bytes,
+        Metric::Physical => row.physical_bytes,
+        Metric::Rss => row.rss_bytes,
+        Metric::HistoryBytes => row.pane_history_bytes,
+        Metric::HistoryLines => row.pane_history_size.max(0) as u64,
+    }
+}
+
+// Worst level across all configured metrics; None when no thresholds are set.
+fn severity_of(
+    thresholds: &[SeverityThreshold],
+    value: impl Fn(Metric) -> u64,
+) -> Option<Severity> {
+    thresholds
+        .iter()
+        .map(|t| {
+            let used = value(t.metric);
+            if t.crit.is_some_and(|c| used > c) {
+                Severity::Crit
+            } else if t.warn.is_some_and(|w| used > w) {
+                Severity::Warn
+            } else {
+                Severity::Ok
+            }
+        })
+        .max()
+}
+
+fn severity_name(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Ok => "ok",
+        Severity::Warn => "warn",
+        Severity::Crit => "crit",
+    }
+}
+
+// Glyph plus word so the level survives monochrome output.
+fn severity_label(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Ok => "✓ OK",
+        Severity::Warn => "! WARN",
+        Severity::Crit => "✗ CRIT",
+    }
+}
+
+fn severity_style(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Ok => GREEN,
+        Severity::Warn => YELLOW,
+        Severity::Crit => RED,
+    }
+}
+
+fn target_session(target: &str) -> &str {
+    target
+        .rsplit_once(':')
+        .map_or(target, |(session, _)| session)
+}
+
+fn command_matches_glob(pattern: &str, command: &str) -> bool {
+    glob_match(pattern, &command_name(command)) || glob_match(pattern, command)
+}
+
+// Basename of argv[0], with the login-shell dash dropped (`-zsh` -> `zsh`).
+fn command_name(command: &str) -> String {
+    let exe = command.split_whitespace().next().unwrap_or("");
+    let base = exe.rsplit('/').next().unwrap_or(exe);
+    base.strip_prefix('-').unwrap_or(base).to_string()
+}
+
+fn evaluate_policy(
+    rows: &[ProcRecord],
+    panes: &[PaneRecord],
+    rules: &[PolicyRule],
+) -> Vec<PolicyBreach> {
+    let mut breaches = Vec::new();
+    for rule in rules {
+        let pane_in_scope = |target: &str, window: &str| {
+            rule.session
+                .as_deref()
+                .is_none_or(|g| target != "?" && glob_match(g, target_session(target)))
+                && rule.window.as_deref().is_none_or(|g| glob_match(g, window))
+        };
+
+        // Process-scoped rules judge each matching process on its own numbers.
+        if let Some(pattern) = rule.process.as_deref() {
+            for row in rows {
+                if !pane_in_scope(&row.tmux_target, &row.tmux_window_name)
+                    || !command_matches_glob(pattern, &row.command)
+                {
+                    continue;
+                }
+                let used = process_metric(row, rule.metric);
+                if used > rule.limit {
+                    breaches.push(PolicyBreach {
+                        rule: rule.name.clone(),
+                        action: rule.action,
+                        metric: rule.metric,
+                        used,
+                        limit: rule.limit,
+                        tmux_target: row.tmux_target.clone(),
+                        pids: vec![row.pid],
+                    });
+                }
+            }
+            continue;
+        }
+
+        for pane in panes {
+            if !pane_in_scope(&pane.tmux_target, &pane.tmux_window_name) {
+                continue;
+            }
+            let used = pane_metric(pane, rule.metric);
+            if used > rule.limit {
+                breaches.push(PolicyBreach {
+                    rule: rule.name.clone(),
+                    action: rule.action,
+                    metric: rule.metric,
+                    used,
+                    limit: rule.limit,
+                    tmux_target: pane.tmux_target.clone(),
+                    pids: pane.pids.clone(),
+                });
+            }
+        }
+    }
+    breaches
+}
+
+fn apply_policy(
+    breaches: &[PolicyBreach],
+    rows: &[ProcRecord],
+    dry_run: bool,
+    yes: bool,
+) -> Result<(), String> {
+    set_log_phase("policy");
+    let mut exit_rule = false;
+    let mut planned = Vec::<PlannedAction>::new();
+    for breach in breaches {
+        let message = format!(
+            "policy: rule '{}' breached by {} ({} {} > {}) -> {}",
+            breach.rule,
+            breach.tmux_target,
+            metric_name(breach.metric),
+            budget_amount(Some(breach.metric), breach.used),
+            budget_amount(Some(breach.metric), breach.limit),
+            policy_action_name(breach.action),
+        );
+        // Text output keeps the "policy: ..." line without a warning prefix.
+        if LOG_JSON.load(Ordering::SeqCst) {
+            log_event("warn", &message, None);
+        } else {
+            eprintln!("{message}");
+        }
+        record_warning(message);
+        match breach.action {
+            PolicyAction::Warn => {}
+            PolicyAction::Exit => exit_rule = true,
+            PolicyAction::Kill => {
+                for pid in &breach.pids {
+                    let target = format!("pid {pid} ({})", breach.tmux_target);
+                    if planned.iter().any(|a| a.target == target) {
+                        continue;
+                    }
+                    let before = rows
+                        .iter()
+                        .find(|r| r.pid == *pid)
+                        .map_or(breach.used, |r| process_metric(r, breach.metric));
+                    planned.push(PlannedAction {
+                        action: "kill",
+                        target,
+                        program: "kill",
+                        args: vec!["-TERM".to_string(), pid.to_string()],
+                        metric: metric_name(breach.metric),
+                        before,
+                    });
+                }
+            }
+            PolicyAction::ClearHistory => {
+                let target = format!("pane {}", breach.tmux_target);
+                if breach.tmux_target == "?" || planned.iter().any(|a| a.target == target) {
+                    continue;
+                }
+                planned.push(PlannedAction {
+                    action: "clear-history",
+                    target,
+                    program: "tmux",
+                    args: vec![
+                        "clear-history".to_string(),
+                        "-t".to_string(),
+                        breach.tmux_target.clone(),
+                    ],
+                    metric: metric_name(breach.metric),
+                    before: breach.used,
+                });
+            }
+        }
+    }
+
+    if confirm_actions_stdin(&planned, dry_run, yes)? {
+        execute_actions(&planned, dry_run);
+    }
+    if exit_rule {
+        mark_breached();
+    }
+    Ok(())
+}
+
+// Every action path asks on stdin; confirm_actions takes the input so tests can answer.
+fn confirm_actions_stdin(
+    actions: &[PlannedAction],
+    dry_run: bool,
+    yes: bool,
+) -> Result<bool, String> {
+    confirm_actions(
+        actions,
+        dry_run,
+        yes,
+        &mut io::stdin().lock(),
+        io::stdin().is_terminal(),
+    )
+}
+
+// Ask once per batch; scripts must opt in with --yes since there is nobody to ask.
+fn confirm_actions(
+    actions: &[PlannedAction],
+    dry_run: bool,
+    yes: bool,
+    input: &mut impl BufRead,
+    is_tty: bool,
+) -> Result<bool, String> {
+    if actions.is_empty() || dry_run || yes {
+        return Ok(true);
+    }
+    eprintln!("About to run {} action(s):", actions.len());
+    for action in actions {
+        eprintln!("  {} {}", action.action, action.target);
+    }
+    if !is_tty {
+        return Err("refusing to run actions without confirmation; pass --yes".to_string());
+    }
+    eprint!("Proceed? [y/N] ");
+    let _ = io::stderr().flush();
+    let mut answer = String::new();
+    input
+        .read_line(&mut answer)
+        .map_err(|e| format!("failed reading confirmation: {e}"))?;
+    let confirmed = matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes");
+    if !confirmed {
+        eprintln!("aborted: no actions were run");
+    }
+    Ok(confirmed)
+}
+
+fn execute_actions(actions: &[PlannedAction], dry_run: bool) -> usize {
+    let mut failures = 0usize;
+    for action in actions {
+        let args = action.args.iter().map(String::as_str).collect::<Vec<_>>();
+        let result = run_action(dry_run, action.program, &args);
+        if !dry_run {
+            append_audit_entry(action, result.as_ref().err());
+        }
+        match result {
+            Ok(()) if !dry_run => log_event(
+                "info",
+                &format!("applied: {}", shell_command_line(action.program, &args)),
+                None,
+            ),
+            Ok(()) => {}
+            Err(e) => {
+                warn(format!("{} {} failed: {e}", action.action, action.target));
+                failures += 1;
+            }
+        }
+    }
+    failures
+}
+
+fn state_dir() -> Option<String> {
+    if let Ok(dir) = env::var("XDG_STATE_HOME") {
+        if !dir.is_empty() {
+            return Some(format!("{dir}/opencode-tmux-mem"));
+        }
+    }
+    env::var("HOME")
+        .ok()
+        .map(|home| format!("{home}/.local/state/opencode-tmux-mem"))
+}
+
+fn audit_log_path() -> Option<String> {
+    state_dir().map(|dir| format!("{dir}/actions.log"))
+}
+
+fn audit_entry_json(action: &PlannedAction, timestamp: &str, error: Option<&io::Error>) -> String {
+    let args = action.args.iter().map(String::as_str).collect::<Vec<_>>();
+    let error = error
+        .map(|e| format!("\"{}\"", escape_json(&e.to_string())))
+        .unwrap_or_else(|| "null".to_string());
+    format!(
+        "{{\"timestamp\":\"{}\",\"action\":\"{}\",\"target\":\"{}\",\"metric\":\"{}\",\"before\":{},\"command\":\"{}\",\"ok\":{},\"error\":{}}}",
+        timestamp,
+        action.action,
+        escape_json(&action.target),
+        action.metric,
+        action.before,
+        escape_json(&shell_command_line(action.program, &args)),
+        error == "null",
+        error,
+    )
+}
+
+// Audit logging must never block the action itself, so failures only warn.
+fn append_audit_entry(action: &PlannedAction, error: Option<&io::Error>) {
+    let Some(path) = audit_log_path() else {
+        return;
+    };
+    let line = audit_entry_json(action, &format_utc_timestamp(unix_now()), error);
+    let result = std::path::Path::new(&path)
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::OpenOptions::new().create(true).append(true).open(&path))
+        .and_then(|mut file| writeln!(file, "{line}"));
+    if let Err(e) = result {
+        warn(format!("failed writing audit log '{path}': {e}"));
+    }
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+// RFC 3339 UTC timestamp without pulling in a date crate.
+fn format_utc_timestamp(secs: u64) -> String {
+    let (year, month, day, hour, minute, second) = utc_parts(secs);
+    format!("{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}Z")
+}
+
+fn utc_parts(secs: u64) -> (i64, i64, i64, u64, u64, u64) {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+    // Civil-from-days conversion (Howard Hinnant's algorithm).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day, rem / 3600, (rem % 3600) / 60, rem % 60)
+}
+
+// strftime-style placeholders in --export paths, in UTC: %Y %m %d %H %M %S,
+// %s (Unix seconds), %h (short hostname), and %% for a literal percent sign. Any
+// other % is kept as written, so `100%.json` stays a valid path.
+fn expand_path_template(template: &str, secs: u64, host: impl Fn() -> String) -> String {
+    let (year, month, day, hour, minute, second) = utc_parts(secs);
+    let mut out = String::new();
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => write!(out, "{year:04}"),
+            Some('m') => write!(out, "{month:02}"),
+            Some('d') => write!(out, "{day:02}"),
+            Some('H') => write!(out, "{hour:02}"),
+            Some('M') => write!(out, "{minute:02}"),
+            Some('S') => write!(out, "{second:02}"),
+            Some('s') => write!(out, "{secs}"),
+            Some('h') => write!(out, "{}", host()),
+            Some('%') => write!(out, "%"),
+            Some(other) => write!(out, "%{other}"),
+            None => write!(out, "%"),
+        }
+        .expect("writing to a String cannot fail");
+    }
+    out
+}
+
+fn short_hostname() -> String {
+    run_cmd("hostname", &[])
+        .ok()
+        .and_then(|out| out.trim().split('.').next().map(str::to_string))
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "localhost".to_string())
+}
+
+// A recording is JSONL: one process row per line, stamped with the collection time.
+fn recording_line(ts: u64, row: &ProcRecord) -> String {
+    let body = without_deltas(process_fields(row))
+        .into_iter()
+        .map(|(name, value)| format!("\"{name}\":{}", json_field(&value)))
+        .collect::<Vec<_>>()
+        .join(",");
+    format!("{{\"ts\":{ts},{body}}}")
+}
+
+fn append_recording(
+    path: &str,
+    ts: u64,
+    rows: &[ProcRecord],
+    wait: Duration,
+) -> Result<(), String> {
+    let mut out = String::new();
+    for row in rows {
+        let _ = writeln!(out, "{}", recording_line(ts, row));
+    }
+    append_locked(path, "recording", wait, |_| out)
+}
+
+// A crash can leave a torn last line, so unreadable lines are skipped with a warning.
+fn load_recording(path: &str) -> Result<Vec<RecordedSample>, String> {
+    let raw =
+        fs::read_to_string(path).map_err(|e| format!("failed reading recording '{path}': {e}"))?;
+    let mut samples = Vec::new();
+    for (idx, line) in raw.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match parse_json_object(line).and_then(|fields| recorded_sample(&fields)) {
+            Ok(sample) => samples.push(sample),
+            Err(e) => warn(format!("{path}:{}: skipping line: {e}", idx + 1)),
+        }
+    }
+    Ok(samples)
+}
+
+fn recorded_sample(fields: &HashMap<String, Field>) -> Result<RecordedSample, String> {
+    let uint = |name: &str| match fields.get(name) {
+        Some(Field::Uint(v)) => Ok(*v),
+        Some(Field::Null) | None if name != "ts" => Ok(0),
+        _ => Err(format!("missing or invalid \"{name}\"")),
+    };
+    let text = |name: &str| match fields.get(name) {
+        Some(Field::Str(v)) => v.clone(),
+        _ => String::new(),
+    };
+    let pid = i32::try_from(uint("pid")?).map_err(|_| "invalid \"pid\"".to_string())?;
+    let (swap, physical, rss) = (
+        uint("swap_bytes")?,
+        uint("physical_bytes")?,
+        uint("rss_bytes")?,
+    );
+    Ok(RecordedSample {
+        ts: uint("ts")?,
+        pid,
+        command: text("command"),
+        tmux_target: text("tmux_target"),
+        tmux_window_name: text("tmux_window"),
+        footprint_bytes: footprint_bytes(swap, physical, rss),
+        started_at: match fields.get("started_at") {
+            Some(Field::Uint(v)) => Some(*v),
+            _ => None,
+        },
+    })
+}
+
+// vmmap's footprint when it was measured, RSS otherwise (Linux, skipped PIDs).
+fn footprint_bytes(swap: u64, physical: u64, rss: u64) -> u64 {
+    swap + if physical > 0 { physical } else { rss }
+}
+
+// Just enough JSON for flat objects like the ones we write: no floats, no nesting.
+fn parse_json_object(raw: &str) -> Result<HashMap<String, Field>, String> {
+    let mut chars = raw.trim().chars().peekable();
+    let mut fields = HashMap::new();
+    if chars.next() != Some('{') {
+        return Err("expected a JSON object".to_string());
+    }
+    loop {
+        skip_json_space(&mut chars);
+        match chars.next() {
+            Some('}') if fields.is_empty() => break,
+            Some('"') => {}
+            _ => return Err("expected a field name".to_string()),
+        }
+        let name = parse_json_string(&mut chars)?;
+        skip_json_space(&mut chars);
+        if chars.next() != Some(':') {
+            return Err(format!("expected ':' after \"{name}\""));
+        }
+        skip_json_space(&mut chars);
+        let value = parse_json_value(&mut chars)?;
+        fields.insert(name, value);
+        skip_json_space(&mut chars);
+        match chars.next() {
+            Some(',') => {}
+            Some('}') => break,
+            _ => return Err("expected ',' or '}'".to_string()),
+        }
+    }
+    if chars.next().is_some() {
+        return Err("trailing data after object".to_string());
+    }
+    Ok(fields)
+}
+
+fn skip_json_space(chars: &mut std::iter::Peekable<std::str::Chars>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+fn parse_json_value(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<Field, String> {
+    match chars.peek() {
+        Some('"') => {
+            chars.next();
+            parse_json_string(chars).map(Field::Str)
+        }
+        Some('[') => {
+            chars.next();
+            let mut items = Vec::new();
+            // String lists only come from --envelope metadata (build features).
+            let mut strings = Vec::new();
+            loop {
+                skip_json_space(chars);
+                if chars.next_if_eq(&']').is_some() && items.is_empty() && strings.is_empty() {
+                    break;
+                }
+                match parse_json_value(chars)? {
+                    Field::Uint(v) if strings.is_empty() => {
+                        items.push(i32::try_from(v).map_err(|_| "list item too large")?)
+                    }
+                    Field::Int(v) if strings.is_empty() => {
+                        items.push(i32::try_from(v).map_err(|_| "list item too large")?)
+                    }
+                    Field::Str(v) if items.is_empty() => strings.push(v),
+                    _ => return Err("only integer or string lists are supported".to_string()),
+                }
+                skip_json_space(chars);
+                match chars.next() {
+                    Some(',') => {}
+                    Some(']') => break,
+                    _ => return Err("expected ',' or ']'".to_string()),
+                }
+            }
+            if strings.is_empty() {
+                Ok(Field::List(items))
+            } else {
+                Ok(Field::StrList(strings))
+            }
+        }
+        _ => {
+            let mut word = String::new();
+            while let Some(c) =
+                chars.next_if(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '+' | '.'))
+            {
+                word.push(c);
+            }
+            match word.as_str() {
+                "null" => Ok(Field::Null),
+                "true" => Ok(Field::Bool(true)),
+                "false" => Ok(Field::Bool(false)),
+                _ => word
+                    .parse::<u64>()
+                    .map(Field::Uint)
+                    .or_else(|_| word.parse::<i64>().map(Field::Int))
+                    .or_else(|_| word.parse::<f64>().map(Field::Float))
+                    .map_err(|_| format!("unsupported JSON value: {word:?}")),
+            }
+        }
+    }
+}
+
+// Called after the opening quote; consumes the closing one.
+fn parse_json_string(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<String, String> {
+    let mut out = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(out),
+            Some('\\') => match chars.next() {
+                Some('n') => out.push('\n'),
+                Some('r') => out.push('\r'),
+                Some('t') => out.push('\t'),
+                Some('u') => {
+                    let hex = chars.by_ref().take(4).collect::<String>();
+                    let code = u32::from_str_radix(&hex, 16)
+                        .map_err(|_| format!("invalid \\u escape: {hex}"))?;
+                    out.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                }
+                Some(c) => out.push(c),
+                None => return Err("unterminated string".to_string()),
+            },
+            Some(c) => out.push(c),
+            None => return Err("unterminated string".to_string()),
+        }
+    }
+}
+
+const DEFAULT_LEAK_THRESHOLD: u64 = 50 * 1024 * 1024;
+const LEAK_MIN_SAMPLES: usize = 3;
+
+// Least-squares growth rate in bytes per second; None without two distinct timestamps.
+fn growth_slope(points: &[(u64, u64)]) -> Option<f64> {
+    let n = points.len() as f64;
+    let t0 = points.first()?.0;
+    let mean_t = points.iter().map(|(t, _)| (t - t0) as f64).sum::<f64>() / n;
+    let mean_v = points.iter().map(|(_, v)| *v as f64).sum::<f64>() / n;
+    let (mut cov, mut var) = (0.0, 0.0);
+    for (t, v) in points {
+        let dt = (t - t0) as f64 - mean_t;
+        cov += dt * (*v as f64 - mean_v);
+        var += dt * dt;
+    }
+    (var > 0.0).then(|| cov / var)
+}
+
+// More points make a fitted slope harder to fake with one spike.
+fn leak_confidence(samples: usize) -> &'static str {
+    match samples {
+        0..=5 => "low",
+        6..=19 => "medium",
+        _ => "high",
+    }
+}
+
+// Fits a slope per process (PID + command, so recycled PIDs stay apart) and per pane.
+fn detect_leaks(samples: &[RecordedSample], threshold_per_hour: u64) -> Vec<LeakRecord> {
+    let mut by_pid = HashMap::<(i32, &str), Vec<&RecordedSample>>::new();
+    for sample in samples {
+        by_pid
+            .entry((sample.pid, &sample.command))
+            .or_default()
+            .push(sample);
+    }
+
+    let mut leaks = Vec::new();
+    let mut consider =
+        |scope, subject: String, target: &str, command: &str, mut points: Vec<(u64, u64)>| {
+            points.sort_unstable();
+            if points.len() < LEAK_MIN_SAMPLES {
+                return;
+            }
+            let Some(per_sec) = growth_slope(&points) else {
+                return;
+            };
+            let per_hour = per_sec * 3600.0;
+            if per_hour < threshold_per_hour as f64 {
+                return;
+            }
+            leaks.push(LeakRecord {
+                scope,
+                subject,
+                tmux_target: target.to_string(),
+                command: command.to_string(),
+                samples: points.len(),
+                span_secs: points[points.len() - 1].0 - points[0].0,
+                first_bytes: points[0].1,
+                last_bytes: points[points.len() - 1].1,
+                growth_per_hour_bytes: per_hour as u64,
+                confidence: leak_confidence(points.len()),
+            });
+        };
+    for ((pid, command), rows) in &by_pid {
+        let target = rows.last().map_or("?", |r| r.tmux_target.as_str());
+        let points = rows.iter().map(|r| (r.ts, r.footprint_bytes)).collect();
+        consider("pid", pid.to_string(), target, command, points);
+    }
+    for trend in pane_trends(samples) {
+        let target = &trend.tmux_target;
+        consider("pane", target.clone(), target, "", trend.points);
+    }
+
+    leaks.sort_by(|a, b| {
+        b.growth_per_hour_bytes
+            .cmp(&a.growth_per_hour_bytes)
+            .then_with(|| a.subject.cmp(&b.subject))
+    });
+    leaks
+}
+
+// Footprint per pane and collection time, summed over the pane's processes.
+fn pane_trends(samples: &[RecordedSample]) -> Vec<PaneTrend> {
+    let mut trends = Vec::<PaneTrend>::new();
+    for sample in samples {
+        if sample.tmux_target == "?" || sample.tmux_target.is_empty() {
+            continue;
+        }
+        let idx = match trends
+            .iter()
+            .position(|t| t.tmux_target == sample.tmux_target)
+        {
+            Some(idx) => idx,
+            None => {
+                trends.push(PaneTrend {
+                    tmux_target: sample.tmux_target.clone(),
+                    ..PaneTrend::default()
+                });
+                trends.len() - 1
+            }
+        };
+        let trend = &mut trends[idx];
+        trend.tmux_window_name.clone_from(&sample.tmux_window_name);
+        match trend.points.iter_mut().find(|(ts, _)| *ts == sample.ts) {
+            Some((_, total)) => *total += sample.footprint_bytes,
+            None => trend.points.push((sample.ts, sample.footprint_bytes)),
+        }
+    }
+    for trend in &mut trends {
+        trend.points.sort_unstable();
+        trend.min_bytes = trend.points.iter().map(|p| p.1).min().unwrap_or(0);
+        trend.max_bytes = trend.points.iter().map(|p| p.1).max().unwrap_or(0);
+        trend.growth_per_hour_bytes = growth_slope(&trend.points).map(|s| (s * 3600.0) as i64);
+    }
+    trends.sort_by(|a, b| a.tmux_target.cmp(&b.tmux_target));
+    trends
+}
+
+// Young processes have short uptimes, so they rank high for the same footprint;
+// anything under a minute counts as a minute to keep fresh starts from dominating.
+fn process_trends(samples: &[RecordedSample]) -> Vec<ProcessTrend> {
+    let mut by_pid = HashMap::<(i32, &str), Vec<&RecordedSample>>::new();
+    for sample in samples {
+        by_pid
+            .entry((sample.pid, &sample.command))
+            .or_default()
+            .push(sample);
+    }
+    let mut trends = by_pid
+        .into_iter()
+        .map(|((pid, command), mut rows)| {
+            rows.sort_by_key(|r| r.ts);
+            let (first, last) = (rows[0], rows[rows.len() - 1]);
+            let start = rows.iter().find_map(|r| r.started_at);
+            let uptime_secs = last.ts.saturating_sub(start.unwrap_or(first.ts));
+            let points = rows
+                .iter()
+                .map(|r| (r.ts, r.footprint_bytes))
+                .collect::<Vec<_>>();
+            ProcessTrend {
+                pid,
+                command: command.to_string(),
+                tmux_target: last.tmux_target.clone(),
+                samples: rows.len(),
+                last_ts: last.ts,
+                last_bytes: last.footprint_bytes,
+                growth_per_hour_bytes: growth_slope(&points).map(|s| (s * 3600.0) as i64),
+                uptime_secs,
+                uptime_from_start: start.is_some(),
+                lifetime_per_hour_bytes: last.footprint_bytes.saturating_mul(3600)
+                    / uptime_secs.max(60),
+            }
+        })
+        .collect::<Vec<_>>();
+    trends.sort_by(|a, b| {
+        b.lifetime_per_hour_bytes
+            .cmp(&a.lifetime_per_hour_bytes)
+            .then_with(|| a.pid.cmp(&b.pid))
+    });
+    trends
+}
+
+fn human_duration(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{secs}s"),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86_399 => format!("{}h{:02}m", secs / 3600, (secs % 3600) / 60),
+        _ => format!("{}d{:02}h", secs / 86_400, (secs % 86_400) / 3600),
+    }
+}
+
+fn policy_action_name(action: PolicyAction) -> &'static str {
+    match action {
+        PolicyAction::Warn => "warn",
+        PolicyAction::Exit => "exit",
+        PolicyAction::Kill => "kill",
+        PolicyAction::ClearHistory => "clear-history",
+    }
+}
+
+fn advise_history_limits(panes: &[PaneRecord], target_bytes: u64) -> Vec<HistoryAdvice> {
+    let mut sessions = Vec::<(String, Vec<&PaneRecord>)>::new();
+    for pane in panes {
+        if pane.tmux_target == "?" || pane.pane_history_limit < 0 {
+            continue;
+        }
+        let session = target_session(&pane.tmux_target);
+        match sessions.iter_mut().find(|(name, _)| name == session) {
+            Some((_, members)) => members.push(pane),
+            None => sessions.push((session.to_string(), vec![pane])),
+        }
+    }
+
+    let mut out = Vec::new();
+    for (session, members) in sessions {
+        let total_lines = members
+            .iter()
+            .map(|p| p.pane_history_size.max(0) as u64)
+            .sum::<u64>();
+        let total_bytes = members.iter().map(|p| p.pane_history_bytes).sum::<u64>();
+        let current_limit = members
+            .iter()
+            .map(|p| p.pane_history_limit)
+            .max()
+            .unwrap_or(0);
+        let max_history_lines = members
+            .iter()
+            .map(|p| p.pane_history_size)
+            .max()
+            .unwrap_or(0);
+
+        // Without measured bytes we cannot size lines, so keep the current limit.
+        let bytes_per_line = if total_lines > 0 {
+            total_bytes.div_ceil(total_lines)
+        } else {
+            0
+        };
+        let suggested_limit = match target_bytes.checked_div(bytes_per_line) {
+            Some(lines) => ((lines as i64 / 1000) * 1000).max(1000).min(current_limit),
+            None => current_limit,
+        };
+
+        let mut saved_now_bytes = 0u64;
+        let mut saved_at_capacity_bytes = 0u64;
+        for pane in &members {
+            let over_now = (pane.pane_history_size - suggested_limit).max(0) as u64;
+            let over_cap = (pane.pane_history_limit - suggested_limit).max(0) as u64;
+            saved_now_bytes += over_now * bytes_per_line;
+            saved_at_capacity_bytes += over_cap * bytes_per_line;
+        }
+
+        out.push(HistoryAdvice {
+            session,
+            pane_count: members.len(),
+            current_limit,
+            max_history_lines,
+            bytes_per_line,
+            suggested_limit,
+            saved_now_bytes,
+            saved_at_capacity_bytes,
+        });
+    }
+    out.sort_by(|a, b| {
+        b.saved_at_capacity_bytes
+            .cmp(&a.saved_at_capacity_bytes)
+            .then_with(|| a.session.cmp(&b.session))
+    });
+    out
+}
+
+fn advice_args(advice: &HistoryAdvice) -> Option<Vec<String>> {
+    (advice.suggested_limit < advice.current_limit).then(|| {
+        vec![
+            "set-option".to_string(),
+            "-t".to_string(),
+            advice.session.clone(),
+            "history-limit".to_string(),
+            advice.suggested_limit.to_string(),
+        ]
+    })
+}
+
+fn advice_command(advice: &HistoryAdvice) -> Option<String> {
+    advice_args(advice).map(|args| format!("tmux {}", args.join(" ")))
+}
+
+fn evaluate_budgets(panes: &[PaneRecord], budgets: &[Budget]) -> Vec<BudgetRecord> {
+    let mut out = Vec::new();
+    for pane in panes {
+        if pane.tmux_target == "?" {
+            continue;
+        }
+        // First matching glob wins, mirroring how the config file is read top-down.
+        let Some(budget) = budgets
+            .iter()
+            .find(|b| glob_match(&b.window_glob, &pane.tmux_window_name))
+        else {
+            out.push(BudgetRecord {
+                tmux_target: pane.tmux_target.clone(),
+                tmux_window_name: pane.tmux_window_name.clone(),
+                budget: "-".to_string(),
+                metric: None,
+                used: 0,
+                limit: 0,
+                status: BudgetStatus::Unbudgeted,
+            });
+            continue;
+        };
+        for (metric, limit) in &budget.limits {
+            let used = pane_metric(pane, *metric);
+            out.push(BudgetRecord {
+                tmux_target: pane.tmux_target.clone(),
+                tmux_window_name: pane.tmux_window_name.clone(),
+                budget: budget.window_glob.clone(),
+                metric: Some(*metric),
+                used,
+                limit: *limit,
+                status: if used > *limit {
+                    BudgetStatus::Over
+                } else {
+                    BudgetStatus::Under
+                },
+            });
+        }
+    }
+    out
+}
+
+fn render_table(rows: &[ProcRecord], opts: &RenderOptions) -> String {
+    let mut table = TableLayout {
+        header: vec![
+            "PID",
+            "User",
+            "Tmux window.pane",
+            "Window",
+            "Swap",
+            "Physical",
+            "RSS",
+            "PaneHistory",
+            "History lines",
+            "Tag",
+            "Role",
+            "Command",
+        ],
+        rows: Vec::new(),
+        optional: vec![8, 7, 10, 9, 1, 3],
+    };
+    let mut pagein_cells = Vec::new();
+    let mut severities = Vec::new();
+    let mut gpu_cells = Vec::new();
+    let mut energy_cells = Vec::new();
+    let mut disk_io_cells_by_row = Vec::new();
+    let mut fault_cells = Vec::new();
+    let mut since_last_cells = Vec::new();
+    let mut bar_values = Vec::new();
+    let mut share_cells = Vec::new();
+    let mut duplicate_cells = Vec::new();
+    for group in app_groups(rows) {
+        for row in &group {
+            let history_lines = if row.pane_history_size >= 0 {
+                format!("{}/{}", row.pane_history_size, row.pane_history_limit)
+            } else {
+                "-".to_string()
+            };
+            let mut cells = table_row(vec![
+                row.pid.to_string(),
+                row.user.clone(),
+                row.tmux_target.clone(),
+                row.tmux_window_name.clone(),
+                footprint_cell(row, row.swap_bytes),
+                footprint_cell(row, row.physical_bytes),
+                human_bytes(row.rss_bytes),
+                human_bytes(row.pane_history_bytes),
+                history_lines,
+                row.tag.unwrap_or("-").to_string(),
+                row.role.clone().unwrap_or_else(|| "-".to_string()),
+                truncate_display(&display_command(row), opts.max_command_width),
+            ]);
+            cells.styles[4] = Some(swap_style(row.swap_bytes));
+            if row.app_parent_pid.is_some() {
+                cells.styles[11] = Some(DIM);
+            }
+            if row.growth_alert == Some(true) {
+                cells.style = Some(YELLOW);
+            }
+            table.rows.push(cells);
+            severities.push(row.severity);
+            pagein_cells.push((pageins_cell(row.pageins), None));
+            gpu_cells.push((gpu_cell(row.gpu_bytes), None));
+            energy_cells.push((energy_cell(row.energy_impact), None));
+            disk_io_cells_by_row.push(disk_io_cells(row.disk_io));
+            fault_cells.push(fault_cell(row.fault_delta));
+            since_last_cells.push(delta_cells(row.since_last));
+            bar_values.push(opts.bars.map(|m| process_metric(row, m)));
+            share_cells.push(Some((row.swap_pct, row.physical_pct)));
+            duplicate_cells.push(match row.duplicate_instance {
+                true => ("yes".to_string(), Some(RED)),
+                false => (String::new(), None),
+            });
+        }
+        if let Some(sub) = group_subtotal(&group) {
+            let mut cells = table_row(vec![
+                String::new(),
+                String::new(),
+                sub.tmux_target,
+                sub.tmux_window_name,
+                human_bytes(sub.swap_bytes),
+                human_bytes(sub.physical_bytes),
+                human_bytes(sub.rss_bytes),
+                String::new(),
+                String::new(),
+                String::new(),
+                String::new(),
+                format!("subtotal: {} processes", sub.process_count),
+            ]);
+            cells.style = Some(BOLD);
+            table.rows.push(cells);
+            severities.push(None);
+            pagein_cells.push((String::new(), None));
+            let gpu = group.iter().filter_map(|r| r.gpu_bytes).sum::<u64>();
+            gpu_cells.push((human_bytes(gpu), None));
+            let energy = group.iter().filter_map(|r| r.energy_impact).sum::<f64>();
+            energy_cells.push((energy_cell(Some(energy)), None));
+            disk_io_cells_by_row.push(disk_io_cells(sum_disk_io(group.iter().copied())));
+            fault_cells.push((String::new(), None));
+            since_last_cells.push(Default::default());
+            bar_values.push(None);
+            share_cells.push(None);
+            duplicate_cells.push((String::new(), None));
+        }
+    }
+    if opts.pageins {
+        insert_table_column(&mut table, 9, "Pageins", pagein_cells);
+        table.optional.insert(4, 9);
+    }
+    if opts.watch {
+        insert_table_column(
+            &mut table,
+            9 + opts.pageins as usize,
+            "Faults maj/min",
+            fault_cells,
+        );
+    }
+    if opts.gpu {
+        insert_table_column(&mut table, 7, "GPU", gpu_cells);
+    }
+    if opts.energy {
+        insert_table_column(&mut table, 7 + opts.gpu as usize, "Energy", energy_cells);
+    }
+    let extra = 7 + opts.gpu as usize + opts.energy as usize;
+    if opts.disk_io {
+        let (read, written): (Vec<_>, Vec<_>) = disk_io_cells_by_row
+            .into_iter()
+            .map(|[read, written]| ((read, None), (written, None)))
+            .unzip();
+        insert_table_column(&mut table, extra, "Disk read", read);
+        insert_table_column(&mut table, extra + 1, "Disk write", written);
+    }
+    if opts.since_last {
+        insert_delta_columns(
+            &mut table,
+            extra + 2 * opts.disk_io as usize,
+            since_last_cells,
+        );
+    }
+    if opts.percent || opts.cumulative {
+        insert_share_columns(&mut table, share_cells, opts);
+    }
+    if let Some(metric) = opts.bars {
+        insert_bar_column(&mut table, metric, &bar_values, opts.table_style);
+    }
+    if rows.iter().any(|r| r.duplicate_instance) {
+        let at = table.header.iter().position(|h| *h == "Tag").unwrap_or(0);
+        insert_table_column(&mut table, at, "Duplicate", duplicate_cells);
+    }
+    if rows.iter().any(|r| r.severity.is_some()) {
+        prepend_severity_column(&mut table, &severities);
+    }
+    let mut out = render_layout(&table, opts);
+
+    let total_swap = rows.iter().map(|r| r.swap_bytes).sum::<u64>();
+    let total_phys = rows.iter().map(|r| r.physical_bytes).sum::<u64>();
+    let total_rss = rows.iter().map(|r| r.rss_bytes).sum::<u64>();
+
+    // Count pane history bytes once per pane to avoid duplicate totals.
+    let mut unique_hist = HashMap::<String, u64>::new();
+    for row in rows {
+        unique_hist
+            .entry(row.tmux_target.clone())
+            .and_modify(|v| *v = (*v).max(row.pane_history_bytes))
+            .or_insert(row.pane_history_bytes);
+    }
+    let total_hist = unique_hist.values().sum::<u64>();
+
+    out.push('\n');
+    let _ = writeln!(out, "Total swap:\t{}", human_bytes(total_swap));
+    let _ = writeln!(out, "Total physical:\t{}", human_bytes(total_phys));
+    let _ = writeln!(out, "Total RSS:\t{}", human_bytes(total_rss));
+    if opts.gpu {
+        let total_gpu = rows.iter().filter_map(|r| r.gpu_bytes).sum::<u64>();
+        let _ = writeln!(out, "Total GPU:\t{}", human_bytes(total_gpu));
+    }
+    if opts.energy {
+        let total_energy = rows.iter().filter_map(|r| r.energy_impact).sum::<f64>();
+        let _ = writeln!(out, "Total energy impact:\t{total_energy:.1}");
+    }
+    if opts.disk_io {
+        let [read, written] = disk_io_cells(sum_disk_io(rows));
+        let _ = writeln!(out, "Total disk read:\t{read}");
+        let _ = writeln!(out, "Total disk write:\t{written}");
+    }
+    let _ = writeln!(
+        out,
+        "Total pane history bytes:\t{}",
+        human_bytes(total_hist)
+    );
+    out
+}
+
+// A table before layout: cells stay plain so widths can be measured, styles apply last.
+struct TableLayout {
+    header: Vec<&'static str>,
+    rows: Vec<TableRow>,
+    // Column indexes dropped on narrow terminals, least important first.
+    optional: Vec<usize>,
+}
+
+struct TableRow {
+    cells: Vec<String>,
+    styles: Vec<Option<&'static str>>,
+    style: Option<&'static str>,
+}
+
+// Severity leads the row when thresholds are configured.
+fn prepend_severity_column(table: &mut TableLayout, severities: &[Option<Severity>]) {
+    let cells = severities
+        .iter()
+        .map(|s| {
+            (
+                s.map_or("", severity_label).to_string(),
+                s.map(severity_style),
+            )
+        })
+        .collect();
+    insert_table_column(table, 0, "Severity", cells);
+}
+
+// Adds an opt-in column at `at`; optional indexes behind it shift along.
+fn insert_table_column(
+    table: &mut TableLayout,
+    at: usize,
+    header: &'static str,
+    cells: Vec<(String, Option<&'static str>)>,
+) {
+    table.header.insert(at, header);
+    for c in &mut table.optional {
+        if *c >= at {
+            *c += 1;
+        }
+    }
+    for (row, (cell, style)) in table.rows.iter_mut().zip(cells) {
+        row.cells.insert(at, cell);
+        row.styles.insert(at, style);
+    }
+}
+
+const BAR_WIDTH: u64 = 20;
+// Unicode bars end in an eighth block, so close values still look different.
+const BAR_EIGHTHS: [&str; 8] = ["", "▏", "▎", "▍", "▌", "▋", "▊", "▉"];
+
+// --bars: scaled to the largest row, which gets the full width; rows without a
+// value (subtotals) stay blank. It is the first column dropped on narrow terminals.
+fn insert_bar_column(
+    table: &mut TableLayout,
+    metric: Metric,
+    values: &[Option<u64>],
+    style: TableStyle,
+) {
+    let (column, header) = match metric {
+        Metric::Swap => ("Swap", "Swap bar"),
+        Metric::Physical => ("Physical", "Physical bar"),
+        Metric::Rss => ("RSS", "RSS bar"),
+        Metric::HistoryBytes => ("PaneHistory", "PaneHistory bar"),
+        Metric::HistoryLines => ("History lines", "History lines bar"),
+    };
+    let Some(at) = table.header.iter().position(|h| *h == column) else {
+        return;
+    };
+    let max = values.iter().flatten().copied().max().unwrap_or(0);
+    let cells = values
+        .iter()
+        .map(|v| (v.map_or(String::new(), |v| bar(v, max, style)), None))
+        .collect();
+    insert_table_column(table, at + 1, header, cells);
+    table.optional.insert(0, at + 1);
+}
+
+fn bar(value: u64, max: u64, style: TableStyle) -> String {
+    if max == 0 {
+        return String::new();
+    }
+    let eighths = (u128::from(value) * u128::from(BAR_WIDTH * 8) / u128::from(max)) as usize;
+    match style {
+        TableStyle::Ascii => "#".repeat((eighths + 4) / 8),
+        _ => "█".repeat(eighths / 8) + BAR_EIGHTHS[eighths % 8],
+    }
+}
+
+fn remove_table_column(table: &mut TableLayout, at: usize) {
+    table.header.remove(at);
+    table.optional.retain(|&c| c != at);
+    for c in &mut table.optional {
+        if *c > at {
+            *c -= 1;
+        }
+    }
+    for row in &mut table.rows {
+        row.cells.remove(at);
+        row.styles.remove(at);
+    }
+}
+
+fn table_row(cells: Vec<String>) -> TableRow {
+    TableRow {
+        styles: vec![None; cells.len()],
+        cells,
+        style: None,
+    }
+}
+
+fn render_layout(table: &TableLayout, opts: &RenderOptions) -> String {
+    let style = opts.table_style;
+    let mut lines = vec![table
+        .header
+        .iter()
+        .map(|h| {
+            header_label(&opts.header_labels, h)
+                .unwrap_or(h)
+                .to_string()
+        })
+        .collect::<Vec<_>>()];
+    lines.extend(table.rows.iter().map(|r| r.cells.clone()));
+    let keep = match opts.term_width {
+        Some(width) => fit_to_width(&mut lines, &table.optional, width, style),
+        None => (0..table.header.len()).collect(),
+    };
+    let widths = column_widths(&lines, &keep);
+    let borders = table_borders(style);
+
+    let mut out = String::new();
+    if let Some(b) = &borders {
+        let _ = writeln!(out, "{}", table_rule(&widths, b.top));
+    }
+    for (i, line) in lines.iter().enumerate() {
+        let cells = keep
+            .iter()
+            .enumerate()
+            .map(|(k, &c)| {
+                let code = match i {
+                    0 => Some(BOLD),
+                    _ => table.rows[i - 1].style.or(table.rows[i - 1].styles[c]),
+                };
+                // Padding happens before painting so escapes never count as width.
+                let text = match style {
+                    TableStyle::Plain => line[c].clone(),
+                    TableStyle::Compact if k + 1 == keep.len() => line[c].clone(),
+                    _ => format!("{:<w$}", line[c], w = widths[k]),
+                };
+                match code {
+                    Some(code) => paint(&text, code, opts.color),
+                    None => text,
+                }
+            })
+            .collect::<Vec<_>>();
+        let row = match (style, &borders) {
+            (_, Some(b)) => format!("{0} {1} {0}", b.bar, cells.join(&format!(" {} ", b.bar))),
+            (TableStyle::Compact, None) => cells.join("  "),
+            _ => cells.join("\t"),
+        };
+        let _ = writeln!(out, "{row}");
+        if let (0, Some(b)) = (i, &borders) {
+            let _ = writeln!(out, "{}", table_rule(&widths, b.mid));
+        }
+    }
+    if let Some(b) = &borders {
+        let _ = writeln!(out, "{}", table_rule(&widths, b.bottom));
+    }
+    out
+}
+
+// Rule glyphs are [left, junction, right, fill].
+struct TableBorders {
+    top: [char; 4],
+    mid: [char; 4],
+    bottom: [char; 4],
+    bar: char,
+}
+
+fn table_borders(style: TableStyle) -> Option<TableBorders> {
+    match style {
+        TableStyle::Ascii => Some(TableBorders {
+            top: ['+', '+', '+', '-'],
+            mid: ['+', '+', '+', '-'],
+            bottom: ['+', '+', '+', '-'],
+            bar: '|',
+        }),
+        TableStyle::Unicode => Some(TableBorders {
+            top: ['┌', '┬', '┐', '─'],
+            mid: ['├', '┼', '┤', '─'],
+            bottom: ['└', '┴', '┘', '─'],
+            bar: '│',
+        }),
+        TableStyle::Plain | TableStyle::Compact => None,
+    }
+}
+
+fn table_rule(widths: &[usize], [left, junction, right, fill]: [char; 4]) -> String {
+    let cells = widths
+        .iter()
+        .map(|w| fill.to_string().repeat(w + 2))
+        .collect::<Vec<_>>();
+    format!("{left}{}{right}", cells.join(&junction.to_string()))
+}
+
+fn column_widths(lines: &[Vec<String>], keep: &[usize]) -> Vec<usize> {
+    keep.iter()
+        .map(|&c| {
+            lines
+                .iter()
+                .map(|l| l[c].chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect()
+}
+
+// Characters a style spends on separators and borders for `columns` columns.
+fn table_overhead(style: TableStyle, columns: usize) -> usize {
+    match style {
+        TableStyle::Plain => 0,
+        TableStyle::Compact => 2 * columns.saturating_sub(1),
+        TableStyle::Ascii | TableStyle::Unicode => 3 * columns + 1,
+    }
+}
+
+fn layout_width(lines: &[Vec<String>], keep: &[usize], style: TableStyle) -> usize {
+    match style {
+        TableStyle::Plain => lines
+            .iter()
+            .map(|l| tabbed_width(&keep.iter().map(|&c| l[c].as_str()).collect::<Vec<_>>()))
+            .max()
+            .unwrap_or(0),
+        _ => column_widths(lines, keep).iter().sum::<usize>() + table_overhead(style, keep.len()),
+    }
+}
+
+// Display width of a tab-separated line with the terminal's 8-column tab stops.
+fn tabbed_width(cells: &[&str]) -> usize {
+    let mut col = 0;
+    for (i, cell) in cells.iter().enumerate() {
+        col += cell.chars().count();
+        if i + 1 < cells.len() {
+            col = (col / 8 + 1) * 8;
+        }
+    }
+    col
+}
+
+const NARROW_WINDOW_WIDTH: usize = 12;
+const MIN_LAST_COLUMN_WIDTH: usize = 8;
+
+// Shrinks a table (header first in `lines`) to `width`: shorten window names, drop
+// optional columns, then cut the last column. Returns the column indexes to print.
+fn fit_to_width(
+    lines: &mut [Vec<String>],
+    optional: &[usize],
+    width: usize,
+    style: TableStyle,
+) -> Vec<usize> {
+    let mut keep = (0..lines[0].len()).collect::<Vec<_>>();
+    if layout_width(lines, &keep, style) <= width {
+        return keep;
+    }
+
+    if let Some(w) = lines[0].iter().position(|h| h == "Window") {
+        for line in lines.iter_mut().skip(1) {
+            line[w] = truncate_display(&line[w], Some(NARROW_WINDOW_WIDTH));
+        }
+    }
+    for col in optional {
+        if layout_width(lines, &keep, style) <= width {
+            return keep;
+        }
+        keep.retain(|c| c != col);
+    }
+
+    let Some((&last, prefix)) = keep.split_last() else {
+        return keep;
+    };
+    // Padded styles share one column width; tab stops depend on each line.
+    let padded_prefix = (style != TableStyle::Plain).then(|| {
+        column_widths(lines, prefix).iter().sum::<usize>() + table_overhead(style, keep.len())
+    });
+    for line in lines.iter_mut().skip(1) {
+        let used = padded_prefix.unwrap_or_else(|| {
+            let mut cells = prefix.iter().map(|&c| line[c].as_str()).collect::<Vec<_>>();
+            cells.push("");
+            tabbed_width(&cells)
+        });
+        let room = width.saturating_sub(used).max(MIN_LAST_COLUMN_WIDTH);
+        line[last] = truncate_display(&line[last], Some(room));
+    }
+    keep
+}
+
+// Groups app helpers right below their parent; without --app every row is its own group.
+fn app_groups(rows: &[ProcRecord]) -> Vec<Vec<&ProcRecord>> {
+    let mut groups = Vec::<(i32, Vec<&ProcRecord>)>::new();
+    for row in rows {
+        let root = row.app_parent_pid.unwrap_or(row.pid);
+        match groups.iter_mut().find(|(r, _)| *r == root) {
+            Some((_, members)) if row.app_parent_pid.is_none() => members.insert(0, row),
+            Some((_, members)) => members.push(row),
+            None => groups.push((root, vec![row])),
+        }
+    }
+    let mut groups = groups.into_iter().map(|(_, g)| g).collect::<Vec<_>>();
+    let total =
+        |g: &Vec<&ProcRecord>, f: fn(&ProcRecord) -> u64| g.iter().map(|r| f(r)).sum::<u64>();
+    groups.sort_by(|a, b| {
+        total(b, |r| r.swap_bytes)
+            .cmp(&total(a, |r| r.swap_bytes))
+            .then_with(|| total(b, |r| r.physical_bytes).cmp(&total(a, |r| r.physical_bytes)))
+            .then_with(|| a[0].pid.cmp(&b[0].pid))
+    });
+    groups
+}
+
+// Subtotal for an app group, reusing PaneRecord as a plain memory accumulator.
+fn group_subtotal(group: &[&ProcRecord]) -> Option<PaneRecord> {
+    if group.len() < 2 {
+        return None;
+    }
+    let first = group[0];
+    let same_pane = group.iter().all(|r| r.tmux_target == first.tmux_target);
+    Some(PaneRecord {
+        tmux_target: if same_pane {
+            first.tmux_target.clone()
+        } else {
+            "*".to_string()
+        },
+        tmux_window_name: if same_pane {
+            first.tmux_window_name.clone()
+        } else {
+            "*".to_string()
+        },
+        process_count: group.len(),
+        pids: group.iter().map(|r| r.pid).collect(),
+        swap_bytes: group.iter().map(|r| r.swap_bytes).sum(),
+        physical_bytes: group.iter().map(|r| r.physical_bytes).sum(),
+        rss_bytes: group.iter().map(|r| r.rss_bytes).sum(),
+        swap_pct: group.iter().filter_map(|r| r.swap_pct).reduce(|a, b| a + b),
+        physical_pct: group
+            .iter()
+            .filter_map(|r| r.physical_pct)
+            .reduce(|a, b| a + b),
+        ..PaneRecord::default()
+    })
+}
+
+// Major faults hit the disk, so any of them are worth a color.
+fn fault_cell(delta: Option<(u64, u64)>) -> (String, Option<&'static str>) {
+    match delta {
+        Some((minor, major)) => (
+            format!("{major}/{minor}"),
+            Some(if major > 0 { YELLOW } else { DIM }),
+        ),
+        None => ("-".to_string(), None),
+    }
+}
+
+fn gpu_cell(bytes: Option<u64>) -> String {
+    bytes.map_or_else(|| "-".to_string(), human_bytes)
+}
+
+// Swap/physical cell; "-" when --vmmap-min-rss skipped the measurement.
+fn footprint_cell(row: &ProcRecord, bytes: u64) -> String {
+    if row.vmmap_skipped {
+        "-".to_string()
+    } else {
+        human_bytes(bytes)
+    }
+}
+
+fn display_command(row: &ProcRecord) -> String {
+    if row.app_parent_pid.is_some() {
+        format!("  ↳ {}", row.command)
+    } else {
+        row.command.clone()
+    }
+}
+
+// Cuts to `width` characters, marking the cut with an ellipsis.
+fn truncate_display(s: &str, width: Option<usize>) -> String {
+    match width {
+        Some(w) if s.chars().count() > w => {
+            let mut out = s.chars().take(w.saturating_sub(1)).collect::<String>();
+            out.push('…');
+            out
+        }
+        _ => s.to_string(),
+    }
+}
+
+fn history_lines_field(size: i64, limit: i64) -> Field {
+    if size >= 0 {
+        Field::Str(format!("{size}/{limit}"))
+    } else {
+        Field::Null
+    }
+}
+
+// Every structured process/pane field with the schema version that added it, in
+// output order. Version N is the fields added at or before N, in this order, so a
+// released version never changes: a new field bumps SCHEMA_VERSION and is listed
+// here with the new number, and released fields are never moved or removed.
+const SCHEMA_VERSION: u32 = 15;
+const PROCESS_SCHEMA: &[(&str, u32)] = &[
+    ("pid", 1),
+    ("tmux_target", 1),
+    ("tmux_window", 1),
+    ("swap_bytes", 1),
+    ("swap_human", 1),
+    ("physical_bytes", 1),
+    ("physical_human", 1),
+    ("rss_bytes", 1),
+    ("rss_human", 1),
+    ("pane_history_bytes", 1),
+    ("pane_history_human", 1),
+    ("pane_history_estimated_bytes", 15),
+    ("pane_history_lines", 1),
+    ("history_measured", 13),
+    ("command_name", 2),
+    ("command", 1),
+    ("app_parent_pid", 2),
+    ("role", 2),
+    ("tag", 5),
+    ("growth_alert", 6),
+    ("duplicate_instance", 12),
+    ("user", 2),
+    ("started_at", 10),
+    ("severity", 2),
+    ("vmmap_skipped", 2),
+    ("anon_bytes", 2),
+    ("file_backed_bytes", 2),
+    ("gpu_bytes", 2),
+    ("energy_impact", 8),
+    ("swap_pct", 11),
+    ("physical_pct", 11),
+    ("disk_read_bytes", 9),
+    ("disk_write_bytes", 9),
+    ("pageins", 2),
+    ("minor_faults_delta", 2),
+    ("major_faults_delta", 2),
+    ("swap_delta_bytes", 7),
+    ("physical_delta_bytes", 7),
+    ("rss_delta_bytes", 7),
+];
+const PANE_SCHEMA: &[(&str, u32)] = &[
+    ("tmux_target", 1),
+    ("tmux_window", 1),
+    ("session_age_secs", 14),
+    ("session_attached", 14),
+    ("process_count", 1),
+    ("app_instances", 12),
+    ("pids", 1),
+    ("swap_bytes", 1),
+    ("swap_human", 1),
+    ("physical_bytes", 1),
+    ("physical_human", 1),
+    ("rss_bytes", 1),
+    ("rss_human", 1),
+    ("pane_history_bytes", 1),
+    ("pane_history_human", 1),
+    ("pane_history_estimated_bytes", 15),
+    ("pane_history_lines", 1),
+    ("history_measured", 13),
+    ("severity", 2),
+    ("shell_rss_bytes", 4),
+    ("growth_alert", 6),
+    ("swap_pct", 11),
+    ("physical_pct", 11),
+    ("swap_delta_bytes", 7),
+    ("physical_delta_bytes", 7),
+    ("rss_delta_bytes", 7),
+];
+
+// Keeps exactly the fields of a version (the current one when not pinned), in
+// schema order.
+fn pin_schema(
+    mut fields: Vec<(&'static str, Field)>,
+    schema: &[(&str, u32)],
+    version: Option<u32>,
+) -> Vec<(&'static str, Field)> {
+    let version = version.unwrap_or(SCHEMA_VERSION);
+    if version < 3 {
+        restore_unknown_pane_sentinels(&mut fields);
+    }
+    schema
+        .iter()
+        .filter(|(_, since)| *since <= version)
+        .filter_map(|(name, _)| {
+            let idx = fields.iter().position(|(n, _)| n == name)?;
+            Some(fields.swap_remove(idx))
+        })
+        .collect()
+}
+
+// Before version 3 an unattached process reported pane "?" and zero history bytes.
+fn restore_unknown_pane_sentinels(fields: &mut [(&'static str, Field)]) {
+    for (name, value) in fields.iter_mut() {
+        if *value != Field::Null {
+            continue;
+        }
+        *value = match *name {
+            "tmux_target" | "tmux_window" => Field::Str("?".to_string()),
+            "pane_history_bytes" => Field::Uint(0),
+            "pane_history_human" => Field::Str(human_bytes(0)),
+            _ => continue,
+        };
+    }
+}
+
+// Pane attribution is null, not "?", for processes outside any pane.
+fn pane_field(target: &str, value: Field) -> Field {
+    if target == "?" {
+        Field::Null
+    } else {
+        value
+    }
+}
+
+// Field order here is the column order of every structured export.
+fn process_fields(row: &ProcRecord) -> Vec<(&'static str, Field)> {
+    vec![
+        ("pid", Field::Int(row.pid.into())),
+        (
+            "tmux_target",
+            pane_field(&row.tmux_target, Field::Str(row.tmux_target.clone())),
+        ),
+        (
+            "tmux_window",
+            pane_field(&row.tmux_target, Field::Str(row.tmux_window_name.clone())),
+        ),
+        ("swap_bytes", Field::Uint(row.swap_bytes)),
+        ("swap_human", Field::Str(human_bytes(row.swap_bytes))),
+        ("physical_bytes", Field::Uint(row.physical_bytes)),
+        (
+            "physical_human",
+            Field::Str(human_bytes(row.physical_bytes)),
+        ),
+        ("rss_bytes", Field::Uint(row.rss_bytes)),
+        ("rss_human", Field::Str(human_bytes(row.rss_bytes))),
+        (
+            "pane_history_bytes",
+            pane_field(&row.tmux_target, Field::Uint(row.pane_history_bytes)),
+        ),
+        (
+            "pane_history_human",
+            pane_field(
+                &row.tmux_target,
+                Field::Str(human_bytes(row.pane_history_bytes)),
+            ),
+        ),
+        (
+            "pane_history_estimated_bytes",
+            pane_field(
+                &row.tmux_target,
+                Field::Uint(row.pane_history_estimated_bytes),
+            ),
+        ),
+        (
+            "pane_history_lines",
+            history_lines_field(row.pane_history_size, row.pane_history_limit),
+        ),
+        (
+            "history_measured",
+            row.history_measured.map_or(Field::Null, Field::Bool),
+        ),
+        ("command_name", Field::Str(row.command_name.clone())),
+        ("command", Field::Str(row.command.clone())),
+        (
+            "app_parent_pid",
+            row.app_parent_pid
+                .map_or(Field::Null, |pid| Field::Int(pid.into())),
+        ),
+        ("role", row.role.clone().map_or(Field::Null, Field::Str)),
+        (
+            "tag",
+            row.tag.map_or(Field::Null, |t| Field::Str(t.to_string())),
+        ),
+        (
+            "growth_alert",
+            row.growth_alert.map_or(Field::Null, Field::Bool),
+        ),
+        ("duplicate_instance", Field::Bool(row.duplicate_instance)),
+        ("user", Field::Str(row.user.clone())),
+        (
+            "started_at",
+            row.started_at.map_or(Field::Null, Field::Uint),
+        ),
+        ("severity", severity_field(row.severity)),
+        ("vmmap_skipped", Field::Bool(row.vmmap_skipped)),
+        (
+            "anon_bytes",
+            row.anon_bytes.map_or(Field::Null, Field::Uint),
+        ),
+        (
+            "file_backed_bytes",
+            row.file_backed_bytes.map_or(Field::Null, Field::Uint),
+        ),
+        ("gpu_bytes", row.gpu_bytes.map_or(Field::Null, Field::Uint)),
+        (
+            "energy_impact",
+            row.energy_impact.map_or(Field::Null, Field::Float),
+        ),
+        ("swap_pct", row.swap_pct.map_or(Field::Null, Field::Float)),
+        (
+            "physical_pct",
+            row.physical_pct.map_or(Field::Null, Field::Float),
+        ),
+        (
+            "disk_read_bytes",
+            row.disk_io
+                .map_or(Field::Null, |(read, _)| Field::Uint(read)),
+        ),
+        (
+            "disk_write_bytes",
+            row.disk_io
+                .map_or(Field::Null, |(_, written)| Field::Uint(written)),
+        ),
+        ("pageins", row.pageins.map_or(Field::Null, Field::Uint)),
+        (
+            "minor_faults_delta",
+            row.fault_delta
+                .map_or(Field::Null, |(minor, _)| Field::Uint(minor)),
+        ),
+        (
+            "major_faults_delta",
+            row.fault_delta
+                .map_or(Field::Null, |(_, major)| Field::Uint(major)),
+        ),
+    ]
+    .into_iter()
+    .chain(delta_fields(row.since_last))
+    .collect()
+}
+
+fn pane_fields(row: &PaneRecord) -> Vec<(&'static str, Field)> {
+    vec![
+        (
+            "tmux_target",
+            pane_field(&row.tmux_target, Field::Str(row.tmux_target.clone())),
+        ),
+        (
+            "tmux_window",
+            pane_field(&row.tmux_target, Field::Str(row.tmux_window_name.clone())),
+        ),
+        ("session_age_secs", session_age_field(row.session_created)),
+        (
+            "session_attached",
+            row.session_attached.map_or(Field::Null, Field::Bool),
+        ),
+        ("process_count", Field::Uint(row.process_count as u64)),
+        ("app_instances", Field::Uint(row.app_instances as u64)),
+        ("pids", Field::List(row.pids.clone())),
+        ("swap_bytes", Field::Uint(row.swap_bytes)),
+        ("swap_human", Field::Str(human_bytes(row.swap_bytes))),
+        ("physical_bytes", Field::Uint(row.physical_bytes)),
+        (
+            "physical_human",
+            Field::Str(human_bytes(row.physical_bytes)),
+        ),
+        ("rss_bytes", Field::Uint(row.rss_bytes)),
+        ("rss_human", Field::Str(human_bytes(row.rss_bytes))),
+        (
+            "pane_history_bytes",
+            pane_field(&row.tmux_target, Field::Uint(row.pane_history_bytes)),
+        ),
+        (
+            "pane_history_human",
+            pane_field(
+                &row.tmux_target,
+                Field::Str(human_bytes(row.pane_history_bytes)),
+            ),
+        ),
+        (
+            "pane_history_estimated_bytes",
+            pane_field(
+                &row.tmux_target,
+                Field::Uint(row.pane_history_estimated_bytes),
+            ),
+        ),
+        (
+            "pane_history_lines",
+            history_lines_field(row.pane_history_size, row.pane_history_limit),
+        ),
+        (
+            "history_measured",
+            row.history_measured.map_or(Field::Null, Field::Bool),
+        ),
+        ("severity", severity_field(row.severity)),
+        (
+            "shell_rss_bytes",
+            row.shell_rss_bytes.map_or(Field::Null, Field::Uint),
+        ),
+        (
+            "growth_alert",
+            row.growth_alert.map_or(Field::Null, Field::Bool),
+        ),
+        ("swap_pct", row.swap_pct.map_or(Field::Null, Field::Float)),
+        (
+            "physical_pct",
+            row.physical_pct.map_or(Field::Null, Field::Float),
+        ),
+    ]
+    .into_iter()
+    .chain(delta_fields(row.since_last))
+    .collect()
+}
+
+fn severity_field(severity: Option<Severity>) -> Field {
+    severity.map_or(Field::Null, |s| Field::Str(severity_name(s).to_string()))
+}
+
+fn budget_fields(row: &BudgetRecord) -> Vec<(&'static str, Field)> {
+    let checked = |v: u64| row.metric.map_or(Field::Null, |_| Field::Uint(v));
+    vec![
+        ("tmux_target", Field::Str(row.tmux_target.clone())),
+        ("tmux_window", Field::Str(row.tmux_window_name.clone())),
+        ("budget", Field::Str(row.budget.clone())),
+        (
+            "metric",
+            row.metric
+                .map_or(Field::Null, |m| Field::Str(metric_name(m).to_string())),
+        ),
+        ("used", checked(row.used)),
+        ("limit", checked(row.limit)),
+        (
+            "status",
+            Field::Str(budget_status_name(row.status).to_string()),
+        ),
+    ]
+}
+
+fn advice_fields(row: &HistoryAdvice) -> Vec<(&'static str, Field)> {
+    vec![
+        ("session", Field::Str(row.session.clone())),
+        ("pane_count", Field::Uint(row.pane_count as u64)),
+        ("current_limit", Field::Int(row.current_limit)),
+        ("max_history_lines", Field::Int(row.max_history_lines)),
+        ("bytes_per_line", Field::Uint(row.bytes_per_line)),
+        ("suggested_limit", Field::Int(row.suggested_limit)),
+        ("saved_now_bytes", Field::Uint(row.saved_now_bytes)),
+        (
+            "saved_at_capacity_bytes",
+            Field::Uint(row.saved_at_capacity_bytes),
+        ),
+        (
+            "command",
+            advice_command(row).map_or(Field::Null, Field::Str),
+        ),
+    ]
+}
+
+fn leak_fields(row: &LeakRecord) -> Vec<(&'static str, Field)> {
+    vec![
+        ("scope", Field::Str(row.scope.to_string())),
+        ("subject", Field::Str(row.subject.clone())),
+        ("tmux_target", Field::Str(row.tmux_target.clone())),
+        ("command", Field::Str(row.command.clone())),
+        ("samples", Field::Uint(row.samples as u64)),
+        ("span_secs", Field::Uint(row.span_secs)),
+        ("first_bytes", Field::Uint(row.first_bytes)),
+        ("last_bytes", Field::Uint(row.last_bytes)),
+        (
+            "growth_per_hour_bytes",
+            Field::Uint(row.growth_per_hour_bytes),
+        ),
+        ("confidence", Field::Str(row.confidence.to_string())),
+    ]
+}
+
+fn trend_fields(row: &PaneTrend) -> Vec<(&'static str, Field)> {
+    let first = row.points.first().copied().unwrap_or_default();
+    let last = row.points.last().copied().unwrap_or_default();
+    vec![
+        ("tmux_target", Field::Str(row.tmux_target.clone())),
+        ("tmux_window", Field::Str(row.tmux_window_name.clone())),
+        ("samples", Field::Uint(row.points.len() as u64)),
+        ("first_seen", Field::Str(format_utc_timestamp(first.0))),
+        ("last_seen", Field::Str(format_utc_timestamp(last.0))),
+        ("min_bytes", Field::Uint(row.min_bytes)),
+        ("max_bytes", Field::Uint(row.max_bytes)),
+        ("last_bytes", Field::Uint(last.1)),
+        (
+            "growth_per_hour_bytes",
+            row.growth_per_hour_bytes.map_or(Field::Null, Field::Int),
+        ),
+    ]
+}
+
+fn process_trend_fields(row: &ProcessTrend) -> Vec<(&'static str, Field)> {
+    vec![
+        ("pid", Field::Int(row.pid.into())),
+        (
+            "tmux_target",
+            pane_field(
+                &pane_or_unknown(&row.tmux_target),
+                Field::Str(row.tmux_target.clone()),
+            ),
+        ),
+        ("command", Field::Str(row.command.clone())),
+        ("samples", Field::Uint(row.samples as u64)),
+        ("last_seen", Field::Str(format_utc_timestamp(row.last_ts))),
+        ("last_bytes", Field::Uint(row.last_bytes)),
+        (
+            "growth_per_hour_bytes",
+            row.growth_per_hour_bytes.map_or(Field::Null, Field::Int),
+        ),
+        ("uptime_secs", Field::Uint(row.uptime_secs)),
+        ("uptime_from_start", Field::Bool(row.uptime_from_start)),
+        (
+            "lifetime_per_hour_bytes",
+            Field::Uint(row.lifetime_per_hour_bytes),
+        ),
+    ]
+}
+
+fn check_status_name(status: CheckStatus) -> &'static str {
+    match status {
+        CheckStatus::Pass => "pass",
+        CheckStatus::Warn => "warn",
+        CheckStatus::Fail => "fail",
+    }
+}
+
+fn doctor_fields(row: &DoctorCheck) -> Vec<(&'static str, Field)> {
+    vec![
+        ("check", Field::Str(row.name.to_string())),
+        (
+            "status",
+            Field::Str(check_status_name(row.status).to_string()),
+        ),
+        ("detail", Field::Str(row.detail.clone())),
+        (
+            "hint",
+            if row.hint.is_empty() {
+                Field::Null
+            } else {
+                Field::Str(row.hint.clone())
+            },
+        ),
+    ]
+}
+
+fn render_fields_json<T>(rows: &[T], fields: impl Fn(&T) -> Vec<(&'static str, Field)>) -> String {
+    let mut out = String::new();
+    out.push_str("[\n");
+    for (idx, row) in rows.iter().enumerate() {
+        let comma = if idx + 1 == rows.len() { "" } else { "," };
+        let body = fields(row)
+            .into_iter()
+            .map(|(name, value)| format!("\"{name}\":{}", json_field(&value)))
+            .collect::<Vec<_>>()
+            .join(",");
+        let _ = writeln!(out, "  {{{body}}}{comma}");
+    }
+    out.push_str("]\n");
+    out
+}
+
+fn json_field(value: &Field) -> String {
+    match value {
+        Field::Int(v) => v.to_string(),
+        Field::Uint(v) => v.to_string(),
+        Field::Float(v) => float_field(*v),
+        Field::Bool(v) => v.to_string(),
+        Field::Str(v) => format!("\"{}\"", escape_json(v)),
+        Field::List(v) => format!(
+            "[{}]",
+            v.iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(",")
+        ),
+        Field::StrList(v) => format!(
+            "[{}]",
+            v.iter()
+                .map(|s| format!("\"{}\"", escape_json(s)))
+                .collect::<Vec<_>>()
+                .join(",")
+        ),
+        Field::Records(v) => format!(
+            "[{}]",
+            v.iter()
+                .map(|record| json_object(record))
+                .collect::<Vec<_>>()
+                .join(",")
+        ),
+        Field::Null => "null".to_string(),
+    }
+}
+
+fn render_fields_csv<T: Default>(
+    rows: &[T],
+    fields: impl Fn(&T) -> Vec<(&'static str, Field)>,
+) -> String {
+    let mut out = String::new();
+    // The header comes from a default row so empty reports still get columns.
+    let header = fields(&T::default())
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>()
+        .join(",");
+    let _ = writeln!(out, "{header}");
+    for row in rows {
+        let line = fields(row)
+            .into_iter()
+            .map(|(_, value)| match value {
+                Field::Int(v) => v.to_string(),
+                Field::Uint(v) => v.to_string(),
+                Field::Float(v) => float_field(v),
+                Field::Bool(v) => v.to_string(),
+                Field::Str(v) => escape_csv(&v),
+                Field::List(v) => escape_csv(
+                    &v.iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(","),
+                ),
+                Field::StrList(v) => escape_csv(&v.join(",")),
+                // CSV cells are flat, so the list goes in as its JSON.
+                value @ Field::Records(_) => escape_csv(&json_field(&value)),
+                Field::Null => String::new(),
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        let _ = writeln!(out, "{line}");
+    }
+    out
+}
+
+fn render_fields_yaml<T>(
+    rows: &[T],
+    fields: impl Fn(&T) -> Vec<(&'static str, Field)>,
+    opts: &RenderOptions,
+) -> String {
+    let mut out = String::new();
+    out.push_str("---\n");
+    let indent = match opts.yaml_style {
+        YamlStyle::Sequence => "",
+        YamlStyle::Document => {
+            let rows = rows.iter().map(&fields).collect::<Vec<_>>();
+            let _ = writeln!(
+                out,
+                "generated_at: {}",
+                yaml_field(&Field::Str(format_utc_timestamp(unix_now())))
+            );
+            out.push_str(&yaml_summary(&rows));
+            out.push_str(if rows.is_empty() {
+                "records: []\n"
+            } else {
+                "records:\n"
+            });
+            "  "
+        }
+    };
+    for row in rows {
+        for (idx, (name, value)) in fields(row).into_iter().enumerate() {
+            let prefix = if idx == 0 { "- " } else { "  " };
+            let _ = writeln!(out, "{indent}{prefix}{name}: {}", yaml_field(&value));
+        }
+    }
+    out
+}
+
+// Memory totals of the views that carry them; other views summarize as a count.
+// Pane history is left out: process rows repeat it for every process in a pane.
+const YAML_SUMMARY_FIELDS: [&str; 4] = ["swap_bytes", "physical_bytes", "rss_bytes", "gpu_bytes"];
+
+fn yaml_summary(rows: &[Vec<(&'static str, Field)>]) -> String {
+    let mut out = format!("summary:\n  records: {}\n", rows.len());
+    for name in YAML_SUMMARY_FIELDS {
+        let values = rows
+            .iter()
+            .filter_map(|row| match row.iter().find(|(field, _)| *field == name) {
+                Some((_, Field::Uint(v))) => Some(*v),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        // Unmeasured everywhere (gpu_bytes without --gpu) is left out, not 0.
+        if !values.is_empty() {
+            let _ = writeln!(out, "  {name}: {}", values.iter().sum::<u64>());
+        }
+    }
+    out
+}
+
+fn yaml_field(value: &Field) -> String {
+    match value {
+        Field::Int(v) => v.to_string(),
+        Field::Uint(v) => v.to_string(),
+        Field::Float(v) => float_field(*v),
+        Field::Bool(v) => v.to_string(),
+        Field::Str(v) => format!("\"{}\"", v.replace('"', "\\\"")),
+        Field::List(v) => format!(
+            "[{}]",
+            v.iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Field::StrList(v) => format!(
+            "[{}]",
+            v.iter()
+                .map(|s| yaml_field(&Field::Str(s.clone())))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Field::Records(v) => format!(
+            "[{}]",
+            v.iter()
+                .map(|record| {
+                    let pairs = record
+                        .iter()
+                        .map(|(name, value)| format!("{name}: {}", yaml_field(value)))
+                        .collect::<Vec<_>>();
+                    format!("{{{}}}", pairs.join(", "))
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Field::Null => "null".to_string(),
+    }
+}
+
+// Always with a fraction, so readers keep the value a float; non-finite has no JSON form.
+fn float_field(v: f64) -> String {
+    if v.is_finite() {
+        format!("{v:.1}")
+    } else {
+        "null".to_string()
+    }
+}
+
+fn escape_json(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 8);
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+fn escape_csv(s: &str) -> String {
+    if s.contains(',') || s.contains('"') || s.contains('\n') {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn render_markdown(rows: &[ProcRecord], opts: &RenderOptions) -> String {
+    let mut out = String::new();
+    let lead = |cell: &str| md_severity_cell(rows.iter().any(|r| r.severity.is_some()), cell);
+    out.push_str(&lead("Severity"));
+    let gpu = |cell: String| {
+        if opts.gpu {
+            format!(" {cell} |")
+        } else {
+            String::new()
+        }
+    };
+    let energy = |cell: String| {
+        if opts.energy {
+            format!(" {cell} |")
+        } else {
+            String::new()
+        }
+    };
+    let disk_io = |[read, written]: [String; 2]| {
+        if opts.disk_io {
+            format!(" {read} | {written} |")
+        } else {
+            String::new()
+        }
+    };
+    let pageins = |cell: String| {
+        if opts.pageins {
+            format!(" {cell} |")
+        } else {
+            String::new()
+        }
+    };
+    let deltas = |cells: [String; 3]| md_delta_cells(opts.since_last, cells);
+    // Like the table, only when some pane has a double launch.
+    let show_duplicate = rows.iter().any(|r| r.duplicate_instance);
+    let duplicate = |cell: &str| {
+        if show_duplicate {
+            format!(" {cell} |")
+        } else {
+            String::new()
+        }
+    };
+    let _ = writeln!(
+        out,
+        "| PID | User | Tmux window.pane | Window | Swap | Physical | RSS |{}{}{}{} PaneHistory | History lines |{}{} Tag | Role | Command |",
+        gpu("GPU".to_string()),
+        energy("Energy".to_string()),
+        disk_io(["Disk read".to_string(), "Disk write".to_string()]),
+        deltas(DELTA_HEADERS.map(str::to_string)),
+        pageins("Pageins".to_string()),
+        duplicate("Duplicate")
+    );
+    out.push_str(&lead("---"));
+    let _ = writeln!(
+        out,
+        "|---:|---|---|---|---:|---:|---:|{}{}{}{}---:|---:|{}{}---|---|---|",
+        if opts.gpu { "---:|" } else { "" },
+        if opts.energy { "---:|" } else { "" },
+        if opts.disk_io { "---:|---:|" } else { "" },
+        if opts.since_last {
+            "---:|---:|---:|"
+        } else {
+            ""
+        },
+        if opts.pageins { "---:|" } else { "" },
+        if show_duplicate { "---|" } else { "" }
+    );
+    for group in app_groups(rows) {
+        for row in &group {
+            let history_lines = if row.pane_history_size >= 0 {
+                format!("{}/{}", row.pane_history_size, row.pane_history_limit)
+            } else {
+                "-".to_string()
+            };
+            let cmd = truncate_display(&display_command(row), opts.max_command_width)
+                .replace('|', "\\|")
+                .replace('\n', " ");
+            out.push_str(&lead(row.severity.map_or("", severity_label)));
+            let _ = writeln!(
+                out,
+                "| {} | {} | {} | {} | {} | {} | {} |{}{}{}{} {} | {} |{}{} {} | {} | {} |",
+                row.pid,
+                row.user,
+                row.tmux_target,
+                row.tmux_window_name,
+                footprint_cell(row, row.swap_bytes),
+                footprint_cell(row, row.physical_bytes),
+                human_bytes(row.rss_bytes),
+                gpu(gpu_cell(row.gpu_bytes)),
+                energy(energy_cell(row.energy_impact)),
+                disk_io(disk_io_cells(row.disk_io)),
+                deltas(delta_cells(row.since_last)),
+                human_bytes(row.pane_history_bytes),
+                history_lines,
+                pageins(pageins_cell(row.pageins)),
+                duplicate(if row.duplicate_instance { "yes" } else { "" }),
+                row.tag.unwrap_or("-"),
+                row.role.as_deref().unwrap_or("-"),
+                cmd,
+            );
+        }
+        if let Some(sub) = group_subtotal(&group) {
+            out.push_str(&lead(""));
+            let _ = writeln!(
+                out,
+                "|  |  | {} | {} | **{}** | **{}** | **{}** |{}{}{}{}  |  |{}{}  |  | _subtotal: {} processes_ |",
+                sub.tmux_target,
+                sub.tmux_window_name,
+                human_bytes(sub.swap_bytes),
+                human_bytes(sub.physical_bytes),
+                human_bytes(sub.rss_bytes),
+                gpu(format!(
+                    "**{}**",
+                    human_bytes(group.iter().filter_map(|r| r.gpu_bytes).sum())
+                )),
+                energy(format!(
+                    "**{}**",
+                    energy_cell(Some(group.iter().filter_map(|r| r.energy_impact).sum()))
+                )),
+                disk_io(disk_io_cells(sum_disk_io(group.iter().copied())).map(|cell| format!("**{cell}**"))),
+                deltas(Default::default()),
+                pageins(String::new()),
+                duplicate(""),
+                sub.process_count,
+            );
+        }
+    }
+    out
+}
+
+// The --since-last ΔSwap/ΔPhysical/ΔRSS cells, when a run compared against an export.
+fn md_delta_cells(show: bool, [swap, physical, rss]: [String; 3]) -> String {
+    if show {
+        format!(" {swap} | {physical} | {rss} |")
+    } else {
+        String::new()
+    }
+}
+
+// Leading Markdown cell for the optional severity column; the row supplies the rest.
+fn md_severity_cell(show: bool, cell: &str) -> String {
+    if show {
+        format!("| {cell} ")
+    } else {
+        String::new()
+    }
+}
+
+const PIDS_SUMMARY_SHOWN: usize = 3;
+
+// Summary keeps busy panes readable: `1234,1240,1251,+7 more`.
+fn pids_cell(pids: &[i32], display: PidsDisplay) -> String {
+    let shown = match display {
+        PidsDisplay::None => return String::new(),
+        PidsDisplay::Summary if pids.len() > PIDS_SUMMARY_SHOWN => PIDS_SUMMARY_SHOWN,
+        _ => pids.len(),
+    };
+    let mut cell = pids[..shown]
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(",");
+    if shown < pids.len() {
+        let _ = write!(cell, ",+{} more", pids.len() - shown);
+    }
+    cell
+}
+
+// `members` (--pane-detail) adds an indented row per process under its pane.
+fn render_pane_table(
+    rows: &[PaneRecord],
+    members: Option<&[ProcRecord]>,
+    opts: &RenderOptions,
+) -> String {
+    let mut table = TableLayout {
+        header: vec![
+            "Tmux window.pane",
+            "Window",
+            "Processes",
+            "PIDs",
+            "Swap",
+            "Physical",
+            "RSS",
+            "PaneHistory",
+            "History lines",
+        ],
+        rows: Vec::new(),
+        optional: vec![3, 7, 1],
+    };
+    for row in rows {
+        let history_lines = if row.pane_history_size >= 0 {
+            format!("{}/{}", row.pane_history_size, row.pane_history_limit)
+        } else {
+            "-".to_string()
+        };
+        let mut cells = table_row(vec![
+            row.tmux_target.clone(),
+            row.tmux_window_name.clone(),
+            row.process_count.to_string(),
+            pids_cell(&row.pids, opts.pids),
+            human_bytes(row.swap_bytes),
+            human_bytes(row.physical_bytes),
+            human_bytes(row.rss_bytes),
+            human_bytes(row.pane_history_bytes),
+            history_lines,
+        ]);
+        cells.styles[4] = Some(swap_style(row.swap_bytes));
+        if row.growth_alert == Some(true) {
+            cells.style = Some(YELLOW);
+        }
+        table.rows.push(cells);
+    }
+    if rows.iter().any(|r| r.shell_rss_bytes.is_some()) {
+        let shells = rows
+            .iter()
+            .map(|r| (r.shell_rss_bytes.map_or("-".to_string(), human_bytes), None))
+            .collect();
+        insert_table_column(&mut table, 7, "Shell RSS", shells);
+    }
+    if opts.since_last {
+        let deltas = rows.iter().map(|r| delta_cells(r.since_last)).collect();
+        insert_delta_columns(&mut table, 7, deltas);
+    }
+    if rows.iter().any(|r| r.app_instances > 1) {
+        let instances = rows
+            .iter()
+            .map(|r| {
+                let style = (r.app_instances > 1).then_some(RED);
+                (r.app_instances.to_string(), style)
+            })
+            .collect();
+        insert_table_column(&mut table, 3, "Instances", instances);
+    }
+    if opts.pids == PidsDisplay::None {
+        let at = table.header.iter().position(|h| *h == "PIDs").unwrap_or(3);
+        remove_table_column(&mut table, at);
+    }
+    if opts.percent || opts.cumulative {
+        let cells = rows
+            .iter()
+            .map(|r| Some((r.swap_pct, r.physical_pct)))
+            .collect();
+        insert_share_columns(&mut table, cells, opts);
+    }
+    if let Some(metric) = opts.bars {
+        let values = rows
+            .iter()
+            .map(|r| Some(pane_metric(r, metric)))
+            .collect::<Vec<_>>();
+        insert_bar_column(&mut table, metric, &values, opts.table_style);
+    }
+    if rows.iter().any(|r| r.session_created.is_some()) {
+        insert_session_columns(
+            &mut table,
+            rows.iter()
+                .map(|r| (r.session_created, r.session_attached))
+                .collect(),
+        );
+    }
+    if rows.iter().any(|r| r.severity.is_some()) {
+        let severities = rows.iter().map(|r| r.severity).collect::<Vec<_>>();
+        prepend_severity_column(&mut table, &severities);
+    }
+    if let Some(members) = members {
+        insert_pane_members(&mut table, rows, members, opts);
+    }
+    let mut out = render_layout(&table, opts);
+
+    let total_swap = rows.iter().map(|r| r.swap_bytes).sum::<u64>();
+    let total_phys = rows.iter().map(|r| r.physical_bytes).sum::<u64>();
+    let total_rss = rows.iter().map(|r| r.rss_bytes).sum::<u64>();
+    let total_hist = rows.iter().map(|r| r.pane_history_bytes).sum::<u64>();
+
+    out.push('\n');
+    let _ = writeln!(out, "Total swap:\t{}", human_bytes(total_swap));
+    let _ = writeln!(out, "Total physical:\t{}", human_bytes(total_phys));
+    let _ = writeln!(out, "Total RSS:\t{}", human_bytes(total_rss));
+    let _ = writeln!(
+        out,
+        "Total pane history bytes:\t{}",
+        human_bytes(total_hist)
+    );
+    out
+}
+
+// Session age and attach state right after the window (or group) name; both are
+// dropped before anything else on a narrow terminal.
+fn insert_session_columns(table: &mut TableLayout, sessions: Vec<(Option<u64>, Option<bool>)>) {
+    let at = table
+        .header
+        .iter()
+        .position(|h| *h == "Window")
+        .unwrap_or(0)
+        + 1;
+    let (ages, attached) = sessions
+        .into_iter()
+        .map(|(created, attached)| {
+            (
+                (session_age_cell(created), None),
+                (session_attached_cell(attached), None),
+            )
+        })
+        .unzip();
+    insert_table_column(table, at, "Session age", ages);
+    insert_table_column(table, at + 1, "Attached", attached);
+    table.optional.splice(0..0, [at + 1, at]);
+}
+
+// Runs last, once every optional column is in place: member rows fill only the
+// target and memory columns, found by header, and leave the rest blank.
+fn insert_pane_members(
+    table: &mut TableLayout,
+    panes: &[PaneRecord],
+    members: &[ProcRecord],
+    opts: &RenderOptions,
+) {
+    let column = |name: &str| table.header.iter().position(|h| *h == name);
+    let (Some(target), Some(swap), Some(physical), Some(rss)) = (
+        column("Tmux window.pane"),
+        column("Swap"),
+        column("Physical"),
+        column("RSS"),
+    ) else {
+        return;
+    };
+    let width = table.header.len();
+    for (idx, pane) in panes.iter().enumerate().rev() {
+        let detail = members
+            .iter()
+            .filter(|r| pane.pids.contains(&r.pid))
+            .map(|r| {
+                let mut cells = table_row(vec![String::new(); width]);
+                cells.cells[target] = truncate_display(
+                    &format!("  {} {}", r.pid, display_command(r)),
+                    opts.max_command_width,
+                );
+                cells.cells[swap] = human_bytes(r.swap_bytes);
+                cells.cells[physical] = human_bytes(r.physical_bytes);
+                cells.cells[rss] = human_bytes(r.rss_bytes);
+                cells.style = Some(DIM);
+                cells
+            })
+            .collect::<Vec<_>>();
+        table.rows.splice(idx + 1..idx + 1, detail);
+    }
+}
+
+const MD_REPORT_HEAVIEST: usize = 3;
+
+// --format md-report: one Markdown document to paste into an issue or wiki page.
+// History clears are suggested for panes holding more than --history-target.
+fn render_md_report(
+    rows: &[ProcRecord],
+    panes: &[PaneRecord],
+    history_target: u64,
+    (generated, host): (u64, &str),
+    opts: &RenderOptions,
+) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "# opencode-tmux-mem report\n\nGenerated {} on `{host}`.\n",
+        format_utc_timestamp(generated)
+    );
+    let tmux_panes = panes
+        .iter()
+        .filter(|p| p.tmux_target != "?")
+        .collect::<Vec<_>>();
+    out.push_str("## Summary\n\n");
+    let _ = writeln!(out, "- Processes: {}", rows.len());
+    let _ = writeln!(out, "- Tmux panes: {}", tmux_panes.len());
+    let _ = writeln!(
+        out,
+        "- Swap: {}, physical: {}, RSS: {}",
+        human_bytes(rows.iter().map(|r| r.swap_bytes).sum()),
+        human_bytes(rows.iter().map(|r| r.physical_bytes).sum()),
+        human_bytes(rows.iter().map(|r| r.rss_bytes).sum())
+    );
+    let _ = writeln!(
+        out,
+        "- Pane history: {}",
+        human_bytes(tmux_panes.iter().map(|p| p.pane_history_bytes).sum())
+    );
+    let flagged = panes.iter().filter(|p| p.severity.is_some()).count();
+    if flagged > 0 {
+        let _ = writeln!(out, "- Panes over a severity threshold: {flagged}");
+    }
+    out.push_str("\n## Panes\n\n");
+    out.push_str(&render_pane_markdown(panes, opts));
+    out.push_str("\n## Processes\n\n");
+    out.push_str(&render_markdown(rows, opts));
+
+    out.push_str("\n## Recommendations\n\n### Heaviest panes\n\n");
+    let mut heaviest = tmux_panes.clone();
+    heaviest.sort_by_key(|p| {
+        std::cmp::Reverse(footprint_bytes(p.swap_bytes, p.physical_bytes, p.rss_bytes))
+    });
+    if heaviest.is_empty() {
+        out.push_str("No matching processes run inside tmux.\n");
+    }
+    for (idx, pane) in heaviest.iter().take(MD_REPORT_HEAVIEST).enumerate() {
+        let _ = writeln!(
+            out,
+            "{}. `{}` ({}): {} across {} processes",
+            idx + 1,
+            pane.tmux_target,
+            pane.tmux_window_name,
+            human_bytes(footprint_bytes(
+                pane.swap_bytes,
+                pane.physical_bytes,
+                pane.rss_bytes
+            )),
+            pane.process_count
+        );
+    }
+    out.push_str("\n### Suggested history clears\n\n");
+    let mut clears = tmux_panes
+        .iter()
+        .filter(|p| p.pane_history_bytes > history_target)
+        .collect::<Vec<_>>();
+    clears.sort_by_key(|p| std::cmp::Reverse(p.pane_history_bytes));
+    if clears.is_empty() {
+        let _ = writeln!(
+            out,
+            "No pane holds more than {} of scrollback.",
+            human_bytes(history_target)
+        );
+    }
+    for pane in clears {
+        let _ = writeln!(
+            out,
+            "- `{}` holds {} of scrollback ({}/{} lines): `{}`",
+            pane.tmux_target,
+            human_bytes(pane.pane_history_bytes),
+            pane.pane_history_size,
+            pane.pane_history_limit,
+            shell_command_line("tmux", &["clear-history", "-t", &pane.tmux_target])
+        );
+    }
+    out
+}
+
+fn render_pane_markdown(rows: &[PaneRecord], opts: &RenderOptions) -> String {
+    let mut out = String::new();
+    let lead = |cell: &str| md_severity_cell(rows.iter().any(|r| r.severity.is_some()), cell);
+    let pids = opts.pids;
+    let show_pids = pids != PidsDisplay::None;
+    out.push_str(&lead("Severity"));
+    let _ = writeln!(
+        out,
+        "| Tmux window.pane | Window | Processes |{} Swap | Physical | RSS |{} PaneHistory | History lines |",
+        if show_pids { " PIDs |" } else { "" },
+        md_delta_cells(opts.since_last, DELTA_HEADERS.map(str::to_string))
+    );
+    out.push_str(&lead("---"));
+    let _ = writeln!(
+        out,
+        "|---|---|---:|{}---:|---:|---:|{}---:|---:|",
+        if show_pids { "---|" } else { "" },
+        if opts.since_last {
+            "---:|---:|---:|"
+        } else {
+            ""
+        }
+    );
+    for row in rows {
+        let history_lines = if row.pane_history_size >= 0 {
+            format!("{}/{}", row.pane_history_size, row.pane_history_limit)
+        } else {
+            "-".to_string()
+        };
+        let pids_column = if show_pids {
+            format!(" {} |", pids_cell(&row.pids, pids))
+        } else {
+            String::new()
+        };
+        out.push_str(&lead(row.severity.map_or("", severity_label)));
+        let _ = writeln!(
+            out,
+            "| {} | {} | {} |{} {} | {} | {} |{} {} | {} |",
+            row.tmux_target,
+            row.tmux_window_name,
+            row.process_count,
+            pids_column,
+            human_bytes(row.swap_bytes),
+            human_bytes(row.physical_bytes),
+            human_bytes(row.rss_bytes),
+            md_delta_cells(opts.since_last, delta_cells(row.since_last)),
+            human_bytes(row.pane_history_bytes),
+            history_lines,
+        );
+    }
+    out
+}
+
+fn render_mappings(rows: &[MappingRecord], fmt: OutputFormat, opts: &RenderOptions) -> String {
+    match fmt {
+        OutputFormat::Table => render_mappings_table(rows, opts),
+        OutputFormat::Json => render_fields_json(rows, mapping_fields),
+        OutputFormat::Csv => render_fields_csv(rows, mapping_fields),
+        OutputFormat::Yaml => render_fields_yaml(rows, mapping_fields, opts),
+        OutputFormat::Markdown => render_mappings_markdown(rows),
+        OutputFormat::Html
+        | OutputFormat::Porcelain
+        | OutputFormat::MdReport
+        | OutputFormat::Svg => {
+            unreachable!("checked in run()")
+        }
+    }
+}
+
+fn mapping_fields(row: &MappingRecord) -> Vec<(&'static str, Field)> {
+    vec![
+        ("name", Field::Str(row.name.clone())),
+        ("kind", Field::Str(row.kind.to_string())),
+        ("regions", Field::Uint(row.regions as u64)),
+        ("size_bytes", Field::Uint(row.size_bytes)),
+        ("size_human", Field::Str(human_bytes(row.size_bytes))),
+        ("resident_bytes", Field::Uint(row.resident_bytes)),
+        (
+            "resident_human",
+            Field::Str(human_bytes(row.resident_bytes)),
+        ),
+        ("swap_bytes", Field::Uint(row.swap_bytes)),
+        ("swap_human", Field::Str(human_bytes(row.swap_bytes))),
+    ]
+}
+
+fn render_mappings_table(rows: &[MappingRecord], opts: &RenderOptions) -> String {
+    let mut table = TableLayout {
+        header: vec!["Kind", "Regions", "Size", "Resident", "Swap", "Mapping"],
+        rows: Vec::new(),
+        optional: vec![1, 2],
+    };
+    for row in rows {
+        let mut cells = table_row(vec![
+            row.kind.to_string(),
+            row.regions.to_string(),
+            human_bytes(row.size_bytes),
+            human_bytes(row.resident_bytes),
+            human_bytes(row.swap_bytes),
+            truncate_display(&row.name, opts.max_command_width),
+        ]);
+        cells.styles[4] = Some(swap_style(row.swap_bytes));
+        table.rows.push(cells);
+    }
+    let mut out = render_layout(&table, opts);
+
+    let resident = rows.iter().map(|r| r.resident_bytes).sum::<u64>();
+    let swap = rows.iter().map(|r| r.swap_bytes).sum::<u64>();
+    out.push('\n');
+    let _ = writeln!(out, "Listed resident:\t{}", human_bytes(resident));
+    let _ = writeln!(out, "Listed swap:\t{}", human_bytes(swap));
+    out
+}
+
+fn render_mappings_markdown(rows: &[MappingRecord]) -> String {
+    let mut out = String::new();
+    out.push_str("| Kind | Regions | Size | Resident | Swap | Mapping |\n");
+    out.push_str("|---|---:|---:|---:|---:|---|\n");
+    for row in rows {
+        let _ = writeln!(
+            out,
+            "| {} | {} | {} | {} | {} | {} |",
+            row.kind,
+            row.regions,
+            human_bytes(row.size_bytes),
+            human_bytes(row.resident_bytes),
+            human_bytes(row.swap_bytes),
+            row.name.replace('|', "\\|"),
+        );
+    }
+    out
+}
+
+fn render_tmux(rows: &[TmuxRecord], fmt: OutputFormat, opts: &RenderOptions) -> String {
+    match fmt {
+        OutputFormat::Table => render_tmux_table(rows, opts),
+        OutputFormat::Json => render_fields_json(rows, tmux_fields),
+        OutputFormat::Csv => render_fields_csv(rows, tmux_fields),
+        OutputFormat::Yaml => render_fields_yaml(rows, tmux_fields, opts),
+        OutputFormat::Markdown => render_tmux_markdown(rows),
+        OutputFormat::Html
+        | OutputFormat::Porcelain
+        | OutputFormat::MdReport
+        | OutputFormat::Svg => {
+            unreachable!("checked in run()")
+        }
+    }
+}
+
+fn tmux_fields(row: &TmuxRecord) -> Vec<(&'static str, Field)> {
+    let measured = |bytes: u64| {
+        if row.vmmap_skipped {
+            Field::Null
+        } else {
+            Field::Uint(bytes)
+        }
+    };
+    vec![
+        ("kind", Field::Str(row.kind.to_string())),
+        ("pid", Field::Int(row.pid.into())),
+        (
+            "session",
+            row.session.clone().map_or(Field::Null, Field::Str),
+        ),
+        ("tty", row.tty.clone().map_or(Field::Null, Field::Str)),
+        ("swap_bytes", measured(row.swap_bytes)),
+        ("physical_bytes", measured(row.physical_bytes)),
+        ("rss_bytes", Field::Uint(row.rss_bytes)),
+        ("rss_human", Field::Str(human_bytes(row.rss_bytes))),
+        (
+            "pane_history_bytes",
+            row.pane_history_bytes.map_or(Field::Null, Field::Uint),
+        ),
+        (
+            "pane_count",
+            row.pane_count
+                .map_or(Field::Null, |n| Field::Uint(n as u64)),
+        ),
+    ]
+}
+
+// The sanity check: what the server holds against the scrollback it serves.
+fn tmux_server_summary(rows: &[TmuxRecord]) -> Option<String> {
+    let server = rows.iter().find(|r| r.kind == "server")?;
+    let history = server.pane_history_bytes?;
+    let (label, bytes) = if server.vmmap_skipped {
+        ("RSS", server.rss_bytes)
+    } else {
+        ("physical", server.physical_bytes)
+    };
+    let ratio = if history > 0 {
+        format!(" ({:.1}x)", bytes as f64 / history as f64)
+    } else {
+        String::new()
+    };
+    Some(format!(
+        "Server {label} vs pane history of {} panes: {} vs {}{ratio}",
+        server.pane_count.unwrap_or(0),
+        human_bytes(bytes),
+        human_bytes(history)
+    ))
+}
+
+fn render_tmux_table(rows: &[TmuxRecord], opts: &RenderOptions) -> String {
+    let mut table = TableLayout {
+        header: vec![
+            "Kind",
+            "PID",
+            "Session",
+            "TTY",
+            "Swap",
+            "Physical",
+            "RSS",
+            "PaneHistory",
+        ],
+        rows: Vec::new(),
+        optional: vec![3, 4],
+    };
+    for row in rows {
+        let measured = |bytes: u64| {
+            if row.vmmap_skipped {
+                "-".to_string()
+            } else {
+                human_bytes(bytes)
+            }
+        };
+        let mut cells = table_row(vec![
+            row.kind.to_string(),
+            row.pid.to_string(),
+            row.session.clone().unwrap_or_else(|| "-".to_string()),
+            row.tty.clone().unwrap_or_else(|| "-".to_string()),
+            measured(row.swap_bytes),
+            measured(row.physical_bytes),
+            human_bytes(row.rss_bytes),
+            row.pane_history_bytes
+                .map_or_else(|| "-".to_string(), human_bytes),
+        ]);
+        cells.styles[4] = Some(swap_style(row.swap_bytes));
+        table.rows.push(cells);
+    }
+    let mut out = render_layout(&table, opts);
+    out.push('\n');
+    let _ = writeln!(
+        out,
+        "Total tmux RSS:\t{} ({} client(s))",
+        human_bytes(rows.iter().map(|r| r.rss_bytes).sum()),
+        rows.iter().filter(|r| r.kind == "client").count()
+    );
+    if let Some(summary) = tmux_server_summary(rows) {
+        let _ = writeln!(out, "{summary}");
+    }
+    out
+}
+
+fn render_tmux_markdown(rows: &[TmuxRecord]) -> String {
+    let mut out = String::new();
+    out.push_str("| Kind | PID | Session | TTY | Swap | Physical | RSS | PaneHistory |\n");
+    out.push_str("|---|---:|---|---|---:|---:|---:|---:|\n");
+    for row in rows {
+        let measured = |bytes: u64| {
+            if row.vmmap_skipped {
+                "-".to_string()
+            } else {
+                human_bytes(bytes)
+            }
+        };
+        let _ = writeln!(
+            out,
+            "| {} | {} | {} | {} | {} | {} | {} | {} |",
+            row.kind,
+            row.pid,
+            row.session.as_deref().unwrap_or("-"),
+            row.tty.as_deref().unwrap_or("-"),
+            measured(row.swap_bytes),
+            measured(row.physical_bytes),
+            human_bytes(row.rss_bytes),
+            row.pane_history_bytes
+                .map_or_else(|| "-".to_string(), human_bytes),
+        );
+    }
+    if let Some(summary) = tmux_server_summary(rows) {
+        let _ = write!(out, "\n{summary}\n");
+    }
+    out
+}
+
+fn render_budget(rows: &[BudgetRecord], fmt: OutputFormat, opts: &RenderOptions) -> String {
+    match fmt {
+        OutputFormat::Table => render_budget_table(rows, opts),
+        OutputFormat::Json => render_fields_json(rows, budget_fields),
+        OutputFormat::Csv => render_fields_csv(rows, budget_fields),
+        OutputFormat::Yaml => render_fields_yaml(rows, budget_fields, opts),
+        OutputFormat::Markdown => render_budget_markdown(rows),
+        OutputFormat::Html
+        | OutputFormat::Porcelain
+        | OutputFormat::MdReport
+        | OutputFormat::Svg => {
+            unreachable!("checked in run()")
+        }
+    }
+}
+
+fn render_advice(rows: &[HistoryAdvice], fmt: OutputFormat, opts: &RenderOptions) -> String {
+    match fmt {
+        OutputFormat::Table => render_advice_table(rows, opts),
+        OutputFormat::Json => render_fields_json(rows, advice_fields),
+        OutputFormat::Csv => render_fields_csv(rows, advice_fields),
+        OutputFormat::Yaml => render_fields_yaml(rows, advice_fields, opts),
+        OutputFormat::Markdown => render_advice_markdown(rows),
+        OutputFormat::Html
+        | OutputFormat::Porcelain
+        | OutputFormat::MdReport
+        | OutputFormat::Svg => {
+            unreachable!("checked in run()")
+        }
+    }
+}
+
+fn render_advice_table(rows: &[HistoryAdvice], opts: &RenderOptions) -> String {
+    let mut table = TableLayout {
+        header: vec![
+            "Session",
+            "Panes",
+            "Limit",
+            "Max lines",
+            "Bytes/line",
+            "Suggested",
+            "Saved now",
+            "Saved at capacity",
+            "Command",
+        ],
+        rows: Vec::new(),
+        optional: vec![4, 3, 1],
+    };
+    for row in rows {
+        table.rows.push(table_row(vec![
+            row.session.clone(),
+            row.pane_count.to_string(),
+            row.current_limit.to_string(),
+            row.max_history_lines.to_string(),
+            row.bytes_per_line.to_string(),
+            row.suggested_limit.to_string(),
+            human_bytes(row.saved_now_bytes),
+            human_bytes(row.saved_at_capacity_bytes),
+            advice_command(row).unwrap_or_else(|| "(keep)".to_string()),
+        ]));
+    }
+    let mut out = render_layout(&table, opts);
+
+    let saved_now = rows.iter().map(|r| r.saved_now_bytes).sum::<u64>();
+    let saved_cap = rows.iter().map(|r| r.saved_at_capacity_bytes).sum::<u64>();
+    out.push('\n');
+    let _ = writeln!(out, "Saved now:\t{}", human_bytes(saved_now));
+    let _ = writeln!(out, "Saved at capacity:\t{}", human_bytes(saved_cap));
+    out.push_str("Note: history-limit applies to new panes; clear-history trims existing ones.\n");
+    out
+}
+
+fn render_advice_markdown(rows: &[HistoryAdvice]) -> String {
+    let mut out = String::new();
+    out.push_str("| Session | Panes | Limit | Max lines | Bytes/line | Suggested | Saved now | Saved at capacity | Command |\n");
+    out.push_str("|---|---:|---:|---:|---:|---:|---:|---:|---|\n");
+    for row in rows {
+        let command = advice_command(row)
+            .map(|c| format!("`{c}`"))
+            .unwrap_or_else(|| "(keep)".to_string());
+        let _ = writeln!(
+            out,
+            "| {} | {} | {} | {} | {} | {} | {} | {} | {} |",
+            row.session,
+            row.pane_count,
+            row.current_limit,
+            row.max_history_lines,
+            row.bytes_per_line,
+            row.suggested_limit,
+            human_bytes(row.saved_now_bytes),
+            human_bytes(row.saved_at_capacity_bytes),
+            command,
+        );
+    }
+    out
+}
+
+fn render_doctor(rows: &[DoctorCheck], fmt: OutputFormat, opts: &RenderOptions) -> String {
+    match fmt {
+        OutputFormat::Table => render_doctor_table(rows, opts),
+        OutputFormat::Json => render_fields_json(rows, doctor_fields),
+        OutputFormat::Csv => render_fields_csv(rows, doctor_fields),
+        OutputFormat::Yaml => render_fields_yaml(rows, doctor_fields, opts),
+        OutputFormat::Markdown => render_doctor_markdown(rows),
+        OutputFormat::Html
+        | OutputFormat::Porcelain
+        | OutputFormat::MdReport
+        | OutputFormat::Svg => {
+            unreachable!("checked in run()")
+        }
+    }
+}
+
+fn check_status_label(status: CheckStatus) -> (&'static str, &'static str) {
+    match status {
+        CheckStatus::Pass => ("✓ PASS", GREEN),
+        CheckStatus::Warn => ("! WARN", YELLOW),
+        CheckStatus::Fail => ("✗ FAIL", RED),
+    }
+}
+
+fn render_doctor_table(rows: &[DoctorCheck], opts: &RenderOptions) -> String {
+    let mut table = TableLayout {
+        header: vec!["Status", "Check", "Detail", "Fix"],
+        rows: Vec::new(),
+        optional: Vec::new(),
+    };
+    for row in rows {
+        let (label, style) = check_status_label(row.status);
+        let mut cells = table_row(vec![
+            label.to_string(),
+            row.name.to_string(),
+            row.detail.clone(),
+            if row.hint.is_empty() {
+                "-".to_string()
+            } else {
+                row.hint.clone()
+            },
+        ]);
+        cells.styles[0] = Some(style);
+        table.rows.push(cells);
+    }
+    let mut out = render_layout(&table, opts);
+    let count = |status| rows.iter().filter(|r| r.status == status).count();
+    let _ = writeln!(
+        out,
+        "\n{} passed, {} warning(s), {} failed",
+        count(CheckStatus::Pass),
+        count(CheckStatus::Warn),
+        count(CheckStatus::Fail)
+    );
+    out
+}
+
+fn render_doctor_markdown(rows: &[DoctorCheck]) -> String {
+    let mut out = String::new();
+    out.push_str("| Status | Check | Detail | Fix |\n");
+    out.push_str("|---|---|---|---|\n");
+    for row in rows {
+        let _ = writeln!(
+            out,
+            "| {} | {} | {} | {} |",
+            check_status_label(row.status).0,
+            row.name,
+            row.detail.replace('|', "\\|"),
+            if row.hint.is_empty() { "-" } else { &row.hint },
+        );
+    }
+    out
+}
+
+fn render_verify(
+    rows: &[VerifyRecord],
+    tolerance_pct: f64,
+    fmt: OutputFormat,
+    opts: &RenderOptions,
+) -> String {
+    match fmt {
+        OutputFormat::Table => render_verify_table(rows, tolerance_pct, opts),
+        OutputFormat::Json => render_fields_json(rows, verify_fields),
+        OutputFormat::Csv => render_fields_csv(rows, verify_fields),
+        OutputFormat::Yaml => render_fields_yaml(rows, verify_fields, opts),
+        OutputFormat::Markdown => render_verify_markdown(rows),
+        OutputFormat::Html
+        | OutputFormat::Porcelain
+        | OutputFormat::MdReport
+        | OutputFormat::Svg => {
+            unreachable!("checked in run()")
+        }
+    }
+}
+
+fn verify_fields(row: &VerifyRecord) -> Vec<(&'static str, Field)> {
+    vec![
+        ("pid", Field::Int(row.pid.into())),
+        ("command_name", Field::Str(command_name(&row.command))),
+        ("command", Field::Str(row.command.clone())),
+        ("rss_bytes", Field::Uint(row.rss_bytes)),
+        (
+            "vmmap_physical_bytes",
+            row.vmmap_bytes.map_or(Field::Null, Field::Uint),
+        ),
+        (
+            "footprint_tool_bytes",
+            row.footprint_tool_bytes.map_or(Field::Null, Field::Uint),
+        ),
+        (
+            "spread_pct",
+            row.spread_pct.map_or(Field::Null, Field::Float),
+        ),
+        ("status", Field::Str(row.status.to_string())),
+    ]
+}
+
+const VERIFY_HEADER: [&str; 7] = [
+    "PID",
+    "RSS",
+    "vmmap physical",
+    "footprint(1)",
+    "Spread",
+    "Status",
+    "Command",
+];
+
+fn verify_cells(row: &VerifyRecord) -> [String; 7] {
+    let bytes = |v: Option<u64>| v.map_or("-".to_string(), human_bytes);
+    [
+        row.pid.to_string(),
+        human_bytes(row.rss_bytes),
+        bytes(row.vmmap_bytes),
+        bytes(row.footprint_tool_bytes),
+        pct_cell(row.spread_pct),
+        row.status.to_string(),
+        row.command.clone(),
+    ]
+}
+
+fn render_verify_table(rows: &[VerifyRecord], tolerance_pct: f64, opts: &RenderOptions) -> String {
+    let mut table = TableLayout {
+        header: VERIFY_HEADER.to_vec(),
+        rows: Vec::new(),
+        optional: vec![1],
+    };
+    for row in rows {
+        let [pid, rss, vmmap, tool, spread, status, command] = verify_cells(row);
+        let mut cells = table_row(vec![
+            pid,
+            rss,
+            vmmap,
+            tool,
+            spread,
+            status,
+            truncate_display(&command, opts.max_command_width),
+        ]);
+        if row.status == "mismatch" {
+            cells.styles[4] = Some(RED);
+            cells.styles[5] = Some(RED);
+        }
+        table.rows.push(cells);
+    }
+    let mut out = render_layout(&table, opts);
+    let mismatches = rows.iter().filter(|r| r.status == "mismatch").count();
+    out.push('\n');
+    if rows.iter().all(|r| r.status == "unverified") {
+        out.push_str("Nothing to cross-check: vmmap and footprint(1) are macOS tools, and both are needed per process.\n");
+    } else {
+        let _ = writeln!(
+            out,
+            "{mismatches} of {} processes: vmmap and footprint(1) disagree by more than {tolerance_pct}%.",
+            rows.len()
+        );
+    }
+    out
+}
+
+fn render_verify_markdown(rows: &[VerifyRecord]) -> String {
+    let mut out = format!("| {} |\n", VERIFY_HEADER.join(" | "));
+    out.push_str("|---:|---:|---:|---:|---:|---|---|\n");
+    for row in rows {
+        let cells = verify_cells(row);
+        let _ = writeln!(
+            out,
+            "| {} |",
+            cells
+                .iter()
+                .map(|c| c.replace('|', "\\|"))
+                .collect::<Vec<_>>()
+                .join(" | ")
+        );
+    }
+    out
+}
+
+fn render_leaks(rows: &[LeakRecord], fmt: OutputFormat, opts: &RenderOptions) -> String {
+    match fmt {
+        OutputFormat::Table => render_leaks_table(rows, opts),
+        OutputFormat::Json => render_fields_json(rows, leak_fields),
+        OutputFormat::Csv => render_fields_csv(rows, leak_fields),
+        OutputFormat::Yaml => render_fields_yaml(rows, leak_fields, opts),
+        OutputFormat::Markdown => render_leaks_markdown(rows),
+        OutputFormat::Html
+        | OutputFormat::Porcelain
+        | OutputFormat::MdReport
+        | OutputFormat::Svg => {
+            unreachable!("checked in run()")
+        }
+    }
+}
+
+fn render_leaks_table(rows: &[LeakRecord], opts: &RenderOptions) -> String {
+    let mut table = TableLayout {
+        header: vec![
+            "Scope",
+            "Subject",
+            "Tmux window.pane",
+            "Samples",
+            "Span",
+            "First",
+            "Last",
+            "Growth/h",
+            "Confidence",
+            "Command",
+        ],
+        rows: Vec::new(),
+        optional: vec![5, 4, 2],
+    };
+    for row in rows {
+        let mut cells = table_row(vec![
+            row.scope.to_string(),
+            row.subject.clone(),
+            row.tmux_target.clone(),
+            row.samples.to_string(),
+            human_duration(row.span_secs),
+            human_bytes(row.first_bytes),
+            human_bytes(row.last_bytes),
+            human_bytes(row.growth_per_hour_bytes),
+            row.confidence.to_string(),
+            truncate_display(&row.command, opts.max_command_width),
+        ]);
+        cells.styles[7] = Some(RED);
+        table.rows.push(cells);
+    }
+    let mut out = render_layout(&table, opts);
+    if rows.is_empty() {
+        out.push_str("No sustained growth above the leak threshold.\n");
+    }
+    out
+}
+
+fn render_leaks_markdown(rows: &[LeakRecord]) -> String {
+    let mut out = String::new();
+    out.push_str("| Scope | Subject | Tmux window.pane | Samples | Span | First | Last | Growth/h | Confidence | Command |\n");
+    out.push_str("|---|---|---|---:|---:|---:|---:|---:|---|---|\n");
+    for row in rows {
+        let _ = writeln!(
+            out,
+            "| {} | {} | {} | {} | {} | {} | {} | {} | {} | {} |",
+            row.scope,
+            row.subject,
+            row.tmux_target,
+            row.samples,
+            human_duration(row.span_secs),
+            human_bytes(row.first_bytes),
+            human_bytes(row.last_bytes),
+            human_bytes(row.growth_per_hour_bytes),
+            row.confidence,
+            row.command.replace('|', "\\|"),
+        );
+    }
+    out
+}
+
+fn render_snapshots(rows: &[SnapshotRecord], fmt: OutputFormat, opts: &RenderOptions) -> String {
+    match fmt {
+        OutputFormat::Table => render_snapshots_table(rows, opts),
+        OutputFormat::Json => render_fields_json(rows, snapshot_fields),
+        OutputFormat::Csv => render_fields_csv(rows, snapshot_fields),
+        OutputFormat::Yaml => render_fields_yaml(rows, snapshot_fields, opts),
+        OutputFormat::Markdown => render_snapshots_markdown(rows),
+        OutputFormat::Html
+        | OutputFormat::Porcelain
+        | OutputFormat::MdReport
+        | OutputFormat::Svg => {
+            unreachable!("checked in run()")
+        }
+    }
+}
+
+fn snapshot_fields(row: &SnapshotRecord) -> Vec<(&'static str, Field)> {
+    vec![
+        ("name", Field::Str(row.name.clone())),
+        ("saved_at", Field::Str(row.saved_at.clone())),
+        ("version", Field::Str(row.version.clone())),
+        ("process_count", Field::Uint(row.process_count as u64)),
+        ("swap_bytes", Field::Uint(row.swap_bytes)),
+        ("physical_bytes", Field::Uint(row.physical_bytes)),
+        ("rss_bytes", Field::Uint(row.rss_bytes)),
+    ]
+}
+
+fn render_snapshots_table(rows: &[SnapshotRecord], opts: &RenderOptions) -> String {
+    let mut table = TableLayout {
+        header: vec![
+            "Name",
+            "Saved",
+            "Version",
+            "Processes",
+            "Swap",
+            "Physical",
+            "RSS",
+        ],
+        rows: Vec::new(),
+        optional: vec![2, 4],
+    };
+    for row in rows {
+        table.rows.push(table_row(vec![
+            row.name.clone(),
+            row.saved_at.clone(),
+            row.version.clone(),
+            row.process_count.to_string(),
+            human_bytes(row.swap_bytes),
+            human_bytes(row.physical_bytes),
+            human_bytes(row.rss_bytes),
+        ]));
+    }
+    let mut out = render_layout(&table, opts);
+    if rows.is_empty() {
+        out.push_str("No snapshots yet; save one with `snapshot save <name>`.\n");
+    }
+    out
+}
+
+fn render_snapshots_markdown(rows: &[SnapshotRecord]) -> String {
+    let mut out = String::new();
+    out.push_str("| Name | Saved | Version | Processes | Swap | Physical | RSS |\n");
+    out.push_str("|---|---|---|---:|---:|---:|---:|\n");
+    for row in rows {
+        let _ = writeln!(
+            out,
+            "| {} | {} | {} | {} | {} | {} | {} |",
+            row.name.replace('|', "\\|"),
+            row.saved_at,
+            row.version,
+            row.process_count,
+            human_bytes(row.swap_bytes),
+            human_bytes(row.physical_bytes),
+            human_bytes(row.rss_bytes),
+        );
+    }
+    out
+}
+
+fn render_snapshot_diff(
+    rows: &[SnapshotDiffRecord],
+    summaries: &[SnapshotRecord; 2],
+    fmt: OutputFormat,
+    opts: &RenderOptions,
+) -> String {
+    match fmt {
+        OutputFormat::Table => render_snapshot_diff_table(rows, summaries, opts),
+        OutputFormat::Json => render_fields_json(rows, snapshot_diff_fields),
+        OutputFormat::Csv => render_fields_csv(rows, snapshot_diff_fields),
+        OutputFormat::Yaml => render_fields_yaml(rows, snapshot_diff_fields, opts),
+        OutputFormat::Markdown => render_snapshot_diff_markdown(rows),
+        OutputFormat::Html
+        | OutputFormat::Porcelain
+        | OutputFormat::MdReport
+        | OutputFormat::Svg => {
+            unreachable!("checked in run()")
+        }
+    }
+}
+
+fn snapshot_diff_fields(row: &SnapshotDiffRecord) -> Vec<(&'static str, Field)> {
+    let (swap, physical, rss) = snapshot_delta(row);
+    vec![
+        ("tmux_target", Field::Str(row.tmux_target.clone())),
+        ("tmux_window", Field::Str(row.tmux_window_name.clone())),
+        ("processes_before", Field::Uint(row.before.0 as u64)),
+        ("processes_after", Field::Uint(row.after.0 as u64)),
+        ("swap_before_bytes", Field::Uint(row.before.1)),
+        ("swap_after_bytes", Field::Uint(row.after.1)),
+        ("swap_delta_bytes", Field::Int(swap)),
+        ("physical_before_bytes", Field::Uint(row.before.2)),
+        ("physical_after_bytes", Field::Uint(row.after.2)),
+        ("physical_delta_bytes", Field::Int(physical)),
+        ("rss_before_bytes", Field::Uint(row.before.3)),
+        ("rss_after_bytes", Field::Uint(row.after.3)),
+        ("rss_delta_bytes", Field::Int(rss)),
+    ]
+}
+
+// Cells shared by the table and Markdown diff: processes, then after/Δ per metric.
+fn snapshot_diff_cells(row: &SnapshotDiffRecord) -> Vec<String> {
+    let [swap, physical, rss] = delta_cells(Some(snapshot_delta(row)));
+    vec![
+        format!("{}→{}", row.before.0, row.after.0),
+        human_bytes(row.after.1),
+        swap,
+        human_bytes(row.after.2),
+        physical,
+        human_bytes(row.after.3),
+        rss,
+    ]
+}
+
+fn render_snapshot_diff_table(
+    rows: &[SnapshotDiffRecord],
+    summaries: &[SnapshotRecord; 2],
+    opts: &RenderOptions,
+) -> String {
+    let mut table = TableLayout {
+        header: vec![
+            "Tmux window.pane",
+            "Window",
+            "Processes",
+            "Swap",
+            "ΔSwap",
+            "Physical",
+            "ΔPhysical",
+            "RSS",
+            "ΔRSS",
+        ],
+        rows: Vec::new(),
+        optional: vec![1, 3, 5],
+    };
+    for row in rows {
+        let mut cells = vec![row.tmux_target.clone(), row.tmux_window_name.clone()];
+        cells.extend(snapshot_diff_cells(row));
+        let mut cells = table_row(cells);
+        cells.styles[8] = Some(if snapshot_delta(row).2 > 0 {
+            RED
+        } else {
+            GREEN
+        });
+        table.rows.push(cells);
+    }
+    let mut out = String::new();
+    for summary in summaries {
+        let _ = writeln!(
+            out,
+            "{}: saved {}, version {}, {} processes",
+            summary.name, summary.saved_at, summary.version, summary.process_count
+        );
+    }
+    out.push('\n');
+    out.push_str(&render_layout(&table, opts));
+    out.push('\n');
+    let [a, b] = summaries;
+    for (label, before, after) in [
+        ("swap", a.swap_bytes, b.swap_bytes),
+        ("physical", a.physical_bytes, b.physical_bytes),
+        ("RSS", a.rss_bytes, b.rss_bytes),
+    ] {
+        let _ = writeln!(
+            out,
+            "Total {label}:\t{} → {} ({})",
+            human_bytes(before),
+            human_bytes(after),
+            delta_cells(Some((after as i64 - before as i64, 0, 0)))[0]
+        );
+    }
+    out
+}
+
+fn render_snapshot_diff_markdown(rows: &[SnapshotDiffRecord]) -> String {
+    let mut out = String::new();
+    out.push_str(
+        "| Tmux window.pane | Window | Processes | Swap | ΔSwap | Physical | ΔPhysical | RSS | ΔRSS |\n",
+    );
+    out.push_str("|---|---|---:|---:|---:|---:|---:|---:|---:|\n");
+    for row in rows {
+        let _ = writeln!(
+            out,
+            "| {} | {} | {} |",
+            row.tmux_target,
+            row.tmux_window_name.replace('|', "\\|"),
+            snapshot_diff_cells(row).join(" | "),
+        );
+    }
+    out
+}
+
+// `key` names the key column in tables and structured output ("Group", "group");
+// `source` says what produced the keys.
+fn render_groups(
+    rows: &[GroupRecord],
+    key: (&'static str, &'static str),
+    source: &str,
+    fmt: OutputFormat,
+    opts: &RenderOptions,
+) -> String {
+    let (label, field) = key;
+    let fields = |row: &GroupRecord| group_fields(row, field);
+    match fmt {
+        OutputFormat::Table => render_groups_table(rows, label, source, opts),
+        OutputFormat::Json => render_fields_json(rows, fields),
+        OutputFormat::Csv => render_fields_csv(rows, fields),
+        OutputFormat::Yaml => render_fields_yaml(rows, fields, opts),
+        OutputFormat::Markdown => render_groups_markdown(rows, label),
+        OutputFormat::Html
+        | OutputFormat::Porcelain
+        | OutputFormat::MdReport
+        | OutputFormat::Svg => {
+            unreachable!("checked in run()")
+        }
+    }
+}
+
+fn group_fields(row: &GroupRecord, key: &'static str) -> Vec<(&'static str, Field)> {
+    let mut fields = vec![(key, Field::Str(row.key.clone()))];
+    if key == "session" {
+        fields.push(("session_age_secs", session_age_field(row.session_created)));
+        fields.push((
+            "session_attached",
+            row.session_attached.map_or(Field::Null, Field::Bool),
+        ));
+    }
+    fields.extend([
+        ("pane_count", Field::Uint(row.pane_count as u64)),
+        ("process_count", Field::Uint(row.process_count as u64)),
+        ("swap_bytes", Field::Uint(row.swap_bytes)),
+        ("swap_human", Field::Str(human_bytes(row.swap_bytes))),
+        ("physical_bytes", Field::Uint(row.physical_bytes)),
+        (
+            "physical_human",
+            Field::Str(human_bytes(row.physical_bytes)),
+        ),
+        ("rss_bytes", Field::Uint(row.rss_bytes)),
+        ("rss_human", Field::Str(human_bytes(row.rss_bytes))),
+        ("pane_history_bytes", Field::Uint(row.pane_history_bytes)),
+        (
+            "pane_history_human",
+            Field::Str(human_bytes(row.pane_history_bytes)),
+        ),
+        ("commands", command_records(&row.commands)),
+    ]);
+    fields
+}
+
+// Structured output keeps name and count apart, so nobody parses "3× node".
+fn command_records(commands: &[(String, usize)]) -> Field {
+    Field::Records(
+        commands
+            .iter()
+            .map(|(name, count)| {
+                vec![
+                    ("command", Field::Str(name.clone())),
+                    ("count", Field::Uint(*count as u64)),
+                ]
+            })
+            .collect(),
+    )
+}
+
+// "3× node, 1× opencode": what a group runs, at a glance.
+fn command_counts(commands: &[(String, usize)]) -> String {
+    commands
+        .iter()
+        .map(|(name, count)| format!("{count}× {name}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn group_cells(row: &GroupRecord) -> Vec<String> {
+    vec![
+        row.key.clone(),
+        row.pane_count.to_string(),
+        row.process_count.to_string(),
+        human_bytes(row.swap_bytes),
+        human_bytes(row.physical_bytes),
+        human_bytes(row.rss_bytes),
+        human_bytes(row.pane_history_bytes),
+        command_counts(&row.commands),
+    ]
+}
+
+fn render_groups_table(
+    rows: &[GroupRecord],
+    label: &'static str,
+    source: &str,
+    opts: &RenderOptions,
+) -> String {
+    let mut table = TableLayout {
+        header: vec![
+            label,
+            "Panes",
+            "Processes",
+            "Swap",
+            "Physical",
+            "RSS",
+            "PaneHistory",
+            "Commands",
+        ],
+        rows: Vec::new(),
+        optional: vec![6, 1],
+    };
+    for row in rows {
+        let mut cells = table_row(group_cells(row));
+        cells.cells[7] = truncate_display(&cells.cells[7], opts.max_command_width);
+        cells.styles[3] = Some(swap_style(row.swap_bytes));
+        table.rows.push(cells);
+    }
+    if rows.iter().any(|r| r.session_created.is_some()) {
+        insert_session_columns(
+            &mut table,
+            rows.iter()
+                .map(|r| (r.session_created, r.session_attached))
+                .collect(),
+        );
+    }
+    let mut out = render_layout(&table, opts);
+    out.push('\n');
+    let _ = writeln!(out, "Grouped by {source}");
+    let _ = writeln!(
+        out,
+        "Total swap:\t{}",
+        human_bytes(rows.iter().map(|r| r.swap_bytes).sum())
+    );
+    let _ = writeln!(
+        out,
+        "Total physical:\t{}",
+        human_bytes(rows.iter().map(|r| r.physical_bytes).sum())
+    );
+    let _ = writeln!(
+        out,
+        "Total RSS:\t{}",
+        human_bytes(rows.iter().map(|r| r.rss_bytes).sum())
+    );
+    out
+}
+
+fn render_groups_markdown(rows: &[GroupRecord], label: &str) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "| {label} | Panes | Processes | Swap | Physical | RSS | PaneHistory | Commands |"
+    );
+    out.push_str("|---|---:|---:|---:|---:|---:|---:|---|\n");
+    for row in rows {
+        let mut cells = group_cells(row);
+        cells[0] = cells[0].replace('|', "\\|");
+        let _ = writeln!(out, "| {} |", cells.join(" | "));
+    }
+    out
+}
+
+fn render_merge(rows: &[MergeRecord], fmt: OutputFormat, opts: &RenderOptions) -> String {
+    match fmt {
+        OutputFormat::Table => render_merge_table(rows, opts),
+        OutputFormat::Json => render_fields_json(rows, merge_fields),
+        OutputFormat::Csv => render_fields_csv(rows, merge_fields),
+        OutputFormat::Yaml => render_fields_yaml(rows, merge_fields, opts),
+        OutputFormat::Markdown => render_merge_markdown(rows),
+        OutputFormat::Html
+        | OutputFormat::Porcelain
+        | OutputFormat::MdReport
+        | OutputFormat::Svg => {
+            unreachable!("checked in run()")
+        }
+    }
+}
+
+fn merge_fields(row: &MergeRecord) -> Vec<(&'static str, Field)> {
+    vec![
+        ("host", Field::Str(row.host.clone())),
+        ("timestamp", Field::Str(row.timestamp.clone())),
+        ("source", Field::Str(row.source.clone())),
+        ("process_count", Field::Uint(row.process_count)),
+        ("pane_count", Field::Uint(row.pane_count as u64)),
+        ("swap_bytes", Field::Uint(row.swap_bytes)),
+        ("swap_human", Field::Str(human_bytes(row.swap_bytes))),
+        ("physical_bytes", Field::Uint(row.physical_bytes)),
+        (
+            "physical_human",
+            Field::Str(human_bytes(row.physical_bytes)),
+        ),
+        ("rss_bytes", Field::Uint(row.rss_bytes)),
+        ("rss_human", Field::Str(human_bytes(row.rss_bytes))),
+        (
+            "top_pane",
+            row.top_pane.clone().map_or(Field::Null, Field::Str),
+        ),
+        (
+            "top_pane_rss_bytes",
+            row.top_pane
+                .as_ref()
+                .map_or(Field::Null, |_| Field::Uint(row.top_pane_rss_bytes)),
+        ),
+    ]
+}
+
+// Cells shared by the table and Markdown merge reports.
+fn merge_cells(row: &MergeRecord) -> Vec<String> {
+    vec![
+        row.host.clone(),
+        row.timestamp.clone(),
+        row.process_count.to_string(),
+        row.pane_count.to_string(),
+        human_bytes(row.swap_bytes),
+        human_bytes(row.physical_bytes),
+        human_bytes(row.rss_bytes),
+        row.top_pane.as_ref().map_or_else(
+            || "-".to_string(),
+            |pane| format!("{pane} ({})", human_bytes(row.top_pane_rss_bytes)),
+        ),
+        row.source.clone(),
+    ]
+}
+
+fn render_merge_table(rows: &[MergeRecord], opts: &RenderOptions) -> String {
+    let mut table = TableLayout {
+        header: vec![
+            "Host",
+            "Time",
+            "Processes",
+            "Panes",
+            "Swap",
+            "Physical",
+            "RSS",
+            "Top pane",
+            "Source",
+        ],
+        rows: Vec::new(),
+        optional: vec![8, 3, 7],
+    };
+    for row in rows {
+        let mut cells = table_row(merge_cells(row));
+        cells.styles[4] = Some(swap_style(row.swap_bytes));
+        table.rows.push(cells);
+    }
+    let mut out = render_layout(&table, opts);
+    let hosts = rows
+        .iter()
+        .map(|r| r.host.as_str())
+        .collect::<std::collections::HashSet<_>>();
+    out.push('\n');
+    let _ = writeln!(
+        out,
+        "Merged {} reports from {} hosts",
+        rows.len(),
+        hosts.len()
+    );
+    out
+}
+
+fn render_merge_markdown(rows: &[MergeRecord]) -> String {
+    let mut out = String::new();
+    out.push_str(
+        "| Host | Time | Processes | Panes | Swap | Physical | RSS | Top pane | Source |\n",
+    );
+    out.push_str("|---|---|---:|---:|---:|---:|---:|---|---|\n");
+    for row in rows {
+        let cells = merge_cells(row)
+            .into_iter()
+            .map(|c| c.replace('|', "\\|"))
+            .collect::<Vec<_>>();
+        let _ = writeln!(out, "| {} |", cells.join(" | "));
+    }
+    out
+}
+
+fn render_trends(rows: &[PaneTrend], fmt: OutputFormat, opts: &RenderOptions) -> String {
+    match fmt {
+        OutputFormat::Table => render_trends_table(rows, opts),
+        OutputFormat::Json => render_fields_json(rows, trend_fields),
+        OutputFormat::Csv => render_fields_csv(rows, trend_fields),
+        OutputFormat::Yaml => render_fields_yaml(rows, trend_fields, opts),
+        OutputFormat::Markdown => render_trends_markdown(rows),
+        OutputFormat::Html => render_trends_html(rows, unix_now()),
+        OutputFormat::Porcelain | OutputFormat::MdReport | OutputFormat::Svg => {
+            unreachable!("checked in run()")
+        }
+    }
+}
+
+// Shrinking panes read as "-1.50 MiB" rather than a huge unsigned number.
+fn signed_human_bytes(bytes: Option<i64>) -> String {
+    match bytes {
+        None => "-".to_string(),
+        Some(v) if v < 0 => format!("-{}", human_bytes(v.unsigned_abs())),
+        Some(v) => human_bytes(v as u64),
+    }
+}
+
+// Cells shared by the table, Markdown, and HTML summaries.
+fn trend_cells(row: &PaneTrend) -> Vec<String> {
+    let first = row.points.first().copied().unwrap_or_default();
+    let last = row.points.last().copied().unwrap_or_default();
+    vec![
+        row.tmux_target.clone(),
+        row.tmux_window_name.clone(),
+        row.points.len().to_string(),
+        human_duration(last.0 - first.0),
+        human_bytes(row.min_bytes),
+        human_bytes(row.max_bytes),
+        human_bytes(last.1),
+        signed_human_bytes(row.growth_per_hour_bytes),
+    ]
+}
+
+const TREND_HEADER: [&str; 8] = [
+    "Tmux window.pane",
+    "Window",
+    "Samples",
+    "Span",
+    "Min",
+    "Max",
+    "Last",
+    "Growth/h",
+];
+
+fn render_trends_table(rows: &[PaneTrend], opts: &RenderOptions) -> String {
+    let table = TableLayout {
+        header: TREND_HEADER.to_vec(),
+        rows: rows.iter().map(|r| table_row(trend_cells(r))).collect(),
+        optional: vec![3, 1, 2],
+    };
+    render_layout(&table, opts)
+}
+
+fn render_trends_markdown(rows: &[PaneTrend]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "| {} |", TREND_HEADER.join(" | "));
+    out.push_str("|---|---|---:|---:|---:|---:|---:|---:|\n");
+    for row in rows {
+        let _ = writeln!(out, "| {} |", trend_cells(row).join(" | "));
+    }
+    out
+}
+
+fn render_process_trends(rows: &[ProcessTrend], fmt: OutputFormat, opts: &RenderOptions) -> String {
+    match fmt {
+        OutputFormat::Table => render_layout(
+            &TableLayout {
+                header: PROCESS_TREND_HEADER.to_vec(),
+                rows: rows
+                    .iter()
+                    .map(|r| table_row(process_trend_cells(r)))
+                    .collect(),
+                optional: vec![2, 1],
+            },
+            opts,
+        ),
+        OutputFormat::Json => render_fields_json(rows, process_trend_fields),
+        OutputFormat::Csv => render_fields_csv(rows, process_trend_fields),
+        OutputFormat::Yaml => render_fields_yaml(rows, process_trend_fields, opts),
+        OutputFormat::Markdown => {
+            let mut out = String::new();
+            let _ = writeln!(out, "| {} |", PROCESS_TREND_HEADER.join(" | "));
+            out.push_str("|---:|---|---:|---:|---:|---:|---:|---|\n");
+            for row in rows {
+                let cells = process_trend_cells(row)
+                    .into_iter()
+                    .map(|c| c.replace('|', "\\|"))
+                    .collect::<Vec<_>>();
+                let _ = writeln!(out, "| {} |", cells.join(" | "));
+            }
+            out
+        }
+        OutputFormat::Html
+        | OutputFormat::Porcelain
+        | OutputFormat::MdReport
+        | OutputFormat::Svg => {
+            unreachable!("checked in run()")
+        }
+    }
+}
+
+const PROCESS_TREND_HEADER: [&str; 8] = [
+    "PID",
+    "Tmux window.pane",
+    "Samples",
+    "Uptime",
+    "Last",
+    "Growth/h",
+    "Lifetime/h",
+    "Command",
+];
+
+// Recordings store unattached processes as null (schema 3) or "?" (older).
+fn pane_or_unknown(target: &str) -> String {
+    if target.is_empty() { "?" } else { target }.to_string()
+}
+
+// Uptime counted from the first sample is a lower bound, marked with "≥".
+fn process_trend_cells(row: &ProcessTrend) -> Vec<String> {
+    vec![
+        row.pid.to_string(),
+        pane_or_unknown(&row.tmux_target),
+        row.samples.to_string(),
+        format!(
+            "{}{}",
+            if row.uptime_from_start { "" } else { "≥" },
+            human_duration(row.uptime_secs)
+        ),
+        human_bytes(row.last_bytes),
+        signed_human_bytes(row.growth_per_hour_bytes),
+        human_bytes(row.lifetime_per_hour_bytes),
+        row.command.clone(),
+    ]
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const CHART_WIDTH: u64 = 640;
+const CHART_HEIGHT: u64 = 180;
+const CHART_MARGIN: u64 = 70;
+
+// Line chart of one pane's footprint; the y axis starts at zero so growth is not exaggerated.
+fn svg_line_chart(points: &[(u64, u64)]) -> String {
+    let (Some(first), Some(last)) = (points.first(), points.last()) else {
+        return String::new();
+    };
+    let max = points.iter().map(|p| p.1).max().unwrap_or(0).max(1);
+    let span = (last.0 - first.0).max(1);
+    let (plot_w, plot_h) = (CHART_WIDTH - CHART_MARGIN - 10, CHART_HEIGHT - 30);
+    let coords = points
+        .iter()
+        .map(|(ts, v)| {
+            let x = CHART_MARGIN as f64 + (ts - first.0) as f64 / span as f64 * plot_w as f64;
+            let y = 10.0 + plot_h as f64 * (1.0 - *v as f64 / max as f64);
+            format!("{x:.1},{y:.1}")
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    let base = 10 + plot_h;
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{CHART_WIDTH}\" height=\"{CHART_HEIGHT}\" role=\"img\">"
+    );
+    let _ = writeln!(
+        out,
+        "<line x1=\"{CHART_MARGIN}\" y1=\"10\" x2=\"{CHART_MARGIN}\" y2=\"{base}\" class=\"axis\"/>\n<line x1=\"{CHART_MARGIN}\" y1=\"{base}\" x2=\"{}\" y2=\"{base}\" class=\"axis\"/>",
+        CHART_MARGIN + plot_w
+    );
+    let _ = writeln!(
+        out,
+        "<text x=\"{}\" y=\"14\" text-anchor=\"end\">{}</text>\n<text x=\"{}\" y=\"{base}\" text-anchor=\"end\">0 B</text>",
+        CHART_MARGIN - 4,
+        human_bytes(max),
+        CHART_MARGIN - 4
+    );
+    let _ = writeln!(
+        out,
+        "<text x=\"{CHART_MARGIN}\" y=\"{}\">{}</text>\n<text x=\"{}\" y=\"{}\" text-anchor=\"end\">{}</text>",
+        CHART_HEIGHT - 4,
+        format_utc_timestamp(first.0),
+        CHART_MARGIN + plot_w,
+        CHART_HEIGHT - 4,
+        format_utc_timestamp(last.0)
+    );
+    let _ = writeln!(
+        out,
+        "<polyline points=\"{coords}\" class=\"series\"/>\n</svg>"
+    );
+    out
+}
+
+const BAR_CHART_WIDTH: u64 = 720;
+const BAR_CHART_LABEL: u64 = 180;
+const BAR_CHART_ROW: u64 = 24;
+const BAR_CHART_TOP: u64 = 56;
+const SWAP_FILL: &str = "#c0392b";
+const PHYSICAL_FILL: &str = "#2e86c1";
+
+// Standalone SVG: one stacked swap/physical bar per pane, largest first, all
+// sharing one scale. Physical falls back to RSS where vmmap was not measured.
+fn render_pane_svg(panes: &[PaneRecord], generated: u64) -> String {
+    let mut bars = panes
+        .iter()
+        .map(|p| {
+            let physical = if p.physical_bytes > 0 {
+                p.physical_bytes
+            } else {
+                p.rss_bytes
+            };
+            (p, p.swap_bytes, physical)
+        })
+        .collect::<Vec<_>>();
+    bars.sort_by_key(|(_, swap, physical)| std::cmp::Reverse(swap + physical));
+    let max = bars.iter().map(|(_, s, p)| s + p).max().unwrap_or(0).max(1);
+    // Room on the right for the total printed after the longest bar.
+    let plot_w = BAR_CHART_WIDTH - BAR_CHART_LABEL - 90;
+    let height = BAR_CHART_TOP + BAR_CHART_ROW * bars.len() as u64 + 10;
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{BAR_CHART_WIDTH}\" height=\"{height}\" font-family=\"system-ui, sans-serif\" font-size=\"12\" role=\"img\">"
+    );
+    let _ = writeln!(
+        out,
+        "<title>opencode-tmux-mem memory per pane</title>\n<text x=\"4\" y=\"18\" font-size=\"14\" font-weight=\"bold\">Memory per pane, {}</text>",
+        format_utc_timestamp(generated)
+    );
+    let _ = writeln!(
+        out,
+        "<rect x=\"{BAR_CHART_LABEL}\" y=\"30\" width=\"10\" height=\"10\" fill=\"{SWAP_FILL}\"/><text x=\"{}\" y=\"39\">Swap</text>\n<rect x=\"{}\" y=\"30\" width=\"10\" height=\"10\" fill=\"{PHYSICAL_FILL}\"/><text x=\"{}\" y=\"39\">Physical (RSS where not measured)</text>",
+        BAR_CHART_LABEL + 14,
+        BAR_CHART_LABEL + 60,
+        BAR_CHART_LABEL + 74
+    );
+    for (idx, (pane, swap, physical)) in bars.iter().enumerate() {
+        let y = BAR_CHART_TOP + BAR_CHART_ROW * idx as u64;
+        let swap_w = swap * plot_w / max;
+        let physical_w = physical * plot_w / max;
+        let label = if pane.tmux_target == "?" {
+            "outside tmux".to_string()
+        } else {
+            format!("{} {}", pane.tmux_target, pane.tmux_window_name)
         };
         let _ = writeln!(
             out,
-            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
-            row.pid,
+            "<text x=\"{}\" y=\"{}\" text-anchor=\"end\">{}</text>",
+            BAR_CHART_LABEL - 6,
+            y + 14,
+            escape_html(&label)
+        );
+        let _ = writeln!(
+            out,
+            "<rect x=\"{BAR_CHART_LABEL}\" y=\"{y}\" width=\"{swap_w}\" height=\"18\" fill=\"{SWAP_FILL}\"/><rect x=\"{}\" y=\"{y}\" width=\"{physical_w}\" height=\"18\" fill=\"{PHYSICAL_FILL}\"/>",
+            BAR_CHART_LABEL + swap_w
+        );
+        let _ = writeln!(
+            out,
+            "<text x=\"{}\" y=\"{}\">{}</text>",
+            BAR_CHART_LABEL + swap_w + physical_w + 6,
+            y + 14,
+            human_bytes(swap + physical)
+        );
+    }
+    out.push_str("</svg>\n");
+    out
+}
+
+// No scripts or external assets, so the file can be mailed or attached as is.
+fn render_trends_html(rows: &[PaneTrend], generated: u64) -> String {
+    let mut out = String::new();
+    out.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+    let _ = writeln!(
+        out,
+        "<meta name=\"generator\" content=\"{}\">",
+        escape_html(render_version(OutputFormat::Table).trim())
+    );
+    out.push_str("<title>opencode-tmux-mem history</title>\n<style>\n");
+    out.push_str("body{font-family:system-ui,sans-serif;margin:2em;color:#222}\n");
+    out.push_str(
+        "table{border-collapse:collapse}th,td{padding:4px 10px;border-bottom:1px solid #ddd}\n",
+    );
+    out.push_str("td.n{text-align:right}svg text{font-size:11px;fill:#555}\n");
+    out.push_str(".axis{stroke:#999}.series{fill:none;stroke:#c0392b;stroke-width:2}\n");
+    out.push_str("</style>\n</head>\n<body>\n<h1>opencode-tmux-mem history</h1>\n");
+    let _ = writeln!(
+        out,
+        "<p>Generated {}. Footprint is swap plus physical footprint (RSS where vmmap was not measured), summed per pane.</p>",
+        format_utc_timestamp(generated)
+    );
+    out.push_str("<table>\n<tr>");
+    for name in TREND_HEADER {
+        let _ = write!(out, "<th>{name}</th>");
+    }
+    out.push_str("</tr>\n");
+    for row in rows {
+        out.push_str("<tr>");
+        for (idx, cell) in trend_cells(row).iter().enumerate() {
+            let class = if idx >= 2 { " class=\"n\"" } else { "" };
+            let _ = write!(out, "<td{class}>{}</td>", escape_html(cell));
+        }
+        out.push_str("</tr>\n");
+    }
+    out.push_str("</table>\n");
+    for row in rows {
+        let _ = writeln!(
+            out,
+            "<h2>{} <small>{}</small></h2>",
+            escape_html(&row.tmux_target),
+            escape_html(&row.tmux_window_name)
+        );
+        out.push_str(&svg_line_chart(&row.points));
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn budget_status_name(status: BudgetStatus) -> &'static str {
+    match status {
+        BudgetStatus::Under => "under",
+        BudgetStatus::Over => "over",
+        BudgetStatus::Unbudgeted => "unbudgeted",
+    }
+}
+
+fn budget_amount(metric: Option<Metric>, v: u64) -> String {
+    match metric {
+        None => "-".to_string(),
+        Some(Metric::HistoryLines) => v.to_string(),
+        Some(_) => human_bytes(v),
+    }
+}
+
+fn budget_usage_pct(row: &BudgetRecord) -> String {
+    if row.metric.is_none() || row.limit == 0 {
+        return "-".to_string();
+    }
+    format!("{:.1}%", row.used as f64 * 100.0 / row.limit as f64)
+}
+
+fn render_budget_table(rows: &[BudgetRecord], opts: &RenderOptions) -> String {
+    let mut table = TableLayout {
+        header: vec![
+            "Tmux window.pane",
+            "Window",
+            "Budget",
+            "Metric",
+            "Used",
+            "Limit",
+            "Usage",
+            "Status",
+        ],
+        rows: Vec::new(),
+        optional: vec![2, 1],
+    };
+    for row in rows {
+        let mut cells = table_row(vec![
+            row.tmux_target.clone(),
+            row.tmux_window_name.clone(),
+            row.budget.clone(),
+            row.metric.map_or("-", metric_name).to_string(),
+            budget_amount(row.metric, row.used),
+            budget_amount(row.metric, row.limit),
+            budget_usage_pct(row),
+            budget_status_name(row.status).to_string(),
+        ]);
+        cells.styles[7] = Some(match row.status {
+            BudgetStatus::Over => RED,
+            BudgetStatus::Under => GREEN,
+            BudgetStatus::Unbudgeted => DIM,
+        });
+        table.rows.push(cells);
+    }
+    let mut out = render_layout(&table, opts);
+
+    let checked = rows.iter().filter(|r| r.metric.is_some()).count();
+    let over = rows
+        .iter()
+        .filter(|r| r.status == BudgetStatus::Over)
+        .count();
+    out.push('\n');
+    let _ = writeln!(out, "Over budget:\t{over}/{checked} checks");
+    out
+}
+
+fn render_budget_markdown(rows: &[BudgetRecord]) -> String {
+    let mut out = String::new();
+    out.push_str(
+        "| Tmux window.pane | Window | Budget | Metric | Used | Limit | Usage | Status |\n",
+    );
+    out.push_str("|---|---|---|---|---:|---:|---:|---|\n");
+    for row in rows {
+        let _ = writeln!(
+            out,
+            "| {} | {} | {} | {} | {} | {} | {} | {} |",
             row.tmux_target,
             row.tmux_window_name,
-            human_bytes(row.swap_bytes),
-            human_bytes(row.physical_bytes),
-            human_bytes(row.rss_bytes),
-            human_bytes(row.pane_history_bytes),
-            history_lines,
-            row.command,
+            row.budget.replace('|', "\\|"),
+            row.metric.map_or("-", metric_name),
+            budget_amount(row.metric, row.used),
+            budget_amount(row.metric, row.limit),
+            budget_usage_pct(row),
+            budget_status_name(row.status),
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_compact_bytes_supports_units() {
+        assert_eq!(parse_compact_bytes("1024"), 1024);
+        assert_eq!(parse_compact_bytes("1K"), 1024);
+        assert_eq!(parse_compact_bytes("1.5M"), 1_572_864);
+        assert_eq!(parse_compact_bytes("2G"), 2_147_483_648);
+    }
+
+    #[test]
+    fn infer_export_format_from_extension() {
+        assert_eq!(
+            infer_format_from_path("report.json"),
+            Some(OutputFormat::Json)
+        );
+        assert_eq!(
+            infer_format_from_path("report.csv"),
+            Some(OutputFormat::Csv)
+        );
+        assert_eq!(
+            infer_format_from_path("report.yaml"),
+            Some(OutputFormat::Yaml)
+        );
+        assert_eq!(
+            infer_format_from_path("report.md"),
+            Some(OutputFormat::Markdown)
+        );
+        assert_eq!(
+            infer_format_from_path("history.html"),
+            Some(OutputFormat::Html)
+        );
+        assert_eq!(infer_format_from_path("report.txt"), None);
+    }
+
+    #[test]
+    fn csv_escape_quotes_and_commas() {
+        let got = escape_csv("a,\"b\"");
+        assert_eq!(got, "\"a,\"\"b\"\"\"");
+    }
+
+    #[test]
+    fn parse_view_mode_supports_process_and_pane() {
+        assert_eq!(parse_view_mode("process"), Ok(ViewMode::Process));
+        assert_eq!(parse_view_mode("pane"), Ok(ViewMode::Pane));
+    }
+
+    #[test]
+    fn aggregate_by_pane_sums_process_memory() {
+        let rows = vec![
+            ProcRecord {
+                pid: 1,
+                command: "a".to_string(),
+                swap_bytes: 100,
+                physical_bytes: 200,
+                rss_bytes: 300,
+                tmux_target: "s:1.0".to_string(),
+                tmux_window_name: "w".to_string(),
+                pane_history_size: 10,
+                pane_history_limit: 100,
+                pane_history_bytes: 1000,
+                ..ProcRecord::default()
+            },
+            ProcRecord {
+                pid: 2,
+                command: "b".to_string(),
+                swap_bytes: 50,
+                physical_bytes: 70,
+                rss_bytes: 90,
+                tmux_target: "s:1.0".to_string(),
+                tmux_window_name: "w".to_string(),
+                pane_history_size: 11,
+                pane_history_limit: 100,
+                pane_history_bytes: 900,
+                ..ProcRecord::default()
+            },
+        ];
+
+        let panes = aggregate_by_pane(&rows);
+        assert_eq!(panes.len(), 1);
+        assert_eq!(panes[0].process_count, 2);
+        assert_eq!(panes[0].swap_bytes, 150);
+        assert_eq!(panes[0].physical_bytes, 270);
+        assert_eq!(panes[0].rss_bytes, 390);
+        assert_eq!(panes[0].pane_history_bytes, 1000);
+    }
+
+    #[test]
+    fn glob_match_supports_wildcards() {
+        assert!(glob_match("ai-*", "ai-review"));
+        assert!(glob_match("*lsp*", "tsserver-lsp-1"));
+        assert!(glob_match("w?n", "win"));
+        assert!(!glob_match("ai-*", "docs"));
+        assert!(glob_match("*", ""));
+    }
+
+    #[test]
+    fn parse_config_reads_budget_globs() {
+        let raw = "# pane budgets\nbudgets:\n  \"ai-*\":\n    physical: 2G\n    history: 50MB\n  docs:\n    history_lines: 5000\n";
+        let config = parse_config(raw).expect("valid config");
+        assert_eq!(config.budgets.len(), 2);
+        assert_eq!(config.budgets[0].window_glob, "ai-*");
+        assert_eq!(
+            config.budgets[0].limits,
+            vec![
+                (Metric::Physical, 2 * 1024 * 1024 * 1024),
+                (Metric::HistoryBytes, 50 * 1024 * 1024),
+            ]
+        );
+        assert_eq!(config.budgets[1].limits, vec![(Metric::HistoryLines, 5000)]);
+        assert!(parse_config("budgets:\n  ai:\n    heap: 1G\n").is_err());
+    }
+
+    #[test]
+    fn evaluate_budgets_flags_over_limit_panes() {
+        let pane = |target: &str, window: &str, physical: u64| PaneRecord {
+            tmux_target: target.to_string(),
+            tmux_socket: None,
+            tmux_window_name: window.to_string(),
+            process_count: 1,
+            pids: vec![1],
+            swap_bytes: 0,
+            physical_bytes: physical,
+            rss_bytes: 0,
+            pane_history_size: 10,
+            pane_history_limit: 100,
+            pane_history_bytes: 0,
+            pane_history_estimated_bytes: 0,
+            history_measured: None,
+            session_created: None,
+            session_attached: None,
+            shell_rss_bytes: None,
+            severity: None,
+            growth_alert: None,
+            since_last: None,
+            swap_pct: None,
+            physical_pct: None,
+            app_instances: 0,
+        };
+        let budgets = vec![Budget {
+            window_glob: "ai-*".to_string(),
+            limits: vec![(Metric::Physical, 1000)],
+        }];
+        let rows = evaluate_budgets(
+            &[pane("s:1.0", "ai-main", 2000), pane("s:2.0", "shell", 10)],
+            &budgets,
+        );
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].status, BudgetStatus::Over);
+        assert_eq!(rows[1].status, BudgetStatus::Unbudgeted);
+    }
+
+    #[test]
+    fn policy_rules_scope_by_window_and_process() {
+        let raw = "rules:\n  - name: big-ai\n    window: \"ai-*\"\n    metric: swap\n    limit: 100\n    action: exit\n  - name: fat-node\n    process: node\n    metric: rss\n    limit: 50\n    action: kill\n";
+        let rules = parse_policy(raw).expect("valid policy");
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].action, PolicyAction::Exit);
+        assert_eq!(rules[1].process.as_deref(), Some("node"));
+
+        let row = |pid: i32, command: &str, window: &str, swap: u64, rss: u64| ProcRecord {
+            pid,
+            command: command.to_string(),
+            swap_bytes: swap,
+            physical_bytes: 0,
+            rss_bytes: rss,
+            tmux_target: format!("s:{pid}.0"),
+            tmux_window_name: window.to_string(),
+            pane_history_size: 0,
+            pane_history_limit: 0,
+            pane_history_bytes: 0,
+            ..ProcRecord::default()
+        };
+        let rows = vec![
+            row(1, "opencode", "ai-main", 500, 10),
+            row(2, "/usr/local/bin/node server.js", "docs", 0, 80),
+            row(3, "opencode", "docs", 500, 10),
+        ];
+        let panes = aggregate_by_pane(&rows);
+        let breaches = evaluate_policy(&rows, &panes, &rules);
+        assert_eq!(breaches.len(), 2);
+        assert_eq!(breaches[0].rule, "big-ai");
+        assert_eq!(breaches[0].tmux_target, "s:1.0");
+        assert_eq!(breaches[1].rule, "fat-node");
+        assert_eq!(breaches[1].pids, vec![2]);
+    }
+
+    #[test]
+    fn advise_history_limits_sizes_limit_from_bytes_per_line() {
+        let pane = |target: &str, size: i64, bytes: u64| PaneRecord {
+            tmux_target: target.to_string(),
+            tmux_socket: None,
+            tmux_window_name: "w".to_string(),
+            process_count: 1,
+            pids: vec![1],
+            swap_bytes: 0,
+            physical_bytes: 0,
+            rss_bytes: 0,
+            pane_history_size: size,
+            pane_history_limit: 50_000,
+            pane_history_bytes: bytes,
+            pane_history_estimated_bytes: bytes,
+            history_measured: None,
+            session_created: None,
+            session_attached: None,
+            shell_rss_bytes: None,
+            severity: None,
+            growth_alert: None,
+            since_last: None,
+            swap_pct: None,
+            physical_pct: None,
+            app_instances: 0,
+        };
+        // 100 bytes per line and a 1 MB target -> 10k lines, rounded to thousands.
+        let panes = vec![
+            pane("ai:1.0", 30_000, 3_000_000),
+            pane("ai:2.0", 10_000, 1_000_000),
+            pane("idle:1.0", 0, 0),
+        ];
+        let advice = advise_history_limits(&panes, 1_000_000);
+        assert_eq!(advice.len(), 2);
+        assert_eq!(advice[0].session, "ai");
+        assert_eq!(advice[0].bytes_per_line, 100);
+        assert_eq!(advice[0].suggested_limit, 10_000);
+        assert_eq!(advice[0].saved_now_bytes, 2_000_000);
+        assert_eq!(advice[0].saved_at_capacity_bytes, 8_000_000);
+        assert_eq!(
+            advice_command(&advice[0]).as_deref(),
+            Some("tmux set-option -t ai history-limit 10000")
+        );
+        assert_eq!(advice[1].suggested_limit, 50_000);
+        assert_eq!(advice_command(&advice[1]), None);
+
+        // tune plans the same commands whether it previews or applies; only a
+        // bare --apply runs them.
+        let planned = |apply, dry_run| {
+            let (actions, dry_run) = plan_tune(&panes, 1_000_000, apply, dry_run);
+            let commands = actions
+                .iter()
+                .map(|a| {
+                    let args = a.args.iter().map(String::as_str).collect::<Vec<_>>();
+                    format!("{}: {}", a.target, shell_command_line(a.program, &args))
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(execute_actions(&actions, true), 0);
+            (commands, dry_run)
+        };
+        let commands = vec!["session ai: tmux set-option -t ai history-limit 10000".to_string()];
+        assert_eq!(planned(false, false), (commands.clone(), true));
+        assert_eq!(planned(true, true), (commands.clone(), true));
+        assert_eq!(planned(true, false), (commands, false));
+        assert_eq!(plan_tune(&panes, 1 << 40, false, false).0.len(), 0);
+    }
+
+    #[test]
+    fn shell_command_line_quotes_unsafe_args() {
+        assert_eq!(
+            shell_command_line("tmux", &["clear-history", "-t", "ai:1.0"]),
+            "tmux clear-history -t ai:1.0"
+        );
+        assert_eq!(
+            shell_command_line("tmux", &["rename-window", "my win", "it's"]),
+            "tmux rename-window 'my win' 'it'\\''s'"
+        );
+    }
+
+    #[test]
+    fn format_utc_timestamp_handles_epoch_and_leap_years() {
+        assert_eq!(format_utc_timestamp(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_utc_timestamp(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(format_utc_timestamp(1_791_979_199), "2026-10-14T11:59:59Z");
+    }
+
+    #[test]
+    fn audit_entry_records_action_target_and_size() {
+        let action = PlannedAction {
+            action: "clear-history",
+            target: "pane ai:1.0".to_string(),
+            program: "tmux",
+            args: vec![
+                "clear-history".to_string(),
+                "-t".to_string(),
+                "ai:1.0".to_string(),
+            ],
+            metric: "history_bytes",
+            before: 4096,
+        };
+        assert_eq!(
+            audit_entry_json(&action, "2026-01-01T00:00:00Z", None),
+            "{\"timestamp\":\"2026-01-01T00:00:00Z\",\"action\":\"clear-history\",\"target\":\"pane ai:1.0\",\"metric\":\"history_bytes\",\"before\":4096,\"command\":\"tmux clear-history -t ai:1.0\",\"ok\":true,\"error\":null}"
+        );
+    }
+
+    #[test]
+    fn confirm_actions_needs_a_terminal_and_a_yes() {
+        let actions = [PlannedAction {
+            action: "kill",
+            target: "pid 42".to_string(),
+            program: "kill",
+            args: vec!["-TERM".to_string(), "42".to_string()],
+            metric: "rss",
+            before: 4096,
+        }];
+        let confirm = |answer: &str, is_tty| {
+            confirm_actions(&actions, false, false, &mut answer.as_bytes(), is_tty)
+        };
+        assert!(confirm("y\n", false).unwrap_err().contains("pass --yes"));
+        assert_eq!(confirm("y\n", true), Ok(true));
+        assert_eq!(confirm("YES\n", true), Ok(true));
+        assert_eq!(confirm(" yes ", true), Ok(true));
+        assert_eq!(confirm("n\n", true), Ok(false));
+        assert_eq!(confirm("yep\n", true), Ok(false));
+        assert_eq!(confirm("\n", true), Ok(false));
+        // EOF (Ctrl-D) counts as no.
+        assert_eq!(confirm("", true), Ok(false));
+        // --yes, --dry-run, and an empty batch never ask.
+        assert_eq!(
+            confirm_actions(&actions, false, true, &mut &b""[..], false),
+            Ok(true)
+        );
+        assert_eq!(
+            confirm_actions(&actions, true, false, &mut &b""[..], false),
+            Ok(true)
+        );
+        assert_eq!(
+            confirm_actions(&[], false, false, &mut &b""[..], false),
+            Ok(true)
+        );
+    }
+
+    #[test]
+    fn app_helper_pids_walks_descendants_of_each_app() {
+        let table = parse_ps_table(
+            "  10     1 opencode\n  11    10 /usr/local/bin/node tool-server.js\n  12    10 sh -c rust-analyzer\n  13    12 rust-analyzer\n  14    10 vim notes.md\n  20     1 opencode --continue\n  21    20 rg --files\n",
+        );
+        assert_eq!(table.len(), 7);
+        assert_eq!(table[1].command, "/usr/local/bin/node tool-server.js");
+
+        let mut helpers = app_helper_pids(AppProfile::Opencode, &[10, 20], &table);
+        helpers.sort_unstable();
+        assert_eq!(helpers, vec![(11, 10), (13, 10), (21, 20)]);
+    }
+
+    #[test]
+    fn app_groups_put_helpers_under_parent_with_subtotal() {
+        let row = |pid: i32, parent: Option<i32>, swap: u64| ProcRecord {
+            pid,
+            swap_bytes: swap,
+            tmux_target: "s:1.0".to_string(),
+            app_parent_pid: parent,
+            ..ProcRecord::default()
+        };
+        // Rows arrive sorted by swap, so the heavy helper comes before its parent.
+        let rows = vec![
+            row(11, Some(10), 500),
+            row(20, None, 300),
+            row(10, None, 100),
+        ];
+        let groups = app_groups(&rows);
+        assert_eq!(groups.len(), 2);
+        assert_eq!(
+            groups[0].iter().map(|r| r.pid).collect::<Vec<_>>(),
+            vec![10, 11]
+        );
+        let sub = group_subtotal(&groups[0]).expect("group has helpers");
+        assert_eq!(sub.swap_bytes, 600);
+        assert_eq!(sub.process_count, 2);
+        assert!(group_subtotal(&groups[1]).is_none());
+    }
+
+    #[test]
+    fn process_role_labels_language_servers() {
+        assert_eq!(
+            process_role("/opt/bin/rust-analyzer").as_deref(),
+            Some("lsp:rust")
+        );
+        assert_eq!(
+            process_role("node /x/node_modules/typescript/lib/tsserver.js --serverMode semantic")
+                .as_deref(),
+            Some("lsp:typescript")
+        );
+        assert_eq!(
+            process_role("node --max-old-space-size=4096 /x/pyright-langserver.js --stdio")
+                .as_deref(),
+            Some("lsp:python")
+        );
+        assert_eq!(process_role("gopls serve").as_deref(), Some("lsp:go"));
+        assert_eq!(process_role("rg tsserver"), None);
+        assert_eq!(process_role("node server.js"), None);
+    }
+
+    #[test]
+    fn command_name_strips_path_args_and_login_dash() {
+        assert_eq!(
+            command_name("/usr/local/bin/opencode --continue"),
+            "opencode"
+        );
+        assert_eq!(command_name("-zsh"), "zsh");
+        assert_eq!(command_name("node server.js"), "node");
+        assert_eq!(command_name(""), "");
+    }
+
+    #[test]
+    fn truncate_display_adds_ellipsis_only_when_needed() {
+        assert_eq!(truncate_display("opencode --continue", Some(8)), "opencod…");
+        assert_eq!(truncate_display("opencode", Some(8)), "opencode");
+        assert_eq!(
+            truncate_display("opencode --continue", None),
+            "opencode --continue"
+        );
+    }
+
+    #[test]
+    fn use_color_honors_mode_no_color_and_tty() {
+        let table = OutputFormat::Table;
+        assert!(use_color(ColorMode::Auto, table, false, true));
+        assert!(!use_color(ColorMode::Auto, table, true, true));
+        assert!(!use_color(ColorMode::Auto, table, false, false));
+        assert!(use_color(ColorMode::Always, table, true, false));
+        assert!(!use_color(ColorMode::Never, table, false, true));
+        assert!(!use_color(
+            ColorMode::Always,
+            OutputFormat::Json,
+            false,
+            true
+        ));
+        assert_eq!(paint("x", RED, false), "x");
+        assert_eq!(paint("x", RED, true), "\x1b[31mx\x1b[0m");
+    }
+
+    #[test]
+    fn fit_to_width_drops_optional_columns_then_truncates_last() {
+        assert_eq!(tabbed_width(&["ab", "cdefghij", "k"]), 25);
+
+        let row = |cells: &[&str]| cells.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+        let mut lines = vec![
+            row(&["PID", "Window", "Role", "Command"]),
+            row(&[
+                "42",
+                "a-very-long-window-name",
+                "lsp:rust",
+                "opencode --continue",
+            ]),
+        ];
+        let keep = fit_to_width(&mut lines.clone(), &[2], 80, TableStyle::Plain);
+        assert_eq!(keep, vec![0, 1, 2, 3]);
+
+        let keep = fit_to_width(&mut lines, &[2], 40, TableStyle::Plain);
+        assert_eq!(keep, vec![0, 1, 3]);
+        assert_eq!(lines[1][1], "a-very-long…");
+        assert_eq!(lines[1][3], "opencode --cont…");
+    }
+
+    #[test]
+    fn render_layout_pads_columns_for_bordered_styles() {
+        let table = TableLayout {
+            header: vec!["PID", "Command"],
+            rows: vec![table_row(vec!["7".to_string(), "opencode".to_string()])],
+            optional: Vec::new(),
+        };
+        let opts = |table_style| RenderOptions {
+            table_style,
+            ..RenderOptions::default()
+        };
+        assert_eq!(
+            render_layout(&table, &opts(TableStyle::Plain)),
+            "PID\tCommand\n7\topencode\n"
+        );
+        assert_eq!(
+            render_layout(&table, &opts(TableStyle::Ascii)),
+            "+-----+----------+\n| PID | Command  |\n+-----+----------+\n| 7   | opencode |\n+-----+----------+\n"
+        );
+        assert_eq!(
+            render_layout(&table, &opts(TableStyle::Compact)),
+            "PID  Command\n7    opencode\n"
+        );
+    }
+
+    #[test]
+    fn severity_thresholds_take_the_worst_level() {
+        let config = parse_config(
+            "severity:\n  swap:\n    warn: 1K\n    crit: 1M\n  history_lines:\n    warn: 100\n",
+        )
+        .unwrap();
+        assert_eq!(config.severity.len(), 2);
+        assert_eq!(config.severity[0].crit, Some(1024 * 1024));
+
+        let row = |swap_bytes, pane_history_size| ProcRecord {
+            swap_bytes,
+            pane_history_size,
+            ..ProcRecord::default()
+        };
+        let level = |r: &ProcRecord| severity_of(&config.severity, |m| process_metric(r, m));
+        assert_eq!(level(&row(0, 10)), Some(Severity::Ok));
+        assert_eq!(level(&row(0, 500)), Some(Severity::Warn));
+        assert_eq!(level(&row(2 * 1024 * 1024, 500)), Some(Severity::Crit));
+        assert_eq!(severity_of(&[], |_| 1), None);
+        assert!(parse_config("severity:\n  swap:\n    bad: 1K\n").is_err());
+    }
+
+    #[test]
+    fn parallel_map_keeps_input_order() {
+        let items = (0..50).collect::<Vec<u64>>();
+        let expected = items.iter().map(|n| n * n).collect::<Vec<_>>();
+        assert_eq!(parallel_map(&items, 4, |n| n * n), expected);
+        assert_eq!(parallel_map(&items, 1, |n| n * n), expected);
+        assert!(parallel_map(&[] as &[u64], 4, |n| *n).is_empty());
+    }
+
+    #[test]
+    fn parse_duration_accepts_common_units() {
+        assert_eq!(parse_duration("500ms"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_duration("30s"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_duration("5"), Some(Duration::from_secs(5)));
+        assert_eq!(parse_duration("2m"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_duration("1h"), Some(Duration::from_secs(3600)));
+        assert_eq!(parse_duration("1.5s"), None);
+        assert_eq!(parse_duration("s"), None);
+    }
+
+    #[test]
+    fn mapping_parsers_read_smaps_and_vmmap_regions() {
+        let smaps = "562b2ddd2000-562b2ddd4000 r--p 00000000 fe:00 280762   /usr/bin/node\n\
+Size:                  8 kB\n\
+Rss:                   4 kB\n\
+Swap:                  2 kB\n\
+7f0000000000-7f0000100000 rw-p 00000000 00:00 0\n\
+Size:               1024 kB\n\
+Rss:                 512 kB\n";
+        let regions = parse_smaps(smaps);
+        assert_eq!(regions.len(), 2);
+        assert_eq!(regions[0].name, "/usr/bin/node");
+        assert_eq!(regions[0].kind, "file");
+        assert_eq!(regions[0].resident_bytes, 4096);
+        assert_eq!(regions[0].swap_bytes, 2048);
+        assert_eq!(regions[1].name, "[anon]");
+        assert_eq!(regions[1].kind, "anon");
+
+        let vmmap = "REGION TYPE    START - END    [ VSIZE  RSDNT  DIRTY   SWAP] PRT/MAX SHRMOD  REGION DETAIL\n\
+__TEXT         102a3c000-102a40000    [   16K    16K     0K     0K] r-x/r-x SM=COW  /usr/local/bin/opencode\n\
+MALLOC_SMALL   7f8000000-7f8800000    [ 8192K  1234K  1234K   100K] rw-/rwx SM=PRV  DefaultMallocZone\n\
+VM_ALLOCATE (reserved) 300000000-300100000 [ 1024K 0K 0K 0K] rw-/rwx SM=NUL\n";
+        let regions = parse_vmmap_regions(vmmap);
+        assert_eq!(regions.len(), 3);
+        assert_eq!(regions[0].name, "/usr/local/bin/opencode");
+        assert_eq!(regions[0].kind, "file");
+        assert_eq!(regions[1].name, "DefaultMallocZone");
+        assert_eq!(regions[1].resident_bytes, 1234 * 1024);
+        assert_eq!(regions[1].swap_bytes, 100 * 1024);
+        assert_eq!(regions[2].name, "VM_ALLOCATE (reserved)");
+        assert_eq!(parse_view_mode("mappings"), Ok(ViewMode::Mappings));
+    }
+
+    #[test]
+    fn parse_status_anon_file_counts_shmem_as_file_backed() {
+        let status =
+            "Name:\tnode\nRssAnon:\t     140 kB\nRssFile:\t    1660 kB\nRssShmem:\t       4 kB\n";
+        assert_eq!(
+            parse_status_anon_file(status),
+            Some((140 * 1024, 1664 * 1024))
+        );
+        assert_eq!(parse_status_anon_file("Name:\tkthreadd\n"), None);
+    }
+
+    #[test]
+    fn gpu_memory_parsers_sum_per_process() {
+        let nvidia =
+            parse_nvidia_compute_apps("4242, 512\n4242, 256\n77, 10\nNo running processes found\n");
+        assert_eq!(nvidia.get(&4242), Some(&(768 * 1024 * 1024)));
+        assert_eq!(nvidia.get(&77), Some(&(10 * 1024 * 1024)));
+
+        let summary = parse_vmmap_summary(
+            "Physical footprint:         1.5G\n\
+REGION TYPE                        SIZE     SIZE     SIZE     SIZE\n\
+IOAccelerator                     128.0M    64.0M    64.0M       0K\n\
+IOAccelerator (graphics)           32.0M    16.0M    16.0M       0K\n\
+IOSurface                           8.0M     4.0M     4.0M       0K\n\
+Memory Tag 253                      2.0M     1.0M     1.0M       0K\n\
+__TEXT                             40.0M    30.0M       0K       0K\n\
+mapped file                        20.0M    10.0M       0K       0K\n\
+===========                     ======= ========    =====  =======\n\
+TOTAL                               2.0G     1.0G   512.0M   100.0M\n",
+        );
+        assert_eq!(summary.gpu_bytes, 84 * 1024 * 1024);
+        assert_eq!(summary.swap_bytes, 100 * 1024 * 1024);
+        assert_eq!(summary.anon_bytes, 85 * 1024 * 1024);
+        assert_eq!(summary.file_backed_bytes, 40 * 1024 * 1024);
+    }
+
+    #[test]
+    fn fault_delta_needs_a_previous_tick() {
+        assert_eq!(fault_delta(None, Some((10, 1))), None);
+        assert_eq!(fault_delta(Some((10, 1)), None), None);
+        assert_eq!(fault_delta(Some((10, 1)), Some((250, 4))), Some((240, 3)));
+        // A recycled PID starts its counters over.
+        assert_eq!(fault_delta(Some((900, 9)), Some((5, 0))), Some((0, 0)));
+    }
+
+    #[test]
+    fn recording_lines_round_trip() {
+        let row = ProcRecord {
+            pid: 42,
+            command: "opencode \"run\"\tx".to_string(),
+            tmux_target: "ai:1.0".to_string(),
+            rss_bytes: 300,
+            swap_bytes: 20,
+            ..ProcRecord::default()
+        };
+        let fields = parse_json_object(&recording_line(1_700_000_000, &row)).unwrap();
+        let sample = recorded_sample(&fields).unwrap();
+        assert_eq!(sample.ts, 1_700_000_000);
+        assert_eq!(sample.pid, 42);
+        assert_eq!(sample.command, "opencode \"run\"\tx");
+        assert_eq!(sample.footprint_bytes, 320);
+        assert!(parse_json_object("{\"ts\":12").is_err());
+    }
+
+    #[test]
+    fn detect_leaks_flags_sustained_growth_only() {
+        let mut samples = Vec::new();
+        for i in 0..10u64 {
+            for (pid, growth) in [(1, 10 << 20), (2, 0)] {
+                samples.push(RecordedSample {
+                    ts: i * 360,
+                    pid,
+                    command: format!("cmd{pid}"),
+                    tmux_target: format!("ai:{pid}.0"),
+                    footprint_bytes: (100 << 20) + growth * i,
+                    ..RecordedSample::default()
+                });
+            }
+        }
+        let leaks = detect_leaks(&samples, 50 << 20);
+        assert_eq!(leaks.len(), 2);
+        assert_eq!((leaks[0].scope, leaks[0].subject.as_str()), ("pid", "1"));
+        assert_eq!(
+            (leaks[1].scope, leaks[1].subject.as_str()),
+            ("pane", "ai:1.0")
+        );
+        assert_eq!(leaks[0].growth_per_hour_bytes, 100 << 20);
+        assert_eq!(leaks[0].confidence, "medium");
+        assert!(detect_leaks(&samples[..4], 0).is_empty());
+    }
+
+    #[test]
+    fn percentile_uses_nearest_rank() {
+        let values = (1..=20).collect::<Vec<u64>>();
+        assert_eq!(percentile(&values, 50), 10);
+        assert_eq!(percentile(&values, 95), 19);
+        assert_eq!(percentile(&values, 100), 20);
+        assert_eq!(percentile(&[7], 95), 7);
+        assert_eq!(percentile(&[], 50), 0);
+    }
+
+    #[test]
+    fn forced_exit_restores_the_screen_and_drops_only_our_pid_file() {
+        let dir = env::temp_dir().join(format!("otm-exit-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let screen = dir.join("screen");
+        let out = fs::File::create(&screen).unwrap();
+        let pid_path = dir.join("daemon.pid");
+        let ours = (
+            format!("{}\0", pid_path.display()).into_bytes(),
+            b"4242\n".to_vec(),
+        );
+
+        fs::write(&pid_path, "4242\nThu Oct 15 05:24:24 2026\n").unwrap();
+        release_for_exit(out.as_raw_fd(), true, Some(&ours));
+        assert_eq!(fs::read_to_string(&screen).unwrap(), LEAVE_WATCH_SCREEN);
+        assert!(!pid_path.exists());
+
+        // A newer daemon's file stays, and nothing is written off the watch screen.
+        fs::write(&pid_path, "42421\nThu Oct 15 05:30:00 2026\n").unwrap();
+        release_for_exit(out.as_raw_fd(), false, Some(&ours));
+        assert!(pid_path.exists());
+        assert_eq!(fs::read_to_string(&screen).unwrap(), LEAVE_WATCH_SCREEN);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn pane_trends_sum_processes_and_chart_each_pane() {
+        let sample = |ts, pid, target: &str, bytes| RecordedSample {
+            ts,
+            pid,
+            tmux_target: target.to_string(),
+            tmux_window_name: "<oc>".to_string(),
+            footprint_bytes: bytes,
+            ..RecordedSample::default()
+        };
+        let samples = vec![
+            sample(60, 1, "ai:1.0", 100),
+            sample(60, 2, "ai:1.0", 50),
+            sample(0, 1, "ai:1.0", 100),
+            sample(0, 3, "?", 999),
+        ];
+        let trends = pane_trends(&samples);
+        assert_eq!(trends.len(), 1);
+        assert_eq!(trends[0].points, vec![(0, 100), (60, 150)]);
+        assert_eq!(trends[0].growth_per_hour_bytes, Some(3000));
+
+        let html = render_trends_html(&trends, 0);
+        assert!(html.contains("<small>&lt;oc&gt;</small>"));
+        assert_eq!(html.matches("<polyline").count(), 1);
+        assert!(!html.contains("<script"));
+    }
+
+    #[test]
+    fn parse_request_line_accepts_get_only() {
+        assert_eq!(
+            parse_request_line("GET /api/panes?x=1 HTTP/1.1\r\n"),
+            Ok("/api/panes".to_string())
+        );
+        assert_eq!(
+            parse_request_line("POST / HTTP/1.1\r\n"),
+            Err("405 Method Not Allowed")
+        );
+        assert_eq!(parse_request_line("\r\n"), Err("400 Bad Request"));
+    }
+
+    #[test]
+    fn schema_v1_pins_baseline_fields_in_order() {
+        let row = ProcRecord {
+            pid: 7,
+            command: "opencode".to_string(),
+            ..ProcRecord::default()
+        };
+        let names = |version| {
+            pin_schema(process_fields(&row), PROCESS_SCHEMA, version)
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names(Some(1)),
+            [
+                "pid",
+                "tmux_target",
+                "tmux_window",
+                "swap_bytes",
+                "swap_human",
+                "physical_bytes",
+                "physical_human",
+                "rss_bytes",
+                "rss_human",
+                "pane_history_bytes",
+                "pane_history_human",
+                "pane_history_lines",
+                "command",
+            ]
+        );
+        assert_eq!(
+            names(Some(SCHEMA_VERSION)),
+            names(None),
+            "the current version is the full field set"
+        );
+        let pane = pin_schema(pane_fields(&PaneRecord::default()), PANE_SCHEMA, Some(1));
+        assert_eq!(pane.len(), 13);
+    }
+
+    // A failure here means a structured field changed without a schema bump: add
+    // the field to PROCESS_SCHEMA/PANE_SCHEMA under a new SCHEMA_VERSION and append
+    // that version's field count below. Released counts never change.
+    #[test]
+    fn schema_tables_cover_every_field_and_released_versions_are_frozen() {
+        let listed = |schema: &[(&'static str, u32)]| {
+            schema.iter().map(|(name, _)| *name).collect::<Vec<_>>()
+        };
+        let emitted = |fields: Vec<(&'static str, Field)>| {
+            fields.into_iter().map(|(name, _)| name).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            emitted(process_fields(&ProcRecord::default())),
+            listed(PROCESS_SCHEMA)
+        );
+        assert_eq!(
+            emitted(pane_fields(&PaneRecord::default())),
+            listed(PANE_SCHEMA)
+        );
+        let counts = |schema: &[(&str, u32)]| {
+            (1..=SCHEMA_VERSION)
+                .map(|v| schema.iter().filter(|(_, since)| *since <= v).count())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            counts(PROCESS_SCHEMA),
+            [13, 25, 25, 25, 26, 27, 30, 31, 33, 34, 36, 37, 38, 38, 39]
+        );
+        assert_eq!(
+            counts(PANE_SCHEMA),
+            [13, 14, 14, 15, 15, 16, 19, 19, 19, 19, 21, 22, 23, 25, 26]
+        );
+    }
+
+    #[test]
+    fn porcelain_rows_are_raw_and_tab_safe() {
+        let row = ProcRecord {
+            pid: 12,
+            command: "node\tserver\n--port 1".to_string(),
+            tmux_target: "?".to_string(),
+            rss_bytes: 4096,
+            pane_history_size: -1,
+            pane_history_limit: -1,
+            ..ProcRecord::default()
+        };
+        assert_eq!(
+            render_process_porcelain(&[row]),
+            "12\t-\t-\t0\t0\t4096\t0\t-\t-\tnode server --port 1\n"
+        );
+    }
+
+    #[test]
+    fn unknown_pane_is_null_unless_schema_is_pinned_before_v3() {
+        let row = ProcRecord {
+            tmux_target: "?".to_string(),
+            tmux_window_name: "?".to_string(),
+            pane_history_size: -1,
+            pane_history_limit: -1,
+            ..ProcRecord::default()
+        };
+        let value = |version, name| {
+            pin_schema(process_fields(&row), PROCESS_SCHEMA, version)
+                .into_iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, v)| json_field(&v))
+        };
+        for name in [
+            "tmux_target",
+            "tmux_window",
+            "pane_history_bytes",
+            "pane_history_lines",
+        ] {
+            assert_eq!(value(None, name).as_deref(), Some("null"), "{name}");
+        }
+        assert_eq!(value(Some(2), "tmux_target").as_deref(), Some("\"?\""));
+        assert_eq!(value(Some(1), "pane_history_bytes").as_deref(), Some("0"));
+        assert_eq!(
+            value(Some(1), "pane_history_lines").as_deref(),
+            Some("null")
+        );
+    }
+
+    #[test]
+    fn status_line_reports_outcome_counts_and_warnings() {
+        let status = RunStatus {
+            records: Some(3),
+            totals: Some((1, 2, 3)),
+            warnings: vec!["tmux panes unavailable: \"no server\"".to_string()],
+            breached: false,
+            partial: false,
+        };
+        assert_eq!(
+            status_line(&status, None, 0, Duration::from_millis(42)),
+            "{\"exit_reason\":\"ok\",\"exit_code\":0,\"error\":null,\"records\":3,\"totals\":{\"swap_bytes\":1,\"physical_bytes\":2,\"rss_bytes\":3},\"warnings\":[\"tmux panes unavailable: \\\"no server\\\"\"],\"duration_ms\":42}"
+        );
+        let empty = RunStatus {
+            records: None,
+            totals: None,
+            warnings: Vec::new(),
+            breached: false,
+            partial: false,
+        };
+        let line = status_line(&empty, Some(&"bad".to_string()), 1, Duration::ZERO);
+        assert!(line.starts_with("{\"exit_reason\":\"error\",\"exit_code\":1,\"error\":\"bad\",\"records\":null,\"totals\":null,"));
+    }
+
+    #[test]
+    fn doctor_fields_null_hint_when_passing() {
+        let fields = doctor_fields(&check(
+            "tmux",
+            CheckStatus::Pass,
+            "tmux 3.4".to_string(),
+            "",
+        ));
+        assert_eq!(json_field(&fields[1].1), "\"pass\"");
+        assert_eq!(fields[3].1, Field::Null);
+        let table = render_doctor_table(
+            &[check(
+                "ps",
+                CheckStatus::Fail,
+                "not found".to_string(),
+                "install procps",
+            )],
+            &RenderOptions::default(),
+        );
+        assert!(table.contains("✗ FAIL\tps\tnot found\tinstall procps"));
+        assert!(table.ends_with("0 passed, 0 warning(s), 1 failed\n"));
+    }
+
+    #[test]
+    fn envelope_wraps_json_and_yaml_records() {
+        let meta = vec![
+            ("tool", Field::Str("opencode-tmux-mem".to_string())),
+            ("schema_version", Field::Uint(3)),
+        ];
+        let json = envelope(
+            &meta,
+            None,
+            OutputFormat::Json,
+            "[\n  {\"pid\":1}\n]\n".to_string(),
+        );
+        assert!(json.starts_with("{\n  \"meta\": {\"tool\":\"opencode-tmux-mem\","));
+        assert!(json.ends_with("\"schema_version\":3},\n  \"records\": [\n  {\"pid\":1}\n]\n}\n"));
+
+        let yaml = envelope(
+            &meta,
+            None,
+            OutputFormat::Yaml,
+            "---\n- pid: 1\n  user: \"a\"\n".to_string(),
+        );
+        assert!(yaml.starts_with("---\nmeta:\n  tool: \"opencode-tmux-mem\"\n"));
+        assert!(yaml.ends_with("records:\n  - pid: 1\n    user: \"a\"\n"));
+        assert!(
+            envelope(&meta, None, OutputFormat::Yaml, "---\n".to_string())
+                .ends_with("records: []\n")
+        );
+        assert_eq!(
+            envelope(&meta, None, OutputFormat::Csv, "pid\n".to_string()),
+            "pid\n"
+        );
+    }
+
+    #[test]
+    fn envelope_leads_with_the_schema_url() {
+        let meta = vec![("schema_version", Field::Uint(3))];
+        let url = "https://example.com/opencode-tmux-mem/v3.json";
+        let json = envelope(&meta, Some(url), OutputFormat::Json, "[]\n".to_string());
+        assert!(json.starts_with(&format!("{{\n  \"$schema\": \"{url}\",\n  \"meta\": ")));
+        assert!(
+            !envelope(&meta, Some(url), OutputFormat::Yaml, "---\n".to_string())
+                .contains("schema\":")
+        );
+        assert_eq!(
+            parse_config(&format!("json_schema_url: {url}\n"))
+                .unwrap()
+                .json_schema_url
+                .as_deref(),
+            Some(url)
+        );
+    }
+
+    #[test]
+    fn export_append_locks_and_keeps_one_csv_header() {
+        let path = env::temp_dir().join(format!("otm-lock-{}.csv", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+        let body = || "pid,command\n42,opencode\n".to_string();
+
+        append_locked(path, "export file", Duration::ZERO, |existing| {
+            appended_export(OutputFormat::Csv, body(), existing)
+        })
+        .unwrap();
+        let holder = fs::OpenOptions::new().append(true).open(path).unwrap();
+        lock_file(&holder, path, Duration::ZERO).unwrap();
+        let err = append_locked(path, "export file", Duration::ZERO, |_| body()).unwrap_err();
+        assert!(err.contains("locked by another"), "{err}");
+        drop(holder);
+        append_locked(path, "export file", Duration::ZERO, |existing| {
+            appended_export(OutputFormat::Csv, body(), existing)
+        })
+        .unwrap();
+
+        assert_eq!(
+            fs::read_to_string(path).unwrap(),
+            "pid,command\n42,opencode\n42,opencode\n"
+        );
+        let _ = fs::remove_file(path);
+        assert_eq!(
+            appended_export(OutputFormat::Yaml, "- pid: 1\n".to_string(), true),
+            "---\n- pid: 1\n"
+        );
+    }
+
+    #[test]
+    fn atomic_export_replaces_without_leftovers() {
+        let dir = env::temp_dir().join(format!("otm-atomic-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("report.json");
+        let path = path.to_str().unwrap();
+
+        write_atomic(path, "[old]").unwrap();
+        write_atomic(path, "[new]").unwrap();
+
+        assert_eq!(fs::read_to_string(path).unwrap(), "[new]");
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        assert!(write_atomic(dir.join("missing/x.json").to_str().unwrap(), "[]").is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn export_path_template_expands_time_and_host() {
+        // 2024-02-29T13:05:09Z
+        let secs = 1_709_211_909;
+        let host = || "mbp".to_string();
+        assert_eq!(
+            expand_path_template("report-%Y%m%d-%H%M%S-%h.json", secs, host),
+            "report-20240229-130509-mbp.json"
+        );
+        assert_eq!(
+            expand_path_template("100%%-%s.csv", secs, host),
+            "100%-1709211909.csv"
+        );
+        assert_eq!(
+            expand_path_template("plain.json", secs, || unreachable!()),
+            "plain.json"
+        );
+        // Anything that is not a placeholder passes through unchanged.
+        assert_eq!(expand_path_template("x-%q.json", secs, host), "x-%q.json");
+        assert_eq!(expand_path_template("100%.json", secs, host), "100%.json");
+        assert_eq!(expand_path_template("x-%", secs, host), "x-%");
+        assert!(template_matches("x-%q-%Y.json", "x-%q-2024.json"));
+        assert!(template_matches("x-%", "x-%"));
+        assert!(!template_matches("x-%q.json", "x-q.json"));
+    }
+
+    #[test]
+    fn jitter_stays_within_bound() {
+        assert_eq!(jitter(Duration::ZERO), Duration::ZERO);
+        for _ in 0..50 {
+            assert!(jitter(Duration::from_secs(30)) <= Duration::from_secs(30));
+        }
+    }
+
+    #[test]
+    fn install_service_forwards_flags_and_escapes_systemd() {
+        let args = [
+            "--app",
+            "opencode",
+            "--apply",
+            "--service-mode",
+            "record",
+            "--export",
+            "mem-%Y.csv",
+        ]
+        .map(String::from);
+        let forwarded = forwarded_service_args(&args, "/home/me");
+        assert_eq!(
+            forwarded,
+            ["--app", "opencode", "--export", "/home/me/mem-%Y.csv"]
+        );
+        assert_eq!(
+            forwarded_service_args(&["--cwd", "src"].map(String::from), "/home/me"),
+            ["--cwd", "/home/me/src"]
+        );
+        assert_eq!(
+            forwarded_service_args(&["--open-file", "db.sqlite"].map(String::from), "/home/me"),
+            ["--open-file", "/home/me/db.sqlite"]
+        );
+
+        let command = service_command(ServiceMode::Record, &forwarded, Some("/st".to_string()));
+        assert_eq!(
+            command[command.len() - 4..],
+            ["--every", "5m", "--record", "/st/mem.jsonl"]
+        );
+        assert_eq!(service_command(ServiceMode::Serve, &[], None), ["serve"]);
+
+        let unit = systemd_unit("/bin/otm", &forwarded, "/usr/bin", ServiceMode::Record);
+        assert!(unit.contains(
+            "ExecStart=\"/bin/otm\" \"--app\" \"opencode\" \"--export\" \"/home/me/mem-%%Y.csv\"\n"
+        ));
+        assert!(unit.contains("StandardOutput=null"));
+        let plist = launchd_plist(
+            "l",
+            "/bin/otm",
+            &forwarded,
+            "/usr/bin",
+            ServiceMode::Serve,
+            "/log",
+        );
+        assert!(plist.contains("<string>/home/me/mem-%Y.csv</string>"));
+    }
+
+    #[test]
+    fn pids_display_summarizes_busy_panes() {
+        let pids = (1..=10).collect::<Vec<i32>>();
+        assert_eq!(pids_cell(&pids, PidsDisplay::Summary), "1,2,3,+7 more");
+        assert_eq!(pids_cell(&pids[..3], PidsDisplay::Summary), "1,2,3");
+        assert_eq!(pids_cell(&pids, PidsDisplay::Full), "1,2,3,4,5,6,7,8,9,10");
+
+        let pane = PaneRecord {
+            tmux_target: "ai:1.0".to_string(),
+            pids,
+            ..PaneRecord::default()
+        };
+        let opts = RenderOptions {
+            pids: PidsDisplay::None,
+            ..RenderOptions::default()
+        };
+        let table = render_pane_table(std::slice::from_ref(&pane), None, &opts);
+        assert!(!table.contains("PIDs"), "{table}");
+        let markdown = render_pane_markdown(&[pane], &opts);
+        assert!(markdown.starts_with("| Tmux window.pane | Window | Processes | Swap |"));
+        assert!(markdown.contains("|---|---|---:|---:|"));
+    }
+
+    #[test]
+    fn include_shell_adds_root_shell_rss_to_panes() {
+        let mut panes = vec![
+            PaneRecord {
+                tmux_target: "ai:1.0".to_string(),
+                rss_bytes: 100,
+                ..PaneRecord::default()
+            },
+            PaneRecord {
+                tmux_target: "?".to_string(),
+                rss_bytes: 7,
+                ..PaneRecord::default()
+            },
+        ];
+        let shells = HashMap::from([("ai:1.0".to_string(), 20)]);
+        add_shell_memory(&mut panes, &shells);
+
+        assert_eq!(panes[0].rss_bytes, 120);
+        assert_eq!(panes[0].shell_rss_bytes, Some(20));
+        assert_eq!((panes[1].rss_bytes, panes[1].shell_rss_bytes), (7, None));
+        let table = render_pane_table(&panes, None, &RenderOptions::default());
+        assert!(table
+            .lines()
+            .next()
+            .unwrap()
+            .contains("RSS\tShell RSS\tPaneHistory"));
+        assert!(table.contains("120 B\t20 B\t"), "{table}");
+    }
+
+    #[test]
+    fn process_tag_follows_pane_tree_position() {
+        assert_eq!(process_tag(0, "-zsh", false), "shell");
+        assert_eq!(process_tag(0, "bash -l", false), "shell");
+        assert_eq!(
+            process_tag(0, "/bin/bash /usr/local/bin/opencode", false),
+            "app"
+        );
+        assert_eq!(process_tag(0, "/usr/local/bin/opencode", false), "app");
+        assert_eq!(process_tag(1, "opencode --continue", false), "app");
+        assert_eq!(process_tag(2, "opencode run", false), "helper");
+        assert_eq!(process_tag(1, "node lsp.js", true), "helper");
+    }
+
+    #[test]
+    fn tmux_view_compares_server_memory_to_pane_history() {
+        let server = TmuxRecord {
+            kind: "server",
+            pid: 548,
+            physical_bytes: 48 * 1024 * 1024,
+            rss_bytes: 50 * 1024 * 1024,
+            pane_history_bytes: Some(12 * 1024 * 1024),
+            pane_count: Some(9),
+            ..TmuxRecord::default()
+        };
+        assert_eq!(
+            tmux_server_summary(std::slice::from_ref(&server)).as_deref(),
+            Some("Server physical vs pane history of 9 panes: 48.00 MiB vs 12.00 MiB (4.0x)")
+        );
+        let linux = TmuxRecord {
+            vmmap_skipped: true,
+            ..server.clone()
+        };
+        assert!(tmux_server_summary(std::slice::from_ref(&linux))
+            .unwrap()
+            .starts_with("Server RSS vs"));
+        assert_eq!(tmux_fields(&linux)[5], ("physical_bytes", Field::Null));
+        let no_history = TmuxRecord {
+            pane_history_bytes: None,
+            ..server
+        };
+        assert_eq!(tmux_server_summary(&[no_history]), None);
+    }
+
+    #[test]
+    fn parse_tmux_clients_reads_session_and_tty() {
+        let clients = parse_tmux_clients("812\tai\t/dev/ttys003\n907\thist\t\nbogus\n");
+        assert_eq!(clients.len(), 2);
+        assert_eq!(clients[0].kind, "client");
+        assert_eq!(clients[0].pid, 812);
+        assert_eq!(clients[0].session.as_deref(), Some("ai"));
+        assert_eq!(clients[0].tty.as_deref(), Some("/dev/ttys003"));
+        assert_eq!((clients[1].pid, clients[1].tty.as_deref()), (907, None));
+    }
+
+    #[test]
+    fn monitor_clients_do_not_count_as_attached() {
+        assert!(is_monitor_client(
+            "attached,focused,control-mode,ignore-size,no-output,UTF-8"
+        ));
+        assert!(!is_monitor_client("attached,control-mode,UTF-8"));
+        // Our control client's own session never shows up as a pane.
+        let panes = parse_tmux_panes(
+            "opencode-tmux-mem-control-812:0.0\tcat\t42\t0\t2000\t1700000000\t1\n\
+             work:0.0\tsh\t43\t0\t2000\t1700000000\t1\n",
+            None,
+        );
+        assert_eq!(panes.len(), 1);
+        assert_eq!(panes[0].target, "work:0.0");
+        let listed = parse_tmux_clients(
+            "812\tai\t\tattached,control-mode,no-output\n907\twork\t/dev/pts/1\tattached\n",
+        );
+        assert_eq!(listed.iter().map(|c| c.pid).collect::<Vec<_>>(), [907]);
+    }
+
+    #[test]
+    fn history_estimate_scales_captured_bytes() {
+        assert_eq!(history_estimate(10_000, DEFAULT_HISTORY_OVERHEAD), 30_000);
+        assert_eq!(history_estimate(10_000, 1.0), 10_000);
+        assert_eq!(history_estimate(3, 2.5), 8);
+        // The captured bytes stay as they are; the estimate is its own field.
+        let row = ProcRecord {
+            tmux_target: "ai:1.0".to_string(),
+            pane_history_bytes: 10_000,
+            pane_history_estimated_bytes: 30_000,
+            ..ProcRecord::default()
+        };
+        let fields = process_fields(&row);
+        let field = |name: &str| fields.iter().find(|(n, _)| *n == name).map(|(_, v)| v);
+        assert_eq!(field("pane_history_bytes"), Some(&Field::Uint(10_000)));
+        assert_eq!(
+            field("pane_history_estimated_bytes"),
+            Some(&Field::Uint(30_000))
+        );
+    }
+
+    #[test]
+    fn alert_growth_flags_rows_between_ticks() {
+        assert_eq!(
+            parse_growth_threshold("10%/interval"),
+            Some(GrowthThreshold::Percent(10.0))
+        );
+        assert_eq!(
+            parse_growth_threshold("50M"),
+            Some(GrowthThreshold::Bytes(50 * 1024 * 1024))
+        );
+        assert_eq!(parse_growth_threshold("0%"), None);
+
+        let row = |rss: u64| ProcRecord {
+            pid: 7,
+            command: "opencode".to_string(),
+            command_name: "opencode".to_string(),
+            rss_bytes: rss,
+            tmux_target: "ai:1.0".to_string(),
+            ..ProcRecord::default()
+        };
+        let mut cache = CollectCache::default();
+        let threshold = GrowthThreshold::Percent(10.0);
+        let tick = |rss: u64, cache: &mut CollectCache| {
+            let mut rows = vec![row(rss)];
+            let mut panes = aggregate_by_pane(&rows);
+            let grown = mark_growth(&mut rows, &mut panes, cache, threshold);
+            (rows[0].growth_alert, panes[0].growth_alert, grown)
+        };
+        assert_eq!(tick(1000, &mut cache), (Some(false), Some(false), vec![]));
+        assert_eq!(tick(1050, &mut cache).0, Some(false));
+        let (process, pane, grown) = tick(1200, &mut cache);
+        assert_eq!((process, pane), (Some(true), Some(true)));
+        assert_eq!(grown, ["opencode (pid 7)", "pane ai:1.0"]);
+    }
+
+    #[test]
+    fn since_last_matches_template_and_diffs_rows() {
+        let template = "report-%h-%Y%m%d-%H%M.json";
+        assert!(template_matches(
+            template,
+            "report-mac.lan-20260101-0930.json"
+        ));
+        assert!(!template_matches(template, "report-mac-2026011-0930.json"));
+        assert!(!template_matches(template, "report-mac-20260101-0930.csv"));
+        assert!(template_matches(
+            "r-%s-100%%.json",
+            "r-1767225600-100%.json"
+        ));
+        assert!(template_matches("plain.json", "plain.json"));
+
+        let baseline = [
+            r#"{"pid":7,"command":"opencode","swap_bytes":100,"physical_bytes":500,"rss_bytes":300}"#,
+            r#"{"tmux_target":"ai:1.0","swap_bytes":0,"physical_bytes":900,"rss_bytes":0}"#,
+        ]
+        .map(|line| parse_json_object(line).unwrap());
+        let mut rows = vec![
+            ProcRecord {
+                pid: 7,
+                command: "opencode".to_string(),
+                swap_bytes: 150,
+                physical_bytes: 400,
+                rss_bytes: 300,
+                ..ProcRecord::default()
+            },
+            ProcRecord {
+                pid: 8,
+                command: "opencode".to_string(),
+                ..ProcRecord::default()
+            },
+        ];
+        let mut panes = vec![PaneRecord {
+            tmux_target: "ai:1.0".to_string(),
+            physical_bytes: 1000,
+            ..PaneRecord::default()
+        }];
+        apply_since_last(&mut rows, &mut panes, &baseline);
+        assert_eq!(rows[0].since_last, Some((50, -100, 0)));
+        assert_eq!(rows[1].since_last, None);
+        assert_eq!(panes[0].since_last, Some((0, 100, 0)));
+        assert_eq!(delta_cells(rows[0].since_last), ["+50 B", "-100 B", "0 B"]);
+        assert_eq!(delta_cells(None)[0], "new");
+
+        let compared = RenderOptions {
+            since_last: true,
+            ..RenderOptions::default()
+        };
+        let markdown = render_pane_markdown(&panes, &compared);
+        assert!(markdown.contains("| RSS | ΔSwap | ΔPhysical | ΔRSS | PaneHistory |"));
+        assert!(markdown.contains("| 0 B | +100 B | 0 B |"), "{markdown}");
+        assert!(render_markdown(&rows, &compared).contains("| new | new | new |"));
+        let json = render_process(&rows, OutputFormat::Json, &compared);
+        assert!(json.contains("\"swap_delta_bytes\":50"), "{json}");
+        // Without an earlier export there is nothing to compare, so no deltas.
+        let json = render_process(&rows, OutputFormat::Json, &RenderOptions::default());
+        assert!(!json.contains("delta_bytes"), "{json}");
+
+        // Placeholders in directories match older ones, not just this run's.
+        let dir = env::temp_dir().join(format!("otm-since-last-{}", std::process::id()));
+        let older = dir.join("2026-09").join("report.json");
+        let newer = dir.join("2026-10").join("report.json");
+        for (path, age) in [(&older, 7200), (&newer, 3600)] {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            let file = fs::File::create(path).unwrap();
+            file.set_modified(std::time::SystemTime::now() - Duration::from_secs(age))
+                .unwrap();
+        }
+        let template = format!("{}/%Y-%m/report.json", dir.display());
+        assert_eq!(find_last_export(&template), Some(newer.clone()));
+        fs::remove_file(&newer).unwrap();
+        assert_eq!(find_last_export(&template), Some(older));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn snapshot_commands_parse_and_diff_by_pane() {
+        let args = |list: &[&str]| list.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            parse_snapshot_command(&args(&["diff", "before", "after", "--format", "json"])),
+            Ok((SnapshotCommand::Diff("before".into(), "after".into()), 3))
+        );
+        assert_eq!(
+            parse_snapshot_command(&args(&["list"])),
+            Ok((SnapshotCommand::List, 1))
+        );
+        assert!(parse_snapshot_command(&args(&["save", "../up"])).is_err());
+        assert!(parse_snapshot_command(&args(&["save", "--quiet"])).is_err());
+
+        let parse = |lines: &[&str]| {
+            lines
+                .iter()
+                .map(|l| parse_json_object(l).unwrap())
+                .collect::<Vec<_>>()
+        };
+        // The upgrade restarted opencode under a new PID in the same pane.
+        let before = parse(&[
+            r#"{"pid":10,"tmux_target":"ai:1.0","tmux_window":"main","rss_bytes":500}"#,
+            r#"{"pid":11,"tmux_target":null,"tmux_window":null,"rss_bytes":50}"#,
+        ]);
+        let after = parse(&[
+            r#"{"pid":20,"tmux_target":"ai:1.0","tmux_window":"main","rss_bytes":800}"#,
+            r#"{"pid":21,"tmux_target":"ai:1.0","tmux_window":"main","rss_bytes":100}"#,
+        ]);
+        let rows = diff_snapshots(&before, &after);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].tmux_target, "ai:1.0");
+        assert_eq!((rows[0].before.0, rows[0].after.0), (1, 2));
+        assert_eq!(snapshot_delta(&rows[0]).2, 400);
+        assert_eq!(rows[1].tmux_target, "-");
+        assert_eq!(snapshot_delta(&rows[1]).2, -50);
+        assert_eq!(
+            parse_json_object(r#"{"features":["gpu","serve"]}"#).unwrap()["features"],
+            Field::StrList(vec!["gpu".into(), "serve".into()])
+        );
+    }
+
+    #[test]
+    fn merge_splits_recordings_by_sample_and_reads_envelope_host() {
+        let parse = |lines: &[&str]| {
+            lines
+                .iter()
+                .map(|l| parse_json_object(l).unwrap())
+                .collect::<Vec<_>>()
+        };
+        let path = std::path::Path::new("week/mem.jsonl");
+        let recording = parse(&[
+            r#"{"ts":0,"pid":1,"tmux_target":"ai:1.0","rss_bytes":100}"#,
+            r#"{"ts":0,"pid":2,"tmux_target":"ai:2.0","rss_bytes":300}"#,
+            r#"{"ts":60,"pid":1,"tmux_target":"ai:1.0","rss_bytes":500}"#,
+        ]);
+        let meta = parse(&[r#"{"host":"mac","generated_at":"2026-01-01T00:00:00Z"}"#]).remove(0);
+        let rows = merge_records(path, &meta, &recording);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].host, "mac");
+        assert_eq!(rows[0].timestamp, "1970-01-01T00:00:00Z");
+        assert_eq!((rows[0].process_count, rows[0].pane_count), (2, 2));
+        assert_eq!(rows[0].rss_bytes, 400);
+        assert_eq!(rows[0].top_pane.as_deref(), Some("ai:2.0"));
+        assert_eq!(rows[1].timestamp, "1970-01-01T00:01:00Z");
+
+        // Pane exports count their processes; the envelope supplies the time.
+        let panes = parse(&[r#"{"tmux_target":"ai:1.0","process_count":3,"rss_bytes":900}"#]);
+        let rows = merge_records(path, &meta, &panes);
+        assert_eq!(rows[0].timestamp, "2026-01-01T00:00:00Z");
+        assert_eq!(rows[0].process_count, 3);
+        assert_eq!(rows[0].top_pane_rss_bytes, 900);
+    }
+
+    #[test]
+    fn csv_exports_load_like_json() {
+        let raw = "pid,tmux_target,rss_bytes,command\n7,ai:1.0,300,\"node \"\"a,b\"\"\"\n8,,0,sh\n";
+        let records = parse_csv_export(raw).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["pid"], Field::Uint(7));
+        assert_eq!(records[0]["rss_bytes"], Field::Uint(300));
+        assert_eq!(
+            records[0]["command"],
+            Field::Str("node \"a,b\"".to_string())
+        );
+        assert_eq!(records[1]["tmux_target"], Field::Null);
+
+        let mut rows = vec![ProcRecord {
+            pid: 7,
+            command: "node \"a,b\"".to_string(),
+            rss_bytes: 500,
+            ..ProcRecord::default()
+        }];
+        apply_since_last(&mut rows, &mut [], &records);
+        assert_eq!(rows[0].since_last, Some((0, 0, 200)));
+        assert!(parse_csv_export("pid,rss_bytes\n1\n").is_err());
+        assert_eq!(
+            export_file_format("a.json", "pid,rss_bytes\n"),
+            Ok(OutputFormat::Json)
+        );
+        assert_eq!(
+            export_file_format("a.txt", "pid,rss_bytes\n"),
+            Ok(OutputFormat::Csv)
+        );
+        assert_eq!(
+            export_file_format("latest", "[\n  {\"pid\":1}\n]\n"),
+            Ok(OutputFormat::Json)
+        );
+        assert!(export_file_format("a.yaml", "pid,rss_bytes\n").is_err());
+        assert!(export_file_format("latest", "---\n- pid: 1\n").is_err());
+        assert!(export_file_format("latest", "| PID | User |\n").is_err());
+        assert!(export_file_format("a.md", "").is_err());
+        assert!(parse_csv("\"open").is_err());
+    }
+
+    #[test]
+    fn group_panes_adds_up_by_key() {
+        let pane = |target: &str, rss| PaneRecord {
+            tmux_target: target.to_string(),
+            process_count: 1,
+            rss_bytes: rss,
+            ..PaneRecord::default()
+        };
+        let panes = [
+            pane("a:1.0", 100),
+            pane("a:2.0", 200),
+            pane("b:1.0", 50),
+            pane("-", 10),
+        ];
+        let keys = HashMap::from([
+            ("a:1.0".to_string(), "/src/app".to_string()),
+            ("a:2.0".to_string(), "/src/app".to_string()),
+            ("b:1.0".to_string(), String::new()),
+        ]);
+        let groups = group_panes(&panes, &[], |p| keys.get(&p.tmux_target).cloned());
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].key, "/src/app");
+        assert_eq!((groups[0].pane_count, groups[0].rss_bytes), (2, 300));
+        // Empty values and unattached processes land together.
+        assert_eq!(groups[1].key, "-");
+        assert_eq!((groups[1].pane_count, groups[1].rss_bytes), (2, 60));
+    }
+
+    #[test]
+    fn project_root_collapses_to_git_checkout() {
+        let base = env::temp_dir().join(format!("otm-project-{}", std::process::id()));
+        let nested = base.join("repo/src/bin");
+        fs::create_dir_all(&nested).unwrap();
+        fs::create_dir_all(base.join("repo/.git")).unwrap();
+        assert_eq!(project_root(&nested), base.join("repo").to_string_lossy());
+        let plain = base.join("scratch");
+        fs::create_dir_all(&plain).unwrap();
+        assert_eq!(project_root(&plain), plain.to_string_lossy());
+        let _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn pane_marks_follow_severity_and_clear_on_recovery() {
+        let pane = |target: &str, severity| PaneRecord {
+            tmux_target: target.to_string(),
+            severity: Some(severity),
+            ..PaneRecord::default()
+        };
+        let panes = [
+            pane("ai:1.0", Severity::Crit),
+            pane("ai:2.0", Severity::Warn),
+            pane("ai:3.0", Severity::Ok),
+        ];
+        // ai:2.0 is already marked warn; ai:4.0's processes exited since it was marked.
+        let marked = HashMap::from([
+            ("ai:1.0".to_string(), String::new()),
+            ("ai:2.0".to_string(), "warn".to_string()),
+            ("ai:3.0".to_string(), String::new()),
+            ("ai:4.0".to_string(), "crit".to_string()),
+        ]);
+        let actions = pane_mark_actions(&panes, &marked);
+        let commands = actions
+            .iter()
+            .map(|a| format!("{} {}: {}", a.action, a.target, a.args.join(" ")))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            commands,
+            [
+                "mark-pane pane ai:1.0: set-option -p -t ai:1.0 pane-border-style fg=red ; \
+                 set-option -p -t ai:1.0 pane-active-border-style fg=red ; \
+                 set-option -p -t ai:1.0 @opencode-tmux-mem-mark crit",
+                "unmark-pane pane ai:4.0: set-option -p -u -t ai:4.0 pane-border-style ; \
+                 set-option -p -u -t ai:4.0 pane-active-border-style ; \
+                 set-option -p -u -t ai:4.0 @opencode-tmux-mem-mark",
+            ]
+        );
+    }
+
+    #[test]
+    fn window_badges_follow_footprint() {
+        assert_eq!(compact_bytes(2_254_857_830), "2.1G");
+        assert_eq!(compact_bytes(512 * 1024 * 1024), "512M");
+        assert_eq!(compact_bytes(900), "900B");
+        assert_eq!(strip_window_badge("ai-main [2.1G]"), "ai-main");
+        assert_eq!(strip_window_badge("notes [draft]"), "notes [draft]");
+
+        let pane = |target: &str, rss| PaneRecord {
+            tmux_target: target.to_string(),
+            rss_bytes: rss,
+            ..PaneRecord::default()
+        };
+        let panes = [
+            pane("ai:1.0", 600 << 20),
+            pane("ai:1.1", 600 << 20),
+            pane("ai:2.0", 10 << 20),
+        ];
+        let window = |target: &str, name: &str, automatic_rename, autoname_saved| BadgeWindow {
+            target: target.to_string(),
+            name: name.to_string(),
+            automatic_rename,
+            autoname_saved,
+        };
+        let windows = [
+            window("ai:1", "main [900M]", false, false),
+            window("ai:2", "docs [1.5G]", false, false),
+            window("ai:3", "shell", false, false),
+        ];
+        let commands = |actions: Vec<PlannedAction>| {
+            actions
+                .iter()
+                .map(|a| format!("{} {}: {}", a.action, a.target, a.args.join(" ")))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            commands(window_badge_actions(&panes, &windows, Some(1 << 30))),
+            [
+                "badge-window window ai:1: rename-window -t ai:1 main [1.2G]",
+                "clear-badge window ai:2: rename-window -t ai:2 docs",
+            ]
+        );
+        // --badge-clear strips whatever is left.
+        assert_eq!(window_badge_actions(&[], &windows, None).len(), 2);
+
+        // A window tmux was naming after its command gets automatic-rename back
+        // once the badge goes; a window the user named keeps its name.
+        let windows = [
+            window("ai:1", "node", true, false),
+            window("ai:2", "node [1.2G]", false, true),
+            window("ai:3", "notes [1.2G]", false, false),
+        ];
+        assert_eq!(
+            commands(window_badge_actions(&panes, &windows[..1], Some(1 << 30))),
+            [
+                "badge-window window ai:1: rename-window -t ai:1 node [1.2G] ; \
+              set-window-option -t ai:1 @opencode-tmux-mem-autoname on"
+            ]
+        );
+        assert_eq!(
+            commands(window_badge_actions(&[], &windows[1..], None)),
+            [
+                "clear-badge window ai:2: rename-window -t ai:2 node ; \
+                 set-window-option -u -t ai:2 automatic-rename ; \
+                 set-window-option -u -t ai:2 @opencode-tmux-mem-autoname",
+                "clear-badge window ai:3: rename-window -t ai:3 notes",
+            ]
+        );
+    }
+
+    #[test]
+    fn refresh_hooks_signal_the_daemon_pid() {
+        let hooks = refresh_hook_args("/state/daemon.pid");
+        assert_eq!(hooks.len(), REFRESH_HOOKS.len());
+        assert_eq!(
+            hooks[0],
+            [
+                "set-hook",
+                "-g",
+                "after-split-window[77]",
+                concat!(
+                    r#"run-shell -b 'f="/state/daemon.pid"; pid=$(head -n 1 "$f" 2>/dev/null) "#,
+                    r#"&& [ -n "$pid" ] && [ "$(ps -p "$pid" -o lstart= 2>/dev/null)" = "#,
+                    r#""$(sed -n 2p "$f")" ] && kill -USR1 "$pid" 2>/dev/null; true'"#,
+                ),
+            ]
+        );
+        assert!(hook_safe_path(
+            "/home/me/.local/state/opencode-tmux-mem/daemon.pid"
+        ));
+        for path in [
+            "/a'b",
+            "/a\"b",
+            "/$HOME/x",
+            "/`id`/x",
+            "/#{pane_id}/x",
+            "/a\\b",
+        ] {
+            assert!(!hook_safe_path(path), "{path}");
+        }
+    }
+
+    #[test]
+    fn plugin_line_reads_status_summary() {
+        let rows = [
+            ProcRecord {
+                physical_bytes: 1536 << 20,
+                rss_bytes: 2048 << 20,
+                ..ProcRecord::default()
+            },
+            ProcRecord {
+                swap_bytes: 512 << 20,
+                rss_bytes: 100 << 20,
+                ..ProcRecord::default()
+            },
+        ];
+        let summary = parse_json_object(&status_summary(60, "opencode", &rows)).unwrap();
+        assert_eq!(summary["ts"], Field::Uint(60));
+        assert_eq!(summary["flags"], Field::Str("opencode".to_string()));
+        // Footprint: physical where measured, RSS otherwise, plus swap.
+        assert_eq!(plugin_line(&summary), "2.1G (2)");
+    }
+
+    #[test]
+    fn keybinding_opens_watch_popup_with_forwarded_flags() {
+        let forwarded = forwarded_service_args(
+            &[
+                "--key", "C-m", "--apply", "--config", "mem.yaml", "--view", "pane",
+            ]
+            .map(String::from),
+            "/home/me",
+        );
+        assert_eq!(
+            forwarded,
+            ["--config", "/home/me/mem.yaml", "--view", "pane"]
+        );
+        let args = keybinding_args("C-m", "/usr/bin/opencode-tmux-mem", &forwarded);
+        assert_eq!(&args[..3], ["bind-key", "C-m", "display-popup"]);
+        assert_eq!(
+            args.last().unwrap(),
+            "/usr/bin/opencode-tmux-mem report --config /home/me/mem.yaml --view pane --watch 5s"
+        );
+    }
+
+    #[test]
+    fn current_session_scope_matches_whole_session_names() {
+        assert!(in_tmux_scope("ai:1.0", None));
+        assert!(in_tmux_scope("ai:1.0", Some("ai")));
+        assert!(!in_tmux_scope("ai-2:1.0", Some("ai")));
+        assert!(!in_tmux_scope("base:0.0", Some("ai")));
+        assert!(!in_tmux_scope("?", Some("ai")));
+
+        assert!(
+            parse_config("current_session: true\n")
+                .unwrap()
+                .current_session
+        );
+        assert!(
+            !parse_config("severity:\n  swap:\n    warn: 1G\n")
+                .unwrap()
+                .current_session
+        );
+        assert!(parse_config("current_session: yes\n").is_err());
+    }
+
+    #[test]
+    fn window_and_pane_scopes_match_exact_targets() {
+        assert!(in_tmux_scope("ai:1.0", Some("ai:1")));
+        assert!(in_tmux_scope("ai:1.2", Some("ai:1")));
+        assert!(!in_tmux_scope("ai:10.0", Some("ai:1")));
+        assert!(!in_tmux_scope("ai:0.0", Some("ai:1")));
+        assert!(in_tmux_scope("ai:1.0", Some("ai:1.0")));
+        assert!(!in_tmux_scope("ai:1.1", Some("ai:1.0")));
+        assert!(!in_tmux_scope("ai:1.10", Some("ai:1.1")));
+    }
+
+    #[test]
+    fn self_and_children_are_recognized() {
+        let own = std::process::id() as i32;
+        let mut cache = HashMap::from([(4242, own), (4243, 1)]);
+        assert!(is_self_or_child(own, &mut cache));
+        assert!(is_self_or_child(4242, &mut cache));
+        assert!(!is_self_or_child(4243, &mut cache));
+    }
+
+    #[test]
+    fn glob_regex_anchors_and_escapes() {
+        assert_eq!(glob_regex("opencode*"), "^opencode.*$");
+        assert_eq!(glob_regex("*lsp*"), "^.*lsp.*$");
+        assert_eq!(glob_regex("node?"), "^node.$");
+        assert_eq!(glob_regex("a.b+(c)"), "^a\\.b\\+\\(c\\)$");
+    }
+
+    #[test]
+    fn ignore_case_applies_to_every_match_mode() {
+        assert_eq!(
+            pgrep_args("Code", MatchMode::Exact, Some("me"), true),
+            vec!["-i", "-u", "me", "-x", "Code"]
+        );
+        assert_eq!(
+            pgrep_args("Code*", MatchMode::Glob, None, true),
+            vec!["-i", "^Code.*$"]
+        );
+        assert_eq!(
+            pgrep_args("code --x", MatchMode::Full, None, false),
+            vec!["-f", "code --x"]
+        );
+    }
+
+    #[test]
+    fn full_word_regex_wraps_the_pattern_in_word_boundaries() {
+        assert_eq!(
+            pgrep_args("opencode", MatchMode::FullWord, None, false),
+            vec![
+                "-f",
+                "(^([^[:space:]]*/)?|[[:space:]])(opencode)([[:space:]]|$)"
+            ]
+        );
+        assert_eq!(
+            full_word_regex("a|b"),
+            "(^([^[:space:]]*/)?|[[:space:]])(a|b)([[:space:]]|$)"
+        );
+        // pgrep and grep -E share POSIX extended regexes.
+        let matches = |line: &str| {
+            Command::new("grep")
+                .args(["-Eq", &full_word_regex("opencode")])
+                .stdin(std::process::Stdio::piped())
+                .spawn()
+                .and_then(|mut grep| {
+                    grep.stdin.take().unwrap().write_all(line.as_bytes())?;
+                    grep.wait()
+                })
+                .unwrap()
+                .success()
+        };
+        assert!(matches("opencode"));
+        assert!(matches("/usr/bin/opencode --continue"));
+        assert!(matches("node opencode serve"));
+        assert!(!matches("less /var/log/opencode"));
+        assert!(!matches("tail -f /tmp/x/opencode"));
+        assert!(!matches("vim notes/opencode-ideas.md"));
+    }
+
+    #[test]
+    fn process_patterns_take_an_optional_mode_prefix() {
+        assert_eq!(
+            split_pattern_mode("regex:opencode.*serve", MatchMode::Exact),
+            (MatchMode::Full, "opencode.*serve".to_string())
+        );
+        assert_eq!(
+            split_pattern_mode("exact:bun", MatchMode::Glob),
+            (MatchMode::Exact, "bun".to_string())
+        );
+        assert_eq!(
+            split_pattern_mode("full-word:opencode", MatchMode::Exact),
+            (MatchMode::FullWord, "opencode".to_string())
+        );
+        assert_eq!(
+            split_pattern_mode("node:inspect", MatchMode::Full),
+            (MatchMode::Full, "node:inspect".to_string())
+        );
+        assert_eq!(
+            split_pattern_mode("*lsp*", MatchMode::Glob),
+            (MatchMode::Glob, "*lsp*".to_string())
         );
     }
 
-    let total_swap = rows.iter().map(|r| r.swap_bytes).sum::<u64>();
-    let total_phys = rows.iter().map(|r| r.physical_bytes).sum::<u64>();
-    let total_rss = rows.iter().map(|r| r.rss_bytes).sum::<u64>();
-
-    // Count pane history bytes once per pane to avoid duplicate totals.
-    let mut unique_hist = HashMap::<String, u64>::new();
-    for row in rows {
-        unique_hist
-            .entry(row.tmux_target.clone())
-            .and_modify(|v| *v = (*v).max(row.pane_history_bytes))
-            .or_insert(row.pane_history_bytes);
+    #[test]
+    fn parse_ss_pids_reads_every_listener() {
+        let raw = "LISTEN 0 511 127.0.0.1:4096 0.0.0.0:* users:((\"node\",pid=4242,fd=21),(\"node\",pid=4243,fd=21))\nLISTEN 0 511 [::1]:4096 [::]:* users:((\"node\",pid=4242,fd=22))\n";
+        assert_eq!(parse_ss_pids(raw), vec![4242, 4243]);
+        assert!(parse_ss_pids("").is_empty());
     }
-    let total_hist = unique_hist.values().sum::<u64>();
 
-    out.push('\n');
-    let _ = writeln!(out, "Total swap:\t{}", human_bytes(total_swap));
-    let _ = writeln!(out, "Total physical:\t{}", human_bytes(total_phys));
-    let _ = writeln!(out, "Total RSS:\t{}", human_bytes(total_rss));
-    let _ = writeln!(
-        out,
-        "Total pane history bytes:\t{}",
-        human_bytes(total_hist)
-    );
-    out
-}
+    #[test]
+    fn parse_lsof_fields_pairs_names_with_their_pid() {
+        let raw = "p4242\nfcwd\nn/work/repo\np4243\nfcwd\nn/tmp\n";
+        assert_eq!(
+            parse_lsof_fields(raw),
+            vec![(4242, "/work/repo".to_string()), (4243, "/tmp".to_string())]
+        );
+    }
 
-fn escape_json(s: &str) -> String {
-    let mut out = String::with_capacity(s.len() + 8);
-    for c in s.chars() {
-        match c {
-            '"' => out.push_str("\\\""),
-            '\\' => out.push_str("\\\\"),
-            '\n' => out.push_str("\\n"),
-            '\r' => out.push_str("\\r"),
-            '\t' => out.push_str("\\t"),
-            _ => out.push(c),
-        }
+    #[test]
+    fn file_holder_pids_finds_this_process() {
+        let path = env::temp_dir().join(format!("otm-open-file-{}", std::process::id()));
+        let held = fs::File::create(&path).expect("temp file");
+        let path = fs::canonicalize(&path).expect("canonical path");
+        let pids = file_holder_pids(&path).expect("lsof or /proc");
+        drop(held);
+        let _ = fs::remove_file(&path);
+        assert!(pids.contains(&(std::process::id() as i32)));
     }
-    out
-}
 
-fn render_json(rows: &[ProcRecord]) -> String {
-    let mut out = String::new();
-    out.push_str("[\n");
-    for (idx, row) in rows.iter().enumerate() {
-        let comma = if idx + 1 == rows.len() { "" } else { "," };
-        let history_lines = if row.pane_history_size >= 0 {
-            format!("\"{}/{}\"", row.pane_history_size, row.pane_history_limit)
-        } else {
-            "null".to_string()
+    #[test]
+    fn parse_top_power_reads_the_last_sample() {
+        let raw = "Processes: 2 total\n\nPID    POWER\n4242   0.0\n99     0.0\nProcesses: 2 total\n\nPID    POWER\n4242   12.5\n99     0.3\n";
+        let power = parse_top_power(raw);
+        assert_eq!(power.get(&4242), Some(&12.5));
+        assert_eq!(power.get(&99), Some(&0.3));
+        assert_eq!(energy_cell(Some(12.46)), "12.5");
+        assert_eq!(energy_cell(None), "-");
+
+        let row = ProcRecord {
+            energy_impact: Some(12.5),
+            ..ProcRecord::default()
         };
-        let _ = writeln!(
-            out,
-            "  {{\"pid\":{},\"tmux_target\":\"{}\",\"tmux_window\":\"{}\",\"swap_bytes\":{},\"swap_human\":\"{}\",\"physical_bytes\":{},\"physical_human\":\"{}\",\"rss_bytes\":{},\"rss_human\":\"{}\",\"pane_history_bytes\":{},\"pane_history_human\":\"{}\",\"pane_history_lines\":{},\"command\":\"{}\"}}{}",
-            row.pid,
-            escape_json(&row.tmux_target),
-            escape_json(&row.tmux_window_name),
-            row.swap_bytes,
-            escape_json(&human_bytes(row.swap_bytes)),
-            row.physical_bytes,
-            escape_json(&human_bytes(row.physical_bytes)),
-            row.rss_bytes,
-            escape_json(&human_bytes(row.rss_bytes)),
-            row.pane_history_bytes,
-            escape_json(&human_bytes(row.pane_history_bytes)),
-            history_lines,
-            escape_json(&row.command),
-            comma,
+        let json = json_object(&process_fields(&row));
+        assert!(json.contains("\"energy_impact\":12.5"));
+        assert_eq!(
+            parse_json_object(&json).unwrap()["energy_impact"],
+            Field::Float(12.5)
         );
     }
-    out.push_str("]\n");
-    out
-}
 
-fn escape_csv(s: &str) -> String {
-    if s.contains(',') || s.contains('"') || s.contains('\n') {
-        format!("\"{}\"", s.replace('"', "\"\""))
-    } else {
-        s.to_string()
+    #[test]
+    fn parse_proc_io_reads_storage_bytes() {
+        let raw = "rchar: 9000\nwchar: 800\nsyscr: 10\nsyscw: 5\nread_bytes: 4096\nwrite_bytes: 12288\ncancelled_write_bytes: 0\n";
+        assert_eq!(parse_proc_io(raw), Some((4096, 12288)));
+        assert_eq!(parse_proc_io("rchar: 1\n"), None);
+        let rows = [
+            ProcRecord {
+                disk_io: Some((1, 2)),
+                ..ProcRecord::default()
+            },
+            ProcRecord::default(),
+            ProcRecord {
+                disk_io: Some((10, 20)),
+                ..ProcRecord::default()
+            },
+        ];
+        assert_eq!(sum_disk_io(&rows), Some((11, 22)));
+        assert_eq!(sum_disk_io(&rows[1..2]), None);
     }
-}
 
-fn render_csv(rows: &[ProcRecord]) -> String {
-    let mut out = String::new();
-    out.push_str("pid,tmux_target,tmux_window,swap_bytes,swap_human,physical_bytes,physical_human,rss_bytes,rss_human,pane_history_bytes,pane_history_human,pane_history_lines,command\n");
-    for row in rows {
-        let history_lines = if row.pane_history_size >= 0 {
-            format!("{}/{}", row.pane_history_size, row.pane_history_limit)
-        } else {
-            String::new()
+    #[test]
+    fn process_trends_rank_young_growth_above_old_size() {
+        assert_eq!(parse_etime("05:07"), Some(307));
+        assert_eq!(parse_etime("02:00:00"), Some(7200));
+        assert_eq!(parse_etime("3-00:00:01"), Some(3 * 86_400 + 1));
+        assert_eq!(parse_etime("junk"), None);
+        let started = |raw| {
+            parse_ps_counters(raw, 1_000_000)
+                .into_iter()
+                .map(|(pid, ps)| (pid, ps.started_at))
+                .collect::<HashMap<_, _>>()
         };
-        let _ = writeln!(
-            out,
-            "{},{},{},{},{},{},{},{},{},{},{},{},{}",
-            row.pid,
-            escape_csv(&row.tmux_target),
-            escape_csv(&row.tmux_window_name),
-            row.swap_bytes,
-            escape_csv(&human_bytes(row.swap_bytes)),
-            row.physical_bytes,
-            escape_csv(&human_bytes(row.physical_bytes)),
-            row.rss_bytes,
-            escape_csv(&human_bytes(row.rss_bytes)),
-            row.pane_history_bytes,
-            escape_csv(&human_bytes(row.pane_history_bytes)),
-            escape_csv(&history_lines),
-            escape_csv(&row.command),
+        assert_eq!(
+            started("  42    05:07\n 7 3-00:00:01\n  9 junk\n"),
+            HashMap::from([(42, 1_000_000 - 307), (7, 1_000_000 - (3 * 86_400 + 1))])
         );
-    }
-    out
-}
+        let with_faults = parse_ps_counters(" 42 05:07 8200 118\n 7 00:01\n", 1_000_000);
+        assert_eq!(with_faults[&42].faults, Some((8200, 118)));
+        assert_eq!(with_faults[&7].faults, None);
 
-fn render_yaml(rows: &[ProcRecord]) -> String {
-    let mut out = String::new();
-    out.push_str("---\n");
-    for row in rows {
-        let history_lines = if row.pane_history_size >= 0 {
-            format!("\"{}/{}\"", row.pane_history_size, row.pane_history_limit)
-        } else {
-            "null".to_string()
+        let sample = |ts, pid, bytes, started_at| RecordedSample {
+            ts,
+            pid,
+            command: format!("app {pid}"),
+            tmux_target: "ai:0.0".to_string(),
+            tmux_window_name: "ai".to_string(),
+            footprint_bytes: bytes,
+            started_at,
         };
-        let _ = writeln!(
-            out,
-            "- pid: {}\n  tmux_target: \"{}\"\n  tmux_window: \"{}\"\n  swap_bytes: {}\n  swap_human: \"{}\"\n  physical_bytes: {}\n  physical_human: \"{}\"\n  rss_bytes: {}\n  rss_human: \"{}\"\n  pane_history_bytes: {}\n  pane_history_human: \"{}\"\n  pane_history_lines: {}\n  command: \"{}\"",
-            row.pid,
-            row.tmux_target.replace('"', "\\\""),
-            row.tmux_window_name.replace('"', "\\\""),
-            row.swap_bytes,
-            human_bytes(row.swap_bytes).replace('"', "\\\""),
-            row.physical_bytes,
-            human_bytes(row.physical_bytes).replace('"', "\\\""),
-            row.rss_bytes,
-            human_bytes(row.rss_bytes).replace('"', "\\\""),
-            row.pane_history_bytes,
-            human_bytes(row.pane_history_bytes).replace('"', "\\\""),
-            history_lines,
-            row.command.replace('"', "\\\""),
+        let gib = 1024 * 1024 * 1024;
+        let samples = [
+            // Two days old at 4G, flat.
+            sample(200_000, 1, 4 * gib, Some(200_000 - 48 * 3600)),
+            sample(203_600, 1, 4 * gib, Some(200_000 - 48 * 3600)),
+            // One hour old at 1G.
+            sample(200_000, 2, gib / 2, Some(200_000)),
+            sample(203_600, 2, gib, Some(200_000)),
+            // No start time: uptime counts from the first sample.
+            sample(203_000, 3, gib / 8, None),
+            sample(203_600, 3, gib / 8, None),
+        ];
+        let trends = process_trends(&samples);
+        assert_eq!(
+            trends.iter().map(|t| t.pid).collect::<Vec<_>>(),
+            vec![2, 3, 1]
         );
+        assert_eq!(trends[0].lifetime_per_hour_bytes, gib);
+        assert_eq!(trends[0].uptime_secs, 3600);
+        assert!(!trends[1].uptime_from_start);
+        assert_eq!(trends[1].uptime_secs, 600);
+        assert_eq!(process_trend_cells(&trends[1])[3], "≥10m");
     }
-    out
-}
 
-fn render_markdown(rows: &[ProcRecord]) -> String {
-    let mut out = String::new();
-    out.push_str("| PID | Tmux window.pane | Window | Swap | Physical | RSS | PaneHistory | History lines | Command |\n");
-    out.push_str("|---:|---|---|---:|---:|---:|---:|---:|---|\n");
-    for row in rows {
-        let history_lines = if row.pane_history_size >= 0 {
-            format!("{}/{}", row.pane_history_size, row.pane_history_limit)
-        } else {
-            "-".to_string()
+    #[test]
+    fn yaml_document_style_wraps_records_with_summary() {
+        let rows = vec![
+            ProcRecord {
+                pid: 7,
+                rss_bytes: 300,
+                swap_bytes: 10,
+                ..ProcRecord::default()
+            },
+            ProcRecord {
+                pid: 8,
+                rss_bytes: 200,
+                ..ProcRecord::default()
+            },
+        ];
+        let opts = RenderOptions {
+            yaml_style: YamlStyle::Document,
+            ..RenderOptions::default()
         };
-        let cmd = row.command.replace('|', "\\|").replace('\n', " ");
-        let _ = writeln!(
-            out,
-            "| {} | {} | {} | {} | {} | {} | {} | {} | {} |",
-            row.pid,
-            row.tmux_target,
-            row.tmux_window_name,
-            human_bytes(row.swap_bytes),
-            human_bytes(row.physical_bytes),
-            human_bytes(row.rss_bytes),
-            human_bytes(row.pane_history_bytes),
-            history_lines,
-            cmd,
+        let yaml = render_process(&rows, OutputFormat::Yaml, &opts);
+        assert!(yaml.starts_with("---\ngenerated_at: \""), "{yaml}");
+        assert!(
+            yaml.contains("summary:\n  records: 2\n  swap_bytes: 10\n  physical_bytes: 0\n  rss_bytes: 500\nrecords:\n  - pid: 7\n"),
+            "{yaml}"
         );
+        assert!(!yaml.contains("  gpu_bytes: 0"), "{yaml}");
+        assert!(render_process(&[], OutputFormat::Yaml, &opts).ends_with("records: []\n"));
+        let sequence = render_process(&rows, OutputFormat::Yaml, &RenderOptions::default());
+        assert!(sequence.starts_with("---\n- pid: 7\n"), "{sequence}");
     }
-    out
-}
 
-fn render_pane_table(rows: &[PaneRecord]) -> String {
-    let mut out = String::new();
-    out.push_str("Tmux window.pane\tWindow\tProcesses\tPIDs\tSwap\tPhysical\tRSS\tPaneHistory\tHistory lines\n");
-    for row in rows {
-        let history_lines = if row.pane_history_size >= 0 {
-            format!("{}/{}", row.pane_history_size, row.pane_history_limit)
-        } else {
-            "-".to_string()
-        };
-        let pids = row
-            .pids
-            .iter()
-            .map(ToString::to_string)
-            .collect::<Vec<_>>()
-            .join(",");
-        let _ = writeln!(
-            out,
-            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
-            row.tmux_target,
-            row.tmux_window_name,
-            row.process_count,
-            pids,
-            human_bytes(row.swap_bytes),
-            human_bytes(row.physical_bytes),
-            human_bytes(row.rss_bytes),
-            human_bytes(row.pane_history_bytes),
-            history_lines,
+    #[test]
+    fn md_report_renders_summary_tables_and_recommendations() {
+        let rows = vec![ProcRecord {
+            pid: 7,
+            tmux_target: "ai:1.0".to_string(),
+            rss_bytes: 4 << 20,
+            ..ProcRecord::default()
+        }];
+        let panes = vec![
+            PaneRecord {
+                tmux_target: "ai:1.0".to_string(),
+                tmux_window_name: "docs".to_string(),
+                process_count: 1,
+                rss_bytes: 4 << 20,
+                pane_history_size: 1900,
+                pane_history_limit: 2000,
+                pane_history_bytes: 12 << 20,
+                ..PaneRecord::default()
+            },
+            PaneRecord {
+                tmux_target: "ai:2.0".to_string(),
+                tmux_window_name: "logs".to_string(),
+                process_count: 1,
+                rss_bytes: 1 << 20,
+                pane_history_bytes: 1 << 20,
+                ..PaneRecord::default()
+            },
+        ];
+        let md = render_md_report(&rows, &panes, 8 << 20, (0, "vm"), &RenderOptions::default());
+        assert!(md.starts_with(
+            "# opencode-tmux-mem report\n\nGenerated 1970-01-01T00:00:00Z on `vm`.\n"
+        ));
+        assert!(md.contains("- Processes: 1\n- Tmux panes: 2\n"), "{md}");
+        assert!(md.contains("## Panes\n\n| Tmux window.pane |"), "{md}");
+        assert!(md.contains("## Processes\n\n| PID |"), "{md}");
+        assert!(
+            md.contains("1. `ai:1.0` (docs): 4.00 MiB across 1 processes\n2. `ai:2.0`"),
+            "{md}"
+        );
+        assert!(
+            md.ends_with("- `ai:1.0` holds 12.00 MiB of scrollback (1900/2000 lines): `tmux clear-history -t ai:1.0`\n"),
+            "{md}"
         );
     }
 
-    let total_swap = rows.iter().map(|r| r.swap_bytes).sum::<u64>();
-    let total_phys = rows.iter().map(|r| r.physical_bytes).sum::<u64>();
-    let total_rss = rows.iter().map(|r| r.rss_bytes).sum::<u64>();
-    let total_hist = rows.iter().map(|r| r.pane_history_bytes).sum::<u64>();
+    #[test]
+    fn mail_message_sets_headers_by_format() {
+        let html = mail_message(
+            "me@example.com",
+            "nightly",
+            OutputFormat::Html,
+            "<p>hi</p>\n",
+        );
+        assert_eq!(
+            html,
+            "To: me@example.com\nSubject: nightly\nMIME-Version: 1.0\nContent-Type: text/html; charset=utf-8\nContent-Transfer-Encoding: 8bit\n\n<p>hi</p>\n"
+        );
+        let md = mail_message(
+            "me@example.com",
+            "nightly",
+            OutputFormat::MdReport,
+            "# hi\n",
+        );
+        assert!(md.contains("Content-Type: text/plain; charset=utf-8\n"));
+        assert_eq!(
+            parse_config("sendmail: msmtp -a nightly\n")
+                .unwrap()
+                .sendmail
+                .as_deref(),
+            Some("msmtp -a nightly")
+        );
+    }
 
-    out.push('\n');
-    let _ = writeln!(out, "Total swap:\t{}", human_bytes(total_swap));
-    let _ = writeln!(out, "Total physical:\t{}", human_bytes(total_phys));
-    let _ = writeln!(out, "Total RSS:\t{}", human_bytes(total_rss));
-    let _ = writeln!(
-        out,
-        "Total pane history bytes:\t{}",
-        human_bytes(total_hist)
-    );
-    out
-}
+    #[test]
+    fn pane_svg_stacks_swap_and_physical_largest_first() {
+        let panes = vec![
+            PaneRecord {
+                tmux_target: "ai:0.0".to_string(),
+                tmux_window_name: "small".to_string(),
+                rss_bytes: 45,
+                ..PaneRecord::default()
+            },
+            PaneRecord {
+                tmux_target: "ai:1.0".to_string(),
+                tmux_window_name: "<big>".to_string(),
+                swap_bytes: 30,
+                physical_bytes: 60,
+                rss_bytes: 70,
+                ..PaneRecord::default()
+            },
+        ];
+        let svg = render_pane_svg(&panes, 0);
+        assert!(svg
+            .starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"720\" height=\"114\""));
+        assert!(svg.ends_with("</svg>\n"));
+        let big = svg.find("ai:1.0 &lt;big&gt;").unwrap();
+        assert!(big < svg.find("ai:0.0 small").unwrap());
+        // 450px of plot: the 90-byte pane fills it, 30 of it swap.
+        assert!(svg.contains("y=\"56\" width=\"150\" height=\"18\" fill=\"#c0392b\"/><rect x=\"330\" y=\"56\" width=\"300\""));
+        assert!(svg.contains("y=\"80\" width=\"225\" height=\"18\" fill=\"#2e86c1\""));
+        assert_eq!(infer_format_from_path("chart.svg"), Some(OutputFormat::Svg));
+    }
 
-fn render_pane_json(rows: &[PaneRecord]) -> String {
-    let mut out = String::new();
-    out.push_str("[\n");
-    for (idx, row) in rows.iter().enumerate() {
-        let comma = if idx + 1 == rows.len() { "" } else { "," };
-        let history_lines = if row.pane_history_size >= 0 {
-            format!("\"{}/{}\"", row.pane_history_size, row.pane_history_limit)
-        } else {
-            "null".to_string()
+    #[test]
+    fn bars_follow_their_metric_column_scaled_to_the_largest_row() {
+        assert_eq!(bar(100, 100, TableStyle::Plain), "█".repeat(20));
+        assert_eq!(bar(33, 100, TableStyle::Plain), "██████▌");
+        assert_eq!(bar(33, 100, TableStyle::Ascii), "#######");
+        assert_eq!(bar(0, 100, TableStyle::Plain), "");
+        assert_eq!(bar(0, 0, TableStyle::Plain), "");
+
+        let panes = vec![
+            PaneRecord {
+                tmux_target: "ai:0.0".to_string(),
+                swap_bytes: 10,
+                ..PaneRecord::default()
+            },
+            PaneRecord {
+                tmux_target: "ai:1.0".to_string(),
+                swap_bytes: 40,
+                ..PaneRecord::default()
+            },
+        ];
+        let opts = RenderOptions {
+            bars: Some(Metric::Swap),
+            table_style: TableStyle::Ascii,
+            ..RenderOptions::default()
         };
-        let pids = row
-            .pids
+        let table = render_pane_table(&panes, None, &opts);
+        let lines = table.lines().collect::<Vec<_>>();
+        assert!(
+            lines[1].contains("| Swap | Swap bar             | Physical |"),
+            "{table}"
+        );
+        assert!(
+            lines[3].contains("| 10 B | #####                |"),
+            "{table}"
+        );
+        assert!(
+            lines[4].contains("| 40 B | #################### |"),
+            "{table}"
+        );
+    }
+
+    #[test]
+    fn percent_shares_split_the_report_totals() {
+        let mut rows = vec![
+            ProcRecord {
+                pid: 1,
+                tmux_target: "ai:0.0".to_string(),
+                swap_bytes: 70,
+                ..ProcRecord::default()
+            },
+            ProcRecord {
+                pid: 2,
+                tmux_target: "ai:1.0".to_string(),
+                swap_bytes: 30,
+                ..ProcRecord::default()
+            },
+        ];
+        let mut panes = aggregate_by_pane(&rows);
+        apply_shares(&mut rows, &mut panes);
+        assert_eq!(rows[0].swap_pct, Some(70.0));
+        assert_eq!(rows[1].physical_pct, None);
+        let pane = panes.iter().find(|p| p.tmux_target == "ai:1.0").unwrap();
+        assert_eq!(pane.swap_pct, Some(30.0));
+
+        let opts = RenderOptions {
+            percent: true,
+            ..RenderOptions::default()
+        };
+        let table = render_pane_table(&panes, None, &opts);
+        let header = table.lines().next().unwrap();
+        assert!(
+            header.contains("Swap\tSwap %\tPhysical\tPhysical %\tRSS"),
+            "{table}"
+        );
+        assert!(table.contains("30 B\t30.0%\t0 B\t-\t"), "{table}");
+        let json = render_process(&rows, OutputFormat::Json, &opts);
+        assert!(
+            json.contains("\"swap_pct\":70.0,\"physical_pct\":null"),
+            "{json}"
+        );
+    }
+
+    #[test]
+    fn cumulative_share_runs_down_the_sorted_table() {
+        let mut rows = [60, 30, 10, 0]
             .iter()
-            .map(ToString::to_string)
-            .collect::<Vec<_>>()
-            .join(",");
-        let _ = writeln!(
-            out,
-            "  {{\"tmux_target\":\"{}\",\"tmux_window\":\"{}\",\"process_count\":{},\"pids\":[{}],\"swap_bytes\":{},\"swap_human\":\"{}\",\"physical_bytes\":{},\"physical_human\":\"{}\",\"rss_bytes\":{},\"rss_human\":\"{}\",\"pane_history_bytes\":{},\"pane_history_human\":\"{}\",\"pane_history_lines\":{}}}{}",
-            escape_json(&row.tmux_target),
-            escape_json(&row.tmux_window_name),
-            row.process_count,
-            pids,
-            row.swap_bytes,
-            escape_json(&human_bytes(row.swap_bytes)),
-            row.physical_bytes,
-            escape_json(&human_bytes(row.physical_bytes)),
-            row.rss_bytes,
-            escape_json(&human_bytes(row.rss_bytes)),
-            row.pane_history_bytes,
-            escape_json(&human_bytes(row.pane_history_bytes)),
-            history_lines,
-            comma,
+            .enumerate()
+            .map(|(idx, swap)| ProcRecord {
+                pid: idx as i32 + 1,
+                tmux_target: format!("ai:{idx}.0"),
+                swap_bytes: *swap,
+                ..ProcRecord::default()
+            })
+            .collect::<Vec<_>>();
+        let mut panes = aggregate_by_pane(&rows);
+        apply_shares(&mut rows, &mut panes);
+        let opts = RenderOptions {
+            cumulative: true,
+            ..RenderOptions::default()
+        };
+        let table = render_pane_table(&panes, None, &opts);
+        let cells = table
+            .lines()
+            .take(5)
+            .map(|line| line.split('\t').nth(5).unwrap_or_default())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            cells,
+            ["Cum. swap %", "60.0%", "90.0%", "100.0%", "100.0%"],
+            "{table}"
+        );
+        assert!(!table.contains("Physical %"));
+    }
+
+    #[test]
+    fn adaptive_watch_interval_backs_off_and_speeds_up() {
+        let base = Duration::from_secs(10);
+        // Stable: grows by half, up to eight times --watch.
+        assert_eq!(
+            adapt_interval(base, base, 1000, 1005),
+            Duration::from_secs(15)
+        );
+        assert_eq!(
+            adapt_interval(Duration::from_secs(70), base, 1000, 1000),
+            Duration::from_secs(80)
+        );
+        // Fast growth halves it, down to a quarter of --watch.
+        assert_eq!(
+            adapt_interval(Duration::from_secs(80), base, 1000, 1100),
+            Duration::from_secs(40)
+        );
+        assert_eq!(
+            adapt_interval(Duration::from_secs(3), base, 1000, 2000),
+            Duration::from_millis(2500)
+        );
+        // In between (or shrinking) keeps the current pace.
+        assert_eq!(
+            adapt_interval(Duration::from_secs(20), base, 1000, 1030),
+            Duration::from_secs(20)
+        );
+        assert_eq!(
+            adapt_interval(Duration::from_secs(20), base, 1000, 900),
+            Duration::from_secs(20)
+        );
+        assert_eq!(
+            adapt_interval(Duration::from_secs(2), Duration::from_secs(2), 1000, 5000),
+            Duration::from_secs(1)
         );
     }
-    out.push_str("]\n");
-    out
-}
 
-fn render_pane_csv(rows: &[PaneRecord]) -> String {
-    let mut out = String::new();
-    out.push_str("tmux_target,tmux_window,process_count,pids,swap_bytes,swap_human,physical_bytes,physical_human,rss_bytes,rss_human,pane_history_bytes,pane_history_human,pane_history_lines\n");
-    for row in rows {
-        let history_lines = if row.pane_history_size >= 0 {
-            format!("{}/{}", row.pane_history_size, row.pane_history_limit)
-        } else {
-            String::new()
-        };
-        let pids = row
-            .pids
-            .iter()
-            .map(ToString::to_string)
-            .collect::<Vec<_>>()
-            .join(",");
-        let _ = writeln!(
-            out,
-            "{},{},{},{},{},{},{},{},{},{},{},{},{}",
-            escape_csv(&row.tmux_target),
-            escape_csv(&row.tmux_window_name),
-            row.process_count,
-            escape_csv(&pids),
-            row.swap_bytes,
-            escape_csv(&human_bytes(row.swap_bytes)),
-            row.physical_bytes,
-            escape_csv(&human_bytes(row.physical_bytes)),
-            row.rss_bytes,
-            escape_csv(&human_bytes(row.rss_bytes)),
-            row.pane_history_bytes,
-            escape_csv(&human_bytes(row.pane_history_bytes)),
-            escape_csv(&history_lines),
+    #[test]
+    fn pressure_message_names_the_largest_pane() {
+        let rows = vec![
+            ProcRecord {
+                pid: 1,
+                tmux_target: "ai:0.0".to_string(),
+                rss_bytes: 1 << 20,
+                ..ProcRecord::default()
+            },
+            ProcRecord {
+                pid: 2,
+                tmux_target: "ai:1.0".to_string(),
+                swap_bytes: 1 << 30,
+                physical_bytes: 1 << 30,
+                ..ProcRecord::default()
+            },
+            ProcRecord {
+                pid: 3,
+                tmux_target: "?".to_string(),
+                rss_bytes: 4 << 30,
+                ..ProcRecord::default()
+            },
+        ];
+        assert_eq!(
+            pressure_message(&rows),
+            "Memory pressure: 3 processes hold 6.00 GiB; largest pane ai:1.0 (2.00 GiB)"
+        );
+        assert_eq!(
+            pressure_message(&[]),
+            "Memory pressure: 0 processes hold 0 B"
         );
     }
-    out
-}
 
-fn render_pane_yaml(rows: &[PaneRecord]) -> String {
-    let mut out = String::new();
-    out.push_str("---\n");
-    for row in rows {
-        let history_lines = if row.pane_history_size >= 0 {
-            format!("\"{}/{}\"", row.pane_history_size, row.pane_history_limit)
-        } else {
-            "null".to_string()
+    #[test]
+    fn verify_flags_footprint_sources_that_disagree() {
+        assert_eq!(
+            parse_footprint_tool(
+                "======\nopencode [4242]: 64-bit    Footprint: 312 MB (16384 bytes per page)\n======\n"
+            ),
+            Some(312 << 20)
+        );
+        assert_eq!(parse_footprint_tool("footprint: no such process"), None);
+
+        let row = |vmmap, tool| verify_process(7, "opencode".into(), 500, vmmap, tool, 10.0);
+        let ok = row(Some(1000), Some(950));
+        assert_eq!((ok.status, ok.spread_pct), ("ok", Some(5.0)));
+        let off = row(Some(1000), Some(800));
+        assert_eq!((off.status, off.spread_pct), ("mismatch", Some(20.0)));
+        assert_eq!(row(Some(1000), None).status, "unverified");
+
+        let table = render_verify_table(&[off, ok], 10.0, &RenderOptions::default());
+        assert!(
+            table.contains("7\t500 B\t1000 B\t800 B\t20.0%\tmismatch\topencode"),
+            "{table}"
+        );
+        assert!(table
+            .ends_with("1 of 2 processes: vmmap and footprint(1) disagree by more than 10%.\n"));
+    }
+
+    #[test]
+    fn config_headers_relabel_table_csv_and_markdown() {
+        let config =
+            parse_config("headers:\n  swap: \"Compressed+Swap\"\n  Tmux window.pane: Pane\n")
+                .expect("valid config");
+        let labels = config.headers;
+        assert_eq!(header_label(&labels, "Swap"), Some("Compressed+Swap"));
+        assert_eq!(header_label(&labels, "swap_bytes"), Some("Compressed+Swap"));
+        assert_eq!(header_label(&labels, "tmux_target"), None);
+        assert_eq!(header_label(&labels, "Swap %"), None);
+        assert!(parse_config("headers:\n  swap: \"\"\n").is_err());
+
+        let opts = RenderOptions {
+            header_labels: labels.clone(),
+            ..RenderOptions::default()
         };
-        let pids = row
-            .pids
-            .iter()
-            .map(ToString::to_string)
-            .collect::<Vec<_>>()
-            .join(", ");
-        let _ = writeln!(
-            out,
-            "- tmux_target: \"{}\"\n  tmux_window: \"{}\"\n  process_count: {}\n  pids: [{}]\n  swap_bytes: {}\n  swap_human: \"{}\"\n  physical_bytes: {}\n  physical_human: \"{}\"\n  rss_bytes: {}\n  rss_human: \"{}\"\n  pane_history_bytes: {}\n  pane_history_human: \"{}\"\n  pane_history_lines: {}",
-            row.tmux_target.replace('"', "\\\""),
-            row.tmux_window_name.replace('"', "\\\""),
-            row.process_count,
-            pids,
-            row.swap_bytes,
-            human_bytes(row.swap_bytes).replace('"', "\\\""),
-            row.physical_bytes,
-            human_bytes(row.physical_bytes).replace('"', "\\\""),
-            row.rss_bytes,
-            human_bytes(row.rss_bytes).replace('"', "\\\""),
-            row.pane_history_bytes,
-            human_bytes(row.pane_history_bytes).replace('"', "\\\""),
-            history_lines,
+        let table = TableLayout {
+            header: vec!["Tmux window.pane", "Swap"],
+            rows: vec![table_row(vec!["s:1.0".into(), "1 MiB".into()])],
+            optional: Vec::new(),
+        };
+        assert!(render_layout(&table, &opts).starts_with("Pane\tCompressed+Swap\n"));
+
+        let csv = relabel_headers(
+            OutputFormat::Csv,
+            "pid,swap_bytes\n1,2\n".to_string(),
+            &labels,
+        );
+        assert_eq!(csv, "pid,Compressed+Swap\n1,2\n");
+        let md = relabel_headers(
+            OutputFormat::Markdown,
+            "| Tmux window.pane | Swap |\n|---|---:|\n| Swap | 1 MiB |\n".to_string(),
+            &labels,
+        );
+        assert_eq!(
+            md,
+            "| Pane | Compressed+Swap |\n|---|---:|\n| Swap | 1 MiB |\n"
         );
     }
-    out
-}
 
-fn render_pane_markdown(rows: &[PaneRecord]) -> String {
-    let mut out = String::new();
-    out.push_str("| Tmux window.pane | Window | Processes | PIDs | Swap | Physical | RSS | PaneHistory | History lines |\n");
-    out.push_str("|---|---|---:|---|---:|---:|---:|---:|---:|\n");
-    for row in rows {
-        let history_lines = if row.pane_history_size >= 0 {
-            format!("{}/{}", row.pane_history_size, row.pane_history_limit)
-        } else {
-            "-".to_string()
+    #[test]
+    fn duplicate_top_level_apps_in_a_pane_are_flagged() {
+        let row = |pid, target: &str, tag, parent| ProcRecord {
+            pid,
+            command: "opencode".to_string(),
+            tmux_target: target.to_string(),
+            tag: Some(tag),
+            app_parent_pid: parent,
+            ..ProcRecord::default()
         };
-        let pids = row
-            .pids
+        let mut rows = vec![
+            row(1, "s:1.0", "app", None),
+            row(2, "s:1.0", "app", None),
+            row(3, "s:1.0", "helper", Some(1)),
+            row(4, "s:2.0", "app", None),
+        ];
+        let panes = aggregate_by_pane(&rows);
+        let instances = |target: &str| {
+            panes
+                .iter()
+                .find(|p| p.tmux_target == target)
+                .map(|p| p.app_instances)
+        };
+        assert_eq!((instances("s:1.0"), instances("s:2.0")), (Some(2), Some(1)));
+
+        let mut warned = Vec::new();
+        flag_duplicate_instances(&mut rows, &panes, &mut warned);
+        let flagged = rows
             .iter()
-            .map(ToString::to_string)
-            .collect::<Vec<_>>()
-            .join(",");
-        let _ = writeln!(
-            out,
-            "| {} | {} | {} | {} | {} | {} | {} | {} | {} |",
-            row.tmux_target,
-            row.tmux_window_name,
-            row.process_count,
-            pids,
-            human_bytes(row.swap_bytes),
-            human_bytes(row.physical_bytes),
-            human_bytes(row.rss_bytes),
-            human_bytes(row.pane_history_bytes),
-            history_lines,
-        );
+            .filter(|r| r.duplicate_instance)
+            .map(|r| r.pid)
+            .collect::<Vec<_>>();
+        assert_eq!(flagged, vec![1, 2]);
+        assert_eq!(warned, [vec![1, 2]]);
+        // The next tick flags the rows again but doesn't repeat the warning.
+        flag_duplicate_instances(&mut rows, &panes, &mut warned);
+        assert_eq!(warned, [vec![1, 2]]);
+        rows[1].tmux_target = "s:3.0".to_string();
+        let moved = aggregate_by_pane(&rows);
+        flag_duplicate_instances(&mut rows, &moved, &mut warned);
+        assert!(warned.is_empty());
+        rows[1].tmux_target = "s:1.0".to_string();
+        let table = render_table(&rows, &RenderOptions::default());
+        assert!(table.lines().next().unwrap().contains("\tDuplicate\tTag\t"));
+        let markdown = render_markdown(&rows, &RenderOptions::default());
+        assert!(markdown.contains("| History lines | Duplicate | Tag |"));
+        assert!(markdown.contains("| yes | app |"), "{markdown}");
+        let panes_table = render_pane_table(&panes, None, &RenderOptions::default());
+        assert!(panes_table.starts_with("Tmux window.pane\tWindow\tProcesses\tInstances\tPIDs"));
     }
-    out
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn orphans_only_keeps_processes_outside_every_pane() {
+        assert!(is_reported_owner(true, false, false));
+        assert!(is_reported_owner(false, false, false));
+        assert!(!is_reported_owner(false, true, false));
+        assert!(is_reported_owner(false, false, true));
+        assert!(!is_reported_owner(true, false, true));
+    }
 
     #[test]
-    fn parse_compact_bytes_supports_units() {
-        assert_eq!(parse_compact_bytes("1024"), 1024);
-        assert_eq!(parse_compact_bytes("1K"), 1024);
-        assert_eq!(parse_compact_bytes("1.5M"), 1_572_864);
-        assert_eq!(parse_compact_bytes("2G"), 2_147_483_648);
+    fn socket_discovery_lists_only_sockets_and_tags_their_panes() {
+        let dir = env::temp_dir().join(format!("otm-sockets-{}", std::process::id()));
+        fs::create_dir_all(&dir).expect("create dir");
+        let socket = dir.join("work");
+        let _listener = std::os::unix::net::UnixListener::bind(&socket).expect("bind socket");
+        fs::write(dir.join("notes.txt"), "not a socket").expect("write file");
+        let found = tmux_sockets_in(&[dir.display().to_string(), "/nonexistent".to_string()]);
+        let _ = fs::remove_dir_all(&dir);
+        assert_eq!(found, vec![socket.display().to_string()]);
+
+        let panes = parse_tmux_panes("w:1.0\tai\t42\t10\t2000\nbad\n", Some("/tmp/s"));
+        assert_eq!(panes.len(), 1);
+        assert_eq!(panes[0].pane_pid, 42);
+        assert_eq!(panes[0].socket.as_deref(), Some("/tmp/s"));
+
+        // Per-pane queries and planned tmux actions go to the pane's own server.
+        let records = [
+            PaneRecord {
+                tmux_target: "w:1.0".to_string(),
+                tmux_socket: Some("/tmp/s".to_string()),
+                ..PaneRecord::default()
+            },
+            PaneRecord {
+                tmux_target: "?".to_string(),
+                ..PaneRecord::default()
+            },
+        ];
+        assert_eq!(tmux_servers(&records), [Some("/tmp/s".to_string())]);
+        let mut actions = [PlannedAction {
+            action: "mark-pane",
+            target: "pane w:1.0".to_string(),
+            program: "tmux",
+            args: ["set-option", "-p", "-t", "w:1.0"]
+                .map(String::from)
+                .to_vec(),
+            metric: "footprint",
+            before: 0,
+        }];
+        on_tmux_server(&mut actions, Some("/tmp/s"));
+        assert_eq!(actions[0].args[..3], ["-S", "/tmp/s", "set-option"]);
     }
 
     #[test]
-    fn infer_export_format_from_extension() {
+    fn control_mode_replies_are_read_in_command_order() {
+        let stream = "%begin 1700000000 10 0\n%end 1700000000 10 0\n%session-changed $1 ai\n\
+            %begin 1700000000 11 1\nai:0.0\tai-main\n%end 1700000000 9 1\n%end 1700000000 11 1\n\
+            %begin 1700000000 12 1\ncan't find pane: zz\n%error 1700000000 12 1\n";
+        let replies = read_control_replies(&mut io::Cursor::new(stream), 2).expect("two replies");
         assert_eq!(
-            infer_format_from_path("report.json"),
-            Some(OutputFormat::Json)
-        );
-        assert_eq!(
-            infer_format_from_path("report.csv"),
-            Some(OutputFormat::Csv)
-        );
-        assert_eq!(
-            infer_format_from_path("report.yaml"),
-            Some(OutputFormat::Yaml)
+            replies,
+            vec![
+                Ok("ai:0.0\tai-main\n%end 1700000000 9 1\n".to_string()),
+                Err("can't find pane: zz".to_string()),
+            ]
         );
+        let truncated = "%begin 1700000000 11 1\npartial\n";
+        assert!(read_control_replies(&mut io::Cursor::new(truncated), 1).is_err());
+    }
+
+    #[test]
+    fn json_log_lines_carry_level_phase_pid_and_duration() {
         assert_eq!(
-            infer_format_from_path("report.md"),
-            Some(OutputFormat::Markdown)
+            log_line(
+                0,
+                "info",
+                "collect",
+                42,
+                "collected 3 processes",
+                Some(Duration::from_millis(1250))
+            ),
+            "{\"ts\":\"1970-01-01T00:00:00Z\",\"level\":\"info\",\"phase\":\"collect\",\"pid\":42,\"message\":\"collected 3 processes\",\"duration_ms\":1250}"
         );
-        assert_eq!(infer_format_from_path("report.txt"), None);
+        assert!(log_line(0, "warn", "export", 1, "bad \"path\"", None)
+            .ends_with("\"message\":\"bad \\\"path\\\"\",\"duration_ms\":null}"));
     }
 
     #[test]
-    fn csv_escape_quotes_and_commas() {
-        let got = escape_csv("a,\"b\"");
-        assert_eq!(got, "\"a,\"\"b\"\"\"");
+    fn retries_back_off_only_while_the_failure_is_transient() {
+        let mut calls = 0;
+        let flaky = with_retries(
+            2,
+            || {
+                calls += 1;
+                match calls {
+                    1 => Err(io::Error::other("server exited unexpectedly")),
+                    _ => Ok(calls),
+                }
+            },
+            transient_tmux_error,
+        );
+        assert_eq!(flaky.ok(), Some(2));
+
+        let mut calls = 0;
+        let no_server = with_retries(
+            2,
+            || -> io::Result<()> {
+                calls += 1;
+                Err(io::Error::other("no server running on /tmp/tmux-0/default"))
+            },
+            transient_tmux_error,
+        );
+        assert!(no_server.is_err());
+        assert_eq!(calls, 1);
+        assert!(!transient_tmux_error(&io::Error::other(
+            "command failed: tmux capture-pane -p -J -t ai:1.0 => can't find pane: ai:1.0"
+        )));
+        assert!(vmmap_denied(&io::Error::other(
+            "vmmap cannot examine process 4242 because you do not have appropriate privileges"
+        )));
+        assert!(!vmmap_denied(&io::Error::other("vmmap timed out")));
+
+        let mut calls = 0;
+        let _ = with_retries(
+            2,
+            || -> io::Result<()> {
+                calls += 1;
+                Err(io::Error::other("busy"))
+            },
+            |_| true,
+        );
+        assert_eq!(calls, 3);
+        assert!(process_alive(std::process::id() as i32));
     }
 
     #[test]
-    fn parse_view_mode_supports_process_and_pane() {
-        assert_eq!(parse_view_mode("process"), Ok(ViewMode::Process));
-        assert_eq!(parse_view_mode("pane"), Ok(ViewMode::Pane));
+    fn prometheus_metrics_skip_unknown_samples() {
+        let metrics = SelfMetrics {
+            subprocesses: 7,
+            wall: Duration::from_millis(1500),
+            peak_rss_bytes: None,
+        };
+        let text = render_prometheus(&metrics, 3, Some(Duration::from_millis(250)));
+        assert!(text.contains("# TYPE opencode_tmux_mem_collections_total counter\n"));
+        assert!(text.contains("\nopencode_tmux_mem_collections_total 3\n"));
+        assert!(text.contains("\nopencode_tmux_mem_subprocesses_total 7\n"));
+        assert!(text.contains("\nopencode_tmux_mem_uptime_seconds 1.5\n"));
+        assert!(text.contains("\nopencode_tmux_mem_last_collection_seconds 0.25\n"));
+        assert!(!text.contains("peak_rss_bytes"));
+        let fields = self_metric_fields(&metrics);
+        assert_eq!(fields[1], ("self_wall_ms", Field::Uint(1500)));
+        assert_eq!(fields[2], ("self_peak_rss_bytes", Field::Null));
     }
 
     #[test]
-    fn aggregate_by_pane_sums_process_memory() {
-        let rows = vec![
-            ProcRecord {
-                pid: 1,
-                command: "a".to_string(),
-                swap_bytes: 100,
-                physical_bytes: 200,
+    fn pane_detail_lists_members_under_their_pane() {
+        let panes = vec![
+            PaneRecord {
+                tmux_target: "ai:0.0".to_string(),
+                pids: vec![10, 11],
                 rss_bytes: 300,
-                tmux_target: "s:1.0".to_string(),
-                tmux_window_name: "w".to_string(),
-                pane_history_size: 10,
-                pane_history_limit: 100,
-                pane_history_bytes: 1000,
+                ..PaneRecord::default()
             },
-            ProcRecord {
-                pid: 2,
-                command: "b".to_string(),
-                swap_bytes: 50,
-                physical_bytes: 70,
-                rss_bytes: 90,
-                tmux_target: "s:1.0".to_string(),
-                tmux_window_name: "w".to_string(),
-                pane_history_size: 11,
-                pane_history_limit: 100,
-                pane_history_bytes: 900,
+            PaneRecord {
+                tmux_target: "ai:1.0".to_string(),
+                pids: vec![20],
+                rss_bytes: 50,
+                ..PaneRecord::default()
             },
         ];
+        let member = |pid, rss_bytes| ProcRecord {
+            pid,
+            command: format!("opencode {pid}"),
+            rss_bytes,
+            ..ProcRecord::default()
+        };
+        let rows = vec![member(11, 200), member(20, 50), member(10, 100)];
+        let table = render_pane_table(&panes, Some(&rows), &RenderOptions::default());
+        let lines = table.lines().skip(1).take(5).collect::<Vec<_>>();
+        assert!(lines[0].starts_with("ai:0.0\t"), "{table}");
+        assert!(lines[1].starts_with("  11 opencode 11\t"), "{table}");
+        assert!(lines[1].ends_with("\t200 B\t\t"), "{table}");
+        assert!(lines[2].starts_with("  10 opencode 10\t"), "{table}");
+        assert!(lines[3].starts_with("ai:1.0\t"), "{table}");
+        assert!(lines[4].starts_with("  20 opencode 20\t"), "{table}");
+        assert!(table.contains("Total RSS:\t350 B"), "{table}");
+    }
+
+    #[test]
+    fn help_json_matches_the_parsers() {
+        let help = help_text();
+        assert!(
+            help.contains("\n  --process <pattern>         Process pattern (default: opencode)")
+        );
+        assert!(help.contains("\n  --match-mode <exact|full|glob|full-word>\n                              PID scan mode"));
+        let json = render_help_json();
+        let option = |flag: &str| {
+            let line = json
+                .lines()
+                .find(|l| l.contains(&format!("\"flags\":[\"{flag}\"]")))
+                .unwrap_or_else(|| panic!("{flag} missing"));
+            parse_json_object(line.trim().trim_end_matches(',')).unwrap()
+        };
+        let choices = |flag: &str| match option(flag).remove("choices") {
+            Some(Field::StrList(list)) => list,
+            other => panic!("{other:?}"),
+        };
+        for format in choices("--format") {
+            assert!(parse_format(&format).is_ok(), "{format}");
+        }
+        for view in choices("--view") {
+            assert!(parse_view_mode(&view).is_ok(), "{view}");
+        }
+        for mode in choices("--match-mode") {
+            assert!(parse_match_mode(&mode).is_some(), "{mode}");
+        }
+        let process = option("--process");
+        assert_eq!(process["value"], Field::Str("pattern".to_string()));
+        assert_eq!(process["default"], Field::Str("opencode".to_string()));
+        assert_eq!(process["repeatable"], Field::Bool(true));
+        assert_eq!(option("--bars")["value"], Field::Null);
+        assert!(json.contains("{\"flags\":[\"-y\",\"--yes\"],"));
+    }
+
+    #[test]
+    fn exit_code_ranks_failures_over_breaches_over_gaps() {
+        let mut status = RunStatus {
+            records: Some(1),
+            totals: None,
+            warnings: Vec::new(),
+            breached: false,
+            partial: false,
+        };
+        assert_eq!(exit_code(&status, false, false), 0);
+        status.partial = true;
+        assert_eq!(exit_code(&status, false, false), EXIT_PARTIAL);
+        status.breached = true;
+        assert_eq!(exit_code(&status, false, false), EXIT_THRESHOLD);
+        assert_eq!(exit_code(&status, true, false), EXIT_ERROR);
+        assert_eq!(exit_code(&status, true, true), EXIT_USAGE);
+        let line = status_line(&status, None, EXIT_THRESHOLD, Duration::ZERO);
+        assert!(line.starts_with("{\"exit_reason\":\"threshold\",\"exit_code\":3,\"error\":null,"));
+    }
 
+    #[test]
+    fn failed_history_capture_is_flagged_not_zero() {
+        let row = |pid, target: &str, history_measured| ProcRecord {
+            pid,
+            tmux_target: target.to_string(),
+            history_measured,
+            ..ProcRecord::default()
+        };
+        let rows = vec![
+            row(1, "ai:0.0", Some(false)),
+            row(2, "ai:1.0", Some(true)),
+            row(3, "?", None),
+        ];
+        let field = |fields: Vec<(&'static str, Field)>| {
+            fields
+                .into_iter()
+                .find(|(name, _)| *name == "history_measured")
+                .map(|(_, value)| value)
+        };
+        assert_eq!(field(process_fields(&rows[0])), Some(Field::Bool(false)));
         let panes = aggregate_by_pane(&rows);
-        assert_eq!(panes.len(), 1);
-        assert_eq!(panes[0].process_count, 2);
-        assert_eq!(panes[0].swap_bytes, 150);
-        assert_eq!(panes[0].physical_bytes, 270);
-        assert_eq!(panes[0].rss_bytes, 390);
-        assert_eq!(panes[0].pane_history_bytes, 1000);
+        let measured = |target: &str| {
+            let pane = panes.iter().find(|p| p.tmux_target == target).unwrap();
+            field(pane_fields(pane))
+        };
+        assert_eq!(measured("ai:0.0"), Some(Field::Bool(false)));
+        assert_eq!(measured("ai:1.0"), Some(Field::Bool(true)));
+        assert_eq!(measured("?"), Some(Field::Null));
+    }
+
+    #[test]
+    fn history_sample_extrapolates_skipped_lines() {
+        let sample = "abc\n".repeat(10);
+        assert_eq!(sampled_history_bytes(&sample, 100_000, None), 40);
+        // 8 history lines plus 2 on screen stand in for 99_992 more at 4 bytes each.
+        assert_eq!(sampled_history_bytes(&sample, 100_000, Some(8)), 400_008);
+        // A history shorter than the cap is captured whole.
+        assert_eq!(sampled_history_bytes(&sample, 5, Some(8)), 40);
+        assert_eq!(sampled_history_bytes("", 100_000, Some(8)), 0);
+        assert_eq!(capture_start(Some(500)), "-500");
+        assert_eq!(capture_start(None), "-");
+    }
+
+    #[test]
+    fn env_filters_need_every_exact_variable() {
+        let environ = ["OPENCODE_PROFILE=work", "EMPTY=", "PATH=/bin:/usr/bin"]
+            .map(str::to_string)
+            .to_vec();
+        let filter = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<Vec<_>>()
+        };
+        assert!(env_matches(
+            &environ,
+            &filter(&[("OPENCODE_PROFILE", "work")])
+        ));
+        assert!(env_matches(&environ, &filter(&[("EMPTY", "")])));
+        assert!(!env_matches(
+            &environ,
+            &filter(&[("OPENCODE_PROFILE", "wor")])
+        ));
+        assert!(!env_matches(
+            &environ,
+            &filter(&[("OPENCODE_PROFILE", "work"), ("MISSING", "1")])
+        ));
+        // An argument shaped like a variable is part of the command, not the environment.
+        let words = environ_words(
+            b"opencode --set OPENCODE_PROFILE=work  OPENCODE_PROFILE=home TERM=xterm\n",
+            b"opencode --set OPENCODE_PROFILE=work\n",
+        );
+        assert_eq!(
+            words.collect::<Vec<_>>(),
+            [&b"OPENCODE_PROFILE=home"[..], &b"TERM=xterm"[..]]
+        );
+        let mine = process_environ(std::process::id() as i32, &["PATH"]).unwrap();
+        assert!(mine.iter().any(|entry| entry.starts_with("PATH=")));
+        assert!(mine.iter().all(|entry| entry.starts_with("PATH=")));
+    }
+
+    #[test]
+    fn session_view_carries_age_and_attach_state() {
+        let raw = "ai:0.0\tmain\t42\t10\t2000\t1700000000\t0\n\
+                   ai:1.0\tdocs\t43\t0\t2000\t1700000000\t0\n\
+                   work:0.0\tsh\t44\t0\t2000\t1700000500\t2\n";
+        let infos = parse_tmux_panes(raw, None);
+        assert_eq!(infos[0].session_created, Some(1_700_000_000));
+        assert_eq!(infos[0].session_attached, Some(false));
+        assert_eq!(infos[2].session_attached, Some(true));
+        let panes = infos
+            .iter()
+            .map(|info| PaneRecord {
+                tmux_target: info.target.clone(),
+                rss_bytes: 100,
+                session_created: info.session_created,
+                session_attached: info.session_attached,
+                ..PaneRecord::default()
+            })
+            .chain([PaneRecord {
+                tmux_target: "?".to_string(),
+                ..PaneRecord::default()
+            }])
+            .collect::<Vec<_>>();
+        let groups = session_groups(&panes, &[]);
+        let keys = groups.iter().map(|g| g.key.as_str()).collect::<Vec<_>>();
+        assert_eq!(keys, ["ai", "work", "-"]);
+        assert_eq!(groups[0].pane_count, 2);
+        assert_eq!(groups[1].session_attached, Some(true));
+        assert_eq!(groups[2].session_created, None);
+        let json = render_groups(
+            &groups,
+            ("Session", "session"),
+            "tmux session",
+            OutputFormat::Json,
+            &RenderOptions::default(),
+        );
+        assert!(json.contains("\"session\":\"ai\",\"session_age_secs\":"));
+        assert!(json.contains("\"session_attached\":false"));
+        assert_eq!(session_attached_cell(None), "-");
+    }
+
+    #[test]
+    fn group_rows_count_distinct_commands() {
+        let proc = |pid: i32, name: &str| ProcRecord {
+            pid,
+            command_name: name.to_string(),
+            ..ProcRecord::default()
+        };
+        let rows = [
+            proc(1, "node"),
+            proc(2, "zsh"),
+            proc(3, "node"),
+            proc(4, "opencode"),
+            proc(5, "node"),
+        ];
+        let pane = |target: &str, pids: Vec<i32>| PaneRecord {
+            tmux_target: target.to_string(),
+            process_count: pids.len(),
+            pids,
+            ..PaneRecord::default()
+        };
+        let panes = [pane("a:1.0", vec![1, 2, 3]), pane("a:2.0", vec![4, 5])];
+        let groups = group_panes(&panes, &rows, |_| Some("all".to_string()));
+        assert_eq!(
+            command_counts(&groups[0].commands),
+            "3× node, 1× opencode, 1× zsh"
+        );
+        let md = render_groups(
+            &groups,
+            ("Group", "group"),
+            "test",
+            OutputFormat::Markdown,
+            &RenderOptions::default(),
+        );
+        assert!(md.contains("| 3× node, 1× opencode, 1× zsh |"));
+        let render = |fmt| {
+            render_groups(
+                &groups,
+                ("Group", "group"),
+                "test",
+                fmt,
+                &RenderOptions::default(),
+            )
+        };
+        assert!(render(OutputFormat::Json).contains(
+            r#""commands":[{"command":"node","count":3},{"command":"opencode","count":1},{"command":"zsh","count":1}]"#
+        ));
+        assert!(render(OutputFormat::Yaml).contains(
+            r#"commands: [{command: "node", count: 3}, {command: "opencode", count: 1}, {command: "zsh", count: 1}]"#
+        ));
+        assert!(render(OutputFormat::Csv).contains(r#""[{""command"":""node"",""count"":3},"#));
+    }
+
+    #[test]
+    fn bundles_replay_captured_outputs_in_order() {
+        let dir = env::temp_dir().join(format!("otm-bundle-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("index.tsv"), "").unwrap();
+        let next = Mutex::new(0);
+        let key = bundle_key("tmux", &["list-panes", "-F", "#{pane_id}\t#{pane_pid}"]);
+        assert!(!key.contains('\t'));
+        let output = |code: i32, stdout: &str| {
+            Ok(Output {
+                status: std::process::ExitStatus::from_raw(code << 8),
+                stdout: stdout.as_bytes().to_vec(),
+                stderr: Vec::new(),
+            })
+        };
+        capture_output(&dir, &next, &key, &output(0, "%1\t42\n")).unwrap();
+        capture_output(&dir, &next, &key, &output(1, "")).unwrap();
+        let missing = Err(io::Error::new(io::ErrorKind::NotFound, "no vmmap"));
+        capture_output(&dir, &next, "vmmap --summary 42", &missing).unwrap();
+        // /proc reads are recorded like commands, keyed by the path.
+        let stat = escape_bundle_line("< /proc/42/stat");
+        capture_output(&dir, &next, &stat, &output(0, "42 (node) S 1")).unwrap();
+
+        let mut entries = load_bundle(&dir).unwrap();
+        let runs = entries.get_mut(&key).unwrap();
+        let first = bundle_output(runs.pop_front().unwrap()).unwrap();
+        assert_eq!(
+            (first.status.code(), first.stdout),
+            (Some(0), b"%1\t42\n".to_vec())
+        );
+        let second = bundle_output(runs.pop_front().unwrap()).unwrap();
+        assert_eq!(second.status.code(), Some(1));
+        let vmmap = entries["vmmap --summary 42"][0].clone();
+        assert_eq!(
+            bundle_output(vmmap).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        let stat = bundle_output(entries["< /proc/42/stat"][0].clone()).unwrap();
+        assert_eq!(stat.stdout, b"42 (node) S 1");
+        let _ = fs::remove_dir_all(&dir);
     }
 }